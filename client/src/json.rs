@@ -0,0 +1,166 @@
+//! Just enough JSON to read the daemon's status object.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// Kept as written, so integers of any size parse exactly.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut chars = input.chars().peekable();
+    let value = value(&mut chars)?;
+    skip_spaces(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected {c:?} after the value")),
+    }
+}
+
+fn value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_spaces(chars);
+    match chars.peek() {
+        Some('{') => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_spaces(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Ok(Value::Object(fields));
+            }
+            loop {
+                skip_spaces(chars);
+                if chars.next() != Some('"') {
+                    return Err("expected a key".to_string());
+                }
+                let key = string(chars)?;
+                skip_spaces(chars);
+                if chars.next() != Some(':') {
+                    return Err(format!("expected : after {key:?}"));
+                }
+                fields.push((key, value(chars)?));
+                skip_spaces(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some('}') => return Ok(Value::Object(fields)),
+                    _ => return Err("expected , or } in object".to_string()),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_spaces(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Ok(Value::Array(items));
+            }
+            loop {
+                items.push(value(chars)?);
+                skip_spaces(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(items)),
+                    _ => return Err("expected , or ] in array".to_string()),
+                }
+            }
+        }
+        Some('"') => {
+            chars.next();
+            string(chars).map(Value::String)
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-.".contains(*c)) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "null" => Ok(Value::Null),
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ if word.parse::<f64>().is_ok() => Ok(Value::Number(word)),
+                _ => Err(format!("unexpected {word:?}")),
+            }
+        }
+        None => Err("missing value".to_string()),
+    }
+}
+
+/// The rest of a string whose opening quote was read.
+fn string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut out = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(out),
+            Some('\\') => match chars.next() {
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('/') => out.push('/'),
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('r') => out.push('\r'),
+                Some('b') => out.push('\u{8}'),
+                Some('f') => out.push('\u{c}'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("bad escape \\u{hex}"))?;
+                    out.push(c);
+                }
+                other => return Err(format!("unknown escape \\{}", other.unwrap_or(' '))),
+            },
+            Some(c) => out.push(c),
+            None => return Err("unclosed string".to_string()),
+        }
+    }
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_objects_and_escapes() {
+        let value =
+            parse(r#" {"a": "x\"y\u00e9", "b": [1, -2.5e3, null], "c": {"d": true}} "#).unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Value::String("x\"y\u{e9}".to_string()))
+        );
+        assert_eq!(
+            value.get("b"),
+            Some(&Value::Array(vec![
+                Value::Number("1".to_string()),
+                Value::Number("-2.5e3".to_string()),
+                Value::Null,
+            ]))
+        );
+        assert_eq!(
+            value.get("c").and_then(|c| c.get("d")),
+            Some(&Value::Bool(true))
+        );
+        for bad in ["{", r#"{"a" 1}"#, r#"{"a": nope}"#, "\"open", "{} x"] {
+            assert!(parse(bad).is_err(), "{bad:?}");
+        }
+    }
+}
@@ -0,0 +1,794 @@
+//! Typed access to a running interlude's control socket, for bar modules and
+//! scripts that would rather not speak the line protocol by hand.
+//!
+//! ```no_run
+//! use interlude_client::{Client, Phase};
+//!
+//! let client = Client::new()?;
+//! let status = client.status()?;
+//! if status.phase == Phase::Working {
+//!     println!("next break in {}s", status.seconds_left.unwrap_or(0));
+//! }
+//! client.pause(Some(30))?;
+//! # Ok::<(), interlude_client::Error>(())
+//! ```
+//!
+//! The protocol is one request per line; the daemon answers each with `ok`
+//! or `error: ...`, except `status`, which is answered with the status
+//! sentence, or with the status JSON for `status json`, and `capabilities`,
+//! answered with `protocol <version> <command>...`.
+//!
+//! The daemon and this crate share [`Request`], so they agree on the
+//! commands. New commands bump [`PROTOCOL_VERSION`]; a client asks for
+//! [`Client::capabilities`] before using one, so it keeps working against
+//! an older daemon.
+
+mod json;
+
+use std::env;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use json::Value;
+
+/// Something that went wrong talking to the daemon.
+#[derive(Debug)]
+pub enum Error {
+    /// Nothing is listening on the socket.
+    Connect {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Io(io::Error),
+    /// The daemon refused the request.
+    Daemon(String),
+    /// A request line or reply that does not follow the protocol.
+    Protocol(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect { path, source } => write!(
+                f,
+                "connect to {} (is interlude running?): {source}",
+                path.display()
+            ),
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Daemon(message) | Self::Protocol(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connect { source, .. } | Self::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Version of the protocol in this crate. Version 0 is the daemon from
+/// before `capabilities`, which knew every command but that one; version 2
+/// added `set`, version 3 `skip` and `break-now`, version 4 `note` and
+/// version 5 `tag`.
+pub const PROTOCOL_VERSION: u32 = 5;
+
+/// A daemon option that can be changed while it runs, with `set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    IntervalMinutes,
+    BreakSeconds,
+    SnoozeBaseSeconds,
+    /// 0 for unlimited.
+    MaxSnoozes,
+}
+
+impl Setting {
+    pub const ALL: [Self; 4] = [
+        Self::IntervalMinutes,
+        Self::BreakSeconds,
+        Self::SnoozeBaseSeconds,
+        Self::MaxSnoozes,
+    ];
+
+    /// The option's long name, e.g. `interval-minutes`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::IntervalMinutes => "interval-minutes",
+            Self::BreakSeconds => "break-seconds",
+            Self::SnoozeBaseSeconds => "snooze-base-seconds",
+            Self::MaxSnoozes => "max-snoozes",
+        }
+    }
+
+    pub fn parse(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|setting| setting.key() == key)
+    }
+
+    /// The values the daemon accepts.
+    pub fn range(self) -> std::ops::RangeInclusive<u64> {
+        match self {
+            Self::IntervalMinutes => 1..=24 * 60,
+            Self::BreakSeconds | Self::SnoozeBaseSeconds => 1..=24 * 60 * 60,
+            Self::MaxSnoozes => 0..=1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Stop covering outputs matching this name or description, until included again.
+    ExcludeOutput(String),
+    /// Undo an exclusion of this output, whether from the command line or `ExcludeOutput`.
+    IncludeOutput(String),
+    /// Snooze the due break, or push the next one back while still working.
+    Snooze,
+    /// Skip the due or running break.
+    Skip,
+    /// Start a break now, or the due break without waiting for its fade-in.
+    BreakNow,
+    /// Pause the timer, for this many minutes or until resumed.
+    Pause(Option<u64>),
+    Resume,
+    /// Answered by the server from the shared status, not forwarded.
+    Status {
+        json: bool,
+    },
+    /// Answered by the server with its [`Capabilities`], not forwarded.
+    Capabilities,
+    /// Change an option of the running daemon until it restarts.
+    Set(Setting, u64),
+    /// Add a line of text to the next break's overlay, or without text
+    /// clear them; they are cleared after the break either way.
+    Note(Option<String>),
+    /// Tag the current work cycle and the ones after it, for the stats; one
+    /// word, or none to stop tagging.
+    Tag(Option<String>),
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        let invalid = |message: String| Err(Error::Protocol(message));
+        match command {
+            "exclude-output" | "include-output" if arg.is_empty() => {
+                invalid(format!("{command} needs an output name"))
+            }
+            "snooze" => Ok(Self::Snooze),
+            "skip" => Ok(Self::Skip),
+            "break-now" => Ok(Self::BreakNow),
+            "pause" if arg.is_empty() => Ok(Self::Pause(None)),
+            "pause" => match arg.parse() {
+                Ok(minutes) if minutes > 0 => Ok(Self::Pause(Some(minutes))),
+                _ => invalid(format!("pause takes a number of minutes, got {arg:?}")),
+            },
+            "resume" => Ok(Self::Resume),
+            "status" if arg.is_empty() => Ok(Self::Status { json: false }),
+            "status" if arg == "json" => Ok(Self::Status { json: true }),
+            "capabilities" => Ok(Self::Capabilities),
+            "set" => {
+                let (key, value) = arg.split_once(' ').unwrap_or((arg, ""));
+                let setting = Setting::parse(key)
+                    .ok_or_else(|| Error::Protocol(format!("{key:?} cannot be set")))?;
+                match value.trim().parse() {
+                    Ok(value) if setting.range().contains(&value) => Ok(Self::Set(setting, value)),
+                    _ => {
+                        let range = setting.range();
+                        invalid(format!(
+                            "{key} takes a number from {} to {}, got {value:?}",
+                            range.start(),
+                            range.end()
+                        ))
+                    }
+                }
+            }
+            "note" if arg.is_empty() => Ok(Self::Note(None)),
+            "note" if arg.contains(['\n', '\r']) => invalid("note takes one line".to_string()),
+            "note" => Ok(Self::Note(Some(arg.to_string()))),
+            "tag" if arg.is_empty() => Ok(Self::Tag(None)),
+            "tag" if arg.contains(char::is_whitespace) => {
+                invalid(format!("tag takes one word, got {arg:?}"))
+            }
+            "tag" => Ok(Self::Tag(Some(arg.to_string()))),
+            "exclude-output" => Ok(Self::ExcludeOutput(arg.to_string())),
+            "include-output" => Ok(Self::IncludeOutput(arg.to_string())),
+            _ => invalid(format!("unknown command {command:?}")),
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::ExcludeOutput(output) => format!("exclude-output {output}"),
+            Self::IncludeOutput(output) => format!("include-output {output}"),
+            Self::Snooze => "snooze".to_string(),
+            Self::Skip => "skip".to_string(),
+            Self::BreakNow => "break-now".to_string(),
+            Self::Pause(None) => "pause".to_string(),
+            Self::Pause(Some(minutes)) => format!("pause {minutes}"),
+            Self::Resume => "resume".to_string(),
+            Self::Status { json: false } => "status".to_string(),
+            Self::Status { json: true } => "status json".to_string(),
+            Self::Capabilities => "capabilities".to_string(),
+            Self::Set(setting, value) => format!("set {} {value}", setting.key()),
+            Self::Note(None) => "note".to_string(),
+            Self::Note(Some(text)) => format!("note {text}"),
+            Self::Tag(None) => "tag".to_string(),
+            Self::Tag(Some(tag)) => format!("tag {tag}"),
+        }
+    }
+
+    /// The command word, as listed in [`Capabilities::commands`].
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::ExcludeOutput(_) => "exclude-output",
+            Self::IncludeOutput(_) => "include-output",
+            Self::Snooze => "snooze",
+            Self::Skip => "skip",
+            Self::BreakNow => "break-now",
+            Self::Pause(_) => "pause",
+            Self::Resume => "resume",
+            Self::Status { .. } => "status",
+            Self::Capabilities => "capabilities",
+            Self::Set(..) => "set",
+            Self::Note(_) => "note",
+            Self::Tag(_) => "tag",
+        }
+    }
+
+    /// Refuses a request that would not reach the daemon as itself: text
+    /// with a line break would be read as a second command, an empty note
+    /// as clearing them, and a tag of more than one word not at all. The
+    /// client checks before sending and the daemon after reading.
+    pub fn check(&self) -> Result<()> {
+        let line = self.to_line();
+        if line.contains(['\n', '\r']) {
+            return Err(Error::Protocol(format!(
+                "{} takes no line breaks",
+                self.command()
+            )));
+        }
+        if let Self::Note(Some(text)) = self
+            && text.trim().is_empty()
+        {
+            return Err(Error::Protocol("note text is empty".to_string()));
+        }
+        // One word, as the stats log keeps it.
+        if let Self::Tag(Some(tag)) = self
+            && (tag.is_empty() || tag.contains(char::is_whitespace))
+        {
+            return Err(Error::Protocol(format!("tag takes one word, got {tag:?}")));
+        }
+        if Self::parse(&line)? != *self {
+            return Err(Error::Protocol(format!(
+                "{line:?} would be read as a different request"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Every command word this crate's protocol version knows.
+pub const COMMANDS: &[&str] = &[
+    "exclude-output",
+    "include-output",
+    "snooze",
+    "pause",
+    "resume",
+    "status",
+    "capabilities",
+    "set",
+    "skip",
+    "break-now",
+    "note",
+    "tag",
+];
+
+/// What a daemon understands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: u32,
+    pub commands: Vec<String>,
+}
+
+impl Capabilities {
+    /// Those of this crate, which a daemon built with it answers with.
+    pub fn current() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            commands: COMMANDS.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    /// A daemon that predates `capabilities`.
+    fn version_zero() -> Self {
+        Self {
+            version: 0,
+            commands: COMMANDS
+                .iter()
+                .filter(|c| {
+                    !matches!(
+                        **c,
+                        "capabilities" | "set" | "skip" | "break-now" | "note" | "tag"
+                    )
+                })
+                .map(|c| c.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn supports(&self, request: &Request) -> bool {
+        self.commands.iter().any(|c| c == request.command())
+    }
+
+    pub fn to_line(&self) -> String {
+        format!("protocol {} {}", self.version, self.commands.join(" "))
+    }
+
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut words = line.split_whitespace();
+        let version = match (words.next(), words.next().map(str::parse)) {
+            (Some("protocol"), Some(Ok(version))) => version,
+            _ => {
+                return Err(Error::Protocol(format!(
+                    "not a capabilities line: {line:?}"
+                )));
+            }
+        };
+        Ok(Self {
+            version,
+            commands: words.map(str::to_string).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    Working,
+    Snoozed,
+    /// The break is due and waits to start.
+    Due,
+    Break,
+    /// The break is over and waits to be dismissed.
+    Finished,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Working => "working",
+            Self::Snoozed => "snoozed",
+            Self::Due => "due",
+            Self::Break => "break",
+            Self::Finished => "finished",
+        }
+    }
+
+    pub fn parse(label: &str) -> Option<Self> {
+        [
+            Self::Working,
+            Self::Snoozed,
+            Self::Due,
+            Self::Break,
+            Self::Finished,
+        ]
+        .into_iter()
+        .find(|phase| phase.label() == label)
+    }
+}
+
+/// Why breaks are held back while working.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Hold {
+    /// Paused with SIGUSR1 or `interlude ctl pause`.
+    Requested,
+    /// An idle inhibitor, a game or a call.
+    Inhibitor,
+    SessionLocked,
+    /// The next break was pushed back.
+    Deferred,
+    /// Outside the working day.
+    OffHours,
+    /// A hold this crate does not know yet.
+    Other(String),
+}
+
+impl Hold {
+    fn parse(label: &str) -> Self {
+        match label {
+            "requested" => Self::Requested,
+            "inhibitor" => Self::Inhibitor,
+            "session-locked" => Self::SessionLocked,
+            "deferred" => Self::Deferred,
+            "off-hours" => Self::OffHours,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// Today's break counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Today {
+    pub due: u32,
+    pub taken: u32,
+    pub skipped: u32,
+    pub snoozed: u32,
+}
+
+/// The values of every [`Setting`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub interval_minutes: u64,
+    pub break_seconds: u64,
+    pub snooze_base_seconds: u64,
+    /// 0 for unlimited.
+    pub max_snoozes: u64,
+}
+
+impl Settings {
+    pub fn get(&self, setting: Setting) -> u64 {
+        match setting {
+            Setting::IntervalMinutes => self.interval_minutes,
+            Setting::BreakSeconds => self.break_seconds,
+            Setting::SnoozeBaseSeconds => self.snooze_base_seconds,
+            Setting::MaxSnoozes => self.max_snoozes,
+        }
+    }
+}
+
+/// The answer to `status json`, as also written to the status file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// Short text for a bar, e.g. "25m".
+    pub text: String,
+    /// One sentence, e.g. "Paused until 15:30".
+    pub tooltip: String,
+    /// CSS class for bars to style on.
+    pub class: String,
+    pub phase: Phase,
+    /// Time left in the current phase, when it runs on a timer.
+    pub seconds_left: Option<u64>,
+    pub hold: Option<Hold>,
+    /// Local "HH:MM" the hold ends by itself, if it does.
+    pub paused_until: Option<String>,
+    /// Snoozes still allowed before the break; `None` when unlimited.
+    pub snoozes_left: Option<u32>,
+    /// Times the current break has been snoozed; 0 from daemons that do
+    /// not say.
+    pub snooze_count: u32,
+    pub today: Today,
+    /// `None` from daemons older than protocol version 2.
+    pub settings: Option<Settings>,
+    /// A newer interlude release, when the daemon checks for updates.
+    pub update_available: Option<String>,
+}
+
+impl Status {
+    /// Reads the status JSON; fields added by newer versions are ignored.
+    pub fn from_json(input: &str) -> Result<Self> {
+        let value = json::parse(input).map_err(Error::Protocol)?;
+        let string = |key: &str| match value.get(key) {
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(Value::Null) | None => Ok(None),
+            Some(_) => Err(Error::Protocol(format!("{key} is not a string"))),
+        };
+        let text = |key: &str| string(key).map(Option::unwrap_or_default);
+        let phase = string("phase")?.unwrap_or_default();
+        let today = value.get("today");
+        let count = |key: &str| number(today.and_then(|t| t.get(key)), key).map(|n| n.unwrap_or(0));
+        Ok(Self {
+            text: text("text")?,
+            tooltip: text("tooltip")?,
+            class: text("class")?,
+            phase: Phase::parse(&phase)
+                .ok_or_else(|| Error::Protocol(format!("unknown phase {phase:?}")))?,
+            seconds_left: number(value.get("seconds_left"), "seconds_left")?,
+            hold: string("hold")?.map(|label| Hold::parse(&label)),
+            paused_until: string("paused_until")?,
+            snoozes_left: number(value.get("snoozes_left"), "snoozes_left")?,
+            snooze_count: number(value.get("snooze_count"), "snooze_count")?.unwrap_or(0),
+            today: Today {
+                due: count("due")?,
+                taken: count("taken")?,
+                skipped: count("skipped")?,
+                snoozed: count("snoozed")?,
+            },
+            settings: match value.get("settings") {
+                Some(Value::Null) | None => None,
+                settings => {
+                    let setting = |key: &str| {
+                        number(settings.and_then(|s| s.get(key)), key).map(|n| n.unwrap_or(0))
+                    };
+                    Some(Settings {
+                        interval_minutes: setting("interval_minutes")?,
+                        break_seconds: setting("break_seconds")?,
+                        snooze_base_seconds: setting("snooze_base_seconds")?,
+                        max_snoozes: setting("max_snoozes")?,
+                    })
+                }
+            },
+            update_available: string("update_available")?,
+        })
+    }
+}
+
+fn number<T: std::str::FromStr>(value: Option<&Value>, key: &str) -> Result<Option<T>> {
+    match value {
+        Some(Value::Number(n)) => n
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Protocol(format!("{key} is out of range: {n}"))),
+        Some(Value::Null) | None => Ok(None),
+        Some(_) => Err(Error::Protocol(format!("{key} is not a number"))),
+    }
+}
+
+/// A connection target: the control socket of one running instance.
+#[derive(Debug, Clone)]
+pub struct Client {
+    path: PathBuf,
+    timeout: Option<Duration>,
+}
+
+impl Client {
+    /// The socket a daemon started without `--runtime-dir` listens on.
+    pub fn new() -> Result<Self> {
+        let path = match env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+        {
+            Some(dir) => dir.join("interlude.sock"),
+            None => {
+                let uid = std::fs::metadata("/proc/self")?.uid();
+                env::temp_dir().join(format!("interlude-{uid}.sock"))
+            }
+        };
+        Ok(Self {
+            path,
+            timeout: None,
+        })
+    }
+
+    /// The socket at `path`, e.g. in a daemon's `--runtime-dir`.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            timeout: None,
+        }
+    }
+
+    /// Gives up on a daemon that takes longer than `timeout` to take a
+    /// request or answer it; by default the client waits as long as it takes.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sends `request` and returns the reply line.
+    pub fn request(&self, request: &Request) -> Result<String> {
+        request.check()?;
+        let mut stream = UnixStream::connect(&self.path).map_err(|source| Error::Connect {
+            path: self.path.clone(),
+            source,
+        })?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        writeln!(stream, "{}", request.to_line())?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        let reply = reply.trim();
+        match reply.strip_prefix("error: ") {
+            Some(err) => Err(Error::Daemon(err.to_string())),
+            None => Ok(reply.to_string()),
+        }
+    }
+
+    /// What the daemon understands; a daemon too old to say is version 0.
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        match self.request(&Request::Capabilities) {
+            Ok(line) => Capabilities::parse(&line),
+            Err(Error::Daemon(message)) if message.starts_with("unknown command") => {
+                Ok(Capabilities::version_zero())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn status(&self) -> Result<Status> {
+        Status::from_json(&self.request(&Request::Status { json: true })?)
+    }
+
+    /// The status as one sentence, e.g. "Paused until 15:30".
+    pub fn status_line(&self) -> Result<String> {
+        self.request(&Request::Status { json: false })
+    }
+
+    pub fn snooze(&self) -> Result<()> {
+        self.send(&Request::Snooze)
+    }
+
+    /// Pauses for `minutes`, or until [`resume`](Self::resume) without.
+    pub fn pause(&self, minutes: Option<u64>) -> Result<()> {
+        self.send(&Request::Pause(minutes))
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        self.send(&Request::Resume)
+    }
+
+    /// Skips the due or running break; needs protocol version 3.
+    pub fn skip(&self) -> Result<()> {
+        self.send(&Request::Skip)
+    }
+
+    /// Starts a break now; needs protocol version 3.
+    pub fn break_now(&self) -> Result<()> {
+        self.send(&Request::BreakNow)
+    }
+
+    pub fn exclude_output(&self, output: &str) -> Result<()> {
+        self.send(&Request::ExcludeOutput(output.to_string()))
+    }
+
+    pub fn include_output(&self, output: &str) -> Result<()> {
+        self.send(&Request::IncludeOutput(output.to_string()))
+    }
+
+    /// Changes `setting` until the daemon restarts; needs protocol version 2.
+    pub fn set(&self, setting: Setting, value: u64) -> Result<()> {
+        self.send(&Request::Set(setting, value))
+    }
+
+    /// Adds `text` to the next break's overlay, or without it clears what
+    /// was added; needs protocol version 4.
+    pub fn note(&self, text: Option<&str>) -> Result<()> {
+        self.send(&Request::Note(text.map(str::to_string)))
+    }
+
+    /// Tags the current work cycle and the ones after it with `tag`, or
+    /// without one stops tagging; needs protocol version 5.
+    pub fn tag(&self, tag: Option<&str>) -> Result<()> {
+        self.send(&Request::Tag(tag.map(str::to_string)))
+    }
+
+    fn send(&self, request: &Request) -> Result<()> {
+        match self.request(request)?.as_str() {
+            "ok" => Ok(()),
+            other => Err(Error::Protocol(format!("unexpected reply {other:?}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_round_trip() {
+        for request in [
+            Request::ExcludeOutput("HDMI-A-1".to_string()),
+            Request::IncludeOutput("LG TV".to_string()),
+            Request::Snooze,
+            Request::Skip,
+            Request::BreakNow,
+            Request::Pause(None),
+            Request::Pause(Some(30)),
+            Request::Resume,
+            Request::Status { json: false },
+            Request::Status { json: true },
+            Request::Capabilities,
+            Request::Set(Setting::IntervalMinutes, 25),
+            Request::Set(Setting::MaxSnoozes, 0),
+            Request::Note(Some("Stand-up at 11:00".to_string())),
+            Request::Note(None),
+            Request::Tag(Some("project-x".to_string())),
+            Request::Tag(None),
+        ] {
+            assert_eq!(Request::parse(&request.to_line()).unwrap(), request);
+            assert!(Capabilities::current().supports(&request));
+        }
+        assert!(!Capabilities::version_zero().supports(&Request::Capabilities));
+        assert!(Request::parse("exclude-output").is_err());
+        assert!(Request::parse("reboot").is_err());
+        assert!(Request::parse("pause soon").is_err());
+        assert!(Request::parse("pause 0").is_err());
+        assert!(Request::parse("status yaml").is_err());
+        assert!(Request::parse("set interval-minutes 0").is_err());
+        assert!(Request::parse("set break-seconds").is_err());
+        assert!(Request::parse("set fade-fps 30").is_err());
+        assert!(!Capabilities::version_zero().supports(&Request::Set(Setting::MaxSnoozes, 1)));
+        assert!(!Capabilities::version_zero().supports(&Request::BreakNow));
+        assert_eq!(Request::parse("note   ").unwrap(), Request::Note(None));
+        assert!(Request::parse("note a\rskip").is_err());
+        assert!(Request::parse("tag project x").is_err());
+    }
+
+    #[test]
+    fn notes_cannot_smuggle_a_second_command() {
+        let note = |text: &str| Request::Note(Some(text.to_string()));
+        assert!(note("Stand-up at 11:00").check().is_ok());
+        assert!(Request::Note(None).check().is_ok());
+        for text in ["Stand-up\nskip", "Stand-up\rskip", "", "  ", " padded "] {
+            assert!(note(text).check().is_err(), "{text:?} accepted");
+        }
+        let refused = Client::at("/nonexistent").note(Some("Stand-up\nskip"));
+        assert!(matches!(refused, Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn tags_are_one_word() {
+        let client = Client::at("/nonexistent");
+        for tag in ["x\npause", "x\rpause", "project x", ""] {
+            assert!(matches!(client.tag(Some(tag)), Err(Error::Protocol(_))));
+            assert!(Request::Tag(Some(tag.to_string())).check().is_err());
+        }
+        assert!(Request::Tag(Some("project-x".to_string())).check().is_ok());
+    }
+
+    #[test]
+    fn capabilities_round_trip() {
+        let current = Capabilities::current();
+        assert_eq!(Capabilities::parse(&current.to_line()).unwrap(), current);
+        assert!(current.to_line().starts_with("protocol 5 exclude-output "));
+        assert!(Capabilities::parse("ok").is_err());
+        assert!(Capabilities::parse("protocol x snooze").is_err());
+    }
+
+    #[test]
+    fn reads_the_status_json() {
+        let json = r#"{"text":"paused","tooltip":"Paused until 15:30","class":"paused","alt":"paused","phase":"working","seconds_left":600,"hold":"requested","paused_until":"15:30","snoozes_left":null,"snooze_count":1,"today":{"due":4,"taken":3,"skipped":1,"snoozed":2},"settings":{"interval_minutes":30,"break_seconds":180,"snooze_base_seconds":300,"max_snoozes":0},"later":[1]}"#;
+        assert_eq!(
+            Status::from_json(json).unwrap(),
+            Status {
+                text: "paused".to_string(),
+                tooltip: "Paused until 15:30".to_string(),
+                class: "paused".to_string(),
+                phase: Phase::Working,
+                seconds_left: Some(600),
+                hold: Some(Hold::Requested),
+                paused_until: Some("15:30".to_string()),
+                snoozes_left: None,
+                snooze_count: 1,
+                today: Today {
+                    due: 4,
+                    taken: 3,
+                    skipped: 1,
+                    snoozed: 2,
+                },
+                settings: Some(Settings {
+                    interval_minutes: 30,
+                    break_seconds: 180,
+                    snooze_base_seconds: 300,
+                    max_snoozes: 0,
+                }),
+                update_available: None,
+            }
+        );
+        assert!(Status::from_json(r#"{"phase":"napping"}"#).is_err());
+        assert!(Status::from_json(r#"{"phase":"due","seconds_left":-1}"#).is_err());
+    }
+}
@@ -108,10 +108,26 @@
                       default = 300;
                       description = "Initial snooze duration in seconds.";
                     };
+                    snooze_curve = lib.mkOption {
+                      type = lib.types.enum [ "exponential" "linear" "constant" "schedule" ];
+                      default = "exponential";
+                      description = "How snoozes shrink within a cycle.";
+                    };
                     snooze_decay = lib.mkOption {
                       type = lib.types.float;
                       default = 0.6;
-                      description = "Snooze decay multiplier (0 < decay < 1).";
+                      description = "Snooze decay multiplier (0 < decay < 1), for the exponential curve.";
+                    };
+                    snooze_step_seconds = lib.mkOption {
+                      type = lib.types.ints.unsigned;
+                      default = 60;
+                      description = "Seconds each snooze is shorter than the last, for the linear curve.";
+                    };
+                    snooze_schedule = lib.mkOption {
+                      type = lib.types.listOf lib.types.ints.positive;
+                      default = [ ];
+                      example = [ 300 120 60 ];
+                      description = "Snooze lengths in seconds for the schedule curve; the last one repeats.";
                     };
                     snooze_min_seconds = lib.mkOption {
                       type = lib.types.ints.positive;
@@ -131,12 +147,12 @@
                     background = lib.mkOption {
                       type = lib.types.str;
                       default = "#000000CC";
-                      description = "Background overlay color in hex.";
+                      description = "Background overlay color: hex, a CSS name, rgb(a)() or hsl(a)().";
                     };
                     foreground = lib.mkOption {
                       type = lib.types.str;
                       default = "#FFFFFFDD";
-                      description = "Foreground text/icon color in hex.";
+                      description = "Foreground text/icon color, in the same forms as background.";
                     };
                     fade_fps = lib.mkOption {
                       type = lib.types.ints.positive;
@@ -194,7 +210,13 @@
                       [ "--break-seconds" (toString settings.break_seconds) ]
                       [ "--initial-break-seconds" (toString settings.initial_break_seconds) ]
                       [ "--snooze-base-seconds" (toString settings.snooze_base_seconds) ]
+                      [ "--snooze-curve" settings.snooze_curve ]
                       [ "--snooze-decay" (toString settings.snooze_decay) ]
+                      [ "--snooze-step-seconds" (toString settings.snooze_step_seconds) ]
+                      (lib.optionals (settings.snooze_schedule != [ ]) [
+                        "--snooze-schedule"
+                        (lib.concatMapStringsSep "," toString settings.snooze_schedule)
+                      ])
                       [ "--snooze-min-seconds" (toString settings.snooze_min_seconds) ]
                       [ "--max-snoozes" (toString settings.max_snoozes) ]
                       (lib.optional settings.immediate "--immediate")
@@ -0,0 +1,60 @@
+//! The state file is read at every startup, so no contents may panic the
+//! loader or the scheduler it restores.
+#![no_main]
+#![allow(dead_code)]
+
+#[path = "../../src/paths.rs"]
+mod paths;
+#[path = "../../src/scheduler.rs"]
+mod scheduler;
+#[path = "../../src/state.rs"]
+mod state;
+
+use libfuzzer_sys::fuzz_target;
+use scheduler::{Config, SnoozeCurve};
+use std::time::Duration;
+
+fn cfg() -> Config {
+    Config {
+        interval: Duration::from_secs(1800),
+        break_len: Duration::from_secs(180),
+        initial_interval: Duration::from_secs(3600),
+        initial_break_len: Duration::from_secs(180),
+        snooze_base: Duration::from_secs(300),
+        snooze_curve: SnoozeCurve::Exponential { decay: 0.5 },
+        snooze_min: Duration::from_secs(60),
+        max_snoozes: Some(3),
+        max_snooze_total: Some(Duration::from_secs(900)),
+        long_break_every: Some(4),
+        long_break_len: Duration::from_secs(600),
+        carry_over: 0.5,
+        warn_before: Some(Duration::from_secs(60)),
+        milestones: vec![Duration::from_secs(300)],
+        early_finish_after: Some(0.5),
+        idle_credit: 0.5,
+        adaptive_strain: 0.25,
+        planning_break: true,
+        wrap_up_len: Duration::from_secs(900),
+        micro_every: None,
+        micro_len: Duration::from_secs(20),
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Some(mut sched) = state::scheduler_from_str(text, &cfg()) else {
+        return;
+    };
+    // What the main loop does with a restored scheduler first.
+    sched.apply_startup_grace(Duration::from_secs(60));
+    let _ = sched.time_left();
+    let _ = sched.next_wakeup();
+    let _ = sched.break_duration();
+    sched.tick();
+    let _ = sched.snooze();
+    sched.start_break();
+    sched.extend_break(Duration::from_secs(60));
+    sched.skip_break();
+});
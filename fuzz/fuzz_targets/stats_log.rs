@@ -0,0 +1,28 @@
+//! The stats log is read at startup for today's totals and by every
+//! `interlude stats` command; no line may panic the parser or the renderers.
+#![no_main]
+#![allow(dead_code, unused_imports, unused_macros)]
+
+#[path = "../../src/logging.rs"]
+mod logging;
+#[path = "../../src/paths.rs"]
+mod paths;
+#[path = "../../src/scheduler.rs"]
+mod scheduler;
+#[path = "../../src/state.rs"]
+mod state;
+#[path = "../../src/stats.rs"]
+mod stats;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let records = stats::parse_log(text);
+    let today = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let _ = stats::render_chart(&records, today, stats::local_date);
+    let _ = stats::render_metrics(&records);
+    let _ = stats::render_tags(&records, today, stats::local_date);
+});
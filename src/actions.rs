@@ -0,0 +1,263 @@
+//! Routing of user input to scheduler actions.
+//!
+//! Every input source (bound keys, other key presses, pointer gestures,
+//! signals) is turned into an [`Input`]; the [`ActionTable`] decides which
+//! [`Action`], if any, it triggers in the current phase.
+
+use crate::keys::KeyAction;
+use crate::scheduler::Phase;
+
+/// Something the user asked the scheduler to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Snooze,
+    Skip,
+    /// Start the break now instead of waiting for the timer or the fade-in.
+    StartBreak,
+    /// Close the break-complete screen and start the next work interval.
+    Dismiss,
+    /// Toggle a manual pause of the work timer.
+    Pause,
+    /// Add a minute to the running break.
+    ExtendBreak,
+    /// End the running break once its minimum portion has passed.
+    FinishEarly,
+    Rate(u8),
+}
+
+impl Action {
+    /// What a key hint on the overlay calls the action.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Self::Snooze => "snooze",
+            Self::Skip => "skip",
+            Self::StartBreak => "start",
+            Self::Dismiss => "continue",
+            Self::Pause => "pause",
+            Self::ExtendBreak => "extend",
+            Self::FinishEarly => "finish",
+            Self::Rate(_) => "rate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// A key with a binding.
+    Key(KeyAction),
+    /// Any key press, bound or not.
+    AnyKey,
+    /// A bound key held down for a while.
+    KeyHold(KeyAction),
+    PointerGesture,
+    /// Signals and control commands name the action directly; the table only
+    /// decides whether it applies in the current phase.
+    Command(Action),
+}
+
+/// A bound key that does something in a phase, for the overlay's hints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyHint {
+    pub key: KeyAction,
+    /// The key has to be held down.
+    pub hold: bool,
+    pub action: Action,
+}
+
+struct Rule {
+    phases: &'static [Phase],
+    input: Input,
+    action: Action,
+}
+
+const BREAK_DUE: &[Phase] = &[Phase::LockedAwaitingAction, Phase::OnBreak];
+const FINISHED: &[Phase] = &[Phase::BreakFinished];
+const WORKING: &[Phase] = &[Phase::Working, Phase::Snoozing];
+
+pub struct ActionTable {
+    rules: Vec<Rule>,
+}
+
+impl ActionTable {
+    pub fn new(feedback_prompt: bool) -> Self {
+        let rule = |phases, input, action| Rule {
+            phases,
+            input,
+            action,
+        };
+        let mut rules = vec![
+            rule(BREAK_DUE, Input::Key(KeyAction::Snooze), Action::Snooze),
+            rule(BREAK_DUE, Input::Key(KeyAction::Skip), Action::Skip),
+            rule(
+                &[Phase::LockedAwaitingAction],
+                Input::Key(KeyAction::Continue),
+                Action::StartBreak,
+            ),
+            rule(
+                &[Phase::LockedAwaitingAction],
+                Input::Key(KeyAction::StartBreak),
+                Action::StartBreak,
+            ),
+            rule(
+                &[Phase::OnBreak],
+                Input::KeyHold(KeyAction::Continue),
+                Action::FinishEarly,
+            ),
+            rule(FINISHED, Input::AnyKey, Action::Dismiss),
+            rule(FINISHED, Input::PointerGesture, Action::Dismiss),
+            rule(BREAK_DUE, Input::Command(Action::Snooze), Action::Snooze),
+            rule(WORKING, Input::Command(Action::Snooze), Action::Snooze),
+            rule(BREAK_DUE, Input::Command(Action::Skip), Action::Skip),
+            rule(
+                &[Phase::Working, Phase::Snoozing, Phase::LockedAwaitingAction],
+                Input::Command(Action::StartBreak),
+                Action::StartBreak,
+            ),
+            rule(
+                &[Phase::OnBreak],
+                Input::Command(Action::FinishEarly),
+                Action::FinishEarly,
+            ),
+            rule(FINISHED, Input::Command(Action::Dismiss), Action::Dismiss),
+            rule(WORKING, Input::Command(Action::Pause), Action::Pause),
+            rule(
+                &[Phase::OnBreak],
+                Input::Command(Action::ExtendBreak),
+                Action::ExtendBreak,
+            ),
+        ];
+        if feedback_prompt {
+            for rating in 1..=3 {
+                rules.push(rule(
+                    FINISHED,
+                    Input::Key(KeyAction::Rating(rating)),
+                    Action::Rate(rating),
+                ));
+            }
+        }
+        Self { rules }
+    }
+
+    /// Actions triggered by `inputs` in `phase`, in rule order and without
+    /// duplicates.
+    pub fn route(&self, phase: Phase, inputs: &[Input]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for rule in &self.rules {
+            if rule.phases.contains(&phase)
+                && inputs.contains(&rule.input)
+                && !actions.contains(&rule.action)
+            {
+                actions.push(rule.action);
+            }
+        }
+        actions
+    }
+
+    /// The keys that act in `phase`, the first one for each action, in rule
+    /// order. Hints built from this cannot drift from what the keys do.
+    pub fn key_hints(&self, phase: Phase) -> Vec<KeyHint> {
+        let mut hints: Vec<KeyHint> = Vec::new();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.phases.contains(&phase))
+        {
+            let (key, hold) = match rule.input {
+                Input::Key(key) => (key, false),
+                Input::KeyHold(key) => (key, true),
+                _ => continue,
+            };
+            if !hints.iter().any(|hint| hint.action == rule.action) {
+                hints.push(KeyHint {
+                    key,
+                    hold,
+                    action: rule.action,
+                });
+            }
+        }
+        hints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_only_act_in_their_phases() {
+        let table = ActionTable::new(false);
+        let snooze = [Input::Key(KeyAction::Snooze), Input::AnyKey];
+        assert_eq!(table.route(Phase::OnBreak, &snooze), vec![Action::Snooze]);
+        assert_eq!(table.route(Phase::Working, &snooze), vec![]);
+        assert_eq!(
+            table.route(Phase::BreakFinished, &snooze),
+            vec![Action::Dismiss]
+        );
+    }
+
+    #[test]
+    fn rating_also_dismisses() {
+        let inputs = [Input::Key(KeyAction::Rating(2)), Input::AnyKey];
+        assert_eq!(
+            ActionTable::new(true).route(Phase::BreakFinished, &inputs),
+            vec![Action::Dismiss, Action::Rate(2)]
+        );
+        assert_eq!(
+            ActionTable::new(false).route(Phase::BreakFinished, &inputs),
+            vec![Action::Dismiss]
+        );
+    }
+
+    #[test]
+    fn commands_are_gated_by_phase() {
+        let table = ActionTable::new(false);
+        let pause = [Input::Command(Action::Pause)];
+        assert_eq!(table.route(Phase::Working, &pause), vec![Action::Pause]);
+        assert_eq!(table.route(Phase::OnBreak, &pause), vec![]);
+        let start = [Input::Command(Action::StartBreak)];
+        assert_eq!(
+            table.route(Phase::Snoozing, &start),
+            vec![Action::StartBreak]
+        );
+        assert_eq!(table.route(Phase::BreakFinished, &start), vec![]);
+        let snooze = [Input::Command(Action::Snooze)];
+        assert_eq!(table.route(Phase::Working, &snooze), vec![Action::Snooze]);
+        assert_eq!(table.route(Phase::BreakFinished, &snooze), vec![]);
+    }
+
+    #[test]
+    fn hints_follow_the_rules() {
+        let table = ActionTable::new(true);
+        let hint = |key, hold, action| KeyHint { key, hold, action };
+        assert_eq!(
+            table.key_hints(Phase::LockedAwaitingAction),
+            vec![
+                hint(KeyAction::Snooze, false, Action::Snooze),
+                hint(KeyAction::Skip, false, Action::Skip),
+                hint(KeyAction::Continue, false, Action::StartBreak),
+            ]
+        );
+        assert_eq!(
+            table.key_hints(Phase::OnBreak).last(),
+            Some(&hint(KeyAction::Continue, true, Action::FinishEarly))
+        );
+        assert!(table.key_hints(Phase::Working).is_empty());
+        assert_eq!(table.key_hints(Phase::BreakFinished).len(), 3);
+    }
+
+    #[test]
+    fn start_key_only_acts_while_break_is_due() {
+        let table = ActionTable::new(false);
+        let start = [Input::Key(KeyAction::StartBreak), Input::AnyKey];
+        assert_eq!(
+            table.route(Phase::LockedAwaitingAction, &start),
+            vec![Action::StartBreak]
+        );
+        assert_eq!(table.route(Phase::OnBreak, &start), vec![]);
+        let hold = [Input::KeyHold(KeyAction::Continue)];
+        assert_eq!(
+            table.route(Phase::OnBreak, &hold),
+            vec![Action::FinishEarly]
+        );
+    }
+}
@@ -1,99 +1,675 @@
+//! Chimes for scheduler events.
+//!
+//! The audio thread subscribes to the scheduler's event channel and plays
+//! whatever the [`SoundMap`] assigns to each [`Cue`]. Without an output
+//! device it tries again at every break boundary, since the sound server
+//! often starts, or restarts, after we do.
+
+use anyhow::{Context, Result, bail};
+use crossbeam_channel::Receiver;
 use ogg::PacketReader;
 use opus::{Channels, Decoder as OpusDecoder};
+use rodio::source::{SineWave, Source, Zero};
 use rodio::{OutputStream, OutputStreamHandle, Sink};
+use std::collections::HashMap;
+use std::fs;
 use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::cli::AwaySound;
+use crate::logging::{error, info, rate_limited};
+use crate::scheduler::{BreakKind, Phase, SchedulerEvent};
 
 const START_OPUS: &[u8] = include_bytes!("../assets/start.opus");
 const END_OPUS: &[u8] = include_bytes!("../assets/end.opus");
+/// Volume of a start chime with [`AwaySound::Quiet`], relative to normal.
+const QUIET_GAIN: f32 = 0.3;
+
+/// Moments in the break cycle that can have a sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cue {
+    /// Work time left reached a configured milestone.
+    Milestone,
+    Warning,
+    BreakDue,
+    BreakStart,
+    BreakEnd,
+    Snooze,
+    Skip,
+}
+
+impl Cue {
+    pub fn from_event(event: &SchedulerEvent) -> Option<Self> {
+        match *event {
+            SchedulerEvent::PhaseChanged { to, .. } => match to {
+                Phase::LockedAwaitingAction => Some(Self::BreakDue),
+                Phase::OnBreak => Some(Self::BreakStart),
+                Phase::BreakFinished => Some(Self::BreakEnd),
+                Phase::Working | Phase::Snoozing => None,
+            },
+            SchedulerEvent::SnoozeTaken { .. } => Some(Self::Snooze),
+            SchedulerEvent::BreakSkipped { .. } => Some(Self::Skip),
+            SchedulerEvent::Deferred { .. } => None,
+            // Quiet, so a glance away does not turn heads.
+            SchedulerEvent::MicroBreakStarted { .. } | SchedulerEvent::MicroBreakFinished => None,
+            SchedulerEvent::BreakWarning { .. } => Some(Self::Warning),
+            SchedulerEvent::Milestone { .. } => Some(Self::Milestone),
+        }
+    }
+}
+
+/// Built-in sounds, or a file of the user's.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Sound {
+    Start,
+    End,
+    /// Any format [`decode`] reads.
+    File(PathBuf),
+    /// A short, quiet tone, softer than the break chimes.
+    Warning,
+    /// Two quiet tones.
+    Milestone,
+    /// The event read out with `spd-say`, for cues that have something to say.
+    Speech,
+}
+
+impl Sound {
+    /// The encoded audio, for sounds that are not generated.
+    fn data(&self) -> Option<Result<Vec<u8>>> {
+        match self {
+            Self::Start => Some(Ok(START_OPUS.to_vec())),
+            Self::End => Some(Ok(END_OPUS.to_vec())),
+            Self::File(path) => {
+                Some(fs::read(path).with_context(|| format!("read {}", path.display())))
+            }
+            Self::Warning | Self::Milestone | Self::Speech => None,
+        }
+    }
+}
+
+/// Which sound, if any, plays for each cue, with overrides for some sorts
+/// of break.
+#[derive(Debug, Clone)]
+pub struct SoundMap {
+    sounds: HashMap<Cue, Sound>,
+    overrides: HashMap<(BreakKind, Cue), Sound>,
+}
+
+impl Default for SoundMap {
+    fn default() -> Self {
+        let mut map = Self {
+            sounds: HashMap::new(),
+            overrides: HashMap::new(),
+        };
+        map.set(Cue::Milestone, Some(Sound::Milestone));
+        map.set(Cue::Warning, Some(Sound::Warning));
+        map.set(Cue::BreakStart, Some(Sound::Start));
+        map.set(Cue::BreakEnd, Some(Sound::End));
+        map
+    }
+}
+
+impl SoundMap {
+    pub fn set(&mut self, cue: Cue, sound: Option<Sound>) {
+        match sound {
+            Some(sound) => self.sounds.insert(cue, sound),
+            None => self.sounds.remove(&cue),
+        };
+    }
+
+    /// Plays `sound` for `cue` in breaks of `kind` instead of the usual one.
+    pub fn set_for(&mut self, kind: BreakKind, cue: Cue, sound: Sound) {
+        self.overrides.insert((kind, cue), sound);
+    }
+
+    pub fn sound(&self, cue: Cue, kind: BreakKind) -> Option<&Sound> {
+        self.overrides
+            .get(&(kind, cue))
+            .or_else(|| self.sounds.get(&cue))
+    }
+
+    /// Reads the header of every sound file in the map, so one that cannot
+    /// be played is reported at start-up rather than at the first break.
+    pub fn check_files(&self) -> Result<()> {
+        for sound in self.sounds.values().chain(self.overrides.values()) {
+            if let Sound::File(path) = sound
+                && let Some(data) = sound.data()
+            {
+                check_format(data?).with_context(|| format!("{}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plays sounds for scheduler events on a thread of its own, until the
+/// scheduler goes away. Without an output device the events are drained
+/// silently until a break boundary finds one. While `away` is set, break
+/// start chimes follow `away_sound`.
+pub fn spawn_audio(
+    events: Receiver<SchedulerEvent>,
+    sounds: SoundMap,
+    away: Arc<AtomicBool>,
+    away_sound: AwaySound,
+) -> Result<()> {
+    thread::Builder::new()
+        .name("audio".to_string())
+        .spawn(move || {
+            // The output stream is not `Send`, so it lives on this thread.
+            let mut audio = Audio::new()
+                .inspect_err(|err| {
+                    error!("audio output unavailable: {err:#}; retrying at each break")
+                })
+                .ok();
+            for event in events {
+                if audio.is_none() && is_break_boundary(&event) {
+                    audio = Audio::new().ok();
+                    if audio.is_some() {
+                        info!("Audio output available");
+                    }
+                }
+                let Some(cue) = Cue::from_event(&event) else {
+                    continue;
+                };
+                let Some(gain) = cue_gain(cue, away.load(Ordering::Relaxed), away_sound) else {
+                    continue;
+                };
+                let kind = match event {
+                    SchedulerEvent::PhaseChanged { kind, .. } => kind,
+                    _ => BreakKind::Regular,
+                };
+                match sounds.sound(cue, kind) {
+                    Some(Sound::Speech) => {
+                        if let Some(text) = announcement(&event) {
+                            speak(&text);
+                        }
+                    }
+                    Some(sound) => {
+                        if let Some(output) = audio.as_mut()
+                            && let Err(err) = output.play(sound, gain)
+                        {
+                            rate_limited(
+                                "audio output lost",
+                                format!("{err:#}; retrying at the next break"),
+                            );
+                            audio = None;
+                        }
+                    }
+                    None => {}
+                }
+            }
+        })
+        .context("spawn audio thread")?;
+    Ok(())
+}
+
+/// Volume for `cue` relative to normal, or `None` to stay silent. Only the
+/// start of a break is softened when nobody is around; the end still chimes.
+fn cue_gain(cue: Cue, away: bool, away_sound: AwaySound) -> Option<f32> {
+    if !away || !matches!(cue, Cue::BreakDue | Cue::BreakStart) {
+        return Some(1.0);
+    }
+    match away_sound {
+        AwaySound::Play => Some(1.0),
+        AwaySound::Quiet => Some(QUIET_GAIN),
+        AwaySound::Skip => None,
+    }
+}
+
+/// Whether an output device can be opened right now, for status output.
+pub fn probe_output() -> Result<()> {
+    Audio::new().map(drop)
+}
+
+/// Events where a missing output device is looked for again.
+fn is_break_boundary(event: &SchedulerEvent) -> bool {
+    matches!(
+        event,
+        SchedulerEvent::PhaseChanged {
+            to: Phase::LockedAwaitingAction | Phase::OnBreak | Phase::BreakFinished,
+            ..
+        }
+    )
+}
 
-pub struct Audio {
+struct Audio {
     _stream: OutputStream,
     handle: OutputStreamHandle,
+    /// Decoded sounds, or `None` for ones that failed to decode.
+    decoded: HashMap<Sound, Option<Pcm>>,
 }
 
 impl Audio {
-    pub fn new() -> Option<Self> {
-        let (stream, handle) = OutputStream::try_default().ok()?;
-        Some(Self {
+    fn new() -> Result<Self> {
+        let (stream, handle) = OutputStream::try_default().context("open output stream")?;
+        Ok(Self {
             _stream: stream,
             handle,
+            decoded: HashMap::new(),
         })
     }
 
-    pub fn play_start(&self) {
-        play_bytes(&self.handle, START_OPUS);
+    /// Fails only when the output device went away.
+    /// Plays `sound` at `gain` times its normal volume.
+    fn play(&mut self, sound: &Sound, gain: f32) -> Result<()> {
+        if !self.decoded.contains_key(sound) {
+            let Some(data) = sound.data() else {
+                return play_tone(&self.handle, tone_beeps(sound), gain).map(Sink::detach);
+            };
+            let pcm = data
+                .and_then(decode)
+                .inspect_err(|err| rate_limited("audio decode error", format!("{err:#}")))
+                .ok();
+            self.decoded.insert(sound.clone(), pcm);
+        }
+        match &self.decoded[sound] {
+            Some(pcm) => play_samples(&self.handle, pcm, gain).map(Sink::detach),
+            None => Ok(()),
+        }
     }
+}
 
-    pub fn play_end(&self) {
-        play_bytes(&self.handle, END_OPUS);
+/// Plays what `sounds` has for `cue` in breaks of `kind` right away, through
+/// the same output the daemon would use, and waits for it to end. Unlike
+/// the daemon this reports a missing device or an unreadable file.
+pub fn play_test(sounds: &SoundMap, cue: Cue, kind: BreakKind) -> Result<()> {
+    let Some(sound) = sounds.sound(cue, kind) else {
+        bail!("no sound is set for it");
+    };
+    if *sound == Sound::Speech {
+        speak(
+            &announcement(&SchedulerEvent::Milestone {
+                left: Duration::from_secs(5 * 60),
+            })
+            .unwrap_or_default(),
+        );
+        return Ok(());
     }
+    let audio = Audio::new()?;
+    let sink = match sound.data() {
+        Some(data) => play_samples(&audio.handle, &decode(data?)?, 1.0)?,
+        None => play_tone(&audio.handle, tone_beeps(sound), 1.0)?,
+    };
+    sink.sleep_until_end();
+    Ok(())
 }
 
-fn play_bytes(handle: &OutputStreamHandle, bytes: &'static [u8]) {
-    let (samples, channels, sample_rate) = match decode_opus(bytes) {
-        Some(decoded) => decoded,
-        None => return,
-    };
-    let source = rodio::buffer::SamplesBuffer::new(channels, sample_rate, samples);
-    let sink = match Sink::try_new(handle) {
-        Ok(sink) => sink,
-        Err(err) => {
-            eprintln!("audio sink error: {err}");
-            return;
+fn tone_beeps(sound: &Sound) -> u32 {
+    if *sound == Sound::Milestone { 2 } else { 1 }
+}
+
+fn play_samples(handle: &OutputStreamHandle, pcm: &Pcm, gain: f32) -> Result<Sink> {
+    let source =
+        rodio::buffer::SamplesBuffer::new(pcm.channels, pcm.sample_rate, pcm.samples.clone());
+    let sink = Sink::try_new(handle).context("open audio sink")?;
+    sink.set_volume(0.5 * gain);
+    sink.append(source);
+    Ok(sink)
+}
+
+fn play_tone(handle: &OutputStreamHandle, beeps: u32, gain: f32) -> Result<Sink> {
+    let sink = Sink::try_new(handle).context("open audio sink")?;
+    sink.set_volume(gain);
+    for beep in 0..beeps {
+        if beep > 0 {
+            sink.append(Zero::<f32>::new(1, 48_000).take_duration(Duration::from_millis(150)));
         }
+        let tone = SineWave::new(660.0)
+            .take_duration(Duration::from_millis(400))
+            .fade_in(Duration::from_millis(60))
+            .amplify(0.15);
+        sink.append(tone);
+    }
+    Ok(sink)
+}
+
+/// What to say for `event`, if it is worth saying.
+fn announcement(event: &SchedulerEvent) -> Option<String> {
+    let (SchedulerEvent::Milestone { left: time }
+    | SchedulerEvent::BreakWarning { break_in: time }) = *event
+    else {
+        return None;
     };
-    sink.set_volume(0.5);
-    sink.append(source);
-    sink.detach();
+    let minutes = time.as_secs().div_ceil(60);
+    Some(match minutes {
+        0 | 1 => "Break in one minute".to_string(),
+        n => format!("Break in {n} minutes"),
+    })
+}
+
+fn speak(text: &str) {
+    use std::process::{Command, Stdio};
+    // Nothing to read from a terminal, and nothing worth blocking on if our
+    // own output is a stuck pipe.
+    let spawned = Command::new("spd-say")
+        .arg(text)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(err) = spawned {
+        error!("spd-say failed: {err}");
+    }
+}
+
+/// Decoded samples, ready for a [`SamplesBuffer`](rodio::buffer::SamplesBuffer).
+#[derive(Debug, Clone)]
+struct Pcm {
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+/// Decodes Ogg Opus with libopus and everything else rodio reads: WAV, FLAC,
+/// Ogg Vorbis and MP3.
+fn decode(bytes: Vec<u8>) -> Result<Pcm> {
+    if is_ogg_opus(&bytes) {
+        return decode_opus(bytes);
+    }
+    let decoder = rodio::Decoder::new(Cursor::new(bytes)).context("unsupported audio format")?;
+    let channels = decoder.channels();
+    let sample_rate = decoder.sample_rate();
+    let samples: Vec<f32> = decoder.convert_samples().collect();
+    if samples.is_empty() {
+        bail!("no samples decoded");
+    }
+    Ok(Pcm {
+        samples,
+        channels,
+        sample_rate,
+    })
 }
 
-fn decode_opus(bytes: &'static [u8]) -> Option<(Vec<f32>, u16, u32)> {
+/// Fails on a file [`decode`] would refuse, without decoding it.
+fn check_format(bytes: Vec<u8>) -> Result<()> {
+    if is_ogg_opus(&bytes) {
+        let mut reader = PacketReader::new(Cursor::new(bytes));
+        let packet = reader.read_packet().context("read Ogg packet")?;
+        OpusHead::parse(&packet.context("no OpusHead packet")?.data)?;
+        return Ok(());
+    }
+    rodio::Decoder::new(Cursor::new(bytes)).context("unsupported audio format")?;
+    Ok(())
+}
+
+/// Whether the first Ogg page carries an Opus header; rodio has no Opus decoder.
+fn is_ogg_opus(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"OggS")
+        && bytes
+            .get(..bytes.len().min(512))
+            .is_some_and(|head| head.windows(8).any(|w| w == b"OpusHead"))
+}
+
+/// The parts of the `OpusHead` packet (RFC 7845, section 5.1) that change
+/// how decoded samples are played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OpusHead {
+    channels: u16,
+    /// Samples per channel to drop from the start of the decoded stream.
+    pre_skip: usize,
+    /// Linear factor for the header's output gain.
+    gain: f32,
+    /// Whether a stereo stream's channels come out swapped.
+    swapped: bool,
+}
+
+impl OpusHead {
+    fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 19 || !data.starts_with(b"OpusHead") {
+            bail!("truncated OpusHead");
+        }
+        let channels = u16::from(data[9]);
+        let pre_skip = usize::from(u16::from_le_bytes([data[10], data[11]]));
+        let gain_q8 = i16::from_le_bytes([data[16], data[17]]);
+        let family = data[18];
+        // Family 1 only needs more than one decoder with several streams;
+        // with a single stream its mapping can at most swap the channels.
+        // The opus crate has no multistream decoder, so surround files,
+        // which always have several streams, are refused.
+        let swapped = match (family, data.get(19..21 + usize::from(channels))) {
+            (0, _) if (1..=2).contains(&channels) => false,
+            (1, Some([1, coupled, mapping @ ..])) if (1..=2).contains(&channels) => {
+                if u16::from(*coupled) + 1 != channels {
+                    bail!("Opus mapping with {channels} channels and {coupled} coupled streams");
+                }
+                mapping == [1, 0]
+            }
+            _ => bail!(
+                "Opus channel mapping family {family} with {channels} channels is not supported; \
+                 convert the file to Ogg Vorbis, FLAC or WAV"
+            ),
+        };
+        Ok(Self {
+            channels,
+            pre_skip,
+            gain: 10f32.powf(f32::from(gain_q8) / 256.0 / 20.0),
+            swapped,
+        })
+    }
+}
+
+fn decode_opus(bytes: Vec<u8>) -> Result<Pcm> {
+    const SAMPLE_RATE: u32 = 48_000;
+    const MAX_FRAME: usize = 5760;
     let mut reader = PacketReader::new(Cursor::new(bytes));
-    let mut decoder: Option<OpusDecoder> = None;
-    let mut channels: u16 = 2;
-    let sample_rate = 48_000u32;
+    let mut stream: Option<(OpusHead, OpusDecoder)> = None;
     let mut samples: Vec<f32> = Vec::new();
 
-    while let Ok(Some(packet)) = reader.read_packet() {
+    while let Some(packet) = reader.read_packet().context("read Ogg packet")? {
         let data = packet.data;
-        if data.starts_with(b"OpusHead") {
-            if data.len() >= 10 {
-                channels = data[9] as u16;
-                let opus_channels = if channels == 1 {
-                    Channels::Mono
-                } else {
-                    Channels::Stereo
-                };
-                decoder = OpusDecoder::new(sample_rate, opus_channels).ok();
-            }
+        let Some((head, decoder)) = stream.as_mut() else {
+            let head = OpusHead::parse(&data)?;
+            let channels = if head.channels == 1 {
+                Channels::Mono
+            } else {
+                Channels::Stereo
+            };
+            let decoder = OpusDecoder::new(SAMPLE_RATE, channels).context("create Opus decoder")?;
+            stream = Some((head, decoder));
             continue;
-        }
+        };
         if data.starts_with(b"OpusTags") {
             continue;
         }
+        let chan_count = usize::from(head.channels);
+        let mut frame = vec![0f32; MAX_FRAME * chan_count];
+        let decoded = decoder
+            .decode_float(&data, &mut frame, false)
+            .context("decode Opus packet")?;
+        samples.extend_from_slice(&frame[..decoded * chan_count]);
+    }
 
-        let Some(decoder) = decoder.as_mut() else {
-            continue;
+    let Some((head, _)) = stream else {
+        bail!("no OpusHead packet");
+    };
+    let skip = (head.pre_skip * usize::from(head.channels)).min(samples.len());
+    samples.drain(..skip);
+    if samples.is_empty() {
+        bail!("no samples decoded");
+    }
+    if head.gain != 1.0 {
+        samples.iter_mut().for_each(|sample| *sample *= head.gain);
+    }
+    if head.swapped {
+        samples.chunks_exact_mut(2).for_each(|pair| pair.swap(0, 1));
+    }
+    Ok(Pcm {
+        samples,
+        channels: head.channels,
+        sample_rate: SAMPLE_RATE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_map_chimes_on_break_start_and_end() {
+        let sounds = SoundMap::default();
+        let sound_for =
+            |event| Cue::from_event(&event).and_then(|cue| sounds.sound(cue, BreakKind::Regular));
+        let changed = |to| SchedulerEvent::PhaseChanged {
+            from: Phase::Working,
+            to,
+            kind: BreakKind::Regular,
         };
+        assert_eq!(sound_for(changed(Phase::OnBreak)), Some(&Sound::Start));
+        assert_eq!(sound_for(changed(Phase::BreakFinished)), Some(&Sound::End));
+        assert_eq!(sound_for(changed(Phase::LockedAwaitingAction)), None);
+        assert_eq!(
+            sound_for(SchedulerEvent::BreakWarning {
+                break_in: Duration::from_secs(30),
+            }),
+            Some(&Sound::Warning)
+        );
+        assert_eq!(
+            sound_for(SchedulerEvent::Milestone {
+                left: Duration::from_secs(300),
+            }),
+            Some(&Sound::Milestone)
+        );
+        assert_eq!(
+            sound_for(SchedulerEvent::SnoozeTaken {
+                duration: Duration::from_secs(60),
+                count: 1,
+            }),
+            None
+        );
+    }
 
-        let max_frame = 5760usize;
-        let chan_count = channels.max(1) as usize;
-        let mut frame = vec![0f32; max_frame * chan_count];
-        let decoded = match decoder.decode_float(&data, &mut frame, false) {
-            Ok(decoded) => decoded,
-            Err(err) => {
-                eprintln!("audio decode error: {err}");
-                return None;
-            }
+    #[test]
+    fn long_breaks_can_have_their_own_chimes() {
+        let gong = Sound::File(PathBuf::from("/sounds/gong.ogg"));
+        let mut sounds = SoundMap::default();
+        sounds.set_for(BreakKind::Long, Cue::BreakStart, gong.clone());
+        assert_eq!(sounds.sound(Cue::BreakStart, BreakKind::Long), Some(&gong));
+        assert_eq!(
+            sounds.sound(Cue::BreakStart, BreakKind::Regular),
+            Some(&Sound::Start)
+        );
+        assert_eq!(
+            sounds.sound(Cue::BreakEnd, BreakKind::Long),
+            Some(&Sound::End)
+        );
+    }
+
+    #[test]
+    fn audio_is_retried_at_break_boundaries() {
+        let changed = |from, to| SchedulerEvent::PhaseChanged {
+            from,
+            to,
+            kind: BreakKind::Regular,
         };
-        let count = decoded * chan_count;
-        samples.extend_from_slice(&frame[..count]);
+        assert!(is_break_boundary(&changed(
+            Phase::Working,
+            Phase::LockedAwaitingAction
+        )));
+        assert!(is_break_boundary(&changed(
+            Phase::OnBreak,
+            Phase::BreakFinished
+        )));
+        assert!(!is_break_boundary(&changed(
+            Phase::BreakFinished,
+            Phase::Working
+        )));
+        assert!(!is_break_boundary(&SchedulerEvent::BreakWarning {
+            break_in: Duration::from_secs(30),
+        }));
     }
 
-    if samples.is_empty() {
-        eprintln!("audio decode error: no samples decoded");
-        return None;
+    #[test]
+    fn start_chime_softens_when_away() {
+        assert_eq!(cue_gain(Cue::BreakStart, false, AwaySound::Skip), Some(1.0));
+        assert_eq!(
+            cue_gain(Cue::BreakStart, true, AwaySound::Quiet),
+            Some(QUIET_GAIN)
+        );
+        assert_eq!(cue_gain(Cue::BreakStart, true, AwaySound::Skip), None);
+        assert_eq!(cue_gain(Cue::BreakStart, true, AwaySound::Play), Some(1.0));
+        assert_eq!(cue_gain(Cue::BreakEnd, true, AwaySound::Skip), Some(1.0));
+    }
+
+    #[test]
+    fn opus_head_fields_are_applied() {
+        let mut head = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x05\x00".to_vec();
+        let parsed = OpusHead::parse(&head).unwrap();
+        assert_eq!(parsed.channels, 2);
+        assert_eq!(parsed.pre_skip, 312);
+        assert!((parsed.gain - 10f32.powf(5.0 / 20.0)).abs() < 1e-4);
+        assert!(!parsed.swapped);
+
+        // Family 1, one coupled stream, channels swapped.
+        head[18] = 1;
+        head.extend_from_slice(&[1, 1, 1, 0]);
+        assert!(OpusHead::parse(&head).unwrap().swapped);
+        // Two streams need a multistream decoder.
+        head[19] = 2;
+        assert!(OpusHead::parse(&head).is_err());
+        assert!(OpusHead::parse(b"OpusHead\x01").is_err());
+
+        let mut ogg = Vec::new();
+        let mut writer = ogg::PacketWriter::new(&mut ogg);
+        writer
+            .write_packet(
+                head.into_boxed_slice(),
+                1,
+                ogg::PacketWriteEndInfo::EndStream,
+                0,
+            )
+            .unwrap();
+        let err = check_format(ogg).unwrap_err();
+        assert!(format!("{err:#}").contains("channel mapping family 1"));
+        assert!(check_format(START_OPUS.to_vec()).is_ok());
     }
 
-    Some((samples, channels, sample_rate))
+    #[test]
+    fn other_formats_go_through_rodio() {
+        assert!(is_ogg_opus(START_OPUS));
+        assert!(is_ogg_opus(END_OPUS));
+
+        let samples: [i16; 4] = [0, i16::MAX, 0, i16::MIN];
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+        assert!(!is_ogg_opus(&wav));
+
+        let pcm = decode(wav).unwrap();
+        assert_eq!((pcm.channels, pcm.sample_rate), (1, 8000));
+        assert_eq!(pcm.samples.len(), 4);
+        assert!(pcm.samples[1] > 0.99 && pcm.samples[3] < -0.99);
+        assert!(decode(b"not audio".to_vec()).is_err());
+    }
+
+    #[test]
+    fn announcements_round_up_to_minutes() {
+        let say = |secs| {
+            announcement(&SchedulerEvent::Milestone {
+                left: Duration::from_secs(secs),
+            })
+        };
+        assert_eq!(say(600).as_deref(), Some("Break in 10 minutes"));
+        assert_eq!(say(59).as_deref(), Some("Break in one minute"));
+        assert_eq!(
+            announcement(&SchedulerEvent::Deferred {
+                next_in: Duration::from_secs(60),
+            }),
+            None
+        );
+    }
 }
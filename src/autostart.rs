@@ -0,0 +1,312 @@
+//! `interlude enable-autostart` and `disable-autostart`.
+//!
+//! Starting with the session is either a systemd user unit, enabled for
+//! `graphical-session.target`, or an XDG autostart desktop entry, for
+//! sessions that systemd does not manage. Both run this executable with the
+//! options given before the subcommand, so `interlude --break-seconds 120
+//! enable-autostart` starts every session with two-minute breaks. Inside a
+//! Flatpak sandbox neither can be written, and the Background portal is
+//! asked to start the app instead.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::cli::AutostartKind;
+use crate::logging;
+use crate::paths;
+use crate::portal;
+
+const UNIT_NAME: &str = "interlude.service";
+/// Present when systemd is the init system, as `sd_booted()` checks.
+const SYSTEMD_RUNTIME: &str = "/run/systemd/system";
+
+/// Sets up autostart of `exe` with `args`, as `kind` or whichever suits
+/// this session.
+pub fn enable(kind: Option<AutostartKind>, exe: &Path, args: &[OsString]) -> Result<()> {
+    if portal::is_sandboxed() {
+        return request_background(true, exe, args);
+    }
+    let args = args
+        .iter()
+        .map(|arg| {
+            arg.to_str()
+                .map(str::to_string)
+                .with_context(|| format!("{arg:?} is not UTF-8"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut command = vec![
+        exe.to_str()
+            .context("executable path is not UTF-8")?
+            .to_string(),
+    ];
+    command.extend(args);
+    match kind.unwrap_or_else(detect) {
+        AutostartKind::Systemd => {
+            let path = paths::systemd_unit_file().context("no config directory; set $HOME")?;
+            write(&path, &systemd_unit(&command))?;
+            systemctl(&["daemon-reload"])?;
+            systemctl(&["enable", UNIT_NAME])?;
+            logging::output(format_args!(
+                "Wrote {} and enabled it; it starts with your next session, or now with `systemctl --user start {UNIT_NAME}`\n",
+                path.display()
+            ));
+        }
+        AutostartKind::Desktop => {
+            let path = paths::autostart_file().context("no config directory; set $HOME")?;
+            write(&path, &desktop_entry(&command))?;
+            logging::output(format_args!(
+                "Wrote {}; interlude starts with your next session\n",
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Undoes [`enable`]; without `kind`, for both kinds.
+pub fn disable(kind: Option<AutostartKind>) -> Result<()> {
+    if portal::is_sandboxed() {
+        return request_background(false, Path::new("interlude"), &[]);
+    }
+    let mut removed = false;
+    if kind != Some(AutostartKind::Desktop)
+        && let Some(path) = paths::systemd_unit_file()
+        && path.exists()
+    {
+        systemctl(&["disable", UNIT_NAME])?;
+        remove(&path)?;
+        systemctl(&["daemon-reload"])?;
+        removed = true;
+    }
+    if kind != Some(AutostartKind::Systemd)
+        && let Some(path) = paths::autostart_file()
+        && path.exists()
+    {
+        remove(&path)?;
+        removed = true;
+    }
+    if !removed {
+        logging::output("Autostart was not enabled\n");
+    }
+    Ok(())
+}
+
+/// The arguments before the `enable-autostart` subcommand, without the
+/// program name: the daemon options to start with.
+pub fn daemon_args(argv: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    argv.into_iter()
+        .skip(1)
+        .take_while(|arg| arg != "enable-autostart")
+        .collect()
+}
+
+fn detect() -> AutostartKind {
+    if Path::new(SYSTEMD_RUNTIME).is_dir() {
+        AutostartKind::Systemd
+    } else {
+        AutostartKind::Desktop
+    }
+}
+
+fn systemd_unit(command: &[String]) -> String {
+    let exec: Vec<String> = command.iter().map(|arg| systemd_quote(arg)).collect();
+    format!(
+        "# Written by `interlude enable-autostart`; remove with `interlude disable-autostart`.\n\
+         [Unit]\n\
+         Description=Interlude break reminders\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        exec.join(" ")
+    )
+}
+
+fn desktop_entry(command: &[String]) -> String {
+    let exec: Vec<String> = command.iter().map(|arg| desktop_quote(arg)).collect();
+    format!(
+        "# Written by `interlude enable-autostart`; remove with `interlude disable-autostart`.\n\
+         [Desktop Entry]\n\
+         Type=Application\n\
+         Name=Interlude\n\
+         Comment=Break reminders\n\
+         Exec={}\n\
+         NoDisplay=true\n\
+         X-GNOME-Autostart-enabled=true\n",
+        exec.join(" ")
+    )
+}
+
+/// One `ExecStart=` word. Specifiers (`%`) and variables (`$`) are escaped
+/// so they reach interlude as written.
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c)) {
+        escaped
+    } else {
+        format!("\"{escaped}\"")
+    }
+}
+
+/// One `Exec=` word: quoted per the desktop entry spec when it has reserved
+/// characters, then escaped again as a desktop entry string.
+fn desktop_quote(arg: &str) -> String {
+    let quoted = if !arg.is_empty()
+        && !arg.contains(|c: char| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c))
+    {
+        arg.to_string()
+    } else {
+        let mut quoted = String::from("\"");
+        for c in arg.chars() {
+            if "\"`$\\".contains(c) {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    };
+    quoted.replace('\\', "\\\\").replace('%', "%%")
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    fs::write(path, contents).with_context(|| format!("write {}", path.display()))
+}
+
+fn remove(path: &Path) -> Result<()> {
+    fs::remove_file(path).with_context(|| format!("remove {}", path.display()))?;
+    logging::output(format_args!("Removed {}\n", path.display()));
+    Ok(())
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .context("run systemctl --user")?;
+    if !status.success() {
+        bail!("systemctl --user {} failed: {status}", args.join(" "));
+    }
+    Ok(())
+}
+
+/// Asks the Background portal to start the app with the session (or to stop
+/// doing so) and waits for the answer, which may come after a dialog.
+fn request_background(autostart: bool, exe: &Path, args: &[OsString]) -> Result<()> {
+    let connection = Connection::session().context("connect to session bus")?;
+    let token = format!("interlude{}", std::process::id());
+    let sender = connection
+        .unique_name()
+        .context("no unique bus name")?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let request_path = format!("/org/freedesktop/portal/desktop/request/{sender}/{token}");
+    // Subscribe before asking, so a quick answer is not missed.
+    let request = Proxy::new(
+        &connection,
+        "org.freedesktop.portal.Desktop",
+        request_path,
+        "org.freedesktop.portal.Request",
+    )
+    .context("create portal request proxy")?;
+    let mut responses = request
+        .receive_signal("Response")
+        .context("subscribe to portal Response")?;
+
+    let name = exe
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "interlude".to_string());
+    let mut commandline = vec![name];
+    commandline.extend(args.iter().map(|arg| arg.to_string_lossy().into_owned()));
+    let options = HashMap::from([
+        ("handle_token", Value::from(token.as_str())),
+        (
+            "reason",
+            Value::from("Start break reminders with the session"),
+        ),
+        ("autostart", Value::from(autostart)),
+        ("commandline", Value::from(commandline)),
+    ]);
+    portal::proxy(&connection, "org.freedesktop.portal.Background")?
+        .call_method("RequestBackground", &("", options))
+        .context("RequestBackground failed")?;
+
+    let msg = responses.next().context("portal closed the request")?;
+    let (response, results): (u32, HashMap<String, OwnedValue>) =
+        msg.body().context("decode portal Response")?;
+    let granted = |key: &str| {
+        results
+            .get(key)
+            .and_then(|value| bool::try_from(value.clone()).ok())
+            .unwrap_or(false)
+    };
+    if response != 0 || (autostart && !granted("autostart")) {
+        bail!("the desktop declined to start interlude in the background");
+    }
+    logging::output(if autostart {
+        "Interlude starts with your next session\n"
+    } else {
+        "Autostart disabled\n"
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daemon_options_are_the_arguments_before_the_subcommand() {
+        let argv = [
+            "interlude",
+            "--break-seconds",
+            "120",
+            "enable-autostart",
+            "--kind",
+            "desktop",
+        ];
+        assert_eq!(
+            daemon_args(argv.map(OsString::from)),
+            ["--break-seconds", "120"].map(OsString::from)
+        );
+    }
+
+    #[test]
+    fn arguments_survive_both_file_formats() {
+        let command = [
+            "/usr/bin/interlude".to_string(),
+            "--break-message".to_string(),
+            "Stand up, \"stretch\" 100%".to_string(),
+            "--background".to_string(),
+            "$HOME\\x".to_string(),
+        ];
+        assert!(systemd_unit(&command).contains(
+            "ExecStart=/usr/bin/interlude --break-message \"Stand up, \\\"stretch\\\" 100%%\" \
+             --background \"$$HOME\\\\x\"\n"
+        ));
+        assert!(desktop_entry(&command).contains(
+            "Exec=/usr/bin/interlude --break-message \"Stand up, \\\\\"stretch\\\\\" 100%%\" \
+             --background \"\\\\$HOME\\\\\\\\x\"\n"
+        ));
+    }
+}
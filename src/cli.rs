@@ -1,8 +1,46 @@
-use clap::Parser;
+use anyhow::{Context, Result, bail};
+use chrono::NaiveTime;
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::color::{self, Gradient, Rgba};
+use crate::config::{self, Setting};
+use crate::escalation::Stage;
+use crate::paths::{self, Overrides};
 
 #[derive(Parser, Debug, Clone)]
-#[command(name = "interlude", about = "Wayland session-lock break enforcer")]
+#[command(
+    name = "interlude",
+    about = "Wayland session-lock break enforcer",
+    disable_version_flag = true
+)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Print version and git revision
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// With --version, also list cargo features and what this session supports
+    #[arg(long, requires = "version")]
+    pub features: bool,
+
+    /// Config file of `option = value` lines [default: $XDG_CONFIG_HOME/interlude/config.toml]
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Directory for the saved timer and the stats log [default: $XDG_STATE_HOME/interlude]
+    #[arg(long, value_name = "DIR")]
+    pub state_dir: Option<PathBuf>,
+
+    /// Directory for the control socket and the status file [default: $XDG_RUNTIME_DIR]
+    #[arg(long, value_name = "DIR")]
+    pub runtime_dir: Option<PathBuf>,
+
     /// Minutes between breaks after the first one
     #[arg(long, default_value_t = 30)]
     pub interval_minutes: u64,
@@ -23,10 +61,27 @@ pub struct Cli {
     #[arg(long, default_value_t = 300)]
     pub snooze_base_seconds: u64,
 
-    /// Snooze decay multiplier applied each time you snooze (0 < decay < 1)
+    /// How snoozes shrink within a cycle
+    #[arg(long, value_enum, default_value_t = SnoozeCurveKind::Exponential)]
+    pub snooze_curve: SnoozeCurveKind,
+
+    /// Snooze decay multiplier applied each time you snooze (0 < decay < 1), for the exponential curve
     #[arg(long, default_value_t = 0.6)]
     pub snooze_decay: f64,
 
+    /// Seconds each snooze is shorter than the last, for the linear curve
+    #[arg(long, default_value_t = 60)]
+    pub snooze_step_seconds: u64,
+
+    /// Snooze lengths in seconds for the schedule curve, e.g. 300,120,60; the last one repeats
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_delimiter = ',',
+        required_if_eq("snooze_curve", "schedule")
+    )]
+    pub snooze_schedule: Vec<u64>,
+
     /// Minimum snooze duration in seconds
     #[arg(long, default_value_t = 30)]
     pub snooze_min_seconds: u64,
@@ -35,17 +90,173 @@ pub struct Cli {
     #[arg(long, default_value_t = 0)]
     pub max_snoozes: u32,
 
+    /// Most minutes of snoozing per work cycle; the last snooze is cut short to fit (0 = unlimited)
+    #[arg(long, default_value_t = 0)]
+    pub max_snooze_minutes: u64,
+
+    /// Make every Nth break a long break (0 = disabled)
+    #[arg(long, default_value_t = 0)]
+    pub long_break_every: u32,
+
+    /// Long break duration in minutes
+    #[arg(long, default_value_t = 15)]
+    pub long_break_minutes: u64,
+
+    /// Minutes of work between micro-breaks, short eye rests that need no answer (0 = disabled)
+    #[arg(long, default_value_t = 0)]
+    pub micro_break_minutes: u64,
+
+    /// Micro-break duration in seconds
+    #[arg(long, default_value_t = 20)]
+    pub micro_break_seconds: u64,
+
+    /// Overlay message for micro-breaks
+    #[arg(
+        long,
+        value_name = "TEXT",
+        default_value = "Look at something 20 feet away"
+    )]
+    pub micro_break_message: String,
+
+    /// Fraction of a skipped break carried over to the next break (0 = disabled)
+    #[arg(long, default_value_t = 0.0)]
+    pub carry_over: f64,
+
+    /// Fraction of a break after which holding Enter ends it early (0 = disabled)
+    #[arg(long, default_value_t = 0.0)]
+    pub early_finish_after: f64,
+
+    /// Fraction of idle time during work given back to the work timer (0 = disabled)
+    #[arg(long, default_value_t = 0.0)]
+    pub idle_credit: f64,
+
+    /// Seconds without input before a gap counts as idle for --idle-credit and --away-start-sound
+    #[arg(long, default_value_t = 60)]
+    pub idle_credit_after: u64,
+
+    /// Mirror the breaks of the instance listening on this control socket, with no snoozing here
+    #[arg(long, value_name = "SOCKET")]
+    pub follow: Option<PathBuf>,
+
+    /// Look for a newer release on GitHub once a day and mention it in the status tooltip
+    #[arg(long)]
+    pub check_updates: bool,
+
+    /// Count each minute of constant input as 1+F minutes of work and each minute without input as 1-F, so breaks come sooner under heavy use (0 = disabled)
+    #[arg(long, value_name = "F", default_value_t = 0.0)]
+    pub adaptive_strain: f64,
+
+    /// Start chime for a break that begins while nobody is at the keyboard
+    #[arg(long, value_enum, default_value_t = AwaySound::Quiet)]
+    pub away_start_sound: AwaySound,
+
+    /// Play a soft warning tone this many seconds before a break (0 = disabled)
+    #[arg(long, default_value_t = 0)]
+    pub warning_seconds: u64,
+
+    /// With --warning-seconds, also show a notification with "Start now" and "Snooze" buttons
+    #[arg(long, default_value_t = false)]
+    pub warning_notification: bool,
+
+    /// Announce the time left at these minutes before a break, e.g. 10,5,1
+    #[arg(long, value_name = "MINUTES", value_delimiter = ',')]
+    pub announce_minutes: Vec<u64>,
+
+    /// Speak announcements with spd-say instead of playing a tone
+    #[arg(long, default_value_t = false)]
+    pub announce_speech: bool,
+
+    /// Sound file for the break start chime (Ogg Opus or Vorbis, FLAC, WAV or MP3)
+    #[arg(long, value_name = "PATH")]
+    pub start_sound: Option<PathBuf>,
+
+    /// Sound file for the break end chime
+    #[arg(long, value_name = "PATH")]
+    pub end_sound: Option<PathBuf>,
+
+    /// Sound file for the start chime of long breaks, instead of --start-sound
+    #[arg(long, value_name = "PATH")]
+    pub long_break_start_sound: Option<PathBuf>,
+
+    /// Sound file for the end chime of long breaks, instead of --end-sound
+    #[arg(long, value_name = "PATH")]
+    pub long_break_end_sound: Option<PathBuf>,
+
+    /// Minutes after startup before any break can occur, even if restored state says one is due
+    #[arg(long, default_value_t = 0)]
+    pub startup_grace: u64,
+
+    /// What time the system spent suspended counts as: a break (a sleep as long as one restarts the work period) or work (it brings the break closer)
+    #[arg(long, value_enum, default_value_t = MissingTime::Break)]
+    pub missing_time: MissingTime,
+
     /// Immediately start a break sequence (for testing)
     #[arg(long, default_value_t = false)]
     pub immediate: bool,
 
-    /// Background overlay color in hex (#RGB, #RRGGBB, or #RRGGBBAA)
-    #[arg(long, default_value = "#000000CC")]
-    pub background: String,
+    /// Background overlay color: #RGB[A], #RRGGBB[AA], a CSS name, rgb(a)() or hsl(a)()
+    #[arg(long, default_value = "#000000CC", value_parser = color::parse)]
+    pub background: Rgba,
+
+    /// Foreground text/icon color, in the same forms as --background
+    #[arg(long, default_value = "#FFFFFDDD", value_parser = color::parse)]
+    pub foreground: Rgba,
+
+    /// Background color for long breaks, instead of --background
+    #[arg(long, value_name = "COLOR", value_parser = color::parse)]
+    pub long_break_background: Option<Rgba>,
+
+    /// Foreground color for long breaks, instead of --foreground
+    #[arg(long, value_name = "COLOR", value_parser = color::parse)]
+    pub long_break_foreground: Option<Rgba>,
+
+    /// Fixed overlay message for breaks, in place of the rotating suggestions
+    #[arg(long, value_name = "TEXT")]
+    pub break_message: Option<String>,
+
+    /// Fixed overlay message for long breaks, instead of --break-message
+    #[arg(long, value_name = "TEXT")]
+    pub long_break_message: Option<String>,
 
-    /// Foreground text/icon color in hex (#RGB, #RRGGBB, or #RRGGBBAA)
-    #[arg(long, default_value = "#FFFFFDDD")]
-    pub foreground: String,
+    /// Overlay headlines to rotate through in place of "BREAK STARTING", each as TEXT or TEXT@WEIGHT
+    #[arg(long = "headline", value_name = "TEXT[@WEIGHT]", value_delimiter = ',')]
+    pub headlines: Vec<String>,
+
+    /// When the next --headline comes up
+    #[arg(long, value_enum, default_value_t = HeadlineRotation::Break)]
+    pub headline_rotation: HeadlineRotation,
+
+    /// Make the first break of each day a pause for planning it
+    #[arg(long)]
+    pub planning_break: bool,
+
+    /// Overlay message for the planning break
+    #[arg(
+        long,
+        value_name = "TEXT",
+        default_value = "What are your top 3 tasks today?"
+    )]
+    pub planning_message: String,
+
+    /// Local time (HH:MM) the working day ends: a wrap-up break, then no breaks until the next day
+    #[arg(long, value_name = "HH:MM")]
+    pub workday_end: Option<NaiveTime>,
+
+    /// Local time (HH:MM) the working day starts, ending the off-hours after --workday-end (default: midnight)
+    #[arg(long, value_name = "HH:MM", requires = "workday_end")]
+    pub workday_start: Option<NaiveTime>,
+
+    /// Length of the wrap-up break at --workday-end
+    #[arg(long, default_value_t = 10)]
+    pub wrap_up_minutes: u64,
+
+    /// Overlay message for the wrap-up break
+    #[arg(
+        long,
+        value_name = "TEXT",
+        default_value = "Wrap up for today: note where you left off."
+    )]
+    pub wrap_up_message: String,
 
     /// Target FPS during fade animations (lower = less compositor load)
     #[arg(long, default_value_t = 60)]
@@ -54,12 +265,606 @@ pub struct Cli {
     /// Ignore any saved timer state and start fresh
     #[arg(long, default_value_t = false)]
     pub reset_state: bool,
+
+    /// Also accept media keys on the overlay (Play/Pause = Enter, Next = snooze)
+    #[arg(long, default_value_t = false)]
+    pub media_keys: bool,
+
+    /// Never show the overlay on this output (name such as DP-3, or part of its description); repeatable or comma-separated
+    #[arg(long = "exclude-output", value_name = "OUTPUT", value_delimiter = ',')]
+    pub exclude_outputs: Vec<String>,
+
+    /// Show a small break card instead of the full-screen dim on this output (e.g. a shared TV); repeatable or comma-separated
+    #[arg(long = "card-output", value_name = "OUTPUT", value_delimiter = ',')]
+    pub card_outputs: Vec<String>,
+
+    /// Size of a break card in pixels
+    #[arg(long, value_name = "WIDTHxHEIGHT", default_value = "360x160", value_parser = parse_size)]
+    pub card_size: [u32; 2],
+
+    /// Where a break card sits on its output (layer-shell only)
+    #[arg(long, value_enum, default_value_t = WidgetAnchor::Center)]
+    pub card_anchor: WidgetAnchor,
+
+    /// Space between a break card and the output edges it is anchored to, as TOP,RIGHT,BOTTOM,LEFT (layer-shell only)
+    #[arg(long, value_name = "PX,PX,PX,PX", default_value = "0,0,0,0", value_parser = parse_margin)]
+    pub card_margin: [i32; 4],
+
+    /// What to do while a game runs (gamescope or Feral GameMode): ignore, remind (break cards, no keyboard capture) or defer
+    #[arg(long, value_enum, default_value_t = GamePolicy::Ignore)]
+    pub game_policy: GamePolicy,
+
+    /// What happens when a due break is ignored: start it, wait for a key, or snooze it once
+    #[arg(long, value_enum, default_value_t = BreakDuePolicy::Start)]
+    pub break_due_policy: BreakDuePolicy,
+
+    /// Seconds the break-due screen stays up after fading in before the policy applies
+    #[arg(long, default_value_t = 0)]
+    pub break_due_timeout: u64,
+
+    /// Minutes the break-complete screen waits to be dismissed before it fades out and the next work period starts; 0 waits for good
+    #[arg(long, value_name = "MINUTES", default_value_t = 0)]
+    pub break_finished_timeout: u64,
+
+    /// Give the keyboard back and dim the break-complete screen while nobody is there, and fade it out when input resumes (needs idle tracking and the layer-shell backend)
+    #[arg(long, default_value_t = false)]
+    pub dismiss_on_return: bool,
+
+    /// Escalate an ignored break through channels, each as CHANNEL:SECONDS after it fell due, e.g. notify,card:30,dim:60,overlay:120
+    #[arg(long, value_name = "STAGES", value_delimiter = ',')]
+    pub escalation: Vec<Stage>,
+
+    /// Overlay backends in order of preference; the first one the compositor supports is used
+    #[arg(
+        long = "backend",
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [Backend::LayerShell, Backend::XdgFullscreen]
+    )]
+    pub backends: Vec<Backend>,
+
+    /// Never cover the screen: breaks arrive as desktop notifications with action buttons
+    #[arg(long, conflicts_with = "backends")]
+    pub notify_only: bool,
+
+    /// How the time left in a break is shown
+    #[arg(long, value_enum, default_value_t = Countdown::Digits)]
+    pub countdown: Countdown,
+
+    /// Colors the countdown digits move through after the foreground as the break nears its end, e.g. orange,red
+    #[arg(long, value_name = "COLORS", value_parser = color::parse_gradient)]
+    pub countdown_gradient: Option<Gradient>,
+
+    /// Seconds before the end of a break that --countdown-gradient starts; 0 spreads it over the whole break
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    pub countdown_gradient_seconds: u64,
+
+    /// Thickness of the countdown bar in pixels
+    #[arg(long, value_name = "PX", default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..=200))]
+    pub bar_thickness: u32,
+
+    /// Where the countdown bar goes
+    #[arg(long, value_enum, default_value_t = BarPlacement::BelowText)]
+    pub bar_placement: BarPlacement,
+
+    /// Show a small see-through countdown at the top of each output for the last minute before a break (layer-shell only)
+    #[arg(long, default_value_t = false)]
+    pub osd_countdown: bool,
+
+    /// Size of the --osd-countdown card in pixels
+    #[arg(long, value_name = "WIDTHxHEIGHT", default_value = "240x72", value_parser = parse_size)]
+    pub osd_size: [u32; 2],
+
+    /// Where the --osd-countdown card sits on each output
+    #[arg(long, value_enum, default_value_t = WidgetAnchor::Top)]
+    pub osd_anchor: WidgetAnchor,
+
+    /// Space between the --osd-countdown card and the output edges it is anchored to, as TOP,RIGHT,BOTTOM,LEFT
+    #[arg(long, value_name = "PX,PX,PX,PX", default_value = "24,0,0,0", value_parser = parse_margin)]
+    pub osd_margin: [i32; 4],
+
+    /// Show internal state (phase, timers, fade, input capture, frame times) in the top-left corner of the overlay
+    #[arg(long, default_value_t = false)]
+    pub debug_overlay: bool,
+
+    /// Layer-shell layer for the overlay; lower layers let panels and notifications show on top
+    #[arg(long, value_enum, default_value_t = OverlayLayer::Overlay)]
+    pub layer: OverlayLayer,
+
+    /// Exclusive zone of the overlay: -1 covers panels, 0 stays clear of them
+    #[arg(long, default_value_t = -1, allow_negative_numbers = true)]
+    pub exclusive_zone: i32,
+
+    /// Overlay margins in pixels as TOP,RIGHT,BOTTOM,LEFT
+    #[arg(long, value_name = "PX,PX,PX,PX", default_value = "0,0,0,0", value_parser = parse_margin)]
+    pub margin: [i32; 4],
+
+    /// Finish the fade-in as soon as the pointer moves over the middle of the dimming screen
+    #[arg(long, default_value_t = false)]
+    pub wake_on_pointer: bool,
+
+    /// Take the keyboard for the last seconds of the fade-in, so any key press finishes it
+    #[arg(long, default_value_t = false)]
+    pub fast_forward_keys: bool,
+
+    /// Ignore key presses for this many milliseconds after the overlay takes the keyboard, so typing does not snooze or dismiss it
+    #[arg(long, value_name = "MS", default_value_t = 300)]
+    pub key_grace_ms: u64,
+
+    /// Milliseconds to say that the key which dismissed the break-complete screen did not reach your app; 0 fades out straight away
+    #[arg(long, value_name = "MS", default_value_t = 1500)]
+    pub key_toast_ms: u64,
+
+    /// Only show break text on the output you are using (keyboard/pointer focus); others just dim
+    #[arg(long, default_value_t = false)]
+    pub follow_focus: bool,
+
+    /// Ask how you feel (1-3) when a break completes and log the answer to stats
+    #[arg(long, default_value_t = false)]
+    pub feedback_prompt: bool,
+
+    /// Ask why (1 meeting, 2 urgent, 3 other) when a break is skipped with a key, and log it to stats
+    #[arg(long, default_value_t = false)]
+    pub skip_reason: bool,
+}
+
+fn parse_margin(input: &str) -> Result<[i32; 4], String> {
+    let values = input
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<i32>()
+                .map_err(|err| format!("{v:?}: {err}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    values
+        .try_into()
+        .map_err(|_| "expected four comma-separated values".to_string())
+}
+
+fn parse_size(input: &str) -> Result<[u32; 2], String> {
+    let (width, height) = input
+        .split_once(['x', 'X'])
+        .ok_or_else(|| "expected WIDTHxHEIGHT, e.g. 360x160".to_string())?;
+    let side = |v: &str| match v.trim().parse::<u32>() {
+        Ok(0) => Err("size must be above 0".to_string()),
+        Ok(n) => Ok(n),
+        Err(err) => Err(format!("{v:?}: {err}")),
+    };
+    Ok([side(width)?, side(height)?])
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingTime {
+    /// Rest; a sleep at least a break long restarts the work period
+    Break,
+    /// Work; the next break comes that much sooner
+    Work,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadlineRotation {
+    /// A new headline for every break
+    Break,
+    /// One headline for the whole day
+    Day,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Countdown {
+    /// Minutes and seconds, e.g. 02:13
+    Digits,
+    /// A bar that empties as the break runs, without digits
+    Bar,
+    /// The digits with the bar
+    Both,
+}
+
+/// The edges or corner a card keeps to; margins only apply to those edges.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidgetAnchor {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarPlacement {
+    /// Across the top edge of each output
+    Top,
+    /// Under the break text
+    BelowText,
+    /// Across the bottom edge of each output
+    Bottom,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayLayer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Lock the session (ext-session-lock-v1); covers every output and always takes input
+    SessionLock,
+    /// A wlr layer-shell overlay that fades in over the desktop
+    LayerShell,
+    /// Fullscreen xdg-shell windows, which other windows can cover
+    XdgFullscreen,
+    /// No surfaces at all; breaks run on their own
+    NotifyOnly,
+}
+
+impl Backend {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SessionLock => "session-lock",
+            Self::LayerShell => "layer-shell",
+            Self::XdgFullscreen => "xdg-fullscreen",
+            Self::NotifyOnly => "notify-only",
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnoozeCurveKind {
+    /// Each snooze is --snooze-decay times the last
+    Exponential,
+    /// Each snooze is --snooze-step-seconds shorter than the last
+    Linear,
+    /// Every snooze is --snooze-base-seconds long
+    Constant,
+    /// Snoozes follow --snooze-schedule
+    Schedule,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwaySound {
+    /// Play it as usual
+    Play,
+    /// Play it softly
+    Quiet,
+    /// Leave it out; the end chime still plays
+    Skip,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamePolicy {
+    /// Breaks as usual
+    Ignore,
+    /// Show break cards that never take the keyboard, and end the break on their own
+    Remind,
+    /// Pause the work timer until the game exits
+    Defer,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakDuePolicy {
+    /// Start the break
+    Start,
+    /// Keep the break-due screen up until a key starts, snoozes or skips the break
+    Wait,
+    /// Snooze the break if it has not been snoozed yet, otherwise start it
+    Snooze,
+}
+
+/// Prefix of the environment variables that stand in for daemon options.
+const ENV_PREFIX: &str = "INTERLUDE_";
+
+/// Options that only make sense on the command line.
+const COMMAND_LINE_ONLY: &[&str] = &["version", "features"];
+
+impl Cli {
+    /// The command line, with every daemon option also read from
+    /// `INTERLUDE_<OPTION>` (e.g. `INTERLUDE_BREAK_SECONDS=120`) when not
+    /// given as an argument, and from `settings` after that.
+    pub fn command_with(settings: &[Setting]) -> clap::Command {
+        Self::command().mut_args(|arg| {
+            let Some(long) = arg.get_long().map(str::to_string) else {
+                return arg;
+            };
+            if COMMAND_LINE_ONLY.contains(&long.as_str()) {
+                return arg;
+            }
+            let name = format!("{ENV_PREFIX}{}", long.replace('-', "_").to_uppercase());
+            let mut arg = arg.env(name);
+            if let Some(setting) = settings.iter().find(|s| s.key == long) {
+                arg = arg.default_value(setting.value.clone());
+            }
+            // Switches take 1/0, yes/no and on/off as well, as unit files tend to.
+            if matches!(arg.get_action(), ArgAction::SetTrue) {
+                arg.value_parser(BoolishValueParser::new())
+            } else {
+                arg
+            }
+        })
+    }
+
+    /// Like [`Parser::parse`], with the config file and `INTERLUDE_*`
+    /// variables filling in options not given as arguments. Also points
+    /// [`paths`] at `--state-dir` and `--runtime-dir`.
+    pub fn load() -> Result<Self> {
+        let args: Vec<OsString> = env::args_os().collect();
+        let explicit = config_arg(&args)
+            .or_else(|| env::var_os(format!("{ENV_PREFIX}CONFIG")))
+            .map(PathBuf::from);
+        // `init` and `config` write the config file, so they must not need a
+        // readable one.
+        let writing_config = Self::command()
+            .try_get_matches_from(&args)
+            .is_ok_and(|matches| matches!(matches.subcommand_name(), Some("init" | "config")));
+        let mut settings = Vec::new();
+        if !writing_config && let Some(path) = paths::config_file(explicit.as_deref()) {
+            settings = config::load(&path, explicit.is_some())?;
+            config::take_version(&mut settings)
+                .with_context(|| format!("read {}", path.display()))?;
+            if let Some(unknown) = settings.iter().find(|s| !Self::configurable(&s.key)) {
+                let problem = match config::renamed(&unknown.key) {
+                    Some(new) => format!("{} is now called {new}", unknown.key),
+                    None => format!("unknown option {}", unknown.key),
+                };
+                bail!(
+                    "{} line {}: {problem}; `interlude config migrate` updates a config \
+                     written for another version",
+                    path.display(),
+                    unknown.line,
+                );
+            }
+        }
+        let command = Self::command_with(&settings);
+        let matches = command.get_matches_from(args);
+        let cli = Self::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        paths::set_overrides(Overrides {
+            state_dir: cli.state_dir.clone(),
+            runtime_dir: cli.runtime_dir.clone(),
+        });
+        Ok(cli)
+    }
+}
+
+impl Cli {
+    /// Whether the command line, config aside, starts the break daemon
+    /// rather than a subcommand.
+    pub fn starts_daemon() -> bool {
+        Self::command()
+            .try_get_matches_from(env::args_os())
+            .is_ok_and(|matches| {
+                matches.subcommand_name().is_none() && !matches.get_flag("version")
+            })
+    }
+
+    /// Whether a config file may set the option with this long name.
+    pub fn configurable(long: &str) -> bool {
+        long != "config"
+            && !COMMAND_LINE_ONLY.contains(&long)
+            && Self::command()
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(long))
+    }
+}
+
+/// The value of `--config` on the raw command line, which is needed before
+/// the rest can be parsed.
+fn config_arg(args: &[OsString]) -> Option<OsString> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_str()?;
+        if arg == "--" {
+            return None;
+        }
+        if arg == "--config" {
+            return args.next().cloned();
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Inspect logged break statistics
+    Stats {
+        #[command(subcommand)]
+        command: StatsCommand,
+    },
+    /// Control the running instance
+    Ctl {
+        #[command(subcommand)]
+        command: CtlCommand,
+    },
+    /// Status, live settings and today's breaks of the running instance, in the terminal
+    Tui,
+    /// Show the break overlay as configured for a made-up break, to check colors, fonts and the compositor
+    TestOverlay {
+        /// How long the made-up break lasts; any key ends it sooner
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+    },
+    /// Play a sound as configured right away, or each in turn, to check the output device and volume
+    TestSound {
+        #[arg(value_enum)]
+        cue: Option<SoundCue>,
+        /// Play the sounds of a long break
+        #[arg(long)]
+        long: bool,
+    },
+    /// Answer a few questions and write a commented config file
+    Init {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Work with the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Start interlude with the session, with the options given before this command
+    EnableAutostart {
+        /// How to start it [default: systemd when it manages the session, else desktop]
+        #[arg(long, value_enum)]
+        kind: Option<AutostartKind>,
+    },
+    /// Stop starting interlude with the session
+    DisableAutostart {
+        /// Only remove this kind [default: both]
+        #[arg(long, value_enum)]
+        kind: Option<AutostartKind>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigCommand {
+    /// Update a config file written for another version, keeping a backup
+    Migrate,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundCue {
+    /// The chime when a break starts
+    Start,
+    /// The chime when a break ends
+    End,
+    /// The tone before a break is due
+    Warning,
+    /// The tones, or speech, for --announce-minutes
+    Milestone,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutostartKind {
+    /// A systemd user unit, enabled for graphical-session.target
+    Systemd,
+    /// An XDG autostart desktop entry
+    Desktop,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum StatsCommand {
+    /// Bar chart of breaks taken vs. scheduled per day for the last week
+    Chart,
+    /// Print lifetime counters in OpenMetrics text format
+    Metrics,
+    /// Work time under each `ctl tag` today, over the last week and in all
+    Tags,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CtlCommand {
+    /// Stop showing the overlay on an output (name or part of its description) until included again
+    ExcludeOutput { output: String },
+    /// Show the overlay on an output again, including one excluded with --exclude-output
+    IncludeOutput { output: String },
+    /// Snooze the due break, or push the next break back by a snooze while still working
+    Snooze,
+    /// Skip the due or running break
+    Skip,
+    /// Start a break now
+    BreakNow,
+    /// Pause the timer until resumed, or for the given number of minutes
+    Pause { minutes: Option<u64> },
+    /// Resume a timer paused with `ctl pause` or SIGUSR1
+    Resume,
+    /// Print what the daemon is doing, e.g. "Paused until 15:30"
+    Status {
+        /// Print every status field as one JSON object, as in the status file
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the control protocol version and the commands the daemon understands
+    Capabilities,
+    /// Change interval-minutes, break-seconds, snooze-base-seconds or max-snoozes until restart
+    Set { option: String, value: u64 },
+    /// Add a line of text to the next break's overlay, e.g. "Stand-up at 11:00"; without text, clear them
+    Note { text: Option<String> },
+    /// Tag the current work cycle and the ones after it for `stats tags`, e.g. "project-x"; without a tag, stop tagging
+    Tag { tag: Option<String> },
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn every_option_has_an_environment_variable() {
+        let cmd = Cli::command_with(&[]);
+        let env = |long: &str| {
+            cmd.get_arguments()
+                .find(|arg| arg.get_long() == Some(long))
+                .and_then(|arg| arg.get_env())
+                .map(|name| name.to_string_lossy().into_owned())
+        };
+        assert_eq!(
+            env("interval-minutes").as_deref(),
+            Some("INTERLUDE_INTERVAL_MINUTES")
+        );
+        assert_eq!(
+            env("exclude-output").as_deref(),
+            Some("INTERLUDE_EXCLUDE_OUTPUT")
+        );
+        assert_eq!(env("version"), None);
+        for long in cmd.get_arguments().filter_map(|arg| arg.get_long()) {
+            if !COMMAND_LINE_ONLY.contains(&long) {
+                assert!(env(long).is_some(), "--{long} has no environment variable");
+            }
+        }
+    }
+
+    #[test]
+    fn config_settings_are_defaults_under_the_command_line() {
+        let settings = config::parse(
+            "break-seconds = 120\nannounce-minutes = [10, 5]\nimmediate = true\nbackground = \"navy\"\n\
+             exclude-output = [\"DP-1\", \"HDMI-A-1\"]\ncard-output = [\"LG TV\"]",
+        )
+        .unwrap();
+        let parse = |args: &[&str]| {
+            let matches = Cli::command_with(&settings)
+                .try_get_matches_from(args)
+                .unwrap();
+            Cli::from_arg_matches(&matches).unwrap()
+        };
+        let cli = parse(&["interlude"]);
+        assert_eq!(cli.break_seconds, 120);
+        assert_eq!(cli.announce_minutes, vec![10, 5]);
+        assert!(cli.immediate);
+        assert_eq!(cli.background, [0, 0, 0x80, 0xFF]);
+        assert_eq!(cli.exclude_outputs, ["DP-1", "HDMI-A-1"]);
+        assert_eq!(cli.card_outputs, ["LG TV"]);
+        let cli = parse(&[
+            "interlude",
+            "--break-seconds",
+            "90",
+            "--announce-minutes",
+            "1",
+        ]);
+        assert_eq!(cli.break_seconds, 90);
+        assert_eq!(cli.announce_minutes, vec![1]);
+
+        assert!(Cli::configurable("snooze-decay"));
+        assert!(!Cli::configurable("config"));
+        assert!(!Cli::configurable("version"));
+        assert!(!Cli::configurable("break-secs"));
+        assert_eq!(
+            config_arg(&["interlude", "--config=/a", "--config", "/b"].map(OsString::from)),
+            Some(OsString::from("/a"))
+        );
+        assert_eq!(
+            config_arg(&["interlude", "--", "--config", "/b"].map(OsString::from)),
+            None
+        );
+    }
+
     #[test]
     fn parse_defaults() {
         let cli = Cli::try_parse_from(["interlude"]).expect("default parse");
@@ -68,14 +873,107 @@ mod tests {
         assert_eq!(cli.break_seconds, 180);
         assert_eq!(cli.initial_break_seconds, 300);
         assert_eq!(cli.snooze_base_seconds, 300);
+        assert_eq!(cli.snooze_curve, SnoozeCurveKind::Exponential);
         assert_eq!(cli.snooze_decay, 0.6);
+        assert_eq!(cli.snooze_step_seconds, 60);
+        assert!(cli.snooze_schedule.is_empty());
+        assert!(Cli::try_parse_from(["interlude", "--snooze-curve", "schedule"]).is_err());
+        let cli = Cli::try_parse_from([
+            "interlude",
+            "--snooze-curve",
+            "schedule",
+            "--snooze-schedule",
+            "300,120,60",
+        ])
+        .unwrap();
+        assert_eq!(cli.snooze_schedule, vec![300, 120, 60]);
         assert_eq!(cli.snooze_min_seconds, 30);
+        assert!(cli.headlines.is_empty());
+        assert_eq!(cli.headline_rotation, HeadlineRotation::Break);
         assert_eq!(cli.max_snoozes, 0);
+        assert_eq!(cli.max_snooze_minutes, 0);
+        assert_eq!(cli.long_break_every, 0);
+        assert_eq!(cli.long_break_minutes, 15);
+        assert_eq!(cli.micro_break_minutes, 0);
+        assert_eq!(cli.micro_break_seconds, 20);
+        assert_eq!(cli.micro_break_message, "Look at something 20 feet away");
+        assert_eq!(cli.carry_over, 0.0);
+        assert_eq!(cli.early_finish_after, 0.0);
+        assert_eq!(cli.idle_credit, 0.0);
+        assert_eq!(cli.idle_credit_after, 60);
+        assert_eq!(cli.away_start_sound, AwaySound::Quiet);
+        assert_eq!(cli.startup_grace, 0);
+        assert_eq!(cli.missing_time, MissingTime::Break);
+        assert_eq!(cli.warning_seconds, 0);
+        assert!(!cli.warning_notification);
+        assert!(cli.announce_minutes.is_empty());
+        assert!(!cli.announce_speech);
+        assert!(cli.start_sound.is_none());
+        assert!(cli.end_sound.is_none());
         assert!(!cli.immediate);
-        assert_eq!(cli.background, "#000000CC");
-        assert_eq!(cli.foreground, "#FFFFFDDD");
+        assert!(!cli.planning_break);
+        assert_eq!(cli.workday_end, None);
+        assert_eq!(cli.wrap_up_minutes, 10);
+        assert_eq!(cli.planning_message, "What are your top 3 tasks today?");
+        assert_eq!(cli.background, [0, 0, 0, 0xCC]);
+        assert_eq!(cli.foreground, [0xFF, 0xFF, 0xFD, 0xDD]);
         assert_eq!(cli.fade_fps, 60);
         assert!(!cli.reset_state);
+        assert!(!cli.media_keys);
+        assert!(!cli.feedback_prompt);
+        assert!(!cli.skip_reason);
+        assert!(cli.exclude_outputs.is_empty());
+        assert!(cli.card_outputs.is_empty());
+        assert_eq!(cli.card_size, [360, 160]);
+        assert_eq!(cli.card_anchor, WidgetAnchor::Center);
+        assert_eq!(cli.card_margin, [0, 0, 0, 0]);
+        assert_eq!(cli.osd_size, [240, 72]);
+        assert_eq!(cli.osd_anchor, WidgetAnchor::Top);
+        assert_eq!(cli.osd_margin, [24, 0, 0, 0]);
+        assert_eq!(cli.game_policy, GamePolicy::Ignore);
+        assert_eq!(cli.break_due_policy, BreakDuePolicy::Start);
+        assert_eq!(cli.break_due_timeout, 0);
+        assert_eq!(cli.break_finished_timeout, 0);
+        assert!(!cli.dismiss_on_return);
+        assert_eq!(cli.layer, OverlayLayer::Overlay);
+        assert_eq!(cli.countdown, Countdown::Digits);
+        assert_eq!(cli.countdown_gradient, None);
+        assert_eq!(cli.countdown_gradient_seconds, 60);
+        assert_eq!(cli.bar_thickness, 6);
+        assert_eq!(cli.bar_placement, BarPlacement::BelowText);
+        assert!(Cli::try_parse_from(["interlude", "--bar-thickness", "0"]).is_err());
+        assert_eq!(
+            cli.backends,
+            vec![Backend::LayerShell, Backend::XdgFullscreen]
+        );
+        assert_eq!(cli.exclusive_zone, -1);
+        assert_eq!(cli.margin, [0, 0, 0, 0]);
+        assert!(!cli.follow_focus);
+        assert!(!cli.osd_countdown);
+        assert!(!cli.debug_overlay);
+        assert!(!cli.wake_on_pointer);
+        assert!(!cli.fast_forward_keys);
+        assert_eq!(cli.key_grace_ms, 300);
+        assert_eq!(cli.key_toast_ms, 1500);
+        assert!(cli.command.is_none());
+        assert!(!cli.version);
+        assert!(!cli.notify_only);
+        assert!(!cli.check_updates);
+        assert!(
+            Cli::try_parse_from(["interlude", "--notify-only"])
+                .unwrap()
+                .notify_only
+        );
+        assert!(
+            Cli::try_parse_from(["interlude", "--notify-only", "--backend", "layer-shell"])
+                .is_err()
+        );
+        assert!(Cli::try_parse_from(["interlude", "--features"]).is_err());
+        assert!(
+            Cli::try_parse_from(["interlude", "--version", "--features"])
+                .unwrap()
+                .features
+        );
     }
 
     #[test]
@@ -98,14 +996,96 @@ mod tests {
             "45",
             "--max-snoozes",
             "3",
+            "--long-break-every",
+            "4",
+            "--long-break-minutes",
+            "20",
+            "--micro-break-minutes",
+            "20",
+            "--micro-break-seconds",
+            "30",
+            "--micro-break-message",
+            "Blink",
+            "--carry-over",
+            "0.5",
+            "--startup-grace",
+            "2",
+            "--missing-time",
+            "work",
+            "--warning-seconds",
+            "45",
+            "--warning-notification",
+            "--announce-minutes",
+            "10,5,1",
+            "--announce-speech",
             "--immediate",
             "--background",
             "#11223344",
             "--foreground",
-            "#abcdef",
+            "rgba(171, 205, 239, 50%)",
+            "--long-break-background",
+            "midnightblue",
+            "--long-break-message",
+            "Go outside for a bit.",
+            "--headline",
+            "Stretch@2,Look away",
+            "--headline-rotation",
+            "day",
+            "--workday-end",
+            "17:30",
             "--fade-fps",
             "24",
             "--reset-state",
+            "--media-keys",
+            "--feedback-prompt",
+            "--exclude-output",
+            "DP-3",
+            "--exclude-output",
+            "HDMI-A-1",
+            "--card-output",
+            "DP-2",
+            "--card-size",
+            "480x200",
+            "--card-anchor",
+            "bottom-right",
+            "--card-margin",
+            "0,16,16,0",
+            "--game-policy",
+            "defer",
+            "--break-due-policy",
+            "snooze",
+            "--break-due-timeout",
+            "20",
+            "--break-finished-timeout",
+            "45",
+            "--dismiss-on-return",
+            "--layer",
+            "top",
+            "--countdown",
+            "bar",
+            "--countdown-gradient",
+            "orange,rgb(255, 0, 0)",
+            "--bar-thickness",
+            "12",
+            "--bar-placement",
+            "bottom",
+            "--backend",
+            "session-lock,notify-only",
+            "--exclusive-zone",
+            "0",
+            "--margin",
+            "32,0,0,0",
+            "--follow-focus",
+            "--osd-countdown",
+            "--osd-size",
+            "300X90",
+            "--osd-anchor",
+            "bottom",
+            "--osd-margin",
+            "0,0,48,0",
+            "--debug-overlay",
+            "--wake-on-pointer",
+            "--fast-forward-keys",
         ])
         .expect("custom parse");
 
@@ -117,10 +1097,120 @@ mod tests {
         assert_eq!(cli.snooze_decay, 0.75);
         assert_eq!(cli.snooze_min_seconds, 45);
         assert_eq!(cli.max_snoozes, 3);
+        assert_eq!(cli.long_break_every, 4);
+        assert_eq!(cli.long_break_minutes, 20);
+        assert_eq!(cli.micro_break_minutes, 20);
+        assert_eq!(cli.micro_break_seconds, 30);
+        assert_eq!(cli.micro_break_message, "Blink");
+        assert_eq!(cli.carry_over, 0.5);
+        assert_eq!(cli.startup_grace, 2);
+        assert_eq!(cli.missing_time, MissingTime::Work);
+        assert_eq!(cli.warning_seconds, 45);
+        assert!(cli.warning_notification);
+        assert_eq!(cli.announce_minutes, vec![10, 5, 1]);
+        assert!(cli.announce_speech);
         assert!(cli.immediate);
-        assert_eq!(cli.background, "#11223344");
-        assert_eq!(cli.foreground, "#abcdef");
+        assert_eq!(cli.background, [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(cli.foreground, [0xAB, 0xCD, 0xEF, 0x80]);
+        assert_eq!(cli.long_break_background, Some([0x19, 0x19, 0x70, 0xFF]));
+        assert_eq!(cli.long_break_foreground, None);
+        assert_eq!(cli.break_message, None);
+        assert_eq!(
+            cli.long_break_message.as_deref(),
+            Some("Go outside for a bit.")
+        );
+        assert_eq!(cli.headlines, vec!["Stretch@2", "Look away"]);
+        assert_eq!(cli.headline_rotation, HeadlineRotation::Day);
+        assert_eq!(cli.workday_end, NaiveTime::from_hms_opt(17, 30, 0));
         assert_eq!(cli.fade_fps, 24);
         assert!(cli.reset_state);
+        assert!(cli.media_keys);
+        assert!(cli.feedback_prompt);
+        assert_eq!(cli.exclude_outputs, vec!["DP-3", "HDMI-A-1"]);
+        assert_eq!(cli.card_outputs, vec!["DP-2"]);
+        assert_eq!(cli.card_size, [480, 200]);
+        assert_eq!(cli.card_anchor, WidgetAnchor::BottomRight);
+        assert_eq!(cli.card_margin, [0, 16, 16, 0]);
+        assert!(Cli::try_parse_from(["interlude", "--card-size", "0x100"]).is_err());
+        assert!(Cli::try_parse_from(["interlude", "--card-size", "360"]).is_err());
+        assert_eq!(cli.game_policy, GamePolicy::Defer);
+        assert_eq!(cli.break_due_policy, BreakDuePolicy::Snooze);
+        assert_eq!(cli.break_due_timeout, 20);
+        assert_eq!(cli.break_finished_timeout, 45);
+        assert!(cli.dismiss_on_return);
+        assert_eq!(cli.layer, OverlayLayer::Top);
+        assert_eq!(cli.countdown, Countdown::Bar);
+        assert_eq!(
+            cli.countdown_gradient,
+            Some(Gradient(vec![[255, 165, 0, 255], [255, 0, 0, 255]]))
+        );
+        assert_eq!(cli.bar_thickness, 12);
+        assert_eq!(cli.bar_placement, BarPlacement::Bottom);
+        assert_eq!(
+            cli.backends,
+            vec![Backend::SessionLock, Backend::NotifyOnly]
+        );
+        assert_eq!(cli.exclusive_zone, 0);
+        assert_eq!(cli.margin, [32, 0, 0, 0]);
+        assert!(Cli::try_parse_from(["interlude", "--margin", "1,2,3"]).is_err());
+        assert!(cli.follow_focus);
+        assert!(cli.osd_countdown);
+        assert_eq!(cli.osd_size, [300, 90]);
+        assert_eq!(cli.osd_anchor, WidgetAnchor::Bottom);
+        assert_eq!(cli.osd_margin, [0, 0, 48, 0]);
+        assert!(cli.debug_overlay);
+        assert!(cli.wake_on_pointer);
+        assert!(cli.fast_forward_keys);
+    }
+
+    #[test]
+    fn invalid_colors_are_rejected() {
+        let err = Cli::try_parse_from(["interlude", "--background", "#12345"]).unwrap_err();
+        assert!(err.to_string().contains("is not a hex color"));
+        let err = Cli::try_parse_from(["interlude", "--foreground", "whte"]).unwrap_err();
+        assert!(err.to_string().contains("did you mean \"white\"?"));
+    }
+
+    #[test]
+    fn parse_stats_chart() {
+        let cli = Cli::try_parse_from(["interlude", "stats", "chart"]).expect("stats parse");
+        assert!(matches!(
+            cli.command,
+            Some(Command::Stats {
+                command: StatsCommand::Chart
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_ctl_exclude_output() {
+        let cli = Cli::try_parse_from(["interlude", "ctl", "exclude-output", "HDMI-A-1"])
+            .expect("ctl parse");
+        assert!(matches!(
+            cli.command,
+            Some(Command::Ctl {
+                command: CtlCommand::ExcludeOutput { output }
+            }) if output == "HDMI-A-1"
+        ));
+    }
+
+    #[test]
+    fn parse_enable_autostart_after_daemon_options() {
+        let cli = Cli::try_parse_from([
+            "interlude",
+            "--break-seconds",
+            "120",
+            "enable-autostart",
+            "--kind",
+            "desktop",
+        ])
+        .expect("autostart parse");
+        assert_eq!(cli.break_seconds, 120);
+        assert!(matches!(
+            cli.command,
+            Some(Command::EnableAutostart {
+                kind: Some(AutostartKind::Desktop)
+            })
+        ));
     }
 }
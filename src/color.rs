@@ -0,0 +1,523 @@
+//! Color values as users write them: `#rgb`, `#rrggbb`, `#rrggbbaa`, CSS
+//! color names, `rgb()`/`rgba()` and `hsl()`/`hsla()`. Flags parse through
+//! [`parse`], so a typo is an error at startup instead of a default color,
+//! and its [`ColorError`] names the closest color when a name is misspelt.
+
+use std::fmt;
+
+/// Red, green, blue and alpha.
+pub type Rgba = [u8; 4];
+
+/// The accepted syntaxes, for error messages.
+const FORMS: &str = "#RRGGBB[AA], a CSS color name, rgb(), rgba(), hsl() or hsla()";
+/// CSS named colors, as `0xRRGGBB`.
+const NAMED: &[(&str, u32)] = &[
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
+
+/// Why a color did not parse. Flags show it as the clap error; config and
+/// themes can match on it to point at the offending key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorError {
+    Empty,
+    /// `#` followed by something other than 3, 4, 6 or 8 hex digits.
+    Hex(String),
+    /// Not a known name; `suggestion` is the closest one, if any is close.
+    UnknownName {
+        name: String,
+        suggestion: Option<&'static str>,
+    },
+    UnknownFunction(String),
+    Unclosed(String),
+    /// A function called with other than three components plus an optional alpha.
+    Arity {
+        function: String,
+        args: String,
+    },
+    /// A component that is not the number, percentage or angle expected.
+    Component {
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty color; use {FORMS}"),
+            Self::Hex(input) => write!(
+                f,
+                "{input:?} is not a hex color; use 3, 4, 6 or 8 hex digits after #"
+            ),
+            Self::UnknownName {
+                name,
+                suggestion: Some(suggestion),
+            } => write!(f, "unknown color {name:?}; did you mean {suggestion:?}?"),
+            Self::UnknownName {
+                name,
+                suggestion: None,
+            } => write!(f, "unknown color {name:?}; use {FORMS}"),
+            Self::UnknownFunction(function) => {
+                write!(f, "unknown color function {function}(); use {FORMS}")
+            }
+            Self::Unclosed(input) => write!(f, "{input:?} is missing its closing parenthesis"),
+            Self::Arity { function, args } => write!(
+                f,
+                "{function}() takes three components and an optional alpha, got {args:?}"
+            ),
+            Self::Component { value, expected } => write!(f, "{value:?} is not {expected}"),
+        }
+    }
+}
+
+impl std::error::Error for ColorError {}
+
+/// Colors to blend through in order, e.g. a countdown as it nears zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gradient(pub Vec<Rgba>);
+
+impl Gradient {
+    /// The color `t` of the way along, from 0 at the first stop to 1 at the
+    /// last. Alpha is left out; the text fade applies its own.
+    pub fn at(&self, t: f32) -> [u8; 3] {
+        let rgb = |c: &Rgba| [c[0], c[1], c[2]];
+        let Some(last) = self.0.len().checked_sub(1) else {
+            return [0; 3];
+        };
+        let position = t.clamp(0.0, 1.0) * last as f32;
+        let index = (position.floor() as usize).min(last.saturating_sub(1));
+        match (self.0.get(index), self.0.get(index + 1)) {
+            (Some(from), Some(to)) => mix(rgb(from), rgb(to), position - index as f32),
+            (Some(only), None) => rgb(only),
+            _ => [0; 3],
+        }
+    }
+}
+
+/// Linear blend, `t` of the way from `from` to `to`.
+pub fn mix(from: [u8; 3], to: [u8; 3], t: f32) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    std::array::from_fn(|i| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * t).round() as u8)
+}
+
+/// Parses a comma-separated list of colors. Commas inside a color function,
+/// as in `rgb(255, 128, 0)`, do not split it.
+pub fn parse_gradient(input: &str) -> Result<Gradient, ColorError> {
+    let mut stops = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                stops.push(parse(&input[start..i])?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    stops.push(parse(&input[start..])?);
+    Ok(Gradient(stops))
+}
+
+/// Parses any supported color syntax.
+pub fn parse(input: &str) -> Result<Rgba, ColorError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(ColorError::Empty);
+    }
+    if let Some(hex) = input.strip_prefix('#') {
+        return parse_hex(hex).ok_or_else(|| ColorError::Hex(input.to_string()));
+    }
+    let lower = input.to_ascii_lowercase();
+    if let Some((function, args)) = lower.split_once('(') {
+        let args = args
+            .strip_suffix(')')
+            .ok_or_else(|| ColorError::Unclosed(input.to_string()))?;
+        return parse_function(function.trim(), args);
+    }
+    if lower == "transparent" {
+        return Ok([0, 0, 0, 0]);
+    }
+    NAMED
+        .iter()
+        .find(|(name, _)| *name == lower)
+        .map(|(_, rgb)| {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            [r, g, b, 0xFF]
+        })
+        .ok_or_else(|| ColorError::UnknownName {
+            name: input.to_string(),
+            suggestion: closest_name(&lower),
+        })
+}
+
+fn parse_hex(hex: &str) -> Option<Rgba> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let expanded = match hex.len() {
+        3 | 4 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 | 8 => hex.to_string(),
+        _ => return None,
+    };
+    let channel = |i: usize| {
+        expanded
+            .get(i * 2..i * 2 + 2)
+            .map_or(Some(0xFF), |pair| u8::from_str_radix(pair, 16).ok())
+    };
+    Some([channel(0)?, channel(1)?, channel(2)?, channel(3)?])
+}
+
+/// `rgb(255, 0, 0)`, `rgb(255 0 0 / 50%)`, `hsla(120, 50%, 50%, 0.8)` and so on.
+fn parse_function(function: &str, args: &str) -> Result<Rgba, ColorError> {
+    let hsl = match function {
+        "rgb" | "rgba" => false,
+        "hsl" | "hsla" => true,
+        _ => return Err(ColorError::UnknownFunction(function.to_string())),
+    };
+    let (components, alpha) = match args.split_once('/') {
+        Some((components, alpha)) => (components, Some(alpha.trim())),
+        None => (args, None),
+    };
+    let mut values: Vec<&str> = components
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect();
+    let alpha = match (alpha, values.len()) {
+        (Some(alpha), 3) => Some(alpha),
+        (None, 4) => values.pop(),
+        (None, 3) => None,
+        _ => {
+            return Err(ColorError::Arity {
+                function: function.to_string(),
+                args: args.to_string(),
+            });
+        }
+    };
+    let alpha = match alpha {
+        Some(alpha) => unit(alpha, 1.0)?,
+        None => 1.0,
+    };
+    let [r, g, b] = if hsl {
+        let hue = values[0].strip_suffix("deg").unwrap_or(values[0]);
+        let hue = hue.parse::<f64>().map_err(|_| ColorError::Component {
+            value: values[0].to_string(),
+            expected: "a hue in degrees",
+        })?;
+        hsl_to_rgb(hue, percent(values[1])?, percent(values[2])?)
+    } else {
+        [
+            unit(values[0], 255.0)?,
+            unit(values[1], 255.0)?,
+            unit(values[2], 255.0)?,
+        ]
+    };
+    let byte = |v: f64| (v * 255.0).round() as u8;
+    Ok([byte(r), byte(g), byte(b), byte(alpha)])
+}
+
+/// A number out of `max`, or a percentage, as a fraction clamped to 0..=1.
+fn unit(value: &str, max: f64) -> Result<f64, ColorError> {
+    if value.ends_with('%') {
+        return percent(value);
+    }
+    value
+        .parse::<f64>()
+        .map(|v| (v / max).clamp(0.0, 1.0))
+        .map_err(|_| ColorError::Component {
+            value: value.to_string(),
+            expected: "a number or percentage",
+        })
+}
+
+fn percent(value: &str) -> Result<f64, ColorError> {
+    value
+        .strip_suffix('%')
+        .and_then(|v| v.parse::<f64>().ok())
+        .map(|v| (v / 100.0).clamp(0.0, 1.0))
+        .ok_or_else(|| ColorError::Component {
+            value: value.to_string(),
+            expected: "a percentage",
+        })
+}
+
+/// The named color within two edits of `name`, if there is one.
+fn closest_name(name: &str) -> Option<&'static str> {
+    NAMED
+        .iter()
+        .map(|(candidate, _)| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> [f64; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let [r, g, b] = match h as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    let m = lightness - chroma / 2.0;
+    [r + m, g + m, b + m]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_forms() {
+        assert_eq!(parse("#000"), Ok([0, 0, 0, 0xFF]));
+        assert_eq!(parse("#0fF"), Ok([0x00, 0xFF, 0xFF, 0xFF]));
+        assert_eq!(parse("#0f08"), Ok([0x00, 0xFF, 0x00, 0x88]));
+        assert_eq!(parse("#112233"), Ok([0x11, 0x22, 0x33, 0xFF]));
+        assert_eq!(parse("#11223344"), Ok([0x11, 0x22, 0x33, 0x44]));
+        assert!(parse("#12345").is_err());
+        assert!(parse("#ZZZ").is_err());
+        assert!(parse("#1122334455").is_err());
+    }
+
+    #[test]
+    fn names_and_functions() {
+        assert_eq!(parse("RebeccaPurple"), Ok([0x66, 0x33, 0x99, 0xFF]));
+        assert_eq!(parse("transparent"), Ok([0, 0, 0, 0]));
+        assert_eq!(parse("rgb(255, 128, 0)"), Ok([255, 128, 0, 255]));
+        assert_eq!(parse("rgba(0,0,0,0.8)"), Ok([0, 0, 0, 204]));
+        assert_eq!(parse("rgb(100% 0% 50% / 50%)"), Ok([255, 0, 128, 128]));
+        assert_eq!(parse("hsl(0, 100%, 50%)"), Ok([255, 0, 0, 255]));
+        assert_eq!(parse("hsla(240deg 100% 25% / 1)"), Ok([0, 0, 128, 255]));
+        assert_eq!(parse("hsl(120, 50%, 50%)"), Ok([64, 191, 64, 255]));
+        assert!(parse("rgb(1, 2)").is_err());
+        assert!(parse("rgb(1, 2, 3").is_err());
+    }
+
+    #[test]
+    fn gradients_split_between_colors_and_blend_stops() {
+        let gradient = parse_gradient("white, rgb(255, 128, 0),#000").unwrap();
+        assert_eq!(
+            gradient,
+            Gradient(vec![
+                [255, 255, 255, 255],
+                [255, 128, 0, 255],
+                [0, 0, 0, 255]
+            ])
+        );
+        assert_eq!(gradient.at(0.0), [255, 255, 255]);
+        assert_eq!(gradient.at(0.25), [255, 192, 128]);
+        assert_eq!(gradient.at(0.5), [255, 128, 0]);
+        assert_eq!(gradient.at(1.0), [0, 0, 0]);
+        assert_eq!(gradient.at(7.0), [0, 0, 0]);
+        assert_eq!(Gradient(vec![[1, 2, 3, 4]]).at(0.5), [1, 2, 3]);
+        assert!(parse_gradient("white,").is_err());
+    }
+
+    #[test]
+    fn errors_say_what_is_wrong() {
+        assert_eq!(
+            parse("tomatoe"),
+            Err(ColorError::UnknownName {
+                name: "tomatoe".to_string(),
+                suggestion: Some("tomato"),
+            })
+        );
+        assert_eq!(
+            parse("Greyy").unwrap_err().to_string(),
+            "unknown color \"Greyy\"; did you mean \"grey\"?"
+        );
+        assert!(matches!(
+            parse("112233"),
+            Err(ColorError::UnknownName {
+                suggestion: None,
+                ..
+            })
+        ));
+        assert_eq!(parse(" "), Err(ColorError::Empty));
+        assert_eq!(
+            parse("cmyk(0, 0, 0, 0)"),
+            Err(ColorError::UnknownFunction("cmyk".to_string()))
+        );
+        assert_eq!(
+            parse("hsl(red, 50%, 50%)").unwrap_err().to_string(),
+            "\"red\" is not a hue in degrees"
+        );
+        assert_eq!(
+            parse("rgb(0, 0, 0, half)"),
+            Err(ColorError::Component {
+                value: "half".to_string(),
+                expected: "a number or percentage",
+            })
+        );
+    }
+}
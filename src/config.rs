@@ -0,0 +1,465 @@
+//! The config file: `key = value` lines naming long options, e.g.
+//!
+//! ```toml
+//! break-seconds = 120
+//! background = "#1E1E2ECC"
+//! announce-minutes = [10, 5, 1]
+//! notify-only = true
+//! ```
+//!
+//! Keys may use `-` or `_`. Values become the options' defaults, so the
+//! command line and `INTERLUDE_*` variables still win over the file.
+//!
+//! Settings may be grouped under `[section]` headers such as `[colors]` or
+//! `[schedule]`. A section only groups: the keys in it are still the long
+//! option names, so a setting means the same inside a section or outside.
+//!
+//! `interlude config migrate` brings a file written for another version up
+//! to date: renamed options get their new names, options this version does
+//! not have are commented out, and the `version = N` line is set to
+//! [`VERSION`], with the old file kept as a backup.
+
+use anyhow::{Context, Result, anyhow, bail};
+use std::cmp::Ordering;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Options that were renamed, old name first. The daemon refuses the old
+/// names with a pointer to `interlude config migrate`, which rewrites them.
+pub const RENAMED: &[(&str, &str)] = &[];
+
+/// The config format this version reads, written as `version = N`. It goes
+/// up with each entry added to [`RENAMED`], so the daemon can tell a file
+/// written for an older version even before it trips over an old name.
+pub const VERSION: u32 = 1;
+
+/// One setting, keyed by the option's long name (`break-seconds`) with the
+/// value spelled as it would be on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+/// Reads the config file at `path`. A missing file is an empty config unless
+/// `required`, i.e. it was named with `--config`.
+pub fn load(path: &Path, required: bool) -> Result<Vec<Setting>> {
+    match fs::read_to_string(path) {
+        Ok(data) => parse(&data).with_context(|| format!("read {}", path.display())),
+        Err(err) if err.kind() == ErrorKind::NotFound && !required => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("read {}", path.display())),
+    }
+}
+
+pub fn parse(data: &str) -> Result<Vec<Setting>> {
+    let mut settings = Vec::new();
+    for (index, raw) in data.lines().enumerate() {
+        let line = index + 1;
+        let text = raw.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        if text.starts_with('[') {
+            section_header(text).with_context(|| format!("line {line}"))?;
+            continue;
+        }
+        let setting = parse_line(text, line).with_context(|| format!("line {line}"))?;
+        if settings.iter().any(|s: &Setting| s.key == setting.key) {
+            bail!("line {line}: {} is set twice", setting.key);
+        }
+        settings.push(setting);
+    }
+    Ok(settings)
+}
+
+fn parse_line(text: &str, line: usize) -> Result<Setting> {
+    let (key, rest) = text
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected `key = value`"))?;
+    let key = key.trim();
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("invalid key {key:?}");
+    }
+    let mut chars = rest.trim_start().chars().peekable();
+    let value = match chars.peek() {
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                skip_spaces(&mut chars);
+                match chars.peek() {
+                    Some(']') => {
+                        chars.next();
+                        break;
+                    }
+                    None => bail!("unclosed ["),
+                    _ => items.push(scalar(&mut chars)?),
+                }
+                skip_spaces(&mut chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => break,
+                    None => bail!("unclosed ["),
+                    _ => bail!("expected , or ] in array"),
+                }
+            }
+            items.join(",")
+        }
+        _ => scalar(&mut chars)?,
+    };
+    skip_spaces(&mut chars);
+    match chars.next() {
+        None | Some('#') => {}
+        Some(c) => bail!("unexpected {c:?} after the value"),
+    }
+    Ok(Setting {
+        key: key.replace('_', "-"),
+        value,
+        line,
+    })
+}
+
+/// Takes the `version = N` line out of `settings`, refusing a file written
+/// for another version. A file without one predates the line and is read as
+/// it is.
+pub fn take_version(settings: &mut Vec<Setting>) -> Result<()> {
+    let Some(index) = settings.iter().position(|s| s.key == "version") else {
+        return Ok(());
+    };
+    let setting = settings.remove(index);
+    let line = setting.line;
+    let version: u32 = setting
+        .value
+        .parse()
+        .map_err(|_| anyhow!("line {line}: version {:?} is not a number", setting.value))?;
+    match version.cmp(&VERSION) {
+        Ordering::Equal => Ok(()),
+        Ordering::Less => bail!(
+            "line {line}: written for config version {version}, but this interlude reads \
+             version {VERSION}; `interlude config migrate` updates it"
+        ),
+        Ordering::Greater => bail!(
+            "line {line}: written for config version {version} by a newer interlude; this \
+             one reads version {VERSION}"
+        ),
+    }
+}
+
+/// What `interlude config migrate` makes of a config file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Migration {
+    pub text: String,
+    /// One line per change, for the user.
+    pub changes: Vec<String>,
+}
+
+/// The new name of a renamed option.
+pub fn renamed(key: &str) -> Option<&'static str> {
+    RENAMED
+        .iter()
+        .find(|(old, _)| *old == key)
+        .map(|(_, new)| *new)
+}
+
+/// Rewrites `data` for this version, keeping comments and layout: renamed
+/// keys get their new names, settings that are not `known`, do not parse or
+/// repeat an earlier one are commented out, and the version line is set to
+/// [`VERSION`], or added at the top.
+pub fn migrate(data: &str, known: impl Fn(&str) -> bool) -> Migration {
+    migrate_with(data, RENAMED, known)
+}
+
+fn migrate_with(data: &str, renamed: &[(&str, &str)], known: impl Fn(&str) -> bool) -> Migration {
+    let mut migration = Migration::default();
+    // Keys set so far, after renaming, with their lines.
+    let mut seen: Vec<(String, usize)> = Vec::new();
+    let mut versioned = false;
+    for (index, raw) in data.lines().enumerate() {
+        let line = index + 1;
+        let text = raw.trim();
+        let mut out = raw.to_string();
+        if text.starts_with('[') {
+            if let Err(err) = section_header(text) {
+                out = format!("# {raw}");
+                migration
+                    .changes
+                    .push(format!("line {line}: commented out ({err})"));
+            }
+        } else if !text.is_empty() && !text.starts_with('#') {
+            let indent = &raw[..raw.len() - raw.trim_start().len()];
+            match parse_line(text, line) {
+                Ok(setting) => {
+                    let new = renamed
+                        .iter()
+                        .find(|(old, _)| *old == setting.key)
+                        .map(|(_, new)| *new);
+                    let key = new.unwrap_or(&setting.key);
+                    if let Some((_, first)) = seen.iter().find(|(seen, _)| seen == key) {
+                        out = format!("# {raw}");
+                        migration.changes.push(format!(
+                            "line {line}: commented out {key} (already set on line {first})"
+                        ));
+                    } else if key == "version" {
+                        versioned = true;
+                        if setting.value != VERSION.to_string() {
+                            out = format!("{indent}version = {VERSION}");
+                            migration.changes.push(format!(
+                                "line {line}: version {} is now {VERSION}",
+                                setting.value
+                            ));
+                        }
+                    } else if let Some(new) = new {
+                        let (_, rest) = raw.split_once('=').unwrap_or_default();
+                        out = format!("{indent}{new} ={rest}");
+                        migration
+                            .changes
+                            .push(format!("line {line}: {} is now {new}", setting.key));
+                    } else if !known(&setting.key) {
+                        out = format!("# {raw}");
+                        migration.changes.push(format!(
+                            "line {line}: commented out {} (no such option)",
+                            setting.key
+                        ));
+                    }
+                }
+                Err(err) => {
+                    out = format!("# {raw}");
+                    migration
+                        .changes
+                        .push(format!("line {line}: commented out ({err})"));
+                }
+            }
+            if !out.starts_with('#')
+                && let Ok(setting) = parse_line(out.trim(), line)
+            {
+                seen.push((setting.key, line));
+            }
+        }
+        migration.text.push_str(&out);
+        migration.text.push('\n');
+    }
+    if !versioned {
+        migration
+            .text
+            .insert_str(0, &format!("version = {VERSION}\n"));
+        migration
+            .changes
+            .push(format!("added version = {VERSION} at the top"));
+    }
+    migration
+}
+
+/// Migrates the file at `path` in place, after copying it to `<path>.bak`.
+/// Returns the changes and the backup, or no backup if nothing changed.
+pub fn migrate_file(
+    path: &Path,
+    known: impl Fn(&str) -> bool,
+) -> Result<(Vec<String>, Option<PathBuf>)> {
+    let data = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let migration = migrate(&data, known);
+    if migration.changes.is_empty() {
+        return Ok((Vec::new(), None));
+    }
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    fs::copy(path, &backup).with_context(|| format!("write {}", backup.display()))?;
+    fs::write(path, &migration.text).with_context(|| format!("write {}", path.display()))?;
+    Ok((migration.changes, Some(backup)))
+}
+
+/// Checks a `[section]` line. Sections carry no meaning yet, so the name is
+/// not returned.
+fn section_header(text: &str) -> Result<()> {
+    let (header, rest) = text
+        .split_once(']')
+        .ok_or_else(|| anyhow!("unclosed section header"))?;
+    let name = header[1..].trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        bail!("invalid section name {name:?}");
+    }
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        bail!("unexpected {rest:?} after the section header");
+    }
+    Ok(())
+}
+
+/// A quoted string, or a bare word such as a number, `true` or a color name.
+fn scalar(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String> {
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        let mut out = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => match chars.next() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    other => bail!("unknown escape \\{}", other.unwrap_or(' ')),
+                },
+                Some(c) => out.push(c),
+                None => bail!("unclosed string"),
+            }
+        }
+    }
+    let mut out = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || matches!(c, ',' | ']' | '#') {
+            break;
+        }
+        out.push(c);
+        chars.next();
+    }
+    if out.is_empty() {
+        bail!("missing value");
+    }
+    if !(out == "true"
+        || out == "false"
+        || out
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+' | '_')))
+    {
+        bail!("{out:?} needs quotes");
+    }
+    Ok(out)
+}
+
+fn skip_spaces(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(data: &str) -> Vec<(String, String)> {
+        parse(data)
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.key, s.value))
+            .collect()
+    }
+
+    #[test]
+    fn parses_values_as_command_line_spellings() {
+        let data = "\
+# breaks
+break-seconds = 120
+background = \"#1E1E2ECC\" # translucent
+announce_minutes = [10, 5, 1]
+notify-only = true
+
+[audio] # grouping only
+start-sound = \"/home/me/chime \\\"soft\\\".ogg\"
+";
+        assert_eq!(
+            pairs(data),
+            [
+                ("break-seconds", "120"),
+                ("background", "#1E1E2ECC"),
+                ("announce-minutes", "10,5,1"),
+                ("notify-only", "true"),
+                ("start-sound", "/home/me/chime \"soft\".ogg"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+    }
+
+    #[test]
+    fn malformed_lines_name_the_line() {
+        for (data, message) in [
+            ("interval-minutes 30", "line 1: expected `key = value`"),
+            ("\nbackground = #000", "line 2: missing value"),
+            ("a = \"open", "line 1: unclosed string"),
+            ("a = [1, 2", "line 1: unclosed ["),
+            ("a = 1 2", "line 1: unexpected '2' after the value"),
+            ("a = 1\na = 2", "line 2: a is set twice"),
+            ("[colors", "line 1: unclosed section header"),
+            ("[]", "line 1: invalid section name"),
+            ("a = 1\n[x]\na = 2", "line 3: a is set twice"),
+        ] {
+            let err = parse(data).unwrap_err();
+            assert!(format!("{err:#}").starts_with(message), "{data:?}: {err:#}");
+        }
+    }
+
+    #[test]
+    fn migration_renames_and_comments_out_old_options() {
+        let data = "\
+# mine
+  pause-seconds = 120 # short
+fade-ms = 300
+[colors]
+background = \"#000\"
+interval-minutes 30
+break-seconds = 90
+";
+        let migration = migrate_with(data, &[("pause-seconds", "break-seconds")], |key| {
+            key != "fade-ms"
+        });
+        assert_eq!(
+            migration.text,
+            "\
+version = 1
+# mine
+  break-seconds = 120 # short
+# fade-ms = 300
+[colors]
+background = \"#000\"
+# interval-minutes 30
+# break-seconds = 90
+"
+        );
+        assert_eq!(
+            migration.changes,
+            [
+                "line 2: pause-seconds is now break-seconds",
+                "line 3: commented out fade-ms (no such option)",
+                "line 6: commented out (expected `key = value`)",
+                "line 7: commented out break-seconds (already set on line 2)",
+                "added version = 1 at the top",
+            ]
+        );
+        assert!(parse(&migration.text).is_ok());
+        assert_eq!(
+            migrate_with(&migration.text, &[], |_| true).changes.len(),
+            0
+        );
+    }
+
+    #[test]
+    fn version_line_marks_files_for_other_versions() {
+        let mut settings = parse("version = 1\nbreak-seconds = 120").unwrap();
+        take_version(&mut settings).unwrap();
+        assert_eq!(settings.len(), 1);
+        assert_eq!(settings[0].key, "break-seconds");
+        for (data, message) in [
+            ("version = 0", "line 1: written for config version 0"),
+            (
+                "\nversion = 2",
+                "line 2: written for config version 2 by a newer",
+            ),
+            ("version = one", "line 1: version \"one\" is not a number"),
+        ] {
+            let err = take_version(&mut parse(data).unwrap()).unwrap_err();
+            assert!(format!("{err:#}").starts_with(message), "{data:?}: {err:#}");
+        }
+
+        let migration = migrate_with("version = 0\n", &[], |_| true);
+        assert_eq!(migration.text, "version = 1\n");
+        assert_eq!(migration.changes, ["line 1: version 0 is now 1"]);
+    }
+}
@@ -0,0 +1,206 @@
+//! The shared [`Status`] as read-only properties on the session bus, for
+//! panels and scripts that would rather watch D-Bus than a file.
+//!
+//! Properties are read from the status on request; after each change the
+//! main loop sends one `PropertiesChanged` naming them all as invalidated.
+//! `GetCapabilities` reports the control protocol version, as the control
+//! socket's `capabilities` does, and `Snooze`, `StartBreak`, `Pause` and
+//! `Resume` are forwarded to the main loop like the socket's requests.
+//!
+//! The service is `org.interlude.Interlude1`. It is also owned under the
+//! app id `io.github.mikebirdgeneau.Interlude`, the only name a Flatpak
+//! sandbox lets the app own, so it is reachable there too.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use interlude_client::{Capabilities, PROTOCOL_VERSION, Request};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use zbus::blocking::{Connection, ConnectionBuilder};
+use zbus::dbus_interface;
+use zbus::names::BusName;
+use zbus::zvariant::Value;
+
+use crate::status::Status;
+
+const BUS_NAME: &str = "org.interlude.Interlude1";
+/// Also owned, for sandboxes that allow no other name.
+const APP_ID_NAME: &str = "io.github.mikebirdgeneau.Interlude";
+const OBJECT_PATH: &str = "/org/interlude/Interlude1";
+const INTERFACE: &str = "org.interlude.Interlude1";
+const PROPERTIES: &[&str] = &[
+    "Phase",
+    "SecondsLeft",
+    "Hold",
+    "PausedUntil",
+    "SnoozesLeft",
+    "SnoozeCount",
+    "BreaksDueToday",
+    "BreaksTakenToday",
+    "BreaksSkippedToday",
+    "SnoozesToday",
+    "Text",
+    "Tooltip",
+];
+
+struct StatusInterface {
+    status: Arc<Mutex<Status>>,
+    tx: Sender<Request>,
+}
+
+impl StatusInterface {
+    fn status(&self) -> MutexGuard<'_, Status> {
+        self.status.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn forward(&self, request: Request) -> zbus::fdo::Result<()> {
+        self.tx
+            .send(request)
+            .map_err(|_| zbus::fdo::Error::Failed("daemon is shutting down".to_string()))
+    }
+}
+
+#[dbus_interface(name = "org.interlude.Interlude1")]
+impl StatusInterface {
+    #[dbus_interface(property)]
+    fn phase(&self) -> String {
+        self.status().phase_label().to_string()
+    }
+
+    /// -1 when the phase has no timer.
+    #[dbus_interface(property)]
+    fn seconds_left(&self) -> i64 {
+        self.status()
+            .seconds_left
+            .and_then(|secs| i64::try_from(secs).ok())
+            .unwrap_or(-1)
+    }
+
+    /// Empty when nothing holds breaks back.
+    #[dbus_interface(property)]
+    fn hold(&self) -> String {
+        self.status()
+            .hold
+            .map_or("", |hold| hold.label())
+            .to_string()
+    }
+
+    /// Local "HH:MM", empty when the hold has no end.
+    #[dbus_interface(property)]
+    fn paused_until(&self) -> String {
+        self.status()
+            .paused_until
+            .map(|at| at.format("%H:%M").to_string())
+            .unwrap_or_default()
+    }
+
+    /// -1 when unlimited.
+    #[dbus_interface(property)]
+    fn snoozes_left(&self) -> i64 {
+        self.status().snoozes_left.map_or(-1, i64::from)
+    }
+
+    /// Times the current break has been snoozed.
+    #[dbus_interface(property)]
+    fn snooze_count(&self) -> u32 {
+        self.status().snooze_count
+    }
+
+    #[dbus_interface(property)]
+    fn breaks_due_today(&self) -> u32 {
+        self.status().today.due
+    }
+
+    #[dbus_interface(property)]
+    fn breaks_taken_today(&self) -> u32 {
+        self.status().today.taken
+    }
+
+    #[dbus_interface(property)]
+    fn breaks_skipped_today(&self) -> u32 {
+        self.status().today.skipped
+    }
+
+    #[dbus_interface(property)]
+    fn snoozes_today(&self) -> u32 {
+        self.status().today.snoozed
+    }
+
+    #[dbus_interface(property)]
+    fn text(&self) -> String {
+        self.status().text()
+    }
+
+    #[dbus_interface(property)]
+    fn tooltip(&self) -> String {
+        self.status().tooltip()
+    }
+
+    /// The control protocol version, as in `GetCapabilities`.
+    #[dbus_interface(property)]
+    fn protocol_version(&self) -> u32 {
+        PROTOCOL_VERSION
+    }
+
+    /// The control protocol version and the commands the control socket
+    /// understands, as answered to `capabilities`.
+    fn get_capabilities(&self) -> (u32, Vec<String>) {
+        let capabilities = Capabilities::current();
+        (capabilities.version, capabilities.commands)
+    }
+
+    /// Snoozes the due break, or pushes the next one back while working.
+    fn snooze(&self) -> zbus::fdo::Result<()> {
+        self.forward(Request::Snooze)
+    }
+
+    /// Starts a break now, or the due break without waiting for its fade-in.
+    fn start_break(&self) -> zbus::fdo::Result<()> {
+        self.forward(Request::BreakNow)
+    }
+
+    /// Pauses the work timer until `Resume`.
+    fn pause(&self) -> zbus::fdo::Result<()> {
+        self.forward(Request::Pause(None))
+    }
+
+    fn resume(&self) -> zbus::fdo::Result<()> {
+        self.forward(Request::Resume)
+    }
+}
+
+pub struct StatusBus {
+    connection: Connection,
+}
+
+impl StatusBus {
+    /// Claims the bus names and serves `status` until the process exits,
+    /// sending the methods' requests to `tx`. Fails only when neither name
+    /// can be had.
+    pub fn serve(status: Arc<Mutex<Status>>, tx: Sender<Request>) -> Result<Self> {
+        let connection = ConnectionBuilder::session()
+            .context("connect to session bus")?
+            .serve_at(OBJECT_PATH, StatusInterface { status, tx })
+            .context("export status object")?
+            .build()
+            .context("connect to session bus")?;
+        let claimed = connection.request_name(BUS_NAME);
+        if connection.request_name(APP_ID_NAME).is_err() {
+            claimed.with_context(|| format!("claim {BUS_NAME}"))?;
+        }
+        Ok(Self { connection })
+    }
+
+    /// Tells watchers to re-read the properties.
+    pub fn changed(&self) -> Result<()> {
+        self.connection
+            .emit_signal(
+                None::<BusName>,
+                OBJECT_PATH,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &(INTERFACE, HashMap::<&str, Value>::new(), PROPERTIES),
+            )
+            .context("emit PropertiesChanged")
+    }
+}
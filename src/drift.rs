@@ -0,0 +1,93 @@
+//! Notices time the work timer did not see.
+//!
+//! The scheduler runs on the monotonic clock, which stands still while the
+//! machine is suspended or hibernating and ignores changes to the wall
+//! clock. Each pass of the main loop compares it with the boot clock, which
+//! keeps counting through a suspend, and with the wall clock: a gap between
+//! the first two is time spent asleep (even when no `PrepareForSleep` was
+//! seen), one between the last two is the wall clock being set, e.g. by NTP.
+//! `--missing-time` decides what a sleep counts as; a jump of the wall clock
+//! is only logged.
+
+use rustix::time::{ClockId, clock_gettime};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Smaller differences are scheduling noise.
+pub const THRESHOLD: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Drift {
+    /// Time spent suspended since the last check.
+    pub asleep: Duration,
+    /// How far the wall clock was set, forwards or back.
+    pub jump: Duration,
+    pub jump_back: bool,
+}
+
+pub struct DriftWatch {
+    mono: Instant,
+    boot: Duration,
+    wall: SystemTime,
+}
+
+impl DriftWatch {
+    pub fn new() -> Self {
+        Self {
+            mono: Instant::now(),
+            boot: boot_time(),
+            wall: SystemTime::now(),
+        }
+    }
+
+    /// What happened to the clocks since the last check.
+    pub fn check(&mut self) -> Drift {
+        let (mono, boot, wall) = (Instant::now(), boot_time(), SystemTime::now());
+        let drift = measure(
+            mono - self.mono,
+            boot.saturating_sub(self.boot),
+            wall.duration_since(self.wall).map_err(|err| err.duration()),
+        );
+        (self.mono, self.boot, self.wall) = (mono, boot, wall);
+        drift
+    }
+}
+
+fn boot_time() -> Duration {
+    let ts = clock_gettime(ClockId::Boottime);
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Splits the difference between the clocks' elapsed times into sleep and a
+/// wall clock jump. `wall` is `Err` with how far the wall clock went back.
+fn measure(mono: Duration, boot: Duration, wall: Result<Duration, Duration>) -> Drift {
+    let (jump, jump_back) = match wall {
+        Ok(wall) if wall >= boot => (wall - boot, false),
+        Ok(wall) => (boot - wall, true),
+        Err(back) => (boot + back, true),
+    };
+    Drift {
+        asleep: boot.saturating_sub(mono),
+        jump,
+        jump_back,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_sleep_from_a_wall_clock_jump() {
+        let secs = Duration::from_secs;
+        assert_eq!(measure(secs(1), secs(1), Ok(secs(1))), Drift::default());
+        let suspend = measure(secs(1), secs(601), Ok(secs(601)));
+        assert_eq!((suspend.asleep, suspend.jump), (secs(600), secs(0)));
+        let ntp = measure(secs(1), secs(1), Ok(secs(3601)));
+        assert_eq!(
+            (ntp.asleep, ntp.jump, ntp.jump_back),
+            (secs(0), secs(3600), false)
+        );
+        let back = measure(secs(1), secs(1), Err(secs(59)));
+        assert_eq!((back.jump, back.jump_back), (secs(60), true));
+    }
+}
@@ -0,0 +1,178 @@
+//! How an ignored break-due reminder gets louder, set with `--escalation`.
+//!
+//! Each stage names a channel and how long after the break fell due it
+//! takes over, e.g. `notify,card:30,dim:60,overlay:120`: a notification
+//! first, a break card half a minute later, then a dimmed screen without
+//! text, and the full overlay after two minutes. Without the flag the
+//! overlay shows straight away.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Ways to show a due break, quietest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Channel {
+    /// A desktop notification with action buttons
+    Notify,
+    /// Break cards on every output; they never take the keyboard
+    Card,
+    /// The overlay held at part of its opacity, without text or input
+    Dim,
+    /// The full overlay
+    Overlay,
+}
+
+impl Channel {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Notify => "notify",
+            Self::Card => "card",
+            Self::Dim => "dim",
+            Self::Overlay => "overlay",
+        }
+    }
+}
+
+/// One step of the escalation: `channel` takes over `after` the break fell due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    pub channel: Channel,
+    pub after: Duration,
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    /// `channel` or `channel:seconds`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (name, secs) = match input.trim().split_once(':') {
+            Some((name, secs)) => (name, Some(secs)),
+            None => (input.trim(), None),
+        };
+        let channel = [
+            Channel::Notify,
+            Channel::Card,
+            Channel::Dim,
+            Channel::Overlay,
+        ]
+        .into_iter()
+        .find(|c| c.label() == name.trim())
+        .ok_or_else(|| format!("unknown channel {name:?}; use notify, card, dim or overlay"))?;
+        let after = match secs {
+            Some(secs) => secs
+                .trim()
+                .parse::<u64>()
+                .map_err(|err| format!("{secs:?}: {err}"))?,
+            None => 0,
+        };
+        Ok(Self {
+            channel,
+            after: Duration::from_secs(after),
+        })
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.channel.label(), self.after.as_secs())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escalation {
+    /// Sorted by `after`.
+    stages: Vec<Stage>,
+}
+
+impl Escalation {
+    /// No stages means the overlay right away.
+    pub fn new(mut stages: Vec<Stage>) -> Self {
+        if stages.is_empty() {
+            stages.push(Stage {
+                channel: Channel::Overlay,
+                after: Duration::ZERO,
+            });
+        }
+        stages.sort_by_key(|stage| stage.after);
+        Self { stages }
+    }
+
+    /// Drops the stages `keep` rejects, e.g. ones the backend cannot show.
+    pub fn retain(&mut self, keep: impl Fn(Channel) -> bool) {
+        self.stages.retain(|stage| keep(stage.channel));
+        if self.stages.is_empty() {
+            *self = Self::new(Vec::new());
+        }
+    }
+
+    pub fn includes(&self, channel: Channel) -> bool {
+        self.stages.iter().any(|stage| stage.channel == channel)
+    }
+
+    /// The channel in use `elapsed` after the break fell due; `None` before
+    /// the first stage.
+    pub fn channel_at(&self, elapsed: Duration) -> Option<Channel> {
+        self.stages
+            .iter()
+            .rev()
+            .find(|stage| stage.after <= elapsed)
+            .map(|stage| stage.channel)
+    }
+
+    /// Whether the last stage has been reached, so the reminder can get no louder.
+    pub fn is_final(&self, elapsed: Duration) -> bool {
+        self.stages
+            .last()
+            .is_some_and(|stage| stage.after <= elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stages(spec: &str) -> Vec<Stage> {
+        spec.split(',').map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn channels_take_over_in_turn() {
+        let escalation = Escalation::new(stages("overlay:120,notify,dim:60,card:30"));
+        let at = |secs| escalation.channel_at(Duration::from_secs(secs));
+        assert_eq!(at(0), Some(Channel::Notify));
+        assert_eq!(at(29), Some(Channel::Notify));
+        assert_eq!(at(30), Some(Channel::Card));
+        assert_eq!(at(90), Some(Channel::Dim));
+        assert_eq!(at(120), Some(Channel::Overlay));
+        assert!(!escalation.is_final(Duration::from_secs(119)));
+        assert!(escalation.is_final(Duration::from_secs(120)));
+
+        let late = Escalation::new(stages("card:10"));
+        assert_eq!(late.channel_at(Duration::from_secs(5)), None);
+
+        let default = Escalation::new(Vec::new());
+        assert_eq!(default.channel_at(Duration::ZERO), Some(Channel::Overlay));
+        assert!(default.is_final(Duration::ZERO));
+
+        let mut lock_only = Escalation::new(stages("notify,card:30"));
+        lock_only.retain(|c| c == Channel::Notify);
+        assert!(!lock_only.includes(Channel::Card));
+        lock_only.retain(|c| c == Channel::Dim);
+        assert_eq!(lock_only, Escalation::new(Vec::new()));
+    }
+
+    #[test]
+    fn stages_parse_channel_and_seconds() {
+        assert_eq!(
+            "dim:45".parse(),
+            Ok(Stage {
+                channel: Channel::Dim,
+                after: Duration::from_secs(45),
+            })
+        );
+        assert_eq!("card:30".parse::<Stage>().unwrap().to_string(), "card:30");
+        assert!("flash:10".parse::<Stage>().is_err());
+        assert!("dim:soon".parse::<Stage>().is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Idle tracking from `/dev/input`, for compositors without
+//! `ext-idle-notify-v1`. Built with `--features evdev`.
+//!
+//! Reading the event devices needs the `input` group (or an equivalent
+//! udev rule). Only the type of each event is looked at, to count key
+//! presses and pointer motion; key codes are never read. Only keyboards,
+//! buttons and pointers are watched: sensors such as accelerometers report
+//! all the time and would keep the seat from ever going idle. Devices that
+//! cannot be opened are skipped, and with none left idle tracking is
+//! simply unavailable, as it is without the protocol.
+
+use anyhow::{Context, Result, bail};
+use crossbeam_channel::Sender;
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+use rustix::ioctl::{Opcode, Updater, ioctl, opcode};
+use std::ffi::c_long;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::idle::IdleEvent;
+use crate::logging::{error, info};
+
+const INPUT_DIR: &str = "/dev/input";
+/// `struct input_event`: a `timeval`, then type, code and value.
+const EVENT_SIZE: usize = 2 * size_of::<c_long>() + 8;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+/// Bytes in a bitmap of every key and button code, up to `KEY_MAX`.
+const KEY_BYTES: usize = 0x300 / 8;
+/// `EVIOCGBIT(0, ..)`: the event types a device sends.
+const EVIOCGBIT_TYPES: Opcode = opcode::read::<[u8; 4]>(b'E', 0x20);
+/// `EVIOCGBIT(EV_KEY, ..)`: the keys and buttons a device has.
+const EVIOCGBIT_KEYS: Opcode = opcode::read::<[u8; KEY_BYTES]>(b'E', 0x20 + EV_KEY as u8);
+
+/// Like [`crate::idle::spawn_idle_watcher`], but from input device activity.
+pub fn spawn_input_watcher(watches: Vec<(Duration, Sender<IdleEvent>)>) -> Result<()> {
+    let mut devices = open_devices()?;
+    info!("Idle tracking from {} input devices", devices.len());
+    let (timeouts, senders): (Vec<_>, Vec<_>) = watches.into_iter().unzip();
+    let mut heartbeat = Heartbeat::new(timeouts, Instant::now());
+    thread::Builder::new()
+        .name("input-watcher".to_string())
+        .spawn(move || {
+            let mut buf = vec![0u8; EVENT_SIZE * 64];
+            while !devices.is_empty() {
+                let wait = heartbeat
+                    .next_deadline()
+                    .map(|at| at.saturating_duration_since(Instant::now()));
+                let timeout = wait.and_then(|wait| Timespec::try_from(wait).ok());
+                let mut fds: Vec<PollFd> = devices
+                    .iter()
+                    .map(|device| PollFd::new(device, PollFlags::IN))
+                    .collect();
+                if let Err(err) = poll(&mut fds, timeout.as_ref()) {
+                    if err == rustix::io::Errno::INTR {
+                        continue;
+                    }
+                    error!("input watcher failed: {err}");
+                    return;
+                }
+                let ready: Vec<PollFlags> = fds.iter().map(PollFd::revents).collect();
+                drop(fds);
+                let mut active = false;
+                let mut gone = Vec::new();
+                for (index, revents) in ready.into_iter().enumerate() {
+                    if revents.intersects(PollFlags::HUP | PollFlags::ERR) {
+                        gone.push(index);
+                    } else if revents.contains(PollFlags::IN) {
+                        match devices[index].read(&mut buf) {
+                            Ok(n) => active |= count_activity(&buf[..n]) > 0,
+                            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                            Err(_) => gone.push(index),
+                        }
+                    }
+                }
+                for index in gone.into_iter().rev() {
+                    devices.swap_remove(index);
+                }
+                let now = Instant::now();
+                let events = if active {
+                    heartbeat.input(now)
+                } else {
+                    heartbeat.check(now)
+                };
+                for (index, event) in events {
+                    let _ = senders[index].send(event);
+                }
+            }
+            error!("input watcher stopped: no input devices left");
+        })
+        .context("spawn input watcher thread")?;
+    Ok(())
+}
+
+/// Every event device that can be read and that a person works.
+fn open_devices() -> Result<Vec<File>> {
+    let mut devices = Vec::new();
+    let mut denied = 0;
+    for entry in fs::read_dir(INPUT_DIR).with_context(|| format!("list {INPUT_DIR}"))? {
+        let path = entry?.path();
+        if !path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("event"))
+        {
+            continue;
+        }
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(rustix::fs::OFlags::NONBLOCK.bits() as i32)
+            .open(&path)
+        {
+            Ok(file) if is_worked_by_hand(&file) => devices.push(file),
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => denied += 1,
+            Err(_) => {}
+        }
+    }
+    if devices.is_empty() {
+        if denied > 0 {
+            bail!("no access to {denied} devices in {INPUT_DIR}; join the input group");
+        }
+        bail!("no input devices in {INPUT_DIR}");
+    }
+    Ok(devices)
+}
+
+fn is_worked_by_hand(device: &File) -> bool {
+    let Some(types) = event_bits::<EVIOCGBIT_TYPES, 4>(device) else {
+        return false;
+    };
+    let keys = if has_bit(&types, EV_KEY) {
+        event_bits::<EVIOCGBIT_KEYS, KEY_BYTES>(device).unwrap_or([0; KEY_BYTES])
+    } else {
+        [0; KEY_BYTES]
+    };
+    from_hands(&types, &keys)
+}
+
+/// Whether a device with these `EVIOCGBIT` bitmaps is a keyboard, has
+/// buttons or moves a pointer. Touchpads and touchscreens have touch
+/// buttons too; a device that only reports axes is a sensor.
+fn from_hands(types: &[u8], keys: &[u8]) -> bool {
+    has_bit(types, EV_REL) || (has_bit(types, EV_KEY) && keys.iter().any(|bits| *bits != 0))
+}
+
+fn has_bit(bits: &[u8], bit: u16) -> bool {
+    bits.get(usize::from(bit / 8))
+        .is_some_and(|byte| byte >> (bit % 8) & 1 == 1)
+}
+
+/// An `EVIOCGBIT` bitmap of `N` bytes, or `None` if the device will not say.
+fn event_bits<const OPCODE: Opcode, const N: usize>(device: &File) -> Option<[u8; N]> {
+    let mut bits = [0u8; N];
+    // SAFETY: both opcodes are EVIOCGBIT for exactly `N` bytes, which the
+    // kernel fills with a bitmap and nothing else.
+    unsafe { ioctl(device, Updater::<OPCODE, [u8; N]>::new(&mut bits)) }.ok()?;
+    Some(bits)
+}
+
+/// How many events in `buf` come from a person: key presses and pointer,
+/// touchpad or stick movement. Key releases and sync events do not count.
+fn count_activity(buf: &[u8]) -> usize {
+    buf.chunks_exact(EVENT_SIZE)
+        .filter(|event| {
+            let at = EVENT_SIZE - 8;
+            let kind = u16::from_ne_bytes([event[at], event[at + 1]]);
+            let value = i32::from_ne_bytes(event[at + 4..].try_into().unwrap());
+            match kind {
+                EV_KEY => value != 0,
+                EV_REL | EV_ABS => true,
+                _ => false,
+            }
+        })
+        .count()
+}
+
+/// Turns input times into idle and resumed reports, one pair per timeout.
+struct Heartbeat {
+    timeouts: Vec<Duration>,
+    idle: Vec<bool>,
+    last_input: Instant,
+}
+
+impl Heartbeat {
+    fn new(timeouts: Vec<Duration>, now: Instant) -> Self {
+        let idle = vec![false; timeouts.len()];
+        Self {
+            timeouts,
+            idle,
+            last_input: now,
+        }
+    }
+
+    /// Input at `now`: every watch that had gone idle resumes.
+    fn input(&mut self, now: Instant) -> Vec<(usize, IdleEvent)> {
+        self.last_input = now;
+        let mut events = Vec::new();
+        for (index, idle) in self.idle.iter_mut().enumerate() {
+            if std::mem::take(idle) {
+                events.push((index, IdleEvent::Resumed));
+            }
+        }
+        events
+    }
+
+    /// Reports the watches whose timeout has passed without input.
+    fn check(&mut self, now: Instant) -> Vec<(usize, IdleEvent)> {
+        let mut events = Vec::new();
+        for (index, timeout) in self.timeouts.iter().enumerate() {
+            if !self.idle[index] && now.saturating_duration_since(self.last_input) >= *timeout {
+                self.idle[index] = true;
+                events.push((index, IdleEvent::Idle));
+            }
+        }
+        events
+    }
+
+    /// When the next watch goes idle, if any is still waiting to.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.timeouts
+            .iter()
+            .zip(&self.idle)
+            .filter(|(_, idle)| !**idle)
+            .map(|(timeout, _)| self.last_input + *timeout)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: u16, value: i32) -> Vec<u8> {
+        let mut raw = vec![0u8; EVENT_SIZE - 8];
+        raw.extend(kind.to_ne_bytes());
+        raw.extend(30u16.to_ne_bytes());
+        raw.extend(value.to_ne_bytes());
+        raw
+    }
+
+    #[test]
+    fn counts_presses_and_motion_only() {
+        let buf: Vec<u8> = [
+            event(EV_KEY, 1),
+            event(EV_KEY, 0),
+            event(0, 0),
+            event(EV_REL, -3),
+            event(EV_KEY, 2),
+        ]
+        .concat();
+        assert_eq!(count_activity(&buf), 3);
+        assert_eq!(count_activity(&buf[..EVENT_SIZE + 4]), 1);
+    }
+
+    #[test]
+    fn sensors_are_not_watched() {
+        let bits = |set: &[u16], len: usize| {
+            let mut bits = vec![0u8; len];
+            for bit in set {
+                bits[usize::from(bit / 8)] |= 1 << (bit % 8);
+            }
+            bits
+        };
+        const EV_SYN: u16 = 0x00;
+        const BTN_LEFT: u16 = 0x110;
+        const BTN_TOUCH: u16 = 0x14a;
+        let no_keys = bits(&[], KEY_BYTES);
+        // A mouse, a touchpad and a keyboard.
+        assert!(from_hands(
+            &bits(&[EV_SYN, EV_KEY, EV_REL], 4),
+            &bits(&[BTN_LEFT], KEY_BYTES)
+        ));
+        assert!(from_hands(
+            &bits(&[EV_KEY, EV_ABS], 4),
+            &bits(&[BTN_TOUCH], KEY_BYTES)
+        ));
+        assert!(from_hands(&bits(&[EV_KEY], 4), &bits(&[30], KEY_BYTES)));
+        // An accelerometer, and a device that claims keys but has none.
+        assert!(!from_hands(&bits(&[EV_SYN, EV_ABS], 4), &no_keys));
+        assert!(!from_hands(&bits(&[EV_KEY, EV_ABS], 4), &no_keys));
+    }
+
+    #[test]
+    fn watches_go_idle_and_resume_on_input() {
+        let t0 = Instant::now();
+        let secs = Duration::from_secs;
+        let mut heartbeat = Heartbeat::new(vec![secs(5), secs(60)], t0);
+        assert_eq!(heartbeat.next_deadline(), Some(t0 + secs(5)));
+        assert!(heartbeat.check(t0 + secs(4)).is_empty());
+        assert_eq!(heartbeat.check(t0 + secs(5)), [(0, IdleEvent::Idle)]);
+        assert!(heartbeat.check(t0 + secs(6)).is_empty());
+        assert_eq!(heartbeat.next_deadline(), Some(t0 + secs(60)));
+        assert_eq!(heartbeat.input(t0 + secs(10)), [(0, IdleEvent::Resumed)]);
+        assert!(heartbeat.input(t0 + secs(11)).is_empty());
+        assert_eq!(heartbeat.next_deadline(), Some(t0 + secs(16)));
+    }
+}
@@ -0,0 +1,156 @@
+//! Follower mode, `--follow SOCKET`: mirror another instance's breaks.
+//!
+//! The follower asks the leader's control socket for its status every few
+//! seconds and hands each answer to the main loop, which moves its own timer
+//! to match (see [`Scheduler::mirror`](crate::scheduler::Scheduler::mirror)).
+//! Two people pairing on separate machines then break together; forward the
+//! leader's socket with `ssh -L` to follow across the network. While the
+//! leader cannot be reached, the local timer carries on by itself and the
+//! follower asks less and less often, up to once a minute.
+
+use anyhow::{Context, Result, anyhow};
+use crossbeam_channel::Sender;
+use interlude_client::{Client, Hold, Phase as LeaderPhase, Status};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use crate::logging::rate_limited;
+use crate::scheduler::Phase;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How long the leader has to answer a poll.
+const POLL_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What the leader is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leader {
+    pub phase: Phase,
+    /// Time left in the phase; `None` while the leader holds breaks back.
+    pub left: Option<Duration>,
+}
+
+impl Leader {
+    pub fn from_status(status: &Status) -> Result<Self> {
+        let phase = match status.phase {
+            LeaderPhase::Working => Phase::Working,
+            LeaderPhase::Snoozed => Phase::Snoozing,
+            LeaderPhase::Due => Phase::LockedAwaitingAction,
+            LeaderPhase::Break => Phase::OnBreak,
+            LeaderPhase::Finished => Phase::BreakFinished,
+            other => return Err(anyhow!("unknown phase {}", other.label())),
+        };
+        let held = !matches!(status.hold, None | Some(Hold::Deferred));
+        let left = status
+            .seconds_left
+            .filter(|_| !held)
+            .map(Duration::from_secs);
+        Ok(Self { phase, left })
+    }
+}
+
+/// Polls the leader on `socket` and sends what it is doing to `tx`.
+pub fn spawn_follower(socket: PathBuf, tx: Sender<Leader>) -> Result<()> {
+    let client = Client::at(socket).timeout(POLL_TIMEOUT);
+    thread::Builder::new()
+        .name("follower".to_string())
+        .spawn(move || {
+            let mut failures = 0;
+            loop {
+                match poll(&client) {
+                    Ok(leader) => {
+                        failures = 0;
+                        if tx.send(leader).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        failures += 1;
+                        rate_limited("leader unreachable", format!("{err:#}"));
+                    }
+                }
+                thread::sleep(retry_interval(failures));
+            }
+        })
+        .context("spawn follower thread")?;
+    Ok(())
+}
+
+fn poll(client: &Client) -> Result<Leader> {
+    Leader::from_status(&client.status()?)
+}
+
+/// The wait before the next poll after `failures` failed ones in a row:
+/// the poll interval, doubled for each failure up to a minute.
+fn retry_interval(failures: u32) -> Duration {
+    POLL_INTERVAL
+        .saturating_mul(1 << failures.min(16))
+        .min(MAX_RETRY_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::{self, Hold};
+
+    fn leader(status: &status::Status) -> Leader {
+        Leader::from_status(&Status::from_json(&status.to_json()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reads_the_leaders_status() {
+        for phase in [
+            Phase::Working,
+            Phase::Snoozing,
+            Phase::LockedAwaitingAction,
+            Phase::OnBreak,
+            Phase::BreakFinished,
+        ] {
+            let status = status::Status {
+                phase,
+                seconds_left: Some(90),
+                ..status::Status::default()
+            };
+            assert_eq!(
+                leader(&status),
+                Leader {
+                    phase,
+                    left: Some(Duration::from_secs(90)),
+                }
+            );
+        }
+
+        let paused = status::Status {
+            seconds_left: Some(600),
+            hold: Some(Hold::Requested),
+            ..status::Status::default()
+        };
+        assert_eq!(leader(&paused).left, None);
+        let deferred = status::Status {
+            hold: Some(Hold::Deferred),
+            ..paused
+        };
+        assert_eq!(leader(&deferred).left, Some(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn backs_off_from_a_silent_leader() {
+        let path =
+            std::env::temp_dir().join(format!("interlude-silent-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let client = Client::at(&path).timeout(Duration::from_millis(100));
+        let started = std::time::Instant::now();
+        assert!(poll(&client).is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+        assert!(poll(&client).is_err());
+
+        assert_eq!(retry_interval(0), POLL_INTERVAL);
+        assert_eq!(retry_interval(1), Duration::from_secs(4));
+        assert_eq!(retry_interval(5), MAX_RETRY_INTERVAL);
+        assert_eq!(retry_interval(u32::MAX), MAX_RETRY_INTERVAL);
+    }
+}
@@ -0,0 +1,117 @@
+//! Detection of a running game: a gamescope session or an active Feral
+//! GameMode client.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+use zbus::blocking::{Connection, Proxy};
+
+pub struct GameDetector {
+    check_interval: Duration,
+    last_check: Instant,
+    cached: bool,
+    connection: Option<Connection>,
+    /// Whether a session bus was found at startup; without one only gamescope is seen.
+    session_bus: bool,
+}
+
+impl GameDetector {
+    pub fn new(check_interval: Duration, session_bus: bool) -> Self {
+        Self {
+            check_interval,
+            last_check: Instant::now() - check_interval,
+            cached: false,
+            connection: None,
+            session_bus,
+        }
+    }
+
+    pub fn next_check(&self) -> Instant {
+        self.last_check + self.check_interval
+    }
+
+    pub fn is_active(&mut self) -> bool {
+        let now = Instant::now();
+        if now.duration_since(self.last_check) < self.check_interval {
+            return self.cached;
+        }
+        self.last_check = now;
+        self.cached = gamescope_running() || (self.session_bus && self.gamemode_active());
+        self.cached
+    }
+
+    fn gamemode_active(&mut self) -> bool {
+        if self.connection.is_none() {
+            // No session bus means no GameMode either; try again next time.
+            self.connection = Connection::session().ok();
+        }
+        let Some(conn) = &self.connection else {
+            return false;
+        };
+        match gamemode_clients(conn) {
+            Ok(clients) => clients > 0,
+            Err(_) => false,
+        }
+    }
+}
+
+fn gamemode_clients(conn: &Connection) -> Result<i32> {
+    let dbus = Proxy::new(
+        conn,
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+    )
+    .context("create dbus proxy")?;
+    // The daemon is bus-activated, so only ask when it is already running
+    // rather than starting it just to hear "no clients".
+    let running: bool = dbus
+        .call("NameHasOwner", &("com.feralinteractive.GameMode"))
+        .context("NameHasOwner failed")?;
+    if !running {
+        return Ok(0);
+    }
+    let gamemode = Proxy::new(
+        conn,
+        "com.feralinteractive.GameMode",
+        "/com/feralinteractive/GameMode",
+        "com.feralinteractive.GameMode",
+    )
+    .context("create GameMode proxy")?;
+    gamemode
+        .get_property::<i32>("ClientCount")
+        .context("read GameMode ClientCount")
+}
+
+/// Whether we run inside gamescope, or a gamescope process is up.
+fn gamescope_running() -> bool {
+    if env::var_os("GAMESCOPE_WAYLAND_DISPLAY").is_some() {
+        return true;
+    }
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        fs::read_to_string(entry.path().join("comm"))
+            .is_ok_and(|comm| is_gamescope(comm.trim_end()))
+    })
+}
+
+fn is_gamescope(comm: &str) -> bool {
+    // comm is cut to 15 bytes, e.g. "gamescope-wl" or "gamescope-sessi".
+    comm == "gamescope" || comm.starts_with("gamescope-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_gamescope_processes() {
+        assert!(is_gamescope("gamescope"));
+        assert!(is_gamescope("gamescope-wl"));
+        assert!(!is_gamescope("gamescopes"));
+        assert!(!is_gamescope("steam"));
+    }
+}
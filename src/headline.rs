@@ -0,0 +1,229 @@
+//! `--headline`: the overlay's first line, rotated through a list.
+//!
+//! Each headline is `TEXT` or `TEXT@WEIGHT`; a headline of weight 3 comes up
+//! three times as often as one of weight 1. The choice is a smooth weighted
+//! round-robin, so heavy headlines are spread out instead of repeating back
+//! to back, and its counters are kept in the state directory so the rotation
+//! picks up where it left off after a restart. With `--headline-rotation
+//! day` the headline changes with the first break of each day rather than
+//! with every break.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use std::fs;
+
+use crate::cli::HeadlineRotation;
+use crate::paths;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Headline {
+    pub text: String,
+    pub weight: u32,
+}
+
+impl Headline {
+    /// Parses `TEXT` or `TEXT@WEIGHT`. An `@` followed by anything but a
+    /// number is part of the text.
+    pub fn parse(spec: &str) -> Self {
+        if let Some((text, weight)) = spec.rsplit_once('@')
+            && let Ok(weight) = weight.trim().parse()
+        {
+            return Self {
+                text: text.trim().to_string(),
+                weight,
+            };
+        }
+        Self {
+            text: spec.trim().to_string(),
+            weight: 1,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct State {
+    /// Round-robin counters, one per headline.
+    current: Vec<i64>,
+    chosen: Option<usize>,
+    day: Option<NaiveDate>,
+}
+
+pub struct Headlines {
+    headlines: Vec<Headline>,
+    rotation: HeadlineRotation,
+    state: State,
+}
+
+impl Headlines {
+    /// The rotation over `specs`, resumed from the state directory.
+    pub fn load(specs: &[String], rotation: HeadlineRotation) -> Self {
+        let state = paths::headline_file()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|data| state_from_str(&data))
+            .unwrap_or_default();
+        Self::new(specs, rotation, state)
+    }
+
+    fn new(specs: &[String], rotation: HeadlineRotation, mut state: State) -> Self {
+        let headlines: Vec<Headline> = specs
+            .iter()
+            .map(|spec| Headline::parse(spec))
+            .filter(|headline| !headline.text.is_empty() && headline.weight > 0)
+            .collect();
+        // Counters for another list mean nothing for this one.
+        if state.current.len() != headlines.len() {
+            state = State {
+                current: vec![0; headlines.len()],
+                ..State::default()
+            };
+        }
+        Self {
+            headlines,
+            rotation,
+            state,
+        }
+    }
+
+    /// The headline shown now, without moving on.
+    pub fn current(&self) -> Option<String> {
+        let chosen = self.state.chosen?;
+        self.headlines
+            .get(chosen)
+            .map(|headline| headline.text.clone())
+    }
+
+    /// Moves on for a break that just fell due and returns its headline.
+    /// In day rotation the headline only changes on a new day.
+    pub fn next(&mut self, today: NaiveDate) -> Option<String> {
+        if self.headlines.is_empty() {
+            return None;
+        }
+        if self.rotation == HeadlineRotation::Day
+            && self.state.day == Some(today)
+            && self.state.chosen.is_some()
+        {
+            return self.current();
+        }
+        let total: i64 = self.headlines.iter().map(|h| i64::from(h.weight)).sum();
+        for (current, headline) in self.state.current.iter_mut().zip(&self.headlines) {
+            *current += i64::from(headline.weight);
+        }
+        let chosen = (0..self.headlines.len())
+            .rev()
+            .max_by_key(|&i| self.state.current[i])
+            .unwrap_or(0);
+        self.state.current[chosen] -= total;
+        self.state.chosen = Some(chosen);
+        self.state.day = Some(today);
+        self.current()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = paths::headline_file() else {
+            return Ok(());
+        };
+        if self.headlines.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        }
+        fs::write(&path, state_to_string(&self.state))
+            .with_context(|| format!("write {}", path.display()))
+    }
+}
+
+fn state_to_string(state: &State) -> String {
+    let current: Vec<String> = state.current.iter().map(i64::to_string).collect();
+    let show = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+    format!(
+        "current={}\nchosen={}\nday={}\n",
+        current.join(","),
+        show(state.chosen.map(|chosen| chosen.to_string())),
+        show(state.day.map(|day| day.to_string())),
+    )
+}
+
+/// Reads saved state; lines that do not parse are left at their defaults.
+fn state_from_str(data: &str) -> State {
+    let mut state = State::default();
+    for line in data.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "current" => {
+                state.current = value
+                    .split(',')
+                    .filter(|n| !n.is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .unwrap_or_default();
+            }
+            "chosen" => state.chosen = value.parse().ok(),
+            "day" => state.day = value.parse().ok(),
+            _ => {}
+        }
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(specs: &[&str]) -> Vec<String> {
+        specs.iter().map(|spec| spec.to_string()).collect()
+    }
+
+    #[test]
+    fn weights_spread_headlines_and_survive_a_restart() {
+        assert_eq!(
+            Headline::parse("Look away @ 3"),
+            Headline {
+                text: "Look away".to_string(),
+                weight: 3
+            }
+        );
+        assert_eq!(Headline::parse("Mail me@home").text, "Mail me@home");
+
+        let specs = specs(&["A@2", "B", "C@0", ""]);
+        let day = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let mut headlines = Headlines::new(&specs, HeadlineRotation::Break, State::default());
+        let mut shown = Vec::new();
+        for _ in 0..4 {
+            shown.push(headlines.next(day).unwrap());
+        }
+        assert_eq!(shown, ["A", "B", "A", "A"]);
+
+        let saved = state_to_string(&headlines.state);
+        let mut restored = Headlines::new(&specs, HeadlineRotation::Break, state_from_str(&saved));
+        assert_eq!(restored.current().as_deref(), Some("A"));
+        assert_eq!(restored.next(day).as_deref(), Some("B"));
+        // A different list starts over.
+        let other = Headlines::new(
+            &["X".to_string()],
+            HeadlineRotation::Break,
+            state_from_str(&saved),
+        );
+        assert_eq!(other.current(), None);
+    }
+
+    #[test]
+    fn day_rotation_keeps_one_headline_per_day() {
+        let specs = specs(&["A", "B"]);
+        let monday = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let tuesday = monday.succ_opt().unwrap();
+        let mut headlines = Headlines::new(&specs, HeadlineRotation::Day, State::default());
+        assert_eq!(headlines.next(monday).as_deref(), Some("A"));
+        assert_eq!(headlines.next(monday).as_deref(), Some("A"));
+        assert_eq!(headlines.next(tuesday).as_deref(), Some("B"));
+        assert_eq!(headlines.next(tuesday).as_deref(), Some("B"));
+        assert!(
+            Headlines::new(&[], HeadlineRotation::Day, State::default())
+                .next(monday)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! User idle tracking through the `ext-idle-notify-v1` protocol.
+//!
+//! The watcher keeps its own Wayland connection on a separate thread, so idle
+//! reports arrive while the main loop sleeps and no overlay is up.
+//! [`AwayMeter`] turns its reports into the share of a break spent away, and
+//! [`ActivityMeter`] into the share of work time with input.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+use wayland_client::{Connection, Dispatch, QueueHandle, delegate_noop};
+
+use crate::logging::error;
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    /// No input for the watcher's timeout.
+    Idle,
+    /// Input again after [`IdleEvent::Idle`].
+    Resumed,
+}
+
+struct Watcher {
+    senders: Vec<Sender<IdleEvent>>,
+}
+
+/// For each `(timeout, tx)`, reports to `tx` when the seat has had no input
+/// for `timeout`, and when it resumes.
+pub fn spawn_idle_watcher(watches: Vec<(Duration, Sender<IdleEvent>)>) -> Result<()> {
+    let conn = Connection::connect_to_env().context("connect to wayland")?;
+    let (globals, mut queue) =
+        registry_queue_init::<Watcher>(&conn).context("list wayland globals")?;
+    let qh = queue.handle();
+    let notifier: ExtIdleNotifierV1 = globals
+        .bind(&qh, 1..=1, ())
+        .context("compositor lacks ext_idle_notifier_v1")?;
+    let seat: WlSeat = globals.bind(&qh, 1..=1, ()).context("bind wl_seat")?;
+    let mut senders = Vec::new();
+    for (index, (timeout, tx)) in watches.into_iter().enumerate() {
+        let timeout_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        notifier.get_idle_notification(timeout_ms, &seat, &qh, index);
+        senders.push(tx);
+    }
+
+    let mut watcher = Watcher { senders };
+    thread::Builder::new()
+        .name("idle-watcher".to_string())
+        .spawn(move || {
+            loop {
+                if let Err(err) = queue.blocking_dispatch(&mut watcher) {
+                    error!("idle watcher failed: {err}");
+                    break;
+                }
+            }
+        })
+        .context("spawn idle watcher thread")?;
+    Ok(())
+}
+
+impl Dispatch<ExtIdleNotificationV1, usize> for Watcher {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        index: &usize,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let event = match event {
+            ext_idle_notification_v1::Event::Idled => IdleEvent::Idle,
+            ext_idle_notification_v1::Event::Resumed => IdleEvent::Resumed,
+            _ => return,
+        };
+        let _ = state.senders[*index].send(event);
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Watcher {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlRegistry,
+        _event: <WlRegistry as wayland_client::Proxy>::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(Watcher: ignore WlSeat);
+delegate_noop!(Watcher: ExtIdleNotifierV1);
+
+/// Measures how much of a break passes without input, from the reports of a
+/// watcher with a timeout of `idle_after`.
+#[derive(Debug)]
+pub struct AwayMeter {
+    idle_after: Duration,
+    /// When the last input before the current idle stretch happened.
+    idle_since: Option<Instant>,
+    break_start: Option<Instant>,
+    away: Duration,
+}
+
+impl AwayMeter {
+    pub fn new(idle_after: Duration) -> Self {
+        Self {
+            idle_after,
+            idle_since: None,
+            break_start: None,
+            away: Duration::ZERO,
+        }
+    }
+
+    pub fn event(&mut self, event: IdleEvent, now: Instant) {
+        match event {
+            // The report comes `idle_after` after the last input.
+            IdleEvent::Idle => {
+                self.idle_since = Some(now.checked_sub(self.idle_after).unwrap_or(now));
+            }
+            IdleEvent::Resumed => {
+                self.away += self.away_in_break(now);
+                self.idle_since = None;
+            }
+        }
+    }
+
+    /// Whether the last report was of no input.
+    pub fn is_away(&self) -> bool {
+        self.idle_since.is_some()
+    }
+
+    pub fn start_break(&mut self, now: Instant) {
+        self.break_start = Some(now);
+        self.away = Duration::ZERO;
+    }
+
+    /// The share of the break since [`start_break`](Self::start_break) that
+    /// passed without input, in percent; `None` if no break was started.
+    pub fn finish_break(&mut self, now: Instant) -> Option<u8> {
+        let away = self.away + self.away_in_break(now);
+        let start = self.break_start.take()?;
+        let length = now.duration_since(start).as_secs_f64();
+        if length <= 0.0 {
+            return None;
+        }
+        Some((away.as_secs_f64() / length * 100.0).round().min(100.0) as u8)
+    }
+
+    /// The part of the current idle stretch that falls in the break.
+    fn away_in_break(&self, now: Instant) -> Duration {
+        match (self.break_start, self.idle_since) {
+            (Some(start), Some(since)) => now.saturating_duration_since(since.max(start)),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Measures how much of the time since the last reading had input, from the
+/// reports of a watcher with a timeout of `idle_after`.
+#[derive(Debug)]
+pub struct ActivityMeter {
+    idle_after: Duration,
+    /// When the last input before the current idle stretch happened.
+    idle_since: Option<Instant>,
+    window_start: Instant,
+    idle: Duration,
+}
+
+impl ActivityMeter {
+    pub fn new(idle_after: Duration, now: Instant) -> Self {
+        Self {
+            idle_after,
+            idle_since: None,
+            window_start: now,
+            idle: Duration::ZERO,
+        }
+    }
+
+    pub fn event(&mut self, event: IdleEvent, now: Instant) {
+        match event {
+            IdleEvent::Idle => {
+                self.idle_since = Some(now.checked_sub(self.idle_after).unwrap_or(now));
+            }
+            IdleEvent::Resumed => {
+                self.idle += self.idle_in_window(now);
+                self.idle_since = None;
+            }
+        }
+    }
+
+    /// Time since the last reading.
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.window_start)
+    }
+
+    /// The share of the time since the last reading that had input, from 0
+    /// to 1, and how long that was; the next reading starts from `now`.
+    pub fn take_reading(&mut self, now: Instant) -> (f64, Duration) {
+        let length = self.elapsed(now);
+        let idle = self.idle + self.idle_in_window(now);
+        self.window_start = now;
+        self.idle = Duration::ZERO;
+        if length.is_zero() {
+            return (0.0, length);
+        }
+        let share = 1.0 - idle.as_secs_f64() / length.as_secs_f64();
+        (share.clamp(0.0, 1.0), length)
+    }
+
+    /// The part of the current idle stretch since the last reading.
+    fn idle_in_window(&self, now: Instant) -> Duration {
+        self.idle_since.map_or(Duration::ZERO, |since| {
+            now.saturating_duration_since(since.max(self.window_start))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn away_meter_counts_idle_stretches_inside_the_break() {
+        let t0 = Instant::now() + Duration::from_secs(1000);
+        let at = |secs| t0 + Duration::from_secs(secs);
+        let mut meter = AwayMeter::new(Duration::from_secs(5));
+        // Already idle since 5s before the break; back at 30s.
+        meter.event(IdleEvent::Idle, t0);
+        meter.start_break(at(10));
+        meter.event(IdleEvent::Resumed, at(30));
+        // Last input at 60s, still away when the break ends at 110s.
+        meter.event(IdleEvent::Idle, at(65));
+        assert!(meter.is_away());
+        assert_eq!(meter.finish_break(at(110)), Some(70));
+        assert_eq!(meter.finish_break(at(120)), None);
+
+        meter.start_break(at(200));
+        assert_eq!(meter.finish_break(at(300)), Some(100));
+        meter.event(IdleEvent::Resumed, at(301));
+        assert!(!meter.is_away());
+        meter.start_break(at(400));
+        assert_eq!(meter.finish_break(at(500)), Some(0));
+    }
+
+    #[test]
+    fn activity_meter_reads_the_share_with_input() {
+        let t0 = Instant::now() + Duration::from_secs(1000);
+        let at = |secs| t0 + Duration::from_secs(secs);
+        let mut meter = ActivityMeter::new(Duration::from_secs(5), t0);
+        // Last input at 10s, back at 20s.
+        meter.event(IdleEvent::Idle, at(15));
+        meter.event(IdleEvent::Resumed, at(20));
+        // Last input at 50s, still away at the reading.
+        meter.event(IdleEvent::Idle, at(55));
+        assert_eq!(meter.take_reading(at(80)), (0.5, Duration::from_secs(80)));
+        assert_eq!(meter.elapsed(at(100)), Duration::from_secs(20));
+        meter.event(IdleEvent::Resumed, at(100));
+        assert_eq!(meter.take_reading(at(160)), (0.75, Duration::from_secs(80)));
+    }
+}
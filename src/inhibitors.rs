@@ -1,49 +1,112 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
 use std::time::{Duration, Instant};
 use zbus::blocking::{Connection, Proxy};
 
+use crate::logging::rate_limited;
+use crate::probe::Capabilities;
+
+/// What is currently holding breaks back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InhibitorSource {
+    /// A logind `sleep` or `idle` block inhibitor.
+    Logind,
+    /// A Wayland client holding `zwp_idle_inhibitor_v1`, as reported by the compositor.
+    IdleInhibitor,
+    /// A game is running and `--game-policy defer` is set.
+    Game,
+}
+
+impl InhibitorSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Logind => "systemd inhibitor",
+            Self::IdleInhibitor => "idle inhibitor",
+            Self::Game => "game running",
+        }
+    }
+}
+
 pub struct InhibitorWatcher {
     check_interval: Duration,
     last_check: Instant,
-    cached: bool,
+    cached: Option<InhibitorSource>,
     connection: Option<Connection>,
+    /// Sources the session can report, from the startup probe.
+    logind: bool,
+    sway: bool,
 }
 
 impl InhibitorWatcher {
-    pub fn new(check_interval: Duration) -> Self {
+    pub fn new(check_interval: Duration, caps: &Capabilities) -> Self {
         Self {
             check_interval,
             last_check: Instant::now() - check_interval,
-            cached: false,
+            cached: None,
             connection: None,
+            logind: caps.system_bus,
+            sway: caps.sway_ipc,
         }
     }
 
-    pub fn is_active(&mut self) -> bool {
+    /// When `is_active` will next ask logind instead of answering from cache.
+    pub fn next_check(&self) -> Instant {
+        self.last_check + self.check_interval
+    }
+
+    /// The inhibitor holding breaks back, if any. Logind is asked first; the
+    /// compositor only when nothing holds a logind inhibitor.
+    pub fn source(&mut self) -> Option<InhibitorSource> {
         let now = Instant::now();
         if now.duration_since(self.last_check) < self.check_interval {
             return self.cached;
         }
         self.last_check = now;
+        let logind = self.logind
+            && self
+                .logind_active()
+                .unwrap_or(self.cached == Some(InhibitorSource::Logind));
+        self.cached = if logind {
+            Some(InhibitorSource::Logind)
+        } else if !self.sway {
+            None
+        } else {
+            match sway_idle_inhibited() {
+                Ok(true) => Some(InhibitorSource::IdleInhibitor),
+                Ok(false) => None,
+                Err(err) => {
+                    rate_limited("idle inhibitor check failed", err);
+                    None
+                }
+            }
+        };
+        self.cached
+    }
+
+    /// `None` when logind could not be asked.
+    fn logind_active(&mut self) -> Option<bool> {
         if self.connection.is_none() {
             match Connection::system() {
                 Ok(conn) => self.connection = Some(conn),
                 Err(err) => {
-                    eprintln!("inhibitor check skipped: connect to system bus failed: {err}");
-                    return self.cached;
+                    rate_limited(
+                        "inhibitor check skipped",
+                        format!("connect to system bus failed: {err}"),
+                    );
+                    return None;
                 }
             }
         }
-        let Some(conn) = &self.connection else {
-            return self.cached;
-        };
+        let conn = self.connection.as_ref()?;
         match list_inhibitors(conn) {
-            Ok(active) => self.cached = active,
+            Ok(active) => Some(active),
             Err(err) => {
-                eprintln!("inhibitor check failed: {err}");
+                rate_limited("inhibitor check failed", err);
+                None
             }
         }
-        self.cached
     }
 }
 
@@ -65,3 +128,60 @@ fn list_inhibitors(conn: &Connection) -> Result<bool> {
                 .any(|what| matches!(what, "sleep" | "idle"))
     }))
 }
+
+const SWAY_IPC_MAGIC: &[u8] = b"i3-ipc";
+const SWAY_GET_TREE: u32 = 4;
+
+/// Whether any view in sway's tree is inhibiting idle. Other compositors do
+/// not expose idle inhibitors, so without `$SWAYSOCK` this is always false.
+fn sway_idle_inhibited() -> Result<bool> {
+    let Some(path) = env::var_os("SWAYSOCK") else {
+        return Ok(false);
+    };
+    let mut stream = UnixStream::connect(&path).context("connect to SWAYSOCK")?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut request = SWAY_IPC_MAGIC.to_vec();
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    request.extend_from_slice(&SWAY_GET_TREE.to_ne_bytes());
+    stream.write_all(&request)?;
+
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != SWAY_IPC_MAGIC {
+        bail!("unexpected reply from sway");
+    }
+    let len = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]) as usize;
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    Ok(json_has_true(
+        &String::from_utf8_lossy(&payload),
+        "inhibit_idle",
+    ))
+}
+
+/// Whether `"key": true` appears anywhere in `json`, at any nesting level.
+fn json_has_true(json: &str, key: &str) -> bool {
+    let needle = format!("\"{key}\"");
+    json.match_indices(&needle).any(|(pos, _)| {
+        json[pos + needle.len()..]
+            .trim_start()
+            .strip_prefix(':')
+            .is_some_and(|rest| rest.trim_start().starts_with("true"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_inhibiting_views_in_sway_tree() {
+        let tree = r#"{"nodes": [{"name": "mpv", "inhibit_idle": false},
+            {"floating_nodes": [{"name": "firefox", "inhibit_idle":true}]}]}"#;
+        assert!(json_has_true(tree, "inhibit_idle"));
+        assert!(!json_has_true(
+            r#"{"nodes": [{"inhibit_idle": false, "title": "inhibit_idle: true"}]}"#,
+            "inhibit_idle"
+        ));
+    }
+}
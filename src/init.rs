@@ -0,0 +1,384 @@
+//! `interlude init`: a few questions, then a commented config file.
+//!
+//! Answers are read the way the locale writes them: a decimal comma where
+//! the locale uses one, and `5:30 pm` as well as `17:30` for times, with the
+//! examples shown in the locale's own clock. Anything left blank keeps the
+//! default and is written commented out, so the file documents it anyway.
+
+use anyhow::{Context, Result, bail};
+use chrono::NaiveTime;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::config;
+
+/// How the user's locale writes numbers and times, from `LC_ALL`,
+/// `LC_NUMERIC`/`LC_TIME` and `LANG`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Locale {
+    pub decimal_comma: bool,
+    pub twelve_hour: bool,
+}
+
+/// Languages whose locales write `2,5` rather than `2.5`.
+const DECIMAL_COMMA_LANGUAGES: &[&str] = &[
+    "de", "fr", "es", "it", "pt", "nl", "sv", "da", "nb", "nn", "fi", "pl", "cs", "sk", "ru", "uk",
+    "tr", "el", "hu", "ro", "bg", "hr", "sl", "sr", "lt", "lv", "et", "id", "vi",
+];
+/// Locales whose clocks run from 1 to 12.
+const TWELVE_HOUR_LOCALES: &[&str] = &[
+    "en_US", "en_CA", "en_AU", "en_NZ", "en_PH", "en_IN", "hi_IN", "ar_EG", "ar_SA",
+];
+
+impl Locale {
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let pick = |category: &str| {
+            ["LC_ALL", category, "LANG"]
+                .into_iter()
+                .filter_map(&var)
+                .find(|value| !value.is_empty())
+                .unwrap_or_default()
+        };
+        let numeric = pick("LC_NUMERIC");
+        let time = pick("LC_TIME");
+        let language = numeric.split(['_', '.', '@']).next().unwrap_or_default();
+        let territory = time.split(['.', '@']).next().unwrap_or_default();
+        Self {
+            decimal_comma: DECIMAL_COMMA_LANGUAGES.contains(&language),
+            twelve_hour: TWELVE_HOUR_LOCALES.contains(&territory),
+        }
+    }
+
+    fn number(self, value: f64) -> String {
+        let text = value.to_string();
+        if self.decimal_comma {
+            text.replace('.', ",")
+        } else {
+            text
+        }
+    }
+
+    fn time(self, time: NaiveTime) -> String {
+        if self.twelve_hour {
+            time.format("%-I:%M %p").to_string()
+        } else {
+            time.format("%H:%M").to_string()
+        }
+    }
+}
+
+/// A number written with either decimal separator.
+fn parse_number(text: &str) -> Option<f64> {
+    text.trim()
+        .replace(',', ".")
+        .parse()
+        .ok()
+        .filter(|n: &f64| n.is_finite() && *n >= 0.0)
+}
+
+/// `17:30`, `5:30 pm`, `5pm` or `17.30`.
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    let text = text.trim().to_ascii_lowercase().replace('.', ":");
+    let (clock, pm) = match (text.strip_suffix("pm"), text.strip_suffix("am")) {
+        (Some(clock), _) => (clock.trim(), Some(true)),
+        (_, Some(clock)) => (clock.trim(), Some(false)),
+        _ => (text.as_str(), None),
+    };
+    let (hour, minute) = clock.split_once(':').unwrap_or((clock, "0"));
+    let mut hour: u32 = hour.trim().parse().ok()?;
+    let minute: u32 = minute.trim().parse().ok()?;
+    if let Some(pm) = pm {
+        if !(1..=12).contains(&hour) {
+            return None;
+        }
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// How hard interlude pushes for a break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strictness {
+    /// Notifications only; the screen is never covered
+    Gentle,
+    /// The overlay, with up to three snoozes
+    Balanced,
+    /// The session is locked for the break, with one snooze
+    Strict,
+}
+
+/// One line of the config file, with the comment above it; `value` is
+/// `None` for a default left as it is.
+struct Entry {
+    comment: &'static str,
+    key: &'static str,
+    value: Option<String>,
+    default: &'static str,
+}
+
+/// Asks the questions on `output`, reading answers from `input`, and returns
+/// the config file text.
+fn interview(input: &mut impl BufRead, output: &mut impl Write, locale: Locale) -> Result<String> {
+    let mut ask = |question: &str| -> Result<String> {
+        write!(output, "{question} ")?;
+        output.flush()?;
+        let mut line = String::new();
+        input.read_line(&mut line).context("read answer")?;
+        Ok(line.trim().to_string())
+    };
+    let mut entries = Vec::new();
+
+    let interval = loop {
+        let answer = ask("Minutes of work between breaks [30]:")?;
+        if answer.is_empty() {
+            break None;
+        }
+        match parse_number(&answer) {
+            Some(minutes) if minutes >= 1.0 => break Some(minutes.round() as u64),
+            _ => {}
+        }
+    };
+    entries.push(Entry {
+        comment: "Minutes of work between breaks",
+        key: "interval-minutes",
+        value: interval.map(|m| m.to_string()),
+        default: "30",
+    });
+
+    let example = locale.number(2.5);
+    let break_len = loop {
+        let answer = ask(&format!("Minutes each break lasts, e.g. {example} [3]:"))?;
+        if answer.is_empty() {
+            break None;
+        }
+        match parse_number(&answer) {
+            Some(minutes) if minutes > 0.0 => break Some((minutes * 60.0).round() as u64),
+            _ => {}
+        }
+    };
+    entries.push(Entry {
+        comment: "Seconds each break lasts",
+        key: "break-seconds",
+        value: break_len.map(|s| s.max(1).to_string()),
+        default: "180",
+    });
+
+    let strictness = loop {
+        let answer = ask(
+            "How strict? 1 = notifications only, 2 = cover the screen, allow 3 snoozes, \
+             3 = lock the session, allow 1 snooze [2]:",
+        )?;
+        match answer.as_str() {
+            "1" => break Strictness::Gentle,
+            "" | "2" => break Strictness::Balanced,
+            "3" => break Strictness::Strict,
+            _ => {}
+        }
+    };
+    match strictness {
+        Strictness::Gentle => entries.push(Entry {
+            comment: "Breaks as desktop notifications; the screen is never covered",
+            key: "notify-only",
+            value: Some("true".to_string()),
+            default: "false",
+        }),
+        Strictness::Balanced | Strictness::Strict => {
+            let strict = strictness == Strictness::Strict;
+            entries.push(Entry {
+                comment: "Overlay backends to try, in order",
+                key: "backend",
+                value: strict
+                    .then(|| "[\"session-lock\", \"layer-shell\", \"xdg-fullscreen\"]".to_string()),
+                default: "[\"layer-shell\", \"xdg-fullscreen\"]",
+            });
+            entries.push(Entry {
+                comment: "Snoozes allowed before a break (0 = unlimited)",
+                key: "max-snoozes",
+                value: Some(if strict { "1" } else { "3" }.to_string()),
+                default: "0",
+            });
+        }
+    }
+
+    let warning = loop {
+        let answer = ask("Play a soft tone a minute before each break? [y/N]:")?;
+        match yes_no(&answer) {
+            Some(yes) => break yes,
+            None if answer.is_empty() => break false,
+            None => {}
+        }
+    };
+    entries.push(Entry {
+        comment: "Seconds before a break to play a warning tone (0 = none)",
+        key: "warning-seconds",
+        value: warning.then(|| "60".to_string()),
+        default: "0",
+    });
+
+    let start_sound = ask("Sound file for the break chime, or blank for the built-in one:")?;
+    entries.push(Entry {
+        comment: "Sound file for the break start chime",
+        key: "start-sound",
+        value: (!start_sound.is_empty()).then(|| quote(&start_sound)),
+        default: "\"/path/to/chime.ogg\"",
+    });
+
+    let example = locale.time(NaiveTime::from_hms_opt(17, 30, 0).unwrap_or_default());
+    let workday_end = loop {
+        let answer = ask(&format!(
+            "When does your working day end, e.g. {example}? Blank for never:"
+        ))?;
+        if answer.is_empty() {
+            break None;
+        }
+        if let Some(time) = parse_time(&answer) {
+            break Some(time);
+        }
+    };
+    entries.push(Entry {
+        comment: "Local time the working day ends: a wrap-up break, then none until tomorrow",
+        key: "workday-end",
+        value: workday_end.map(|at| quote(&at.format("%H:%M").to_string())),
+        default: "\"17:30\"",
+    });
+
+    Ok(render(&entries))
+}
+
+/// `y`/`n`, also in a few other languages' words for yes.
+fn yes_no(answer: &str) -> Option<bool> {
+    match answer.trim().to_lowercase().chars().next()? {
+        'y' | 'j' | 'o' | 's' | 'д' => Some(true),
+        'n' => Some(false),
+        _ => None,
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn render(entries: &[Entry]) -> String {
+    let mut text = String::from(
+        "# Written by `interlude init`. Any option in `interlude --help` can be set\n\
+         # here as `option = value`; the command line and INTERLUDE_* variables win\n\
+         # over this file. Commented-out lines show the defaults.\n",
+    );
+    text.push_str(&format!("version = {}\n", config::VERSION));
+    for entry in entries {
+        text.push_str(&format!("\n# {}\n", entry.comment));
+        match &entry.value {
+            Some(value) => text.push_str(&format!("{} = {value}\n", entry.key)),
+            None => text.push_str(&format!("# {} = {}\n", entry.key, entry.default)),
+        }
+    }
+    text
+}
+
+/// Runs the wizard on the terminal and writes the answers to `path`.
+pub fn run(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!(
+            "{} already exists; use --force to replace it",
+            path.display()
+        );
+    }
+    let locale = Locale::from_env(|name| std::env::var(name).ok());
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    writeln!(
+        stdout,
+        "A few questions to set up interlude; press Enter to keep the default.\n"
+    )?;
+    let text = interview(&mut stdin.lock(), &mut stdout, locale)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    fs::write(path, text).with_context(|| format!("write {}", path.display()))?;
+    writeln!(stdout, "\nWrote {}", path.display())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_read_in_the_locale() {
+        let env = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let german = Locale::from_env(env(&[("LANG", "de_DE.UTF-8")]));
+        assert_eq!(
+            german,
+            Locale {
+                decimal_comma: true,
+                twelve_hour: false,
+            }
+        );
+        assert_eq!(german.number(2.5), "2,5");
+        let american = Locale::from_env(env(&[("LANG", "de_DE.UTF-8"), ("LC_ALL", "en_US.UTF-8")]));
+        assert!(american.twelve_hour && !american.decimal_comma);
+        let at = NaiveTime::from_hms_opt(17, 30, 0).unwrap();
+        assert_eq!(american.time(at), "5:30 PM");
+
+        assert_eq!(parse_number("2,5"), Some(2.5));
+        assert_eq!(parse_number("-1"), None);
+        for text in ["17:30", "5:30 pm", "5:30PM", "17.30"] {
+            assert_eq!(parse_time(text), Some(at), "{text}");
+        }
+        assert_eq!(parse_time("12am"), NaiveTime::from_hms_opt(0, 0, 0));
+        assert_eq!(parse_time("13pm"), None);
+    }
+
+    #[test]
+    fn writes_a_config_the_daemon_reads() {
+        let answers = "25\n1,5\nmaybe\n3\nja\n/home/u/chime \"soft\".ogg\n5:45 pm\n";
+        let mut output = Vec::new();
+        let text = interview(
+            &mut answers.as_bytes(),
+            &mut output,
+            Locale {
+                decimal_comma: true,
+                twelve_hour: true,
+            },
+        )
+        .unwrap();
+        let settings = config::parse(&text).unwrap();
+        let value = |key: &str| {
+            settings
+                .iter()
+                .find(|s| s.key == key)
+                .map(|s| s.value.as_str())
+        };
+        assert_eq!(value("interval-minutes"), Some("25"));
+        assert_eq!(value("break-seconds"), Some("90"));
+        assert_eq!(
+            value("backend"),
+            Some("session-lock,layer-shell,xdg-fullscreen")
+        );
+        assert_eq!(value("max-snoozes"), Some("1"));
+        assert_eq!(value("warning-seconds"), Some("60"));
+        assert_eq!(value("start-sound"), Some("/home/u/chime \"soft\".ogg"));
+        assert_eq!(value("workday-end"), Some("17:45"));
+        assert!(String::from_utf8(output).unwrap().contains("e.g. 2,5"));
+
+        let defaults = interview(
+            &mut "\n".repeat(7).as_bytes(),
+            &mut Vec::new(),
+            Locale::default(),
+        )
+        .unwrap();
+        let mut settings = config::parse(&defaults).unwrap();
+        config::take_version(&mut settings).unwrap();
+        assert_eq!(settings.len(), 1);
+        assert_eq!(settings[0].key, "max-snoozes");
+        assert!(defaults.contains("# interval-minutes = 30\n"));
+    }
+}
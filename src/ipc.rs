@@ -0,0 +1,159 @@
+//! Control socket for a running instance.
+//!
+//! `interlude ctl ...` connects to `$XDG_RUNTIME_DIR/interlude.sock` (see [`paths`]) and sends
+//! one request per line; the daemon answers each with `ok` or `error: ...`,
+//! except `status`, which is answered with the status sentence, or with the
+//! status JSON for `status json`. The requests and the client side live in
+//! the `interlude-client` crate, so bar modules and scripts can use them too.
+
+use anyhow::{Context, Result, bail};
+use crossbeam_channel::Sender;
+use interlude_client::{Capabilities, Client};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::logging::error;
+use crate::paths;
+use crate::status::Status;
+
+pub use interlude_client::Request;
+
+/// How long a client may sit idle, or leave a reply unread, before its
+/// connection is dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Listens on the control socket and forwards every valid request to `tx`;
+/// `status` is answered from `status`, which the main loop keeps current.
+pub fn spawn_ipc_server(tx: Sender<Request>, status: Arc<Mutex<Status>>) -> Result<()> {
+    let listener = bind(&paths::socket_path())?;
+    thread::Builder::new()
+        .name("ipc".to_string())
+        .spawn(move || serve(listener, tx, status))
+        .context("spawn ipc thread")?;
+    Ok(())
+}
+
+fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("another instance is listening on {}", path.display());
+        }
+        fs::remove_file(path).with_context(|| format!("remove stale {}", path.display()))?;
+    }
+    UnixListener::bind(path).with_context(|| format!("bind {}", path.display()))
+}
+
+/// Serves each connection on its own thread, so a client that stays connected
+/// without sending anything does not hold up the others.
+fn serve(listener: UnixListener, tx: Sender<Request>, status: Arc<Mutex<Status>>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("ipc accept failed: {err}");
+                continue;
+            }
+        };
+        let (tx, status) = (tx.clone(), Arc::clone(&status));
+        let spawned = thread::Builder::new()
+            .name("ipc-client".to_string())
+            .spawn(move || {
+                if let Err(err) = handle_client(stream, &tx, &status) {
+                    error!("ipc client error: {err}");
+                }
+            });
+        if let Err(err) = spawned {
+            error!("spawn ipc client thread: {err}");
+        }
+    }
+}
+
+fn handle_client(stream: UnixStream, tx: &Sender<Request>, status: &Mutex<Status>) -> Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = Request::parse(&line).and_then(|request| request.check().map(|()| request));
+        let reply = match request {
+            Ok(Request::Capabilities) => Capabilities::current().to_line(),
+            Ok(Request::Status { json }) => {
+                let status = status.lock().unwrap_or_else(PoisonError::into_inner);
+                if json {
+                    status.to_json()
+                } else {
+                    status.tooltip()
+                }
+            }
+            Ok(request) => match tx.send(request) {
+                Ok(()) => "ok".to_string(),
+                Err(_) => "error: daemon is shutting down".to_string(),
+            },
+            Err(err) => format!("error: {err}"),
+        };
+        writeln!(writer, "{reply}")?;
+    }
+    Ok(())
+}
+
+/// Sends `request` to the running instance and returns its reply.
+pub fn send(request: &Request) -> Result<String> {
+    Ok(Client::at(paths::socket_path()).request(request)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use std::env;
+
+    #[test]
+    fn server_forwards_requests() {
+        let path = env::temp_dir().join(format!("interlude-test-{}.sock", std::process::id()));
+        let listener = bind(&path).unwrap();
+        let (tx, rx) = unbounded();
+        let status = Arc::new(Mutex::new(Status::default()));
+        thread::spawn(move || serve(listener, tx, status));
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, "exclude-output DP-3\nbogus\nstatus\ncapabilities").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let replies: Vec<_> = BufReader::new(stream).lines().map(Result::unwrap).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replies[0], "ok");
+        assert!(replies[1].starts_with("error: unknown command"));
+        assert_eq!(replies[2], "Next break in 0 min");
+        assert_eq!(replies[3], Capabilities::current().to_line());
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Request::ExcludeOutput("DP-3".to_string())
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn idle_client_does_not_block_others() {
+        let path = env::temp_dir().join(format!("interlude-idle-{}.sock", std::process::id()));
+        let listener = bind(&path).unwrap();
+        let (tx, _rx) = unbounded();
+        let status = Arc::new(Mutex::new(Status::default()));
+        thread::spawn(move || serve(listener, tx, status));
+
+        let _idle = UnixStream::connect(&path).unwrap();
+        let started = std::time::Instant::now();
+        let reply = Client::at(&path).request(&Request::Capabilities).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(reply, Capabilities::current().to_line());
+        assert!(started.elapsed() < CLIENT_TIMEOUT);
+    }
+}
@@ -0,0 +1,318 @@
+//! Key events from the overlay and the bindings that turn them into actions.
+//!
+//! The Wayland side only reports what was pressed ([`KeyEvent`]) and, when the
+//! keymap or active layout changes, which keysyms the layout can type
+//! ([`KeyLayout`]). Deciding what a key does happens in the main loop.
+
+use std::collections::{HashMap, HashSet};
+use xkbcommon::xkb;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+/// A key press as typed on the active layout.
+#[derive(Debug, Clone, Default)]
+pub struct KeyEvent {
+    /// Keysym with modifiers applied, when a keymap is known.
+    pub keysym: Option<u32>,
+    /// Evdev keycode (Wayland keycode minus 8).
+    pub keycode: u32,
+    /// Text the key produces, empty for non-printing keys.
+    pub text: String,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// A bare Shift/Ctrl/Alt/Super/level-shift press that types nothing.
+    pub fn is_modifier_only(&self) -> bool {
+        // Shift_L..Hyper_R = 0xffe1..=0xffee, ISO_Lock..ISO_Level5_Lock = 0xfe01..=0xfe13
+        self.text.is_empty()
+            && self.keysym.is_some_and(|sym| {
+                (0xffe1..=0xffee).contains(&sym) || (0xfe01..=0xfe13).contains(&sym)
+            })
+    }
+
+    /// Whether a modifier that turns a key into a shortcut is held.
+    fn is_chord(&self) -> bool {
+        self.modifiers.ctrl || self.modifiers.alt || self.modifiers.logo
+    }
+}
+
+/// What the active layout can type.
+#[derive(Debug, Clone, Default)]
+pub struct KeyLayout {
+    reachable: HashSet<u32>,
+    /// Unshifted keysym for each evdev keycode.
+    base: HashMap<u32, u32>,
+}
+
+impl KeyLayout {
+    pub fn from_keymap(keymap: &xkb::Keymap, layout: xkb::LayoutIndex) -> Self {
+        let mut reachable = HashSet::new();
+        let mut base = HashMap::new();
+        keymap.key_for_each(|keymap, keycode| {
+            if layout >= keymap.num_layouts_for_key(keycode) {
+                return;
+            }
+            for level in 0..keymap.num_levels_for_key(keycode, layout) {
+                let syms = keymap.key_get_syms_by_level(keycode, layout, level);
+                if level == 0
+                    && let Some(sym) = syms.first()
+                {
+                    base.insert(keycode.raw().saturating_sub(8), sym.raw());
+                }
+                reachable.extend(syms.iter().map(|sym| sym.raw()));
+            }
+        });
+        Self { reachable, base }
+    }
+
+    fn produces(&self, keysym: u32) -> bool {
+        self.reachable.contains(&keysym)
+    }
+}
+
+/// What a bound key does on the overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Continue,
+    /// Start the break without waiting for the rest of the fade-in.
+    StartBreak,
+    Snooze,
+    Skip,
+    Rating(u8),
+}
+
+/// A key binding: the keysyms that trigger it and the evdev keycodes used
+/// when the active layout cannot produce any of them. The first keysym and
+/// keycode are the ones shown in hints.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub action: KeyAction,
+    pub keysyms: Vec<u32>,
+    pub keycodes: Vec<u32>,
+    /// Match the keycodes even when the layout has the keysyms, for keys that
+    /// are not layout dependent or only type their keysym with Shift.
+    pub positional: bool,
+}
+
+// xkbcommon keysyms: Return = 0xff0d, KP_Enter = 0xff8d, z = 0x007a, Z = 0x005a,
+// s = 0x0073, S = 0x0053, XF86AudioPlay = 0x1008ff14, XF86AudioPause = 0x1008ff31,
+// XF86AudioNext = 0x1008ff17, b = 0x0062, B = 0x0042, space = 0x0020
+impl Binding {
+    fn new(action: KeyAction, keysyms: &[u32], keycodes: &[u32]) -> Self {
+        Self {
+            action,
+            keysyms: keysyms.to_vec(),
+            keycodes: keycodes.to_vec(),
+            positional: false,
+        }
+    }
+
+    fn positional(mut self) -> Self {
+        self.positional = true;
+        self
+    }
+}
+
+/// The active set of bindings, resolved against the current layout.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    bindings: Vec<Binding>,
+    layout: Option<KeyLayout>,
+}
+
+impl Bindings {
+    pub fn new(media_keys: bool) -> Self {
+        let mut bindings = vec![
+            Binding::new(KeyAction::Continue, &[0xff0d, 0xff8d], &[28, 96]),
+            Binding::new(KeyAction::StartBreak, &[0x0062, 0x0042], &[48]),
+            Binding::new(KeyAction::StartBreak, &[0x0020], &[57]).positional(),
+            Binding::new(KeyAction::Snooze, &[0x007a, 0x005a], &[44]),
+            Binding::new(KeyAction::Skip, &[0x0073, 0x0053], &[31]),
+        ];
+        if media_keys {
+            bindings.push(
+                Binding::new(
+                    KeyAction::Continue,
+                    &[0x1008ff14, 0x1008ff31],
+                    &[164, 200, 201],
+                )
+                .positional(),
+            );
+            bindings.push(Binding::new(KeyAction::Snooze, &[0x1008ff17], &[163]).positional());
+        }
+        // Rating keys match the digit row and keypad by position, since some
+        // layouts (e.g. AZERTY) only produce digits with Shift held.
+        for (rating, (keysyms, keycodes)) in [
+            ([0x0031, 0xffb1], [2, 79]),
+            ([0x0032, 0xffb2], [3, 80]),
+            ([0x0033, 0xffb3], [4, 81]),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            bindings.push(
+                Binding::new(KeyAction::Rating(rating as u8 + 1), &keysyms, &keycodes).positional(),
+            );
+        }
+        Self {
+            bindings,
+            layout: None,
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: KeyLayout) {
+        self.layout = Some(layout);
+    }
+
+    /// Whether `binding` falls back to its keycodes on the current layout.
+    /// Without a keymap, keycodes are all there is to go on.
+    fn by_keycode(&self, binding: &Binding) -> bool {
+        binding.positional
+            || self
+                .layout
+                .as_ref()
+                .is_none_or(|layout| !binding.keysyms.iter().any(|sym| layout.produces(*sym)))
+    }
+
+    /// The action bound to `key`. Shortcuts held with Ctrl, Alt or Super are
+    /// left to the compositor.
+    pub fn action(&self, key: &KeyEvent) -> Option<KeyAction> {
+        if key.is_chord() {
+            return None;
+        }
+        self.bindings
+            .iter()
+            .find(|binding| {
+                key.keysym.is_some_and(|sym| binding.keysyms.contains(&sym))
+                    || (self.by_keycode(binding) && binding.keycodes.contains(&key.keycode))
+            })
+            .map(|binding| binding.action)
+    }
+
+    /// Hint label for the first binding of `action`: the key that works on
+    /// the user's layout, or what the fallback key types when the layout has
+    /// no such keysym (e.g. Cyrillic layouts lack `z`).
+    pub fn label(&self, action: KeyAction) -> String {
+        let Some(binding) = self.bindings.iter().find(|b| b.action == action) else {
+            return String::new();
+        };
+        let fallback = binding.keysyms[0];
+        if binding.positional || !self.by_keycode(binding) {
+            return keysym_label(fallback);
+        }
+        let sym = self
+            .layout
+            .as_ref()
+            .and_then(|layout| layout.base.get(&binding.keycodes[0]).copied())
+            .unwrap_or(fallback);
+        keysym_label(sym)
+    }
+}
+
+fn keysym_label(sym: u32) -> String {
+    match sym {
+        0xff0d | 0xff8d => "Enter".to_string(),
+        0xff1b => "Esc".to_string(),
+        0x0020 => "Space".to_string(),
+        _ => {
+            let sym = xkb::Keysym::new(sym);
+            let text = xkb::keysym_to_utf8(sym);
+            let text = text.trim_matches(char::from(0));
+            if text.is_empty() || text.chars().any(char::is_control) {
+                xkb::keysym_get_name(sym)
+            } else {
+                text.to_uppercase()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(reachable: &[u32], base: &[(u32, u32)]) -> KeyLayout {
+        KeyLayout {
+            reachable: reachable.iter().copied().collect(),
+            base: base.iter().copied().collect(),
+        }
+    }
+
+    fn key(keysym: u32, keycode: u32) -> KeyEvent {
+        KeyEvent {
+            keysym: Some(keysym),
+            keycode,
+            ..KeyEvent::default()
+        }
+    }
+
+    #[test]
+    fn latin_layout_matches_by_keysym() {
+        // QWERTZ: `z` lives on keycode 21, keycode 44 types `y`.
+        let mut bindings = Bindings::new(false);
+        bindings.set_layout(layout(
+            &[0x7a, 0x79, 0x73, 0xff0d],
+            &[(21, 0x7a), (44, 0x79)],
+        ));
+        assert_eq!(bindings.action(&key(0x7a, 21)), Some(KeyAction::Snooze));
+        assert_eq!(bindings.action(&key(0x79, 44)), None);
+        assert_eq!(bindings.label(KeyAction::Snooze), "Z");
+    }
+
+    #[test]
+    fn missing_keysym_falls_back_to_keycode() {
+        // Russian: keycode 44 types `я` (0x6d1) and there is no `z` at all.
+        let mut bindings = Bindings::new(false);
+        bindings.set_layout(layout(&[0x6d1, 0x6d9, 0xff0d], &[(44, 0x6d1)]));
+        assert_eq!(bindings.action(&key(0x6d1, 44)), Some(KeyAction::Snooze));
+        assert_eq!(bindings.label(KeyAction::Snooze), "Я");
+        assert_eq!(bindings.label(KeyAction::Continue), "Enter");
+    }
+
+    #[test]
+    fn space_starts_a_break_on_any_layout() {
+        let mut bindings = Bindings::new(false);
+        bindings.set_layout(layout(&[0x62, 0x20], &[(48, 0x62), (57, 0x20)]));
+        assert_eq!(bindings.action(&key(0x62, 48)), Some(KeyAction::StartBreak));
+        assert_eq!(bindings.action(&key(0x20, 57)), Some(KeyAction::StartBreak));
+        assert_eq!(bindings.label(KeyAction::StartBreak), "B");
+    }
+
+    #[test]
+    fn media_keys_are_opt_in() {
+        let play = key(0x1008ff14, 164);
+        assert_eq!(Bindings::new(false).action(&play), None);
+        assert_eq!(Bindings::new(true).action(&play), Some(KeyAction::Continue));
+    }
+
+    #[test]
+    fn chords_and_bare_modifiers_do_nothing() {
+        let bindings = Bindings::new(false);
+        let mut skip = key(0x73, 31);
+        skip.text = "s".to_string();
+        assert_eq!(bindings.action(&skip), Some(KeyAction::Skip));
+        skip.modifiers.ctrl = true;
+        assert_eq!(bindings.action(&skip), None);
+        assert!(!skip.is_modifier_only());
+        assert!(key(0xffe3, 29).is_modifier_only());
+    }
+
+    #[test]
+    fn ratings_match_by_position() {
+        // AZERTY: the `1` key types `&` unshifted.
+        let mut bindings = Bindings::new(false);
+        bindings.set_layout(layout(&[0x26, 0x31], &[(2, 0x26)]));
+        assert_eq!(bindings.action(&key(0x26, 2)), Some(KeyAction::Rating(1)));
+        assert_eq!(
+            bindings.action(&key(0xffb3, 81)),
+            Some(KeyAction::Rating(3))
+        );
+    }
+}
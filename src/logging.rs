@@ -0,0 +1,213 @@
+//! Log output that never holds up the caller.
+//!
+//! Under `systemd --user` or a compositor's `exec`, stdout and stderr are
+//! pipes that may be full or closed; a plain `println!` then blocks the main
+//! loop or panics. [`info!`] and [`error!`] hand their line to a writer
+//! thread through a bounded queue instead, dropping lines (and later saying
+//! how many) while the writer is stuck, and ignoring write errors.
+//! [`flush`] lets the writer finish the queue before the process exits.
+//!
+//! Errors that can repeat every frame or poll go through [`rate_limited`]:
+//! the first message of a kind is printed; repeats within [`WINDOW`] are only
+//! counted, and the next one printed after the window says how many were
+//! dropped, so a persistent fault shows up once a minute instead of flooding
+//! the journal.
+
+use crossbeam_channel::{Sender, bounded};
+use std::fmt::{Arguments, Display};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// Lines waiting for the writer before new ones are dropped.
+const QUEUE_LEN: usize = 256;
+/// Longest [`flush`] waits for a writer stuck on a blocked pipe.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+static LIMITER: Mutex<RateLimiter> = Mutex::new(RateLimiter::new());
+static WRITER: Mutex<Writer> = Mutex::new(Writer::Unstarted);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Progress, to stdout.
+    Info,
+    /// Failures, to stderr.
+    Error,
+}
+
+/// Logs a line to stdout without blocking.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Level::Info, format_args!($($arg)*))
+    };
+}
+
+/// Logs a line to stderr without blocking.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Level::Error, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {error, info};
+
+enum Writer {
+    Unstarted,
+    Thread(Sender<(Level, String)>, JoinHandle<()>),
+    /// The thread could not start, or was flushed: the caller writes.
+    Direct,
+}
+
+pub fn log(level: Level, args: Arguments) {
+    let line = args.to_string();
+    let mut writer = WRITER.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Writer::Unstarted = *writer {
+        *writer = spawn_writer();
+    }
+    match &*writer {
+        Writer::Thread(tx, _) => {
+            if tx.try_send((level, line)).is_err() {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Writer::Direct | Writer::Unstarted => {
+            drop(writer);
+            write_line(level, &line);
+        }
+    }
+}
+
+/// Writes out the queued lines and stops the writer thread, waiting at most
+/// [`FLUSH_TIMEOUT`]; lines logged afterwards are written by the caller.
+/// Called on the way out of `main`, so the last lines are not lost.
+pub fn flush() {
+    let writer = std::mem::replace(
+        &mut *WRITER.lock().unwrap_or_else(PoisonError::into_inner),
+        Writer::Direct,
+    );
+    let Writer::Thread(tx, handle) = writer else {
+        return;
+    };
+    // The writer ends once the queue is empty and its sender is gone.
+    drop(tx);
+    let start = Instant::now();
+    while !handle.is_finished() && start.elapsed() < FLUSH_TIMEOUT {
+        thread::sleep(Duration::from_millis(1));
+    }
+    if handle.is_finished() {
+        let _ = handle.join();
+    }
+}
+
+/// The writer thread, or [`Writer::Direct`] if it cannot start.
+fn spawn_writer() -> Writer {
+    let (tx, rx) = bounded::<(Level, String)>(QUEUE_LEN);
+    let handle = thread::Builder::new()
+        .name("log".to_string())
+        .spawn(move || {
+            for (level, line) in rx {
+                let dropped = DROPPED.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    write_line(
+                        Level::Error,
+                        &format!("({dropped} log lines dropped while output was blocked)"),
+                    );
+                }
+                write_line(level, &line);
+            }
+        });
+    match handle {
+        Ok(handle) => Writer::Thread(tx, handle),
+        Err(_) => Writer::Direct,
+    }
+}
+
+fn write_line(level: Level, line: &str) {
+    let _ = match level {
+        Level::Info => writeln!(io::stdout().lock(), "{line}"),
+        Level::Error => writeln!(io::stderr().lock(), "{line}"),
+    };
+}
+
+/// Writes the result of a command (`ctl`, `stats`, `--version`) to stdout.
+/// This one waits for the reader, but a reader that went away, as with
+/// `| head`, only cuts the output short.
+pub fn output(text: impl Display) {
+    let _ = write!(io::stdout().lock(), "{text}");
+}
+
+/// Logs `{kind}: {detail}` to stderr unless a `kind` message was logged
+/// within the last minute.
+pub fn rate_limited(kind: &'static str, detail: impl Display) {
+    let admitted = LIMITER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .admit(kind, Instant::now());
+    match admitted {
+        None => {}
+        Some(0) => error!("{kind}: {detail}"),
+        Some(suppressed) => {
+            error!("{kind}: {detail} (suppressed {suppressed} similar messages)")
+        }
+    }
+}
+
+struct Entry {
+    kind: &'static str,
+    window_start: Instant,
+    suppressed: u32,
+}
+
+struct RateLimiter {
+    entries: Vec<Entry>,
+}
+
+impl RateLimiter {
+    const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// `None` to drop the message, otherwise how many were dropped since the
+    /// last one printed.
+    fn admit(&mut self, kind: &'static str, now: Instant) -> Option<u32> {
+        let Some(entry) = self.entries.iter_mut().find(|entry| entry.kind == kind) else {
+            self.entries.push(Entry {
+                kind,
+                window_start: now,
+                suppressed: 0,
+            });
+            return Some(0);
+        };
+        if now.duration_since(entry.window_start) < WINDOW {
+            entry.suppressed += 1;
+            return None;
+        }
+        entry.window_start = now;
+        Some(std::mem::take(&mut entry.suppressed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_are_counted_until_the_window_passes() {
+        let mut limiter = RateLimiter::new();
+        let start = Instant::now();
+        assert_eq!(limiter.admit("redraw error", start), Some(0));
+        for frame in 1..=240 {
+            let at = start + Duration::from_millis(frame * 16);
+            assert_eq!(limiter.admit("redraw error", at), None);
+        }
+        assert_eq!(limiter.admit("inhibitor check failed", start), Some(0));
+        assert_eq!(limiter.admit("redraw error", start + WINDOW), Some(240));
+        assert_eq!(limiter.admit("redraw error", start + WINDOW * 2), Some(0));
+    }
+}
@@ -1,22 +1,91 @@
-use anyhow::Result;
-use clap::Parser;
-use crossbeam_channel::unbounded;
+use anyhow::{Context, Result};
+use crossbeam_channel::{Select, unbounded};
 
+mod actions;
 mod audio;
+mod autostart;
 mod cli;
+mod color;
+mod config;
+mod dbus;
+mod drift;
+mod escalation;
+#[cfg(feature = "evdev")]
+mod evdev;
+mod follow;
+mod game;
+mod headline;
+mod idle;
 mod inhibitors;
+mod init;
+mod ipc;
+mod keys;
+mod logging;
+mod notify;
+mod paths;
+mod pixels;
+mod portal;
+mod probe;
+mod render;
 mod scheduler;
 mod session_lock;
+mod shm;
+mod signals;
 mod state;
+mod stats;
+mod status;
 mod tiny_font;
+mod tui;
+mod update;
 mod wayland_lock;
+mod workday;
 
-use audio::Audio;
-use cli::Cli;
-use inhibitors::InhibitorWatcher;
-use scheduler::{Config, Phase, Scheduler};
+use actions::{Action, ActionTable, Input};
+use audio::{Cue, Sound, SoundMap, spawn_audio};
+use clap::ValueEnum;
+use cli::{
+    AwaySound, Backend, BreakDuePolicy, Cli, Command, ConfigCommand, CtlCommand, GamePolicy,
+    MissingTime, SnoozeCurveKind, SoundCue, StatsCommand,
+};
+use dbus::StatusBus;
+use drift::DriftWatch;
+use escalation::{Channel, Escalation};
+use follow::spawn_follower;
+use game::GameDetector;
+use headline::Headlines;
+use idle::{ActivityMeter, AwayMeter, IdleEvent, spawn_idle_watcher};
+use inhibitors::{InhibitorSource, InhibitorWatcher};
+use interlude_client::{Setting, Settings};
+use ipc::{Request, spawn_ipc_server};
+use keys::{Bindings, KeyAction};
+use logging::{error, info, rate_limited};
+use notify::{Notice, Notifier};
+use portal::Background;
+use probe::{Capabilities, IDLE_NOTIFIER};
+use scheduler::{BreakKind, BreakVariant, Config, Phase, Scheduler, SchedulerEvent, SnoozeCurve};
 use session_lock::{SessionLockEvent, spawn_session_lock_watcher};
-use wayland_lock::{Locker, UiColors, UiEvent, UiMode};
+use signals::spawn_signal_watcher;
+use stats::{FocusClock, SkipReason, StatsEvent, Today};
+use status::{Hold, Status, write_status_file};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use update::spawn_update_checker;
+use wayland_lock::{Locker, LockerOptions, Placement, UiColors, UiEvent, UiMode};
+use workday::Workday;
+
+const EXTEND_BREAK_BY: std::time::Duration = std::time::Duration::from_secs(60);
+/// Longest sleep while idle, so a suspend is still noticed by the drift check.
+const MAX_IDLE_SLEEP: std::time::Duration = std::time::Duration::from_secs(60);
+/// Input-free time during a break before it counts towards the away score.
+const AWAY_IDLE_AFTER: std::time::Duration = std::time::Duration::from_secs(5);
+/// How long before a break --osd-countdown shows.
+const OSD_COUNTDOWN: std::time::Duration = std::time::Duration::from_secs(60);
+/// Lines added with `ctl note` that the overlay has room for; the oldest
+/// goes first.
+const MAX_NOTES: usize = 3;
+/// How often --adaptive-strain weighs the work time since the last time.
+const STRAIN_WINDOW: std::time::Duration = std::time::Duration::from_secs(60);
 
 fn fmt_duration(d: std::time::Duration) -> String {
     let secs = d.as_secs();
@@ -26,8 +95,54 @@ fn fmt_duration(d: std::time::Duration) -> String {
 }
 
 fn main() -> Result<()> {
-    let args = Cli::parse();
-    println!("interlude {}", env!("CARGO_PKG_VERSION"));
+    let result = run();
+    logging::flush();
+    result
+}
+
+fn run() -> Result<()> {
+    let args = Cli::load().inspect_err(|err| {
+        if Cli::starts_daemon() {
+            report_fatal(err);
+        }
+    })?;
+    if args.version {
+        logging::output(format_args!("{}\n", probe::version()));
+        if args.features {
+            logging::output(probe::report());
+        }
+        return Ok(());
+    }
+    if let Some(command) = &args.command {
+        return run_command(command, &args);
+    }
+    let result = run_daemon(args);
+    if let Err(err) = &result {
+        report_fatal(err);
+    }
+    result
+}
+
+/// Tells the desktop that breaks have stopped, when there is no terminal
+/// to see the error on, as when started with the session.
+fn report_fatal(err: &anyhow::Error) {
+    if std::io::stderr().is_terminal() {
+        return;
+    }
+    let notice = Notice {
+        summary: "Interlude stopped".to_string(),
+        body: format!("Breaks are no longer being enforced: {err:#}"),
+        actions: Vec::new(),
+        persistent: true,
+    };
+    if let Err(notify_err) = notify::send_once(&notice) {
+        error!("could not report the error on the desktop: {notify_err:#}");
+    }
+}
+
+/// The break daemon; only returns on an error it cannot carry on from.
+fn run_daemon(args: Cli) -> Result<()> {
+    info!("interlude {}", env!("CARGO_PKG_VERSION"));
 
     let cfg = Config {
         interval: std::time::Duration::from_secs(args.interval_minutes * 60),
@@ -35,46 +150,280 @@ fn main() -> Result<()> {
         initial_interval: std::time::Duration::from_secs(args.initial_interval_minutes * 60),
         initial_break_len: std::time::Duration::from_secs(args.initial_break_seconds),
         snooze_base: std::time::Duration::from_secs(args.snooze_base_seconds),
-        snooze_decay: args.snooze_decay,
+        snooze_curve: match args.snooze_curve {
+            SnoozeCurveKind::Exponential => SnoozeCurve::Exponential {
+                decay: args.snooze_decay,
+            },
+            SnoozeCurveKind::Linear => SnoozeCurve::Linear {
+                step: std::time::Duration::from_secs(args.snooze_step_seconds),
+            },
+            SnoozeCurveKind::Constant => SnoozeCurve::Constant,
+            SnoozeCurveKind::Schedule => SnoozeCurve::Schedule(
+                args.snooze_schedule
+                    .iter()
+                    .map(|secs| std::time::Duration::from_secs(*secs))
+                    .collect(),
+            ),
+        },
         snooze_min: std::time::Duration::from_secs(args.snooze_min_seconds),
-        max_snoozes: if args.max_snoozes == 0 {
+        // A follower breaks when its leader does, so it cannot snooze.
+        max_snoozes: if args.follow.is_some() {
+            Some(0)
+        } else if args.max_snoozes == 0 {
             None
         } else {
             Some(args.max_snoozes)
         },
+        max_snooze_total: (args.max_snooze_minutes > 0)
+            .then(|| std::time::Duration::from_secs(args.max_snooze_minutes * 60)),
+        long_break_every: if args.long_break_every == 0 {
+            None
+        } else {
+            Some(args.long_break_every)
+        },
+        long_break_len: std::time::Duration::from_secs(args.long_break_minutes * 60),
+        carry_over: args.carry_over,
+        early_finish_after: (args.early_finish_after > 0.0)
+            .then(|| args.early_finish_after.min(1.0)),
+        idle_credit: args.idle_credit,
+        adaptive_strain: args.adaptive_strain,
+        planning_break: args.planning_break,
+        wrap_up_len: std::time::Duration::from_secs(args.wrap_up_minutes * 60),
+        micro_every: (args.micro_break_minutes > 0)
+            .then(|| std::time::Duration::from_secs(args.micro_break_minutes * 60)),
+        micro_len: std::time::Duration::from_secs(args.micro_break_seconds.max(1)),
+        warn_before: (args.warning_seconds > 0)
+            .then(|| std::time::Duration::from_secs(args.warning_seconds)),
+        milestones: args
+            .announce_minutes
+            .iter()
+            .map(|m| std::time::Duration::from_secs(m * 60))
+            .collect(),
     };
 
-    if args.reset_state {
-        if let Err(err) = state::clear_saved_state() {
-            eprintln!("state reset failed: {err}");
-        }
+    if args.reset_state
+        && let Err(err) = state::clear_saved_state()
+    {
+        error!("state reset failed: {err}");
     }
     let mut sched = if args.reset_state {
         Scheduler::new(cfg.clone())
     } else {
         state::load_scheduler(&cfg).unwrap_or_else(|| Scheduler::new(cfg.clone()))
     };
-    let mut last_phase = sched.phase;
+    let sched_events = sched.subscribe();
+    let audio_events = sched.subscribe();
+    sched.apply_startup_grace(std::time::Duration::from_secs(args.startup_grace * 60));
     let mut last_save = std::time::Instant::now() - state::save_interval();
-    let mut last_wall = std::time::SystemTime::now();
+    let mut drift_watch = DriftWatch::new();
     if args.immediate {
-        sched.phase = Phase::LockedAwaitingAction;
-        sched.deadline = None;
-        last_phase = Phase::Working;
+        sched.break_now();
     }
 
     let (tx_ui, rx_ui) = unbounded();
     let (tx_lock, rx_lock) = unbounded();
-    let colors = UiColors {
-        background: parse_color(&args.background).unwrap_or([0, 0, 0, 0xCC]),
-        foreground: parse_color(&args.foreground).unwrap_or([0xFF, 0xFF, 0xFD, 0xDD]),
+    let (tx_command, rx_command) = unbounded();
+    let (tx_ipc, rx_ipc) = unbounded();
+    let (tx_idle, rx_idle) = unbounded();
+    let (tx_presence, rx_presence) = unbounded();
+    let (tx_notify, rx_notify) = unbounded();
+    let (tx_leader, rx_leader) = unbounded();
+    let (tx_update, rx_update) = unbounded();
+    // A receiver whose senders are all gone is always ready, and would keep
+    // the idle `Select` below from ever sleeping; these stay alive for as
+    // long as the loop, whichever producers fail to start.
+    let _keep_alive = (
+        tx_lock.clone(),
+        tx_command.clone(),
+        tx_ipc.clone(),
+        tx_idle.clone(),
+        tx_presence.clone(),
+        tx_notify.clone(),
+        tx_leader.clone(),
+        tx_update.clone(),
+    );
+    let caps = Capabilities::probe();
+    for line in caps.degraded() {
+        info!("Capability {line}");
+    }
+    let preference = if args.notify_only {
+        vec![Backend::NotifyOnly]
+    } else {
+        args.backends.clone()
     };
-    let mut locker = Locker::new(tx_ui, colors)?;
-    let audio = Audio::new();
-    let mut inhibitors = InhibitorWatcher::new(std::time::Duration::from_secs(1));
-    if let Err(err) = spawn_session_lock_watcher(tx_lock) {
-        eprintln!("session lock watcher unavailable: {err:?}");
+    let backends = caps.available_backends(&preference);
+    if backends.is_empty() {
+        let wanted: Vec<&str> = preference.iter().map(|b| b.label()).collect();
+        anyhow::bail!("none of the backends {} is available", wanted.join(", "));
+    }
+    let mut locker = Locker::new(
+        tx_ui,
+        break_colors(&args, sched.break_kind()),
+        locker_options(&args, backends),
+    )?;
+    info!("Overlay backend: {}", locker.backend().label());
+    let mut escalation = Escalation::new(args.escalation.clone());
+    if locker.backend() == Backend::SessionLock {
+        // A locked session has nothing quieter to show than the lock itself.
+        escalation.retain(|channel| !matches!(channel, Channel::Card | Channel::Dim));
+    }
+    // Only a layer-shell overlay can stay up without holding the keyboard.
+    let dismiss_on_return = args.dismiss_on_return && locker.backend() == Backend::LayerShell;
+    if args.dismiss_on_return && !dismiss_on_return {
+        info!(
+            "--dismiss-on-return needs the layer-shell backend, not {}",
+            locker.backend().label()
+        );
+    }
+    if !args.escalation.is_empty() {
+        let stages: Vec<String> = args.escalation.iter().map(|s| s.to_string()).collect();
+        info!("Escalation: {}", stages.join(", "));
+    }
+    let mut notifier = None;
+    if locker.backend() == Backend::NotifyOnly
+        || escalation.includes(Channel::Notify)
+        || (args.warning_notification && args.warning_seconds > 0)
+        || (args.micro_break_minutes > 0 && locker.backend() != Backend::LayerShell)
+    {
+        match Notifier::new(tx_notify) {
+            Ok(n) => notifier = Some(n),
+            Err(err) => error!("notifications unavailable: {err:?}"),
+        }
+    }
+    let mut background = None;
+    if portal::is_sandboxed() {
+        match Background::new() {
+            Ok(b) => background = Some(b),
+            Err(err) => error!("background portal unavailable: {err:?}"),
+        }
+    }
+    let mut bindings = Bindings::new(args.media_keys);
+    let sounds = sound_map(&args);
+    if let Err(err) = sounds.check_files() {
+        error!("sound file unusable: {err:#}");
+    }
+    // Set while the idle watcher says nobody is at the keyboard.
+    let away = Arc::new(AtomicBool::new(false));
+    if let Err(err) = spawn_audio(
+        audio_events,
+        sounds,
+        Arc::clone(&away),
+        args.away_start_sound,
+    ) {
+        error!("audio unavailable: {err:?}");
+    }
+    let mut inhibitors = InhibitorWatcher::new(std::time::Duration::from_secs(5), &caps);
+    let mut games = GameDetector::new(std::time::Duration::from_secs(5), caps.session_bus);
+    if caps.system_bus
+        && let Err(err) = spawn_session_lock_watcher(tx_lock)
+    {
+        error!("session lock watcher unavailable: {err:?}");
+    }
+    if let Err(err) = spawn_signal_watcher(tx_command) {
+        error!("signal watcher unavailable: {err:?}");
+    }
+    // The latest status, for `ctl status`; the status file is written from it too.
+    let shared_status = Arc::new(Mutex::new(Status::default()));
+    let mut last_status_json = String::new();
+    if let Err(err) = spawn_ipc_server(tx_ipc.clone(), Arc::clone(&shared_status)) {
+        error!("control socket unavailable: {err:?}");
+    }
+    if let Some(socket) = &args.follow {
+        info!("Following {}", socket.display());
+        if let Err(err) = spawn_follower(socket.clone(), tx_leader) {
+            error!("follower mode unavailable: {err:?}");
+        }
+    }
+    if args.check_updates
+        && let Err(err) = spawn_update_checker(tx_update)
+    {
+        error!("update check unavailable: {err:?}");
+    }
+    // A newer release, when --check-updates found one.
+    let mut update_available = None;
+    // The leader's phase when last heard from, in follower mode.
+    let mut leader_phase = None;
+    let mut status_bus = None;
+    if caps.session_bus {
+        match StatusBus::serve(Arc::clone(&shared_status), tx_ipc) {
+            Ok(bus) => status_bus = Some(bus),
+            Err(err) => error!("D-Bus status unavailable: {err:?}"),
+        }
+    }
+    let mut today = Today::load();
+    let mut headlines = Headlines::load(&args.headlines, args.headline_rotation);
+    let mut headline = headlines.current();
+    // `ctl note` lines for the next break, cleared once it is over.
+    let mut notes: Vec<String> = Vec::new();
+    let mut focus = FocusClock::default();
+    let idle_after = std::time::Duration::from_secs(args.idle_credit_after);
+    let mut idle_watches = vec![(AWAY_IDLE_AFTER, tx_presence)];
+    if args.idle_credit > 0.0 || args.away_start_sound != AwaySound::Play {
+        idle_watches.push((idle_after, tx_idle));
+    }
+    // Scores how much of each break passed without input, for the stats.
+    let mut away_meter = None;
+    if caps.has(IDLE_NOTIFIER) {
+        match spawn_idle_watcher(idle_watches) {
+            Ok(()) => away_meter = Some(AwayMeter::new(AWAY_IDLE_AFTER)),
+            Err(err) => error!("idle tracking unavailable: {err:?}"),
+        }
+    } else {
+        #[cfg(feature = "evdev")]
+        match evdev::spawn_input_watcher(idle_watches) {
+            Ok(()) => away_meter = Some(AwayMeter::new(AWAY_IDLE_AFTER)),
+            Err(err) => error!("idle tracking from input devices unavailable: {err:?}"),
+        }
     }
+    // Weighs work time by how much of it had input, for --adaptive-strain.
+    let mut activity_meter = (args.adaptive_strain > 0.0 && away_meter.is_some())
+        .then(|| ActivityMeter::new(AWAY_IDLE_AFTER, std::time::Instant::now()));
+    // Start of the current idle gap, which began `idle_after` before it was reported.
+    let mut idle_since = None;
+    let actions = ActionTable::new(args.feedback_prompt);
+    let mut paused_by_user = false;
+    // When a `ctl pause N` ends by itself.
+    let mut pause_until: Option<std::time::Instant> = None;
+    // The deadline a deferral set, shown as deferred until something moves it.
+    let mut deferred_deadline = None;
+    // The skip key asked why, and the answer is awaited or not yet recorded.
+    let mut skip_prompt = false;
+    let mut skip_reason = None;
+    let mut last_inhibitor = None;
+    let workday = args.workday_end.map(|end| Workday {
+        start: args.workday_start,
+        end,
+    });
+    // Set past the end of the working day, to when it starts again.
+    let mut off_hours_until = None;
+    // The working day ended and off-hours start once the current break is over.
+    let mut wrapping_up = false;
+    // Local times are measured again when this changes.
+    let mut utc_offset = *chrono::Local::now().offset();
+    if let Some(workday) = &workday
+        && workday.is_off_hours(chrono::Local::now().time())
+    {
+        off_hours_until = off_hours_end(workday);
+        info!("Off Hours (until {})", fmt_clock(off_hours_until));
+    }
+    // When the current break fell due; the escalation runs from here.
+    let mut due_since = None;
+    // The break is shown on cards for the card stage, to be replaced by overlays.
+    let mut card_stage = false;
+    // When the break-due screen finished fading in; the policy timeout runs from here.
+    let mut break_due_since = None;
+    let break_due_timeout = std::time::Duration::from_secs(args.break_due_timeout);
+    // When the break-complete screen came up, for --break-finished-timeout.
+    let mut break_finished_since = None;
+    let break_finished_timeout = std::time::Duration::from_secs(args.break_finished_timeout * 60);
+    // When a key dismissed the break-complete screen, while it says so.
+    let mut key_toast_since: Option<std::time::Instant> = None;
+    let key_toast = std::time::Duration::from_millis(args.key_toast_ms);
+    // The break-complete screen let go of the keyboard while nobody was there.
+    let mut released_while_away = false;
+    let keys_before_break = args.fast_forward_keys
+        || args.break_due_policy != BreakDuePolicy::Start
+        || args.break_due_timeout > 0;
     let fade_fps = args.fade_fps.max(1);
     let fade_sleep_ms = (1000 / fade_fps as u64).max(1);
 
@@ -83,11 +432,11 @@ fn main() -> Result<()> {
             match ev {
                 SessionLockEvent::Locked => {
                     sched.handle_session_locked();
-                    println!("Timer Paused (session locked)");
+                    info!("Timer Paused (session locked)");
                 }
                 SessionLockEvent::Unlocked => {
                     sched.handle_session_unlocked();
-                    println!(
+                    info!(
                         "Timer Reset (session unlocked, next in {})",
                         fmt_duration(sched.interval_duration())
                     );
@@ -95,33 +444,255 @@ fn main() -> Result<()> {
             }
         }
 
-        let now_wall = std::time::SystemTime::now();
-        if let Ok(elapsed) = now_wall.duration_since(last_wall) {
+        for event in rx_idle.try_iter() {
+            match event {
+                IdleEvent::Idle => {
+                    away.store(true, Ordering::Relaxed);
+                    idle_since = std::time::Instant::now().checked_sub(idle_after);
+                }
+                IdleEvent::Resumed => {
+                    away.store(false, Ordering::Relaxed);
+                    let Some(since) = idle_since.take() else {
+                        continue;
+                    };
+                    if args.idle_credit <= 0.0 {
+                        continue;
+                    }
+                    let idle = since.elapsed();
+                    let working = matches!(sched.phase, Phase::Working | Phase::Snoozing);
+                    if working && idle >= sched.break_duration() && !sched.is_paused() {
+                        sched.finish_and_restart();
+                        info!(
+                            "Timer Reset (away for {}, next in {})",
+                            fmt_duration(idle),
+                            fmt_duration(sched.interval_duration())
+                        );
+                    } else if let Some(credit) = sched.credit_idle(idle) {
+                        info!(
+                            "Idle Credit ({} for {} away, next in {})",
+                            fmt_duration(credit),
+                            fmt_duration(idle),
+                            fmt_duration(sched.time_left().unwrap_or_default())
+                        );
+                    }
+                }
+            }
+        }
+
+        for event in rx_presence.try_iter() {
+            let now = std::time::Instant::now();
+            if let Some(meter) = &mut away_meter {
+                meter.event(event, now);
+            }
+            if let Some(meter) = &mut activity_meter {
+                meter.event(event, now);
+            }
+            // The key that brings someone back reaches their app, not the overlay.
+            if event == IdleEvent::Resumed
+                && released_while_away
+                && sched.phase == Phase::BreakFinished
+            {
+                info!("Break Screen Dismissed (input resumed)");
+                locker.start_fade_out();
+            }
+        }
+        if let Some(meter) = &mut activity_meter {
+            let now = std::time::Instant::now();
+            if meter.elapsed(now) >= STRAIN_WINDOW {
+                let (active, elapsed) = meter.take_reading(now);
+                sched.weigh_work(elapsed, active);
+            }
+        }
+
+        for leader in rx_leader.try_iter() {
+            let changed = leader_phase != Some(leader.phase);
+            leader_phase = Some(leader.phase);
+            let on_break = !matches!(sched.phase, Phase::Working | Phase::Snoozing);
+            sched.mirror(leader.phase, leader.left, changed);
+            if on_break && sched.phase == Phase::Working && locker.is_locked() {
+                locker.start_fade_out();
+            }
+        }
+
+        focus.set_running(
+            matches!(sched.phase, Phase::Working | Phase::Snoozing)
+                && sched.deadline.is_some()
+                && !sched.is_paused(),
+            std::time::Instant::now(),
+        );
+        let mut ipc_commands = Vec::new();
+        for request in rx_ipc.try_iter() {
+            match request {
+                Request::Snooze => ipc_commands.push(Action::Snooze),
+                Request::Skip => ipc_commands.push(Action::Skip),
+                Request::BreakNow => ipc_commands.push(Action::StartBreak),
+                Request::Pause(minutes) => {
+                    if !paused_by_user {
+                        ipc_commands.push(Action::Pause);
+                    }
+                    pause_until = minutes.map(|m| {
+                        std::time::Instant::now() + std::time::Duration::from_secs(m * 60)
+                    });
+                }
+                Request::Resume => {
+                    if paused_by_user {
+                        ipc_commands.push(Action::Pause);
+                    }
+                }
+                // Answered by the ipc thread itself.
+                Request::Status { .. } | Request::Capabilities => {}
+                Request::ExcludeOutput(output) => {
+                    info!("Output excluded: {output}");
+                    locker.exclude_output(&output);
+                }
+                Request::Set(setting, value) => {
+                    info!("Set {} to {value}", setting.key());
+                    let secs = std::time::Duration::from_secs;
+                    match setting {
+                        Setting::IntervalMinutes => sched.set_interval(secs(value * 60)),
+                        Setting::BreakSeconds => sched.cfg.break_len = secs(value),
+                        Setting::SnoozeBaseSeconds => sched.cfg.snooze_base = secs(value),
+                        Setting::MaxSnoozes => {
+                            sched.cfg.max_snoozes =
+                                u32::try_from(value).ok().filter(|max| *max > 0);
+                        }
+                    }
+                }
+                Request::Note(Some(text)) => {
+                    info!("Note Added ({text})");
+                    if notes.len() == MAX_NOTES {
+                        notes.remove(0);
+                    }
+                    notes.push(text);
+                }
+                Request::Note(None) => {
+                    info!("Notes Cleared");
+                    notes.clear();
+                }
+                Request::Tag(tag) => {
+                    match &tag {
+                        Some(tag) => info!("Tagged ({tag})"),
+                        None => info!("Tag Cleared"),
+                    }
+                    if let Some(event) = focus.set_tag(tag, std::time::Instant::now()) {
+                        record_stats(&event);
+                    }
+                }
+                Request::IncludeOutput(output) => {
+                    info!("Output included: {output}");
+                    if let Err(err) = locker.include_output(&output) {
+                        error!("include output failed: {err}");
+                    }
+                }
+            }
+        }
+        if paused_by_user && pause_until.is_some_and(|at| at <= std::time::Instant::now()) {
+            ipc_commands.push(Action::Pause);
+        }
+
+        let drift = drift_watch.check();
+        if drift.jump >= drift::THRESHOLD {
+            info!(
+                "Clock Jump (wall clock set {} by {}, timers unaffected)",
+                if drift.jump_back { "back" } else { "forward" },
+                fmt_duration(drift.jump)
+            );
+        }
+        if drift.asleep >= drift::THRESHOLD {
             let lock_paused =
                 sched.phase == Phase::Working && sched.deadline.is_none() && !sched.is_paused();
-            if elapsed >= sched.break_duration() && !lock_paused {
-                sched.finish_and_restart();
-                if locker.is_locked() {
-                    locker.start_fade_out();
-                }
-                println!(
-                    "Timer Reset (system idle for {}, next in {})",
-                    fmt_duration(elapsed),
-                    fmt_duration(sched.interval_duration())
+            match args.missing_time {
+                _ if lock_paused => {}
+                MissingTime::Break if drift.asleep >= sched.break_duration() => {
+                    sched.finish_and_restart();
+                    if locker.is_locked() {
+                        locker.start_fade_out();
+                    }
+                    info!(
+                        "Timer Reset (system suspended for {}, next in {})",
+                        fmt_duration(drift.asleep),
+                        fmt_duration(sched.interval_duration())
+                    );
+                }
+                MissingTime::Break => info!(
+                    "Timer Held (system suspended for {})",
+                    fmt_duration(drift.asleep)
+                ),
+                MissingTime::Work => {
+                    sched.charge_work(drift.asleep);
+                    info!(
+                        "Timer Advanced (system suspended for {}, counted as work)",
+                        fmt_duration(drift.asleep)
+                    );
+                }
+            }
+        }
+
+        let now = chrono::Local::now();
+        if *now.offset() != utc_offset {
+            info!("Time Zone Changed (UTC{utc_offset} to UTC{})", now.offset());
+            utc_offset = *now.offset();
+            if let Some(workday) = &workday
+                && off_hours_until.is_some()
+            {
+                off_hours_until = match workday.off_hours_left(now.naive_local()) {
+                    Some(end) => local_datetime(end),
+                    None => Some(now),
+                };
+                info!("Off Hours (until {})", fmt_clock(off_hours_until));
+            }
+        }
+        if let Some(workday) = &workday {
+            let working = matches!(sched.phase, Phase::Working | Phase::Snoozing);
+            if off_hours_until.is_some_and(|until| now >= until) {
+                off_hours_until = None;
+                if working && sched.is_paused() {
+                    sched.finish_and_restart();
+                }
+                info!(
+                    "Workday Started (next in {})",
+                    fmt_duration(sched.time_left().unwrap_or(sched.interval_duration()))
                 );
+            } else if off_hours_until.is_none() && !wrapping_up && workday.is_off_hours(now.time())
+            {
+                // A break under way stands in for the wrap-up, and a paused
+                // timer goes straight to off-hours.
+                wrapping_up = true;
+                if working && !sched.is_paused() {
+                    sched.wrap_up();
+                    info!("Workday Over (wrap-up break)");
+                }
+            }
+            if wrapping_up && sched.phase == Phase::Working {
+                wrapping_up = false;
+                off_hours_until = off_hours_end(workday);
+                info!("Off Hours (until {})", fmt_clock(off_hours_until));
             }
         }
-        last_wall = now_wall;
 
-        let inhibitors_active = inhibitors.is_active();
-        if inhibitors_active {
+        let gaming = args.game_policy != GamePolicy::Ignore && games.is_active();
+        let inhibitor = inhibitors
+            .source()
+            .or((gaming && args.game_policy == GamePolicy::Defer).then_some(InhibitorSource::Game));
+        let inhibitors_active = inhibitor.is_some();
+        if let Some(inhibitor) = inhibitor.filter(|_| !paused_by_user) {
+            if sched.pause_interval() {
+                info!("Timer Paused ({})", inhibitor.label());
+            }
+            last_inhibitor = Some(inhibitor);
+        } else if paused_by_user {
+            if sched.pause_interval() {
+                info!("Timer Paused (requested)");
+            }
+        } else if off_hours_until.is_some() {
             if sched.pause_interval() {
-                println!("Timer Paused (systemd inhibitor)");
+                info!("Timer Paused (off hours)");
             }
         } else if sched.resume_interval() {
             let next = sched.time_left().unwrap_or(sched.interval_duration());
-            println!(
-                "Timer Resumed (systemd inhibitor cleared, next in {})",
+            info!(
+                "Timer Resumed ({} cleared, next in {})",
+                last_inhibitor.map_or("inhibitor", InhibitorSource::label),
                 fmt_duration(next)
             );
         }
@@ -129,87 +700,478 @@ fn main() -> Result<()> {
         // Tick core scheduler
         sched.tick();
 
-        // Handle key events
-        if !locker.is_fading() {
-            for ev in rx_ui.try_iter() {
-                match (sched.phase, ev) {
-                    (Phase::LockedAwaitingAction, UiEvent::PressZ)
-                    | (Phase::OnBreak, UiEvent::PressZ) => {
-                        if sched.can_snooze() {
-                            let _d = sched.snooze();
-                            if locker.is_locked() {
-                                locker.start_fade_out();
-                            }
+        // Collect inputs and route them to actions for the current phase
+        let mut inputs: Vec<Vec<Input>> = rx_command
+            .try_iter()
+            .chain(ipc_commands)
+            .chain(rx_notify.try_iter())
+            .map(|action| vec![Input::Command(action)])
+            .collect();
+        for ev in rx_ui.try_iter() {
+            match ev {
+                UiEvent::KeyLayout(layout) => bindings.set_layout(layout),
+                UiEvent::Key(key) if key.is_modifier_only() => {}
+                // A digit names the reason and skips; anything else backs out.
+                UiEvent::Key(key) if skip_prompt => {
+                    skip_prompt = false;
+                    if let Some(KeyAction::Rating(choice)) = bindings.action(&key)
+                        && let Some(reason) = SkipReason::from_key(choice)
+                    {
+                        skip_reason = Some(reason);
+                        inputs.push(vec![Input::Command(Action::Skip)]);
+                    }
+                }
+                // Keys only arrive mid-fade with --fast-forward-keys. They
+                // finish the fade rather than act on a prompt that is not fully
+                // shown yet; only the start key goes straight to the break.
+                UiEvent::Key(key) if locker.is_fading() => match bindings.action(&key) {
+                    Some(KeyAction::StartBreak) => {
+                        inputs.push(vec![Input::Key(KeyAction::StartBreak)])
+                    }
+                    _ => locker.finish_fade_in(),
+                },
+                UiEvent::Key(key) => {
+                    let mut key_inputs = vec![Input::AnyKey];
+                    key_inputs.extend(bindings.action(&key).map(Input::Key));
+                    inputs.push(key_inputs);
+                }
+                UiEvent::KeyHold(key) => {
+                    if let Some(action) = bindings.action(&key) {
+                        inputs.push(vec![Input::KeyHold(action)]);
+                    }
+                }
+                UiEvent::PointerGesture if locker.is_fading() => {}
+                UiEvent::PointerGesture => inputs.push(vec![Input::PointerGesture]),
+            }
+        }
+        for event_inputs in inputs {
+            for action in actions.route(sched.phase, &event_inputs) {
+                match action {
+                    Action::Snooze if sched.can_snooze() => {
+                        let _d = sched.snooze();
+                        if locker.is_locked() {
+                            locker.start_fade_out();
                         }
                     }
-                    (Phase::BreakFinished, UiEvent::PressEnter)
-                    | (Phase::BreakFinished, UiEvent::PointerClick)
-                    | (Phase::BreakFinished, UiEvent::AnyKey) => {
+                    Action::Snooze => {}
+                    // Cards take no keys, so they cannot answer the question.
+                    Action::Skip
+                        if args.skip_reason
+                            && event_inputs.contains(&Input::Key(KeyAction::Skip))
+                            && !locker.is_cards_only() =>
+                    {
+                        skip_prompt = true;
+                    }
+                    Action::Skip => {
+                        sched.skip_break();
                         if locker.is_locked() {
                             locker.start_fade_out();
                         }
                     }
-                    _ => {}
+                    Action::StartBreak => {
+                        paused_by_user = false;
+                        if sched.phase == Phase::LockedAwaitingAction {
+                            sched.start_break();
+                            locker.finish_fade_in();
+                        } else {
+                            sched.break_now();
+                        }
+                    }
+                    // Enter and the rating keys are meant for the overlay; any
+                    // other key may have been typed for the app underneath.
+                    Action::Dismiss
+                        if locker.is_locked()
+                            && !key_toast.is_zero()
+                            && event_inputs.contains(&Input::AnyKey)
+                            && !event_inputs.iter().any(|input| {
+                                matches!(
+                                    input,
+                                    Input::Key(KeyAction::Continue | KeyAction::Rating(_))
+                                )
+                            }) =>
+                    {
+                        key_toast_since.get_or_insert_with(std::time::Instant::now);
+                        locker.release_input();
+                    }
+                    Action::Dismiss => {
+                        if locker.is_locked() {
+                            locker.start_fade_out();
+                        }
+                    }
+                    Action::Pause => {
+                        paused_by_user = !paused_by_user;
+                        if !paused_by_user && !inhibitors_active && sched.resume_interval() {
+                            let next = sched.time_left().unwrap_or(sched.interval_duration());
+                            info!("Timer Resumed (requested, next in {})", fmt_duration(next));
+                        }
+                    }
+                    Action::ExtendBreak => sched.extend_break(EXTEND_BREAK_BY),
+                    Action::FinishEarly => {
+                        let _ = sched.finish_early();
+                    }
+                    Action::Rate(rating) => record_stats(&StatsEvent::Feedback { rating }),
                 }
             }
         }
+        if !paused_by_user {
+            pause_until = None;
+        }
 
+        if sched.phase == Phase::LockedAwaitingAction {
+            due_since.get_or_insert_with(std::time::Instant::now);
+        } else {
+            due_since = None;
+        }
+        // How loud the reminder has got; a break under way always gets the overlay.
+        let channel = match due_since {
+            Some(since) => escalation.channel_at(since.elapsed()),
+            None => Some(Channel::Overlay),
+        };
+        let screen = match channel {
+            Some(Channel::Notify) | None => None,
+            Some(_) if gaming && args.game_policy == GamePolicy::Remind => Some(Channel::Card),
+            screen => screen,
+        };
+        // A micro-break fades in over the work and out again by itself; a
+        // break that comes due takes its place.
+        let micro_left = sched.micro_break_left();
+        if locker.is_micro_break() && micro_left.is_none() {
+            if matches!(sched.phase, Phase::Working | Phase::Snoozing) {
+                locker.start_fade_out();
+            } else {
+                locker.unlock();
+            }
+        }
+        if micro_left.is_some() && !locker.is_locked() {
+            locker.show_micro_break()?;
+        }
+        // The countdown card covers the last minute of work and gives way
+        // to the break.
+        let work_left = (args.osd_countdown
+            && matches!(sched.phase, Phase::Working | Phase::Snoozing)
+            && !sched.is_paused())
+        .then(|| sched.time_left())
+        .flatten()
+        .filter(|left| *left <= OSD_COUNTDOWN);
+        if locker.is_osd() && work_left.is_none() {
+            locker.unlock();
+        }
+        if work_left.is_some() && !locker.is_locked() {
+            locker.show_osd()?;
+        }
         if matches!(
             sched.phase,
             Phase::LockedAwaitingAction | Phase::OnBreak | Phase::BreakFinished
-        ) && !locker.is_locked()
-        {
-            locker.lock()?;
+        ) {
+            if card_stage && screen > Some(Channel::Card) && locker.is_locked() {
+                locker.unlock();
+            }
+            if screen.is_some() && !locker.is_locked() {
+                card_stage = screen == Some(Channel::Card);
+                locker.set_cards_only(card_stage);
+                locker.set_dim(screen == Some(Channel::Dim));
+                locker.lock()?;
+                if sched.phase == Phase::LockedAwaitingAction {
+                    locker.start_fade_in();
+                }
+            }
+            locker.set_dim(screen == Some(Channel::Dim) || released_while_away);
         }
 
-        if sched.phase == Phase::LockedAwaitingAction && last_phase != Phase::LockedAwaitingAction {
-            locker.start_fade_in();
+        let mut phase_changed = false;
+        for event in sched_events.try_iter() {
+            log_event(&event, &sched);
+            let mut away = None;
+            if let (SchedulerEvent::PhaseChanged { to, .. }, Some(meter)) =
+                (&event, &mut away_meter)
+            {
+                match to {
+                    Phase::OnBreak => meter.start_break(std::time::Instant::now()),
+                    Phase::BreakFinished => away = meter.finish_break(std::time::Instant::now()),
+                    _ => {}
+                }
+            }
+            record_event_stats(&event, &sched, away, &mut skip_reason, &mut today);
+            if let SchedulerEvent::PhaseChanged {
+                to: Phase::LockedAwaitingAction,
+                ..
+            } = event
+                && let Some(event) = focus.take(std::time::Instant::now())
+            {
+                record_stats(&event);
+            }
+            if let SchedulerEvent::PhaseChanged { from, to, kind } = event {
+                phase_changed = true;
+                // A snoozed break keeps its notes; one that ended or was
+                // skipped takes them with it.
+                if matches!(from, Phase::LockedAwaitingAction | Phase::OnBreak)
+                    && matches!(to, Phase::BreakFinished | Phase::Working)
+                {
+                    notes.clear();
+                }
+                if to == Phase::LockedAwaitingAction {
+                    // A snoozed break keeps its headline; one pushed back
+                    // while working is still new.
+                    if from != Phase::Snoozing {
+                        headline = headlines.next(chrono::Local::now().date_naive());
+                        if let Err(err) = headlines.save() {
+                            rate_limited("headline state save failed", format!("{err:#}"));
+                        }
+                    }
+                    locker.set_colors(break_colors(&args, kind));
+                    if locker.is_locked() {
+                        locker.start_fade_in();
+                    }
+                }
+            }
+            if let SchedulerEvent::Deferred { .. } = event {
+                deferred_deadline = sched.deadline;
+            }
         }
 
-        if sched.phase == Phase::OnBreak && last_phase != Phase::OnBreak
-            && let Some(audio) = &audio {
-                audio.play_start();
+        if let Some(version) = rx_update.try_iter().last() {
+            update_available = Some(version);
+        }
+        let status = Status {
+            update_available: update_available.clone(),
+            last_fade: locker.last_fade_frames(),
+            ..current_status(
+                &sched,
+                paused_by_user,
+                pause_until,
+                off_hours_until,
+                inhibitor,
+                deferred_deadline,
+                &mut today,
+            )
+        };
+        let status_json = status.to_json();
+        // Every pass, so a message held back by the throttle still goes out.
+        if let Some(background) = &mut background
+            && let Err(err) = background.set_status(&status.tooltip())
+        {
+            rate_limited("background status update failed", format!("{err:#}"));
+        }
+        if status_json != last_status_json {
+            if let Err(err) = write_status_file(&status) {
+                rate_limited("status file write failed", format!("{err:#}"));
             }
-
-        if sched.phase == Phase::BreakFinished && last_phase != Phase::BreakFinished
-            && let Some(audio) = &audio {
-                audio.play_end();
+            last_status_json = status_json;
+            *shared_status.lock().unwrap_or_else(PoisonError::into_inner) = status;
+            if let Some(bus) = &status_bus
+                && let Err(err) = bus.changed()
+            {
+                rate_limited("D-Bus status update failed", format!("{err:#}"));
             }
+        }
+
+        if !matches!(sched.phase, Phase::LockedAwaitingAction | Phase::OnBreak) {
+            skip_prompt = false;
+        }
+
+        if args.debug_overlay && locker.is_locked() {
+            let left = sched.time_left().map_or_else(
+                || "no timer".to_string(),
+                |left| format!("{} left", fmt_duration(left)),
+            );
+            locker.set_debug_info(vec![
+                format!(
+                    "phase {:?}, {left}{}",
+                    sched.phase,
+                    if sched.is_paused() { ", paused" } else { "" }
+                ),
+                format!(
+                    "snoozes {}, due for {}, reminder {}",
+                    sched.snooze_count,
+                    due_since
+                        .map_or_else(|| "-".to_string(), |since| fmt_duration(since.elapsed())),
+                    channel.map_or("none", |channel| channel.label())
+                ),
+            ]);
+        }
 
         // Update overlay UI mode (only meaningful when locked)
-        if locker.is_locked() {
+        if sched.phase != Phase::BreakFinished {
+            key_toast_since = None;
+        }
+        if locker.is_locked() && key_toast_since.is_some() {
+            locker.set_mode(UiMode::KeyCaptured);
+        } else if locker.is_locked() && skip_prompt {
+            let choices: Vec<String> = SkipReason::ALL
+                .iter()
+                .enumerate()
+                .map(|(i, reason)| format!("{} {}", i + 1, reason.label()))
+                .collect();
+            locker.set_mode(UiMode::SkipReason {
+                choices: choices.join(" · "),
+            });
+        } else if locker.is_locked() {
             match sched.phase {
                 Phase::LockedAwaitingAction => {
                     let break_secs = sched.break_duration().as_secs();
                     locker.set_mode(UiMode::BreakDue {
                         break_secs,
                         snooze_count: sched.snooze_count,
+                        long_break: sched.is_long_break(),
+                        cycle: sched.break_in_cycle(),
+                        carried_secs: sched.carried_over.as_secs(),
+                        hints: if keys_before_break {
+                            key_hints(&actions, sched.phase, &bindings, |action| {
+                                hint_applies(&sched, action)
+                            })
+                        } else {
+                            Vec::new()
+                        },
+                        variant: sched.variant,
+                        headline: headline.clone(),
+                        message: break_message(&args, &sched),
+                        notes: notes.clone(),
                     });
                 }
                 Phase::OnBreak => {
                     let left = sched.time_left().map(|d| d.as_secs()).unwrap_or(0);
                     locker.set_mode(UiMode::OnBreak {
                         secs_left: left,
+                        ends_at: sched.deadline.unwrap_or_else(std::time::Instant::now),
+                        break_secs: sched.break_duration().as_secs(),
                         snooze_count: sched.snooze_count,
+                        long_break: sched.is_long_break(),
+                        cycle: sched.break_in_cycle(),
+                        message: break_message(&args, &sched),
+                        notes: notes.clone(),
+                        hints: key_hints(&actions, sched.phase, &bindings, |action| {
+                            hint_applies(&sched, action)
+                        }),
                     });
                 }
                 Phase::BreakFinished => {
-                    locker.set_mode(UiMode::BreakFinished);
+                    locker.set_mode(UiMode::BreakFinished {
+                        feedback_prompt: args.feedback_prompt,
+                        hints: key_hints(&actions, sched.phase, &bindings, |action| {
+                            hint_applies(&sched, action)
+                        }),
+                    });
+                }
+                Phase::Working | Phase::Snoozing => {
+                    if locker.is_micro_break() {
+                        if let Some(left) = micro_left {
+                            locker.set_mode(UiMode::MicroBreak {
+                                secs_left: left.as_secs(),
+                                message: args.micro_break_message.clone(),
+                            });
+                        }
+                    } else if let Some(left) = work_left {
+                        locker.set_mode(UiMode::WorkEnding {
+                            secs_left: left.as_secs(),
+                        });
+                    }
                 }
-                _ => {}
             }
         }
 
+        let notice = if locker.backend() == Backend::NotifyOnly || channel == Some(Channel::Notify)
+        {
+            break_notice(&sched)
+        } else {
+            None
+        }
+        .or_else(|| warning_notice(&args, &sched))
+        .or_else(|| {
+            // Where no overlay can leave the keyboard alone.
+            micro_left
+                .filter(|_| !locker.is_micro_break())
+                .map(|left| Notice {
+                    summary: "Micro-break".to_string(),
+                    body: format!("{} ({})", args.micro_break_message, fmt_duration(left)),
+                    persistent: true,
+                    actions: Vec::new(),
+                })
+        });
+        if let Some(notifier) = &mut notifier
+            && let Err(err) = notifier.update(notice)
+        {
+            rate_limited("notification failed", format!("{err:?}"));
+        }
+
         // Fade updates and auto-dismiss when finished.
         if locker.is_locked() {
-            if sched.phase == Phase::LockedAwaitingAction && locker.take_fade_in_complete() {
-                sched.start_break();
+            if sched.phase != Phase::LockedAwaitingAction {
+                break_due_since = None;
+            } else if locker.take_fade_in_complete() {
+                break_due_since = Some(std::time::Instant::now());
+            }
+            if let Some(since) = break_due_since
+                && since.elapsed() >= break_due_timeout
+                && due_since.is_none_or(|due| escalation.is_final(due.elapsed()))
+            {
+                // Cards take no keys, so a break shown only on cards cannot
+                // wait; notification buttons stand in for keys.
+                let policy = if locker.is_cards_only() && notifier.is_none() {
+                    BreakDuePolicy::Start
+                } else {
+                    args.break_due_policy
+                };
+                match policy {
+                    BreakDuePolicy::Start => {
+                        break_due_since = None;
+                        sched.start_break();
+                    }
+                    BreakDuePolicy::Wait => {}
+                    BreakDuePolicy::Snooze if sched.snooze_count == 0 && sched.can_snooze() => {
+                        break_due_since = None;
+                        let _d = sched.snooze();
+                        locker.start_fade_out();
+                    }
+                    BreakDuePolicy::Snooze => {
+                        break_due_since = None;
+                        sched.start_break();
+                    }
+                }
             }
             if matches!(sched.phase, Phase::LockedAwaitingAction | Phase::OnBreak)
                 && !locker.is_fading()
+                && !locker.is_dimmed()
             {
                 locker.ensure_input_capture();
             }
+            // Cards cannot be dismissed by hand, so the break ends on its own.
+            if sched.phase == Phase::BreakFinished && locker.is_cards_only() && !locker.is_fading()
+            {
+                locker.start_fade_out();
+            }
+            if sched.phase != Phase::BreakFinished {
+                released_while_away = false;
+            } else if dismiss_on_return
+                && !released_while_away
+                && !locker.is_fading()
+                && away_meter.as_ref().is_some_and(AwayMeter::is_away)
+            {
+                // Dimmed, so it is plain the overlay no longer takes keys.
+                locker.release_input();
+                locker.set_dim(true);
+                released_while_away = true;
+            }
+            if key_toast_since.is_some_and(|since| since.elapsed() >= key_toast)
+                && !locker.is_fading()
+            {
+                locker.start_fade_out();
+            }
+            // Nobody came back to dismiss it; give the keyboard back.
+            if sched.phase != Phase::BreakFinished {
+                break_finished_since = None;
+            } else if !break_finished_timeout.is_zero()
+                && !locker.is_fading()
+                && break_finished_since
+                    .get_or_insert_with(std::time::Instant::now)
+                    .elapsed()
+                    >= break_finished_timeout
+            {
+                info!(
+                    "Break Screen Timed Out (unattended for {})",
+                    fmt_duration(break_finished_timeout)
+                );
+                locker.start_fade_out();
+            }
             let fade_out_done = locker.update_fade();
             if fade_out_done {
                 locker.unlock();
@@ -226,71 +1188,581 @@ fn main() -> Result<()> {
             locker.pump()?;
         }
 
-        if sched.phase != last_phase {
-            match sched.phase {
-                Phase::LockedAwaitingAction => {
-                    println!(
-                        "Break Starting (duration {})",
-                        fmt_duration(sched.break_duration())
-                    );
-                }
-                Phase::Snoozing => {
-                    let next = sched.time_left().unwrap_or(sched.cfg.snooze_min);
-                    println!("Snoozed (break in {})", fmt_duration(next));
-                }
-                Phase::BreakFinished => {
-                    println!(
-                        "Break Complete (next in {})",
-                        fmt_duration(sched.interval_duration())
-                    );
-                }
-                _ => {}
+        if last_save.elapsed() >= state::save_interval() || phase_changed {
+            if let Err(err) = state::save_scheduler(&sched) {
+                rate_limited("state save failed", err);
             }
+            last_save = std::time::Instant::now();
         }
 
-        if last_save.elapsed() >= state::save_interval() || sched.phase != last_phase {
-            if let Err(err) = state::save_scheduler(&sched) {
-                eprintln!("state save failed: {err}");
+        if locker.is_fading() {
+            std::thread::sleep(std::time::Duration::from_millis(fade_sleep_ms));
+        } else if locker.is_locked() || !matches!(sched.phase, Phase::Working | Phase::Snoozing) {
+            std::thread::sleep(std::time::Duration::from_millis(150));
+        } else {
+            // Nothing on screen: sleep until the scheduler or the inhibitor
+            // check is due, or a session lock change or command arrives. The
+            // saved state stores a deadline, so it does not go stale meanwhile.
+            let now = std::time::Instant::now();
+            let mut wake = sched.next_wakeup().map_or(inhibitors.next_check(), |at| {
+                at.min(inhibitors.next_check())
+            });
+            if args.game_policy != GamePolicy::Ignore {
+                wake = wake.min(games.next_check());
             }
-            last_save = std::time::Instant::now();
+            if let Some(at) = pause_until {
+                wake = wake.min(at);
+            }
+            // The status file, `ctl status` and D-Bus serve the status built
+            // on waking; keep the minutes they show current.
+            if let Some(at) = sched.next_minute_mark() {
+                wake = wake.min(at);
+            }
+            if args.osd_countdown
+                && !sched.is_paused()
+                && let Some(at) = sched
+                    .deadline
+                    .and_then(|deadline| deadline.checked_sub(OSD_COUNTDOWN))
+                    .filter(|at| *at > now)
+            {
+                wake = wake.min(at);
+            }
+            // Stay well under a break length, or a long sleep would look like
+            // the system having been idle for a whole break.
+            let timeout = wake
+                .saturating_duration_since(now)
+                .min(MAX_IDLE_SLEEP)
+                .min((sched.break_duration() / 2).max(std::time::Duration::from_millis(150)));
+            let mut select = Select::new();
+            select.recv(&rx_lock);
+            select.recv(&rx_command);
+            select.recv(&rx_ipc);
+            select.recv(&rx_idle);
+            select.recv(&rx_presence);
+            select.recv(&rx_notify);
+            select.recv(&rx_leader);
+            select.recv(&rx_update);
+            let _ = select.ready_timeout(timeout);
         }
+    }
+}
 
-        last_phase = sched.phase;
-        let sleep_ms = if locker.is_fading() {
-            fade_sleep_ms
+/// What every status frontend shows; holds only apply while working.
+fn current_status(
+    sched: &Scheduler,
+    paused_by_user: bool,
+    pause_until: Option<std::time::Instant>,
+    off_hours_until: Option<chrono::DateTime<chrono::Local>>,
+    inhibitor: Option<InhibitorSource>,
+    deferred_deadline: Option<std::time::Instant>,
+    today: &mut Today,
+) -> Status {
+    let working = matches!(sched.phase, Phase::Working | Phase::Snoozing);
+    let local_time = |at: std::time::Instant| {
+        let from_now = at.saturating_duration_since(std::time::Instant::now());
+        (chrono::Local::now() + chrono::TimeDelta::from_std(from_now).unwrap_or_default()).time()
+    };
+    let (hold, paused_until) = if !working {
+        (None, None)
+    } else if paused_by_user {
+        (Some(Hold::Requested), pause_until.map(local_time))
+    } else if let Some(until) = off_hours_until {
+        (Some(Hold::OffHours), Some(until.time()))
+    } else if let Some(source) = inhibitor {
+        (Some(Hold::Inhibitor(source)), None)
+    } else if sched.deadline.is_none() && !sched.is_paused() {
+        (Some(Hold::SessionLocked), None)
+    } else if deferred_deadline.is_some() && sched.deadline == deferred_deadline {
+        (Some(Hold::Deferred), deferred_deadline.map(local_time))
+    } else {
+        (None, None)
+    };
+    Status {
+        phase: sched.phase,
+        seconds_left: sched.time_left().map(|left| left.as_secs()),
+        hold,
+        snoozes_left: sched.snoozes_left(),
+        snooze_count: sched.snooze_count,
+        today: today.totals(),
+        paused_until,
+        settings: Settings {
+            interval_minutes: sched.cfg.interval.as_secs() / 60,
+            break_seconds: sched.cfg.break_len.as_secs(),
+            snooze_base_seconds: sched.cfg.snooze_base.as_secs(),
+            max_snoozes: sched.cfg.max_snoozes.map_or(0, u64::from),
+        },
+        update_available: None,
+        last_fade: None,
+    }
+}
+
+/// When the off-hours that just began end, in local time.
+fn off_hours_end(workday: &Workday) -> Option<chrono::DateTime<chrono::Local>> {
+    local_datetime(workday.off_hours_end(chrono::Local::now().naive_local()))
+}
+
+/// The instant a local wall-clock time names. A time skipped by a DST change
+/// moves to the first valid time after it, and a repeated one is the first.
+fn local_datetime(end: chrono::NaiveDateTime) -> Option<chrono::DateTime<chrono::Local>> {
+    end.and_local_timezone(chrono::Local)
+        .earliest()
+        .or_else(|| {
+            (end + chrono::TimeDelta::hours(1))
+                .and_local_timezone(chrono::Local)
+                .earliest()
+        })
+}
+
+fn fmt_clock(at: Option<chrono::DateTime<chrono::Local>>) -> String {
+    at.map_or_else(
+        || "unknown".to_string(),
+        |at| at.format("%H:%M").to_string(),
+    )
+}
+
+/// The overlay's settings from the command line, for `backends`.
+fn locker_options(args: &Cli, backends: Vec<Backend>) -> LockerOptions {
+    LockerOptions {
+        exclude_outputs: args.exclude_outputs.clone(),
+        card_outputs: args.card_outputs.clone(),
+        layer: args.layer,
+        exclusive_zone: args.exclusive_zone,
+        margin: args.margin,
+        wake_on_pointer: args.wake_on_pointer,
+        fast_forward_keys: args.fast_forward_keys,
+        key_grace: std::time::Duration::from_millis(args.key_grace_ms),
+        follow_focus: args.follow_focus,
+        backends,
+        countdown: args.countdown,
+        countdown_gradient: args.countdown_gradient.clone(),
+        countdown_gradient_secs: args.countdown_gradient_seconds,
+        bar_thickness: args.bar_thickness,
+        bar_placement: args.bar_placement,
+        debug_overlay: args.debug_overlay,
+        card: Placement {
+            size: args.card_size,
+            anchor: args.card_anchor,
+            margin: args.card_margin,
+        },
+        osd: Placement {
+            size: args.osd_size,
+            anchor: args.osd_anchor,
+            margin: args.osd_margin,
+        },
+    }
+}
+
+/// `interlude test-overlay`: the overlay of a regular break, as the daemon
+/// would show it with the same options, for a made-up break of `seconds`.
+/// Nothing is scheduled or recorded.
+fn test_overlay(args: &Cli, seconds: u64) -> Result<()> {
+    let backends: Vec<Backend> = Capabilities::probe()
+        .available_backends(&args.backends)
+        .into_iter()
+        .filter(|backend| *backend != Backend::NotifyOnly)
+        .collect();
+    if backends.is_empty() {
+        anyhow::bail!("no overlay backend is available");
+    }
+    let (tx_ui, rx_ui) = unbounded();
+    let mut locker = Locker::new(
+        tx_ui,
+        break_colors(args, BreakKind::Regular),
+        locker_options(args, backends),
+    )?;
+    info!("Overlay backend: {}", locker.backend().label());
+    let bindings = Bindings::new(args.media_keys);
+    let actions = ActionTable::new(false);
+    let length = std::time::Duration::from_secs(seconds.max(1));
+    let fade_sleep = std::time::Duration::from_millis((1000 / args.fade_fps.max(1) as u64).max(1));
+    let started = std::time::Instant::now();
+    let mut closing = false;
+    locker.lock()?;
+    locker.start_fade_in();
+    loop {
+        let left = length.saturating_sub(started.elapsed());
+        locker.set_mode(UiMode::OnBreak {
+            secs_left: left.as_secs(),
+            ends_at: started + length,
+            break_secs: length.as_secs(),
+            snooze_count: 0,
+            long_break: false,
+            cycle: None,
+            message: args.break_message.clone(),
+            notes: Vec::new(),
+            hints: key_hints(&actions, Phase::OnBreak, &bindings, |action| {
+                action != Action::FinishEarly
+            }),
+        });
+        let key = rx_ui
+            .try_iter()
+            .any(|ev| matches!(ev, UiEvent::Key(key) if !key.is_modifier_only()));
+        if (key || left.is_zero()) && !closing {
+            closing = true;
+            locker.start_fade_out();
+        }
+        if locker.update_fade() {
+            locker.unlock();
+            return Ok(());
+        }
+        locker.pump()?;
+        std::thread::sleep(if locker.is_fading() {
+            fade_sleep
         } else {
-            150
+            std::time::Duration::from_millis(150)
+        });
+    }
+}
+
+fn sound_map(args: &Cli) -> SoundMap {
+    let mut sounds = SoundMap::default();
+    if args.announce_speech {
+        sounds.set(Cue::Milestone, Some(Sound::Speech));
+    }
+    if let Some(path) = &args.start_sound {
+        sounds.set(Cue::BreakStart, Some(Sound::File(path.clone())));
+    }
+    if let Some(path) = &args.end_sound {
+        sounds.set(Cue::BreakEnd, Some(Sound::File(path.clone())));
+    }
+    if let Some(path) = &args.long_break_start_sound {
+        sounds.set_for(BreakKind::Long, Cue::BreakStart, Sound::File(path.clone()));
+    }
+    if let Some(path) = &args.long_break_end_sound {
+        sounds.set_for(BreakKind::Long, Cue::BreakEnd, Sound::File(path.clone()));
+    }
+    sounds
+}
+
+/// `interlude test-sound`: plays the sounds as configured, one after another
+/// without a cue, and says which is playing.
+fn test_sound(args: &Cli, cue: Option<SoundCue>, long: bool) -> Result<()> {
+    let sounds = sound_map(args);
+    let kind = if long {
+        BreakKind::Long
+    } else {
+        BreakKind::Regular
+    };
+    let cues = match cue {
+        Some(cue) => vec![cue],
+        None => SoundCue::value_variants().to_vec(),
+    };
+    for cue in cues {
+        let name = cue
+            .to_possible_value()
+            .map_or_else(String::new, |value| value.get_name().to_string());
+        logging::output(format_args!("Playing {name}\n"));
+        let audio_cue = match cue {
+            SoundCue::Start => Cue::BreakStart,
+            SoundCue::End => Cue::BreakEnd,
+            SoundCue::Warning => Cue::Warning,
+            SoundCue::Milestone => Cue::Milestone,
         };
-        std::thread::sleep(std::time::Duration::from_millis(sleep_ms));
+        audio::play_test(&sounds, audio_cue, kind).with_context(|| format!("play {name}"))?;
     }
+    Ok(())
 }
 
-fn parse_color(input: &str) -> Option<[u8; 4]> {
-    let hex = input.trim();
-    if !hex.starts_with('#') {
-        return None;
+/// Overlay colors for a break of `kind`.
+fn break_colors(args: &Cli, kind: BreakKind) -> UiColors {
+    match kind {
+        BreakKind::Regular => UiColors {
+            background: args.background,
+            foreground: args.foreground,
+        },
+        BreakKind::Long => UiColors {
+            background: args.long_break_background.unwrap_or(args.background),
+            foreground: args.long_break_foreground.unwrap_or(args.foreground),
+        },
     }
-    let hex = &hex[1..];
-    let expanded = match hex.len() {
-        3 => {
-            let chars: Vec<char> = hex.chars().collect();
-            format!(
-                "{}{}{}{}{}{}FF",
-                chars[0], chars[0], chars[1], chars[1], chars[2], chars[2]
+}
+
+/// The fixed overlay message for the break `sched` has due, if one is set:
+/// the planning or wrap-up message, else the one for its kind.
+fn break_message(args: &Cli, sched: &Scheduler) -> Option<String> {
+    match sched.variant {
+        BreakVariant::Planning => return Some(args.planning_message.clone()),
+        BreakVariant::WrapUp => return Some(args.wrap_up_message.clone()),
+        BreakVariant::Rest => {}
+    }
+    match sched.break_kind() {
+        BreakKind::Regular => args.break_message.clone(),
+        BreakKind::Long => args
+            .long_break_message
+            .clone()
+            .or_else(|| args.break_message.clone()),
+    }
+}
+
+/// Footer hints for the keys that act in `phase`, e.g. "z: snooze", for
+/// the actions `applies` lets through.
+fn key_hints(
+    actions: &ActionTable,
+    phase: Phase,
+    bindings: &Bindings,
+    applies: impl Fn(Action) -> bool,
+) -> Vec<String> {
+    actions
+        .key_hints(phase)
+        .into_iter()
+        .filter(|hint| applies(hint.action))
+        .map(|hint| {
+            let hold = if hint.hold { "hold " } else { "" };
+            format!("{hold}{}: {}", bindings.label(hint.key), hint.action.hint())
+        })
+        .collect()
+}
+
+/// Whether a key for `action` would do anything right now. Ratings have a
+/// prompt of their own.
+fn hint_applies(sched: &Scheduler, action: Action) -> bool {
+    match action {
+        Action::Snooze => sched.can_snooze(),
+        Action::FinishEarly => sched.can_finish_early(),
+        Action::Rate(_) => false,
+        _ => true,
+    }
+}
+
+/// The notification for the current phase in notify-only mode.
+fn break_notice(sched: &Scheduler) -> Option<Notice> {
+    let mut actions = Vec::new();
+    let (summary, body) = match sched.phase {
+        Phase::LockedAwaitingAction => {
+            actions.push((Action::StartBreak, "Start break"));
+            if sched.can_snooze() {
+                actions.push((Action::Snooze, "Snooze"));
+            }
+            actions.push((Action::Skip, "Skip"));
+            let kind = if sched.is_long_break() {
+                "Long break"
+            } else {
+                "Break"
+            };
+            (
+                format!("{kind} due"),
+                format!("Time for a {} break", fmt_duration(sched.break_duration())),
+            )
+        }
+        Phase::OnBreak => {
+            if sched.can_finish_early() {
+                actions.push((Action::FinishEarly, "Finish"));
+            }
+            if sched.can_snooze() {
+                actions.push((Action::Snooze, "Snooze"));
+            }
+            actions.push((Action::Skip, "Skip"));
+            (
+                "On break".to_string(),
+                format!(
+                    "{} left",
+                    fmt_duration(sched.time_left().unwrap_or_default())
+                ),
             )
         }
-        6 => format!("{hex}FF"),
-        8 => hex.to_string(),
-        _ => return None,
+        Phase::BreakFinished => (
+            "Break complete".to_string(),
+            format!("Next break in {}", fmt_duration(sched.interval_duration())),
+        ),
+        Phase::Working | Phase::Snoozing => return None,
     };
-    if expanded.len() != 8 {
+    Some(Notice {
+        summary,
+        body,
+        persistent: sched.phase != Phase::BreakFinished,
+        actions,
+    })
+}
+
+/// The `--warning-notification` notice for the last stretch of work.
+fn warning_notice(args: &Cli, sched: &Scheduler) -> Option<Notice> {
+    let warning = std::time::Duration::from_secs(args.warning_seconds);
+    let left = sched.time_left()?;
+    if !args.warning_notification
+        || warning.is_zero()
+        || !matches!(sched.phase, Phase::Working | Phase::Snoozing)
+        || sched.is_paused()
+        || left > warning
+    {
         return None;
     }
-    let r = u8::from_str_radix(&expanded[0..2], 16).ok()?;
-    let g = u8::from_str_radix(&expanded[2..4], 16).ok()?;
-    let b = u8::from_str_radix(&expanded[4..6], 16).ok()?;
-    let a = u8::from_str_radix(&expanded[6..8], 16).ok()?;
-    Some([r, g, b, a])
+    let mut actions = vec![(Action::StartBreak, "Start now")];
+    if sched.can_snooze() {
+        actions.push((Action::Snooze, "Snooze"));
+    }
+    Some(Notice {
+        summary: "Break soon".to_string(),
+        body: format!("Next break in {}", fmt_duration(left)),
+        persistent: true,
+        actions,
+    })
+}
+
+fn log_event(event: &SchedulerEvent, sched: &Scheduler) {
+    match *event {
+        SchedulerEvent::PhaseChanged { to, .. } => match to {
+            Phase::LockedAwaitingAction => info!(
+                "{} Starting (duration {})",
+                if sched.is_long_break() {
+                    "Long Break"
+                } else {
+                    "Break"
+                },
+                fmt_duration(sched.break_duration())
+            ),
+            Phase::BreakFinished => match sched.ended_early {
+                Some(taken) => info!(
+                    "Break Ended Early after {} (next in {})",
+                    fmt_duration(taken),
+                    fmt_duration(sched.interval_duration())
+                ),
+                None => info!(
+                    "Break Complete (next in {})",
+                    fmt_duration(sched.interval_duration())
+                ),
+            },
+            _ => {}
+        },
+        SchedulerEvent::SnoozeTaken { duration, .. } => {
+            let break_in = sched.time_left().unwrap_or(duration);
+            info!("Snoozed (break in {})", fmt_duration(break_in));
+        }
+        SchedulerEvent::BreakSkipped { .. } => info!(
+            "Break Skipped (next in {})",
+            fmt_duration(sched.interval_duration())
+        ),
+        SchedulerEvent::Deferred { next_in } => {
+            info!("Break Deferred (next in {})", fmt_duration(next_in));
+        }
+        SchedulerEvent::BreakWarning { break_in } => {
+            info!("Break Soon (in {})", fmt_duration(break_in));
+        }
+        SchedulerEvent::Milestone { left } => {
+            info!("Break in {}", fmt_duration(left));
+        }
+        SchedulerEvent::MicroBreakStarted { duration } => {
+            info!("Micro-Break Starting (duration {})", fmt_duration(duration));
+        }
+        SchedulerEvent::MicroBreakFinished => info!("Micro-Break Finished"),
+    }
+}
+
+/// `away` is the away score of a break that just finished.
+fn record_event_stats(
+    event: &SchedulerEvent,
+    sched: &Scheduler,
+    away: Option<u8>,
+    skip_reason: &mut Option<SkipReason>,
+    today: &mut Today,
+) {
+    let stats_event = match *event {
+        // A break coming back after a snooze was already counted as due.
+        SchedulerEvent::PhaseChanged {
+            from,
+            to: Phase::LockedAwaitingAction,
+            ..
+        } if from != Phase::Snoozing => StatsEvent::BreakDue,
+        SchedulerEvent::PhaseChanged {
+            to: Phase::BreakFinished,
+            ..
+        } => match sched.ended_early {
+            Some(taken) => StatsEvent::BreakPartial {
+                seconds: taken.as_secs(),
+                away,
+            },
+            None => StatsEvent::BreakTaken {
+                seconds: sched.break_duration().as_secs(),
+                away,
+            },
+        },
+        SchedulerEvent::SnoozeTaken { .. } => StatsEvent::Snoozed,
+        SchedulerEvent::BreakSkipped { .. } => StatsEvent::Skipped {
+            reason: skip_reason.take(),
+        },
+        _ => return,
+    };
+    today.add(&stats_event);
+    record_stats(&stats_event);
+}
+
+fn run_command(command: &Command, args: &Cli) -> Result<()> {
+    match command {
+        Command::Stats { command } => {
+            let records = stats::load()?;
+            match command {
+                StatsCommand::Chart => {
+                    let today = chrono::Local::now().date_naive();
+                    logging::output(stats::render_chart(&records, today, stats::local_date));
+                }
+                StatsCommand::Metrics => logging::output(stats::render_metrics(&records)),
+                StatsCommand::Tags => {
+                    let today = chrono::Local::now().date_naive();
+                    logging::output(stats::render_tags(&records, today, stats::local_date));
+                }
+            }
+        }
+        Command::Ctl { command } => {
+            let request = match command {
+                CtlCommand::ExcludeOutput { output } => Request::ExcludeOutput(output.clone()),
+                CtlCommand::IncludeOutput { output } => Request::IncludeOutput(output.clone()),
+                CtlCommand::Snooze => Request::Snooze,
+                CtlCommand::Skip => Request::Skip,
+                CtlCommand::BreakNow => Request::BreakNow,
+                CtlCommand::Pause { minutes } => Request::Pause(*minutes),
+                CtlCommand::Resume => Request::Resume,
+                CtlCommand::Status { json } => Request::Status { json: *json },
+                CtlCommand::Capabilities => Request::Capabilities,
+                CtlCommand::Note { text } => Request::Note(text.clone()),
+                CtlCommand::Tag { tag } => Request::Tag(tag.clone()),
+                CtlCommand::Set { option, value } => Request::Set(
+                    Setting::parse(option)
+                        .with_context(|| format!("{option} cannot be changed while running"))?,
+                    *value,
+                ),
+            };
+            let reply = ipc::send(&request)?;
+            logging::output(format_args!("{reply}\n"));
+        }
+        Command::Tui => tui::run()?,
+        Command::TestOverlay { seconds } => test_overlay(args, *seconds)?,
+        Command::TestSound { cue, long } => test_sound(args, *cue, *long)?,
+        Command::Init { force } => {
+            let path = paths::config_file(args.config.as_deref())
+                .context("no config directory; set $HOME or use --config")?;
+            init::run(&path, *force)?;
+        }
+        Command::Config {
+            command: ConfigCommand::Migrate,
+        } => {
+            let path = paths::config_file(args.config.as_deref())
+                .context("no config directory; set $HOME or use --config")?;
+            let (changes, backup) = config::migrate_file(&path, Cli::configurable)?;
+            let Some(backup) = backup else {
+                logging::output(format_args!("{} is up to date\n", path.display()));
+                return Ok(());
+            };
+            for change in &changes {
+                logging::output(format_args!("{change}\n"));
+            }
+            logging::output(format_args!(
+                "Migrated {}; the old file is {}\n",
+                path.display(),
+                backup.display()
+            ));
+        }
+        Command::EnableAutostart { kind } => {
+            let exe = std::env::current_exe().context("find the interlude executable")?;
+            autostart::enable(*kind, &exe, &autostart::daemon_args(std::env::args_os()))?;
+        }
+        Command::DisableAutostart { kind } => autostart::disable(*kind)?,
+    }
+    Ok(())
+}
+
+fn record_stats(event: &StatsEvent) {
+    if let Err(err) = stats::record(event) {
+        error!("stats write failed: {err}");
+    }
 }
 
 #[cfg(test)]
@@ -303,28 +1775,4 @@ mod tests {
         assert_eq!(fmt_duration(std::time::Duration::from_secs(61)), "01:01");
         assert_eq!(fmt_duration(std::time::Duration::from_secs(3599)), "59:59");
     }
-
-    #[test]
-    fn parse_color_accepts_rgb_hex() {
-        assert_eq!(parse_color("#000"), Some([0, 0, 0, 0xFF]));
-        assert_eq!(parse_color("#0fF"), Some([0x00, 0xFF, 0xFF, 0xFF]));
-    }
-
-    #[test]
-    fn parse_color_accepts_rrggbb_hex() {
-        assert_eq!(parse_color("#112233"), Some([0x11, 0x22, 0x33, 0xFF]));
-    }
-
-    #[test]
-    fn parse_color_accepts_rrggbbaa_hex() {
-        assert_eq!(parse_color("#11223344"), Some([0x11, 0x22, 0x33, 0x44]));
-    }
-
-    #[test]
-    fn parse_color_rejects_invalid_inputs() {
-        assert_eq!(parse_color("112233"), None);
-        assert_eq!(parse_color("#1234"), None);
-        assert_eq!(parse_color("#ZZZ"), None);
-        assert_eq!(parse_color("#1122334455"), None);
-    }
 }
@@ -0,0 +1,400 @@
+//! Desktop notifications through `org.freedesktop.Notifications`.
+//!
+//! With the notify-only backend a break is one persistent notification,
+//! replaced in place as it counts down; its buttons come back as scheduler
+//! [`Action`]s. The countdown is refreshed every few seconds rather than on
+//! every tick, so notification daemons that animate replacements stay calm.
+//!
+//! Inside a Flatpak sandbox the same notices go through the Notification
+//! portal instead. It has no close signal, so a notice the user dismisses
+//! there stays gone until the next one.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::actions::Action;
+use crate::logging::error;
+use crate::portal;
+
+/// Notification action keys and the scheduler action each one requests.
+const ACTION_KEYS: &[(&str, Action)] = &[
+    ("start", Action::StartBreak),
+    ("snooze", Action::Snooze),
+    ("skip", Action::Skip),
+    ("finish", Action::FinishEarly),
+    ("dismiss", Action::Dismiss),
+];
+/// How long a notice that is not persistent stays up.
+const TRANSIENT_TIMEOUT_MS: i32 = 5000;
+/// Shortest gap between updates that only change the body text.
+const COUNTDOWN_REFRESH: Duration = Duration::from_secs(5);
+
+/// Id of our notification with the portal, which lets apps pick their own.
+const PORTAL_ID: &str = "interlude";
+/// Id of the notice that the daemon stopped, kept apart from the break's.
+const FATAL_PORTAL_ID: &str = "interlude-stopped";
+
+/// Who shows the notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    /// `org.freedesktop.Notifications`
+    Daemon,
+    /// `org.freedesktop.portal.Notification`
+    Portal,
+}
+
+/// What the notification should say.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notice {
+    pub summary: String,
+    pub body: String,
+    /// Buttons as `(action, label)`, in display order.
+    pub actions: Vec<(Action, &'static str)>,
+    /// Stays until replaced or closed, instead of expiring.
+    pub persistent: bool,
+}
+
+pub struct Notifier {
+    route: Route,
+    proxy: Proxy<'static>,
+    /// Id of the shown notification, 0 when none or after the daemon closed
+    /// it; shared with the signal listeners. The portal's is always 1.
+    id: Arc<AtomicU32>,
+    shown: Option<Notice>,
+    last_sent: Instant,
+}
+
+impl Notifier {
+    /// Connects to the session bus and forwards button presses on our
+    /// notification to `tx`.
+    pub fn new(tx: Sender<Action>) -> Result<Self> {
+        let connection = Connection::session().context("connect to session bus")?;
+        let route = if portal::is_sandboxed() {
+            Route::Portal
+        } else {
+            Route::Daemon
+        };
+        let proxy = notifications_proxy(&connection, route)?;
+        let id = Arc::new(AtomicU32::new(0));
+        let listener = notifications_proxy(&connection, route)?;
+        let listener_id = Arc::clone(&id);
+        thread::Builder::new()
+            .name("notify-actions".to_string())
+            .spawn(move || {
+                let forwarded = match route {
+                    Route::Daemon => forward_actions(&listener, &listener_id, &tx),
+                    Route::Portal => forward_portal_actions(&listener, &listener_id, &tx),
+                };
+                if let Err(err) = forwarded {
+                    error!("notification actions unavailable: {err:?}");
+                }
+            })
+            .context("spawn notification action thread")?;
+        if route == Route::Portal {
+            return Ok(Self {
+                route,
+                proxy,
+                id,
+                shown: None,
+                last_sent: Instant::now(),
+            });
+        }
+        let listener = notifications_proxy(&connection, route)?;
+        let listener_id = Arc::clone(&id);
+        thread::Builder::new()
+            .name("notify-closed".to_string())
+            .spawn(move || {
+                if let Err(err) = track_closed(&listener, &listener_id) {
+                    error!("notification close tracking unavailable: {err:?}");
+                }
+            })
+            .context("spawn notification close thread")?;
+        Ok(Self {
+            route,
+            proxy,
+            id,
+            shown: None,
+            last_sent: Instant::now(),
+        })
+    }
+
+    /// Shows `notice` in place of the current one; `None` closes a persistent
+    /// notice and lets a transient one run out. A persistent notice the user
+    /// closed comes back on the next call.
+    pub fn update(&mut self, notice: Option<Notice>) -> Result<()> {
+        let closed = self.id.load(Ordering::Relaxed) == 0;
+        let Some(notice) = notice else {
+            if self.route == Route::Portal {
+                // Portal notices never expire, so transient ones go too.
+                if self.shown.take().is_some() && !closed {
+                    self.id.store(0, Ordering::Relaxed);
+                    self.proxy
+                        .call::<_, _, ()>("RemoveNotification", &(PORTAL_ID))
+                        .context("RemoveNotification failed")?;
+                }
+                return Ok(());
+            }
+            if self.shown.take().is_some_and(|shown| shown.persistent) && !closed {
+                let id = self.id.swap(0, Ordering::Relaxed);
+                self.proxy
+                    .call::<_, _, ()>("CloseNotification", &(id))
+                    .context("CloseNotification failed")?;
+            }
+            return Ok(());
+        };
+        if let Some(shown) = &self.shown {
+            let reopen = closed && shown.persistent;
+            if !reopen && !needs_refresh(shown, &notice, self.last_sent.elapsed()) {
+                return Ok(());
+            }
+        }
+        if self.route == Route::Portal {
+            self.proxy
+                .call::<_, _, ()>("AddNotification", &(PORTAL_ID, portal_fields(&notice)))
+                .context("AddNotification failed")?;
+            self.id.store(1, Ordering::Relaxed);
+            self.shown = Some(notice);
+            self.last_sent = Instant::now();
+            return Ok(());
+        }
+        let mut hints = HashMap::new();
+        if notice.persistent {
+            hints.insert("urgency", Value::U8(2));
+            hints.insert("resident", Value::Bool(true));
+        }
+        let timeout = if notice.persistent {
+            0
+        } else {
+            TRANSIENT_TIMEOUT_MS
+        };
+        let id: u32 = self
+            .proxy
+            .call(
+                "Notify",
+                &(
+                    "interlude",
+                    self.id.load(Ordering::Relaxed),
+                    "",
+                    notice.summary.as_str(),
+                    notice.body.as_str(),
+                    action_list(&notice.actions),
+                    hints,
+                    timeout,
+                ),
+            )
+            .context("Notify failed")?;
+        self.id.store(id, Ordering::Relaxed);
+        self.shown = Some(notice);
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+/// Shows `notice` on its own connection, for when the daemon is going away
+/// and a [`Notifier`] may never have been set up. Buttons are left out.
+pub fn send_once(notice: &Notice) -> Result<()> {
+    let connection = Connection::session().context("connect to session bus")?;
+    if portal::is_sandboxed() {
+        let fields = HashMap::from([
+            ("title", Value::from(notice.summary.as_str())),
+            ("body", Value::from(notice.body.as_str())),
+            ("priority", Value::from("urgent")),
+        ]);
+        return notifications_proxy(&connection, Route::Portal)?
+            .call::<_, _, ()>("AddNotification", &(FATAL_PORTAL_ID, fields))
+            .context("AddNotification failed");
+    }
+    let hints = HashMap::from([("urgency", Value::U8(2))]);
+    notifications_proxy(&connection, Route::Daemon)?
+        .call::<_, _, u32>(
+            "Notify",
+            &(
+                "interlude",
+                0u32,
+                "dialog-error",
+                notice.summary.as_str(),
+                notice.body.as_str(),
+                Vec::<&str>::new(),
+                hints,
+                if notice.persistent {
+                    0
+                } else {
+                    TRANSIENT_TIMEOUT_MS
+                },
+            ),
+        )
+        .context("Notify failed")?;
+    Ok(())
+}
+
+/// Whether `notice` should replace `shown`: right away when more than the
+/// body changed, otherwise once the countdown is due for a refresh.
+fn needs_refresh(shown: &Notice, notice: &Notice, since_sent: Duration) -> bool {
+    if shown == notice {
+        return false;
+    }
+    shown.summary != notice.summary
+        || shown.actions != notice.actions
+        || shown.persistent != notice.persistent
+        || since_sent >= COUNTDOWN_REFRESH
+}
+
+fn notifications_proxy(connection: &Connection, route: Route) -> Result<Proxy<'static>> {
+    if route == Route::Portal {
+        return portal::proxy(connection, "org.freedesktop.portal.Notification");
+    }
+    Proxy::new(
+        connection,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+    )
+    .context("create notifications proxy")
+}
+
+fn forward_actions(proxy: &Proxy, id: &AtomicU32, tx: &Sender<Action>) -> Result<()> {
+    let signals = proxy
+        .receive_signal("ActionInvoked")
+        .context("subscribe to ActionInvoked")?;
+    for msg in signals {
+        let (invoked, key): (u32, String) = msg.body().context("decode ActionInvoked signal")?;
+        if invoked != id.load(Ordering::Relaxed) {
+            continue;
+        }
+        if let Some(action) = action_for_key(&key)
+            && tx.send(action).is_err()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Like [`forward_actions`], for the portal's `ActionInvoked(id, action, parameter)`.
+fn forward_portal_actions(proxy: &Proxy, id: &AtomicU32, tx: &Sender<Action>) -> Result<()> {
+    let signals = proxy
+        .receive_signal("ActionInvoked")
+        .context("subscribe to ActionInvoked")?;
+    for msg in signals {
+        let (invoked, key, _parameter): (String, String, Vec<OwnedValue>) =
+            msg.body().context("decode ActionInvoked signal")?;
+        if invoked != PORTAL_ID || id.load(Ordering::Relaxed) == 0 {
+            continue;
+        }
+        if let Some(action) = action_for_key(&key)
+            && tx.send(action).is_err()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Forgets our notification id once the daemon closes it, whether it
+/// expired or the user dismissed it.
+fn track_closed(proxy: &Proxy, id: &AtomicU32) -> Result<()> {
+    let signals = proxy
+        .receive_signal("NotificationClosed")
+        .context("subscribe to NotificationClosed")?;
+    for msg in signals {
+        let (closed, _reason): (u32, u32) =
+            msg.body().context("decode NotificationClosed signal")?;
+        let _ = id.compare_exchange(closed, 0, Ordering::Relaxed, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// The flat `[key, label, key, label, ...]` list the Notify call takes.
+fn action_list(actions: &[(Action, &str)]) -> Vec<String> {
+    actions
+        .iter()
+        .filter_map(|(action, label)| Some([key_for_action(*action)?, label]))
+        .flatten()
+        .map(str::to_string)
+        .collect()
+}
+
+/// The notification dictionary `AddNotification` takes.
+fn portal_fields(notice: &Notice) -> HashMap<&'static str, Value<'_>> {
+    let buttons: Vec<HashMap<&str, Value>> = notice
+        .actions
+        .iter()
+        .filter_map(|(action, label)| {
+            Some(HashMap::from([
+                ("label", Value::from(*label)),
+                ("action", Value::from(key_for_action(*action)?)),
+            ]))
+        })
+        .collect();
+    let priority = if notice.persistent {
+        "urgent"
+    } else {
+        "normal"
+    };
+    HashMap::from([
+        ("title", Value::from(notice.summary.as_str())),
+        ("body", Value::from(notice.body.as_str())),
+        ("priority", Value::from(priority)),
+        ("buttons", Value::from(buttons)),
+    ])
+}
+
+fn key_for_action(action: Action) -> Option<&'static str> {
+    ACTION_KEYS
+        .iter()
+        .find(|(_, a)| *a == action)
+        .map(|(key, _)| *key)
+}
+
+fn action_for_key(key: &str) -> Option<Action> {
+    ACTION_KEYS
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, action)| *action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_keys_round_trip() {
+        for (key, action) in ACTION_KEYS {
+            assert_eq!(action_for_key(key), Some(*action));
+            assert_eq!(key_for_action(*action), Some(*key));
+        }
+        assert_eq!(action_for_key("default"), None);
+        assert_eq!(
+            action_list(&[
+                (Action::StartBreak, "Start break"),
+                (Action::Pause, "Pause"),
+                (Action::Skip, "Skip"),
+            ]),
+            vec!["start", "Start break", "skip", "Skip"]
+        );
+    }
+
+    #[test]
+    fn countdown_refreshes_are_throttled() {
+        let notice = |summary: &str, body: &str| Notice {
+            summary: summary.to_string(),
+            body: body.to_string(),
+            actions: vec![(Action::Skip, "Skip")],
+            persistent: true,
+        };
+        let shown = notice("On break", "02:00 left");
+        let ticked = notice("On break", "01:59 left");
+        assert!(!needs_refresh(&shown, &shown, COUNTDOWN_REFRESH));
+        assert!(!needs_refresh(&shown, &ticked, Duration::from_secs(1)));
+        assert!(needs_refresh(&shown, &ticked, COUNTDOWN_REFRESH));
+        let finished = notice("Break complete", "Next break in 30:00");
+        assert!(needs_refresh(&shown, &finished, Duration::ZERO));
+    }
+}
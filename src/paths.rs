@@ -0,0 +1,221 @@
+//! Where interlude keeps its files.
+//!
+//! - Config file: `--config`, else `$XDG_CONFIG_HOME/interlude/config.toml`,
+//!   else `~/.config/interlude/config.toml`.
+//! - Autostart: `interlude.service` in `$XDG_CONFIG_HOME/systemd/user`, or
+//!   `interlude.desktop` in `$XDG_CONFIG_HOME/autostart`.
+//! - State (the saved timer, the stats log, the headline rotation and when
+//!   updates were last checked): `--state-dir`, else
+//!   `$XDG_STATE_HOME/interlude`, else `~/.local/state/interlude`.
+//! - Runtime (the control socket and the status file): `--runtime-dir`, else
+//!   `$XDG_RUNTIME_DIR`, else the temp directory with the uid in each name.
+//!   Inside a Flatpak sandbox, `$XDG_RUNTIME_DIR/app/$FLATPAK_ID`, the part
+//!   of the runtime directory the host can see too.
+//!
+//! The directory flags are applied once at startup with [`set_overrides`];
+//! everything that opens one of these files asks this module for its path.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const CONFIG_FILE: &str = "config.toml";
+const UNIT_FILE: &str = "interlude.service";
+const DESKTOP_FILE: &str = "interlude.desktop";
+const STATE_FILE: &str = "state.txt";
+const STATS_FILE: &str = "stats.log";
+const UPDATE_CHECK_FILE: &str = "update-check.txt";
+const HEADLINE_FILE: &str = "headline.txt";
+const SOCKET_FILE: &str = "interlude.sock";
+const STATUS_FILE: &str = "interlude-status.json";
+
+/// Directories given on the command line, in place of the XDG ones.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub state_dir: Option<PathBuf>,
+    pub runtime_dir: Option<PathBuf>,
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+/// Redirects the state and runtime directories for the rest of the process.
+/// Only the first call has an effect.
+pub fn set_overrides(overrides: Overrides) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+fn overrides() -> &'static Overrides {
+    OVERRIDES.get_or_init(Overrides::default)
+}
+
+/// `explicit` if given, otherwise the XDG location; `None` without `$HOME`.
+pub fn config_file(explicit: Option<&Path>) -> Option<PathBuf> {
+    match explicit {
+        Some(path) => Some(path.to_path_buf()),
+        None => config_home().map(|dir| dir.join("interlude").join(CONFIG_FILE)),
+    }
+}
+
+pub fn systemd_unit_file() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join("systemd/user").join(UNIT_FILE))
+}
+
+pub fn autostart_file() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join("autostart").join(DESKTOP_FILE))
+}
+
+fn config_home() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config", env_var)
+}
+
+pub fn state_dir() -> Option<PathBuf> {
+    resolve_state_dir(overrides().state_dir.as_deref(), env_var)
+}
+
+pub fn state_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join(STATE_FILE))
+}
+
+pub fn stats_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join(STATS_FILE))
+}
+
+pub fn update_check_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join(UPDATE_CHECK_FILE))
+}
+
+pub fn headline_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join(HEADLINE_FILE))
+}
+
+pub fn socket_path() -> PathBuf {
+    runtime_file(SOCKET_FILE, ".sock")
+}
+
+pub fn status_path() -> PathBuf {
+    runtime_file(STATUS_FILE, "-status.json")
+}
+
+fn resolve_state_dir(
+    explicit: Option<&Path>,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+    match explicit {
+        Some(dir) => Some(dir.to_path_buf()),
+        None => xdg_dir("XDG_STATE_HOME", ".local/state", var).map(|dir| dir.join("interlude")),
+    }
+}
+
+/// `name` in the runtime directory; without one, a per-user
+/// `interlude-<uid><suffix>` in the temp directory, since that is shared.
+fn runtime_file(name: &str, suffix: &str) -> PathBuf {
+    resolve_runtime_file(
+        overrides().runtime_dir.as_deref(),
+        name,
+        suffix,
+        env_var,
+        rustix::process::getuid().as_raw(),
+    )
+}
+
+fn resolve_runtime_file(
+    explicit: Option<&Path>,
+    name: &str,
+    suffix: &str,
+    var: impl Fn(&str) -> Option<OsString>,
+    uid: u32,
+) -> PathBuf {
+    match explicit.map(Path::to_path_buf).or_else(|| {
+        let dir = absolute(var("XDG_RUNTIME_DIR"))?;
+        Some(match var("FLATPAK_ID") {
+            Some(app) if !app.is_empty() => dir.join("app").join(app),
+            _ => dir,
+        })
+    }) {
+        Some(dir) => dir.join(name),
+        None => env::temp_dir().join(format!("interlude-{uid}{suffix}")),
+    }
+}
+
+/// `$<var>`, or `$HOME/<fallback>` when it is unset. The spec says relative
+/// values are invalid and should be ignored.
+fn xdg_dir(
+    var_name: &str,
+    fallback: &str,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+    absolute(var(var_name)).or_else(|| absolute(var("HOME")).map(|home| home.join(fallback)))
+}
+
+fn absolute(value: Option<OsString>) -> Option<PathBuf> {
+    value.map(PathBuf::from).filter(|path| path.is_absolute())
+}
+
+fn env_var(name: &str) -> Option<OsString> {
+    env::var_os(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let pairs: Vec<(String, String)> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| {
+            pairs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| OsString::from(v))
+        }
+    }
+
+    #[test]
+    fn state_dir_prefers_flag_then_xdg_then_home() {
+        let env = vars(&[("XDG_STATE_HOME", "/xdg/state"), ("HOME", "/home/u")]);
+        assert_eq!(
+            resolve_state_dir(Some(Path::new("/srv/interlude")), &env),
+            Some(PathBuf::from("/srv/interlude"))
+        );
+        assert_eq!(
+            resolve_state_dir(None, &env),
+            Some(PathBuf::from("/xdg/state/interlude"))
+        );
+        assert_eq!(
+            resolve_state_dir(
+                None,
+                vars(&[("XDG_STATE_HOME", "rel"), ("HOME", "/home/u")])
+            ),
+            Some(PathBuf::from("/home/u/.local/state/interlude"))
+        );
+        assert_eq!(resolve_state_dir(None, vars(&[])), None);
+    }
+
+    #[test]
+    fn runtime_files_fall_back_to_per_user_temp_names() {
+        let env = vars(&[("XDG_RUNTIME_DIR", "/run/user/1000")]);
+        assert_eq!(
+            resolve_runtime_file(Some(Path::new("/tmp/rt")), SOCKET_FILE, ".sock", &env, 1000),
+            PathBuf::from("/tmp/rt/interlude.sock")
+        );
+        assert_eq!(
+            resolve_runtime_file(None, STATUS_FILE, "-status.json", &env, 1000),
+            PathBuf::from("/run/user/1000/interlude-status.json")
+        );
+        assert_eq!(
+            resolve_runtime_file(None, STATUS_FILE, "-status.json", vars(&[]), 1000),
+            env::temp_dir().join("interlude-1000-status.json")
+        );
+        let flatpak = vars(&[
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("FLATPAK_ID", "io.github.mikebirdgeneau.Interlude"),
+        ]);
+        assert_eq!(
+            resolve_runtime_file(None, SOCKET_FILE, ".sock", &flatpak, 1000),
+            PathBuf::from("/run/user/1000/app/io.github.mikebirdgeneau.Interlude/interlude.sock")
+        );
+    }
+}
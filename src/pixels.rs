@@ -0,0 +1,113 @@
+//! Row-oriented pixel operations on 4-byte-per-pixel shm buffers.
+//!
+//! Everything works on whole rows or slices so the loops stay free of
+//! per-pixel index maths and bounds checks and the compiler can vectorise them.
+
+/// Fills `buf` with `px`, writing the first row and copying it to the rest.
+pub fn fill_rows(buf: &mut [u8], stride: usize, px: [u8; 4]) {
+    if buf.is_empty() || stride == 0 {
+        return;
+    }
+    let first = stride.min(buf.len());
+    for dst in buf[..first].as_chunks_mut::<4>().0 {
+        *dst = px;
+    }
+    let mut start = first;
+    while start < buf.len() {
+        let len = first.min(buf.len() - start);
+        buf.copy_within(0..len, start);
+        start += len;
+    }
+}
+
+/// Scales every pixel's colour by `alpha / 255` and sets its alpha byte to `alpha`.
+pub fn fade(buf: &mut [u8], alpha: u8) {
+    let alpha = alpha as u32;
+    for px in buf.as_chunks_mut::<4>().0 {
+        *px = fade_pixel(u32::from_le_bytes(*px), alpha).to_le_bytes();
+    }
+}
+
+/// Fade of a single little-endian pixel, scaling two channels per multiply.
+fn fade_pixel(px: u32, alpha: u32) -> u32 {
+    let rb = (px & 0x00ff_00ff) * alpha + 0x0080_0080;
+    let rb = ((rb + ((rb >> 8) & 0x00ff_00ff)) >> 8) & 0x00ff_00ff;
+    let g = (px & 0x0000_ff00) * alpha + 0x0000_8000;
+    let g = ((g + ((g >> 8) & 0x0000_ff00)) >> 8) & 0x0000_ff00;
+    (alpha << 24) | rb | g
+}
+
+/// Blends `color` over a row of pixels, one coverage value per pixel, scaled by `alpha`.
+pub fn blend_row(dst: &mut [u8], coverage: impl Iterator<Item = u8>, color: [u8; 3], alpha: u8) {
+    let alpha = alpha as u16;
+    for (px, cov) in dst.as_chunks_mut::<4>().0.iter_mut().zip(coverage) {
+        let a = cov as u16 * alpha / 255;
+        if a == 0 {
+            continue;
+        }
+        let inv = 255 - a;
+        px[0] = ((color[0] as u16 * a + px[0] as u16 * inv) / 255) as u8;
+        px[1] = ((color[1] as u16 * a + px[1] as u16 * inv) / 255) as u8;
+        px[2] = ((color[2] as u16 * a + px[2] as u16 * inv) / 255) as u8;
+        px[3] = 255;
+    }
+}
+
+/// Clips a span of `len` pixels placed at `pos` to `0..limit`.
+///
+/// Returns the offset into the source, the offset into the destination and
+/// the visible length, or `None` when nothing is visible.
+pub fn clip_span(pos: i32, len: u32, limit: u32) -> Option<(usize, usize, usize)> {
+    let start = (pos as i64).max(0);
+    let end = (pos as i64 + len as i64).min(limit as i64);
+    (start < end).then(|| {
+        (
+            (start - pos as i64) as usize,
+            start as usize,
+            (end - start) as usize,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_rows_covers_partial_last_row() {
+        let mut buf = vec![0u8; 4 * 3 * 2 + 4];
+        fill_rows(&mut buf, 12, [1, 2, 3, 4]);
+        assert!(buf.chunks_exact(4).all(|px| px == [1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn fade_matches_per_channel_division() {
+        for alpha in [0u8, 1, 77, 128, 254, 255] {
+            for value in [0u8, 1, 100, 200, 255] {
+                let mut buf = [value, value / 2, 255 - value, 9];
+                fade(&mut buf, alpha);
+                for (out, orig) in buf[..3].iter().zip([value, value / 2, 255 - value]) {
+                    let exact = orig as f32 * alpha as f32 / 255.0;
+                    assert!((*out as f32 - exact).abs() <= 0.5, "{orig} * {alpha}");
+                }
+                assert_eq!(buf[3], alpha);
+            }
+        }
+    }
+
+    #[test]
+    fn blend_row_respects_coverage() {
+        let mut row = [10u8, 20, 30, 255, 10, 20, 30, 255];
+        blend_row(&mut row, [255u8, 0].into_iter(), [200, 100, 50], 255);
+        assert_eq!(row, [200, 100, 50, 255, 10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn clip_span_handles_edges() {
+        assert_eq!(clip_span(2, 4, 10), Some((0, 2, 4)));
+        assert_eq!(clip_span(-3, 5, 10), Some((3, 0, 2)));
+        assert_eq!(clip_span(8, 5, 10), Some((0, 8, 2)));
+        assert_eq!(clip_span(10, 5, 10), None);
+        assert_eq!(clip_span(-5, 5, 10), None);
+    }
+}
@@ -0,0 +1,100 @@
+//! xdg-desktop-portal, for sandboxed (Flatpak) installs.
+//!
+//! Inside a sandbox the notification daemon, systemd and most of the file
+//! system are out of reach, so notifications go through the Notification
+//! portal (see [`crate::notify`]) and the countdown is published as the
+//! app's background status, which desktops show in their list of running
+//! background apps.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::Value;
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+/// Written into every Flatpak sandbox.
+const FLATPAK_INFO: &str = "/.flatpak-info";
+/// The portal rejects longer status messages.
+const STATUS_MAX_CHARS: usize = 96;
+/// Shortest gap between status messages; the break countdown changes every
+/// second, which is more than a background app list needs.
+const STATUS_REFRESH: Duration = Duration::from_secs(10);
+
+/// Whether this process runs inside a Flatpak sandbox.
+pub fn is_sandboxed() -> bool {
+    Path::new(FLATPAK_INFO).exists()
+}
+
+pub fn proxy(connection: &Connection, interface: &'static str) -> Result<Proxy<'static>> {
+    Proxy::new(connection, DESTINATION, OBJECT_PATH, interface)
+        .with_context(|| format!("create {interface} proxy"))
+}
+
+/// The Background portal: the status message shown for the running app.
+pub struct Background {
+    proxy: Proxy<'static>,
+    sent: Option<(String, Instant)>,
+}
+
+impl Background {
+    pub fn new() -> Result<Self> {
+        let connection = Connection::session().context("connect to session bus")?;
+        Ok(Self {
+            proxy: proxy(&connection, "org.freedesktop.portal.Background")?,
+            sent: None,
+        })
+    }
+
+    /// Shows `message`, at most every few seconds; call again later to send
+    /// one that was held back.
+    pub fn set_status(&mut self, message: &str) -> Result<()> {
+        let message = truncate(message);
+        if !needs_send(self.sent.as_ref(), &message, Instant::now()) {
+            return Ok(());
+        }
+        let options = HashMap::from([("message", Value::from(message.as_str()))]);
+        self.proxy
+            .call::<_, _, ()>("SetStatus", &(options))
+            .context("SetStatus failed")?;
+        self.sent = Some((message, Instant::now()));
+        Ok(())
+    }
+}
+
+fn truncate(message: &str) -> String {
+    message.chars().take(STATUS_MAX_CHARS).collect()
+}
+
+fn needs_send(sent: Option<&(String, Instant)>, message: &str, now: Instant) -> bool {
+    match sent {
+        Some((last, at)) => last != message && now.saturating_duration_since(*at) >= STATUS_REFRESH,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_messages_are_throttled_and_short() {
+        let t0 = Instant::now();
+        let sent = ("On break, 02:00 left".to_string(), t0);
+        assert!(needs_send(None, "Break due", t0));
+        assert!(!needs_send(
+            Some(&sent),
+            "On break, 01:59 left",
+            t0 + Duration::from_secs(1)
+        ));
+        assert!(!needs_send(Some(&sent), &sent.0, t0 + STATUS_REFRESH));
+        assert!(needs_send(
+            Some(&sent),
+            "On break, 01:50 left",
+            t0 + STATUS_REFRESH
+        ));
+        assert_eq!(truncate(&"x".repeat(200)).len(), STATUS_MAX_CHARS);
+    }
+}
@@ -0,0 +1,247 @@
+//! Runtime capability probe.
+//!
+//! [`Capabilities`] is filled in once at startup and handed to every
+//! subsystem that depends on an optional protocol or session service, so a
+//! missing one turns that feature off with a single log line instead of
+//! failing or retrying forever. `--version --features` prints the same probe,
+//! so bug reports say what the compositor actually offers.
+
+use anyhow::{Context, Result};
+use std::env;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+
+use crate::audio;
+use crate::cli::Backend;
+use crate::logging::error;
+
+pub const LAYER_SHELL: &str = "zwlr_layer_shell_v1";
+pub const IDLE_NOTIFIER: &str = "ext_idle_notifier_v1";
+const SESSION_LOCK: &str = "ext_session_lock_manager_v1";
+const WM_BASE: &str = "xdg_wm_base";
+/// Every backend, in the order `--version --features` lists them.
+const ALL_BACKENDS: &[Backend] = &[
+    Backend::SessionLock,
+    Backend::LayerShell,
+    Backend::XdgFullscreen,
+    Backend::NotifyOnly,
+];
+
+/// Wayland globals worth reporting, and what goes missing without them.
+const PROTOCOLS: &[(&str, &str)] = &[
+    ("wl_compositor", "no overlay"),
+    ("wl_shm", "no overlay"),
+    ("wl_seat", "no keyboard or pointer input"),
+    (
+        LAYER_SHELL,
+        "fullscreen windows instead, which other windows can cover",
+    ),
+    (WM_BASE, "no xdg-fullscreen backend"),
+    (SESSION_LOCK, "no session-lock backend"),
+    (IDLE_NOTIFIER, "--idle-credit has no effect, no away scores"),
+];
+
+/// What the running session offers.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    /// Wayland globals as `(interface, version)`; empty without a compositor.
+    pub globals: Vec<(String, u32)>,
+    /// `$SWAYSOCK` is set, so sway can report idle inhibitors.
+    pub sway_ipc: bool,
+    /// logind is reachable: session lock tracking and inhibitor checks.
+    pub system_bus: bool,
+    /// The session bus is reachable: GameMode detection.
+    pub session_bus: bool,
+}
+
+struct Probe;
+
+impl Capabilities {
+    pub fn probe() -> Self {
+        let globals = wayland_globals().unwrap_or_else(|err| {
+            error!("wayland probe failed: {err:#}");
+            Vec::new()
+        });
+        Self {
+            globals,
+            sway_ipc: env::var_os("SWAYSOCK").is_some(),
+            system_bus: zbus::blocking::Connection::system().is_ok(),
+            session_bus: zbus::blocking::Connection::session().is_ok(),
+        }
+    }
+
+    pub fn has(&self, interface: &str) -> bool {
+        self.globals.iter().any(|(name, _)| name == interface)
+    }
+
+    /// One line per capability: its version when present, otherwise what
+    /// goes missing without it.
+    pub fn status_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = PROTOCOLS
+            .iter()
+            .map(|(interface, without)| {
+                match self.globals.iter().find(|(name, _)| name == interface) {
+                    Some((_, version)) => format!("{interface} v{version}: active"),
+                    None => format!("{interface}: degraded ({without})"),
+                }
+            })
+            .collect();
+        for (name, active, without) in [
+            ("sway ipc", self.sway_ipc, "idle inhibitors are not seen"),
+            (
+                "system bus",
+                self.system_bus,
+                "no session lock tracking or logind inhibitors",
+            ),
+            ("session bus", self.session_bus, "no GameMode detection"),
+        ] {
+            lines.push(if active {
+                format!("{name}: active")
+            } else {
+                format!("{name}: degraded ({without})")
+            });
+        }
+        lines
+    }
+
+    /// The backends of `preference` this session supports, in that order.
+    pub fn available_backends(&self, preference: &[Backend]) -> Vec<Backend> {
+        let surfaces = ["wl_compositor", "wl_shm", "wl_seat"]
+            .iter()
+            .all(|interface| self.has(interface));
+        preference
+            .iter()
+            .copied()
+            .filter(|backend| match backend {
+                Backend::SessionLock => surfaces && self.has(SESSION_LOCK),
+                Backend::LayerShell => surfaces && self.has(LAYER_SHELL),
+                Backend::XdgFullscreen => surfaces && self.has(WM_BASE),
+                Backend::NotifyOnly => true,
+            })
+            .collect()
+    }
+
+    /// The degraded lines only, for the startup log.
+    pub fn degraded(&self) -> Vec<String> {
+        self.status_lines()
+            .into_iter()
+            .filter(|line| line.contains(": degraded"))
+            .collect()
+    }
+}
+
+pub fn version() -> String {
+    format!(
+        "interlude {} (git {})",
+        env!("CARGO_PKG_VERSION"),
+        env!("INTERLUDE_GIT_HASH")
+    )
+}
+
+/// Build features, then what the running session supports.
+pub fn report() -> String {
+    let features = env!("INTERLUDE_FEATURES");
+    let mut out = format!(
+        "cargo features: {}\n",
+        if features.is_empty() {
+            "none"
+        } else {
+            features
+        }
+    );
+    let caps = Capabilities::probe();
+    for line in caps.status_lines() {
+        out.push_str(&format!("{line}\n"));
+    }
+    let backends: Vec<&str> = caps
+        .available_backends(ALL_BACKENDS)
+        .into_iter()
+        .map(Backend::label)
+        .collect();
+    out.push_str(&format!("backends: {}\n", backends.join(", ")));
+    out.push_str(&match audio::probe_output() {
+        Ok(()) => "audio output: active\n".to_string(),
+        Err(err) => format!("audio output: degraded ({err:#}; retried at each break)\n"),
+    });
+    out
+}
+
+fn wayland_globals() -> Result<Vec<(String, u32)>> {
+    let conn = Connection::connect_to_env().context("connect")?;
+    let (globals, _queue) = registry_queue_init::<Probe>(&conn).context("list globals")?;
+    Ok(globals
+        .contents()
+        .clone_list()
+        .into_iter()
+        .map(|global| (global.interface, global.version))
+        .collect())
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Probe {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlRegistry,
+        _event: <WlRegistry as wayland_client::Proxy>::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_capabilities_say_what_degrades() {
+        let caps = Capabilities {
+            globals: vec![
+                ("wl_compositor".to_string(), 6),
+                (LAYER_SHELL.to_string(), 4),
+            ],
+            session_bus: true,
+            ..Capabilities::default()
+        };
+        let lines = caps.status_lines();
+        assert_eq!(lines[0], "wl_compositor v6: active");
+        assert_eq!(lines[1], "wl_shm: degraded (no overlay)");
+        assert_eq!(lines[3], "zwlr_layer_shell_v1 v4: active");
+        assert!(caps.has(LAYER_SHELL));
+        assert!(!caps.has(IDLE_NOTIFIER));
+
+        let degraded = caps.degraded();
+        assert!(
+            degraded.contains(
+                &"ext_idle_notifier_v1: degraded (--idle-credit has no effect, no away scores)"
+                    .to_string()
+            )
+        );
+        assert!(!degraded.iter().any(|line| line.starts_with("session bus")));
+    }
+
+    #[test]
+    fn backends_keep_preference_order() {
+        let mut caps = Capabilities {
+            globals: ["wl_compositor", "wl_shm", "wl_seat", WM_BASE, SESSION_LOCK]
+                .iter()
+                .map(|name| (name.to_string(), 1))
+                .collect(),
+            ..Capabilities::default()
+        };
+        assert_eq!(
+            caps.available_backends(ALL_BACKENDS),
+            vec![
+                Backend::SessionLock,
+                Backend::XdgFullscreen,
+                Backend::NotifyOnly
+            ]
+        );
+        caps.globals.retain(|(name, _)| name != "wl_shm");
+        assert_eq!(
+            caps.available_backends(&[Backend::XdgFullscreen, Backend::NotifyOnly]),
+            vec![Backend::NotifyOnly]
+        );
+    }
+}
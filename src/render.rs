@@ -0,0 +1,601 @@
+//! Overlay frame composition on a background thread.
+//!
+//! The locker snapshots what a frame should show into a [`FrameSpec`], hands
+//! the surface's shm mapping to the render thread and gets it back, filled in,
+//! through [`Renderer::try_recv`]. Wayland dispatch and scheduler ticks never
+//! wait for a large buffer to be painted.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use memmap2::MmapMut;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::cli::BarPlacement;
+use crate::pixels::{blend_row, clip_span, fade, fill_rows};
+use crate::tiny_font::{TextLayer, line_ascent_size, line_height_size, text_width_size};
+
+const ICON_GAP: i32 = 20;
+/// Space between the small icon and the bottom-right corner.
+const SMALL_ICON_PAD: i32 = 20;
+/// Space between the text and a bar placed below it.
+const BAR_GAP: i32 = 28;
+/// Share of the output width a bar below the text spans.
+const BAR_TEXT_WIDTH: f32 = 0.4;
+/// Opacity of the emptied part of a bar, relative to the filled part.
+const BAR_TRACK_ALPHA: f32 = 0.25;
+/// Text size of the `--debug-overlay` lines.
+const DEBUG_SIZE: f32 = 14.0;
+/// Space between the debug lines and the top-left corner.
+const DEBUG_PAD: i32 = 8;
+
+pub struct Icon {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LineAnchor {
+    Center,
+    CenterOnColon,
+}
+
+pub struct LineSpec {
+    pub text: String,
+    pub size: f32,
+    pub alpha: f32,
+    pub anchor: LineAnchor,
+    /// In place of the frame's foreground.
+    pub color: Option<[u8; 3]>,
+}
+
+/// A countdown bar, full at the start of the break and empty at its end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressBar {
+    /// Share of the break left, from 0 to 1.
+    pub left: f32,
+    pub thickness: u32,
+    pub placement: BarPlacement,
+}
+
+/// Everything needed to compose one frame, copied out of the locker state.
+pub struct FrameSpec {
+    pub width: u32,
+    pub height: u32,
+    pub background: [u8; 3],
+    pub foreground: [u8; 3],
+    pub overlay_alpha: u8,
+    pub text_alpha: u8,
+    pub lines: Vec<LineSpec>,
+    pub icon: Option<Arc<Icon>>,
+    pub small_icon: Option<Arc<Icon>>,
+    pub progress: Option<ProgressBar>,
+    /// `--debug-overlay` lines for the top-left corner.
+    pub debug: Vec<String>,
+}
+
+/// Frames put on screen over a stretch of time, e.g. one fade, and how long
+/// they took from the locker handing them over to getting them back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub frames: u32,
+    /// Redraws folded into a later frame because the surface was still busy
+    /// with the one before.
+    pub dropped: u32,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl FrameStats {
+    pub fn record(&mut self, took: Duration) {
+        self.frames += 1;
+        self.total += took;
+        self.max = self.max.max(took);
+    }
+
+    pub fn average(&self) -> Duration {
+        self.total.checked_div(self.frames).unwrap_or_default()
+    }
+
+    /// E.g. "24 frames, 1 dropped, 3.1 ms average, 8.0 ms max".
+    pub fn summary(&self) -> String {
+        format!(
+            "{} frames, {} dropped, {:.1} ms average, {:.1} ms max",
+            self.frames,
+            self.dropped,
+            self.average().as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Layout of the last fully opaque frame drawn into a buffer slot.
+///
+/// When the next frame has the same layout only the text band is repainted,
+/// so a ticking countdown does not refill and re-fade the whole buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct PaintedFrame {
+    layout: FrameLayout,
+    text_top: i32,
+    text_bottom: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrameLayout {
+    base_y: i32,
+    icon: bool,
+    small_icon: bool,
+    text_alpha: u8,
+    bar: Option<(BarPlacement, u32)>,
+    debug_lines: usize,
+}
+
+/// A frame to paint into `map[plane]` for buffer slot `slot` of `surface`.
+pub struct RenderJob {
+    pub surface: u64,
+    pub slot: usize,
+    pub map: MmapMut,
+    pub plane: Range<usize>,
+    pub prev: Option<PaintedFrame>,
+    pub frame: FrameSpec,
+}
+
+/// A painted frame, returned with the mapping it was drawn into.
+pub struct RenderDone {
+    pub surface: u64,
+    pub slot: usize,
+    pub map: MmapMut,
+    pub painted: Option<PaintedFrame>,
+    /// Rows that changed, as `(top, bottom)`.
+    pub damage: (i32, i32),
+}
+
+enum Request {
+    Render(RenderJob),
+    Forget(u64),
+}
+
+pub struct Renderer {
+    tx: Sender<Request>,
+    rx: Receiver<RenderDone>,
+}
+
+impl Renderer {
+    pub fn spawn() -> Result<Self> {
+        let (tx, rx_jobs) = unbounded::<Request>();
+        let (tx_done, rx) = unbounded();
+        thread::Builder::new()
+            .name("render".into())
+            .spawn(move || {
+                // Rasterised lines per surface; glyphs go through fontdue once per text.
+                let mut caches: HashMap<u64, Vec<(String, f32, TextLayer)>> = HashMap::new();
+                for request in rx_jobs {
+                    match request {
+                        Request::Render(mut job) => {
+                            let cache = caches.entry(job.surface).or_default();
+                            let bytes = &mut job.map[job.plane.clone()];
+                            let (painted, damage) = compose(bytes, &job.frame, job.prev, cache);
+                            let done = RenderDone {
+                                surface: job.surface,
+                                slot: job.slot,
+                                map: job.map,
+                                painted,
+                                damage,
+                            };
+                            if tx_done.send(done).is_err() {
+                                break;
+                            }
+                        }
+                        Request::Forget(surface) => {
+                            caches.remove(&surface);
+                        }
+                    }
+                }
+            })
+            .context("spawn render thread")?;
+        Ok(Self { tx, rx })
+    }
+
+    pub fn submit(&self, job: RenderJob) {
+        let _ = self.tx.send(Request::Render(job));
+    }
+
+    /// Drops cached state for a surface that no longer exists.
+    pub fn forget(&self, surface: u64) {
+        let _ = self.tx.send(Request::Forget(surface));
+    }
+
+    pub fn try_recv(&self) -> Option<RenderDone> {
+        self.rx.try_recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: std::time::Duration) -> Option<RenderDone> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+}
+
+fn compose(
+    bytes: &mut [u8],
+    frame: &FrameSpec,
+    prev: Option<PaintedFrame>,
+    cache: &mut Vec<(String, f32, TextLayer)>,
+) -> (Option<PaintedFrame>, (i32, i32)) {
+    let (w, h) = (frame.width, frame.height);
+    let stride = w as usize * 4;
+
+    let mut cached = std::mem::take(cache);
+    for line in &frame.lines {
+        let layer = match cached
+            .iter()
+            .position(|(text, size, _)| *text == line.text && *size == line.size)
+        {
+            Some(pos) => cached.swap_remove(pos).2,
+            None => TextLayer::new(&line.text, line.size),
+        };
+        cache.push((line.text.clone(), line.size, layer));
+    }
+
+    let icon_height = frame.icon.as_ref().map(|i| i.height as i32).unwrap_or(0);
+    let icon_gap = if icon_height > 0 { ICON_GAP } else { 0 };
+    let text_height: i32 = frame
+        .lines
+        .iter()
+        .map(|line| line_height_size(line.size))
+        .sum();
+    // A bar below the text belongs to the text block; one on an edge does not.
+    let bar_block = frame
+        .progress
+        .filter(|bar| bar.placement == BarPlacement::BelowText)
+        .map_or(0, |bar| BAR_GAP + bar.thickness as i32);
+    let total_height = icon_height + icon_gap + text_height + bar_block;
+    let base_y = ((h as i32 - total_height) / 2).max(0);
+    let text_start_y = base_y + icon_height + icon_gap;
+    let text_end_y = text_start_y + text_height + bar_block;
+    // As (x, y, width, thickness).
+    let bar_rect = frame.progress.map(|bar| {
+        let thickness = bar.thickness as i32;
+        match bar.placement {
+            BarPlacement::Top => (0, 0, w as i32, thickness),
+            BarPlacement::Bottom => (0, h as i32 - thickness, w as i32, thickness),
+            BarPlacement::BelowText => {
+                let width = (w as f32 * BAR_TEXT_WIDTH).round() as i32;
+                let y = text_start_y + text_height + BAR_GAP;
+                ((w as i32 - width) / 2, y, width, thickness)
+            }
+        }
+    });
+
+    let opaque = frame.overlay_alpha == 255;
+    let layout = FrameLayout {
+        base_y,
+        icon: frame.icon.is_some(),
+        small_icon: frame.small_icon.is_some(),
+        text_alpha: frame.text_alpha,
+        bar: frame.progress.map(|bar| (bar.placement, bar.thickness)),
+        debug_lines: frame.debug.len(),
+    };
+    // A bar on an edge and the debug lines are repainted as bands of rows of
+    // their own; icons are only drawn on full repaints, so one reaching into
+    // such a band forces a full one.
+    let edge_band = bar_rect
+        .filter(|_| {
+            layout
+                .bar
+                .is_some_and(|(p, _)| p != BarPlacement::BelowText)
+        })
+        .map(|(_, y, _, thickness)| (y.clamp(0, h as i32), (y + thickness).clamp(0, h as i32)));
+    let debug_height = frame.debug.len() as i32 * line_height_size(DEBUG_SIZE);
+    let debug_band = (!frame.debug.is_empty()).then(|| {
+        (
+            DEBUG_PAD.min(h as i32),
+            (DEBUG_PAD + debug_height).clamp(0, h as i32),
+        )
+    });
+    let bands: Vec<(i32, i32)> = [edge_band, debug_band].into_iter().flatten().collect();
+    let icon_rows = [
+        frame
+            .icon
+            .as_ref()
+            .map(|i| (base_y, base_y + i.height as i32)),
+        frame.small_icon.as_ref().map(|i| {
+            (
+                h as i32 - i.height as i32 - SMALL_ICON_PAD,
+                h as i32 - SMALL_ICON_PAD,
+            )
+        }),
+    ];
+    let bands_clear = bands.iter().all(|&(top, bottom)| {
+        icon_rows
+            .iter()
+            .flatten()
+            .all(|&(icon_top, icon_bottom)| icon_bottom <= top || icon_top >= bottom)
+    });
+    // Rows that need repainting; everything outside is already correct.
+    let (dirty_top, dirty_bottom) = match prev {
+        Some(prev) if opaque && prev.layout == layout && bands_clear => (
+            prev.text_top.min(text_start_y).clamp(0, h as i32),
+            prev.text_bottom.max(text_end_y).clamp(0, h as i32),
+        ),
+        _ => (0, h as i32),
+    };
+    let full_repaint = (dirty_top, dirty_bottom) == (0, h as i32);
+
+    // Dim background: mostly opaque black
+    let bg = frame.background;
+    let band = dirty_top as usize * stride..dirty_bottom as usize * stride;
+    fill_rows(&mut bytes[band], stride, [bg[0], bg[1], bg[2], 255]);
+    // The damage covers every band, and whatever lies between.
+    let (mut dirty_top, mut dirty_bottom) = (dirty_top, dirty_bottom);
+    for &(top, bottom) in bands.iter().filter(|_| !full_repaint) {
+        let band = top as usize * stride..bottom as usize * stride;
+        fill_rows(&mut bytes[band], stride, [bg[0], bg[1], bg[2], 255]);
+        (dirty_top, dirty_bottom) = (dirty_top.min(top), dirty_bottom.max(bottom));
+    }
+
+    if let Some(icon) = frame.icon.as_ref().filter(|_| full_repaint) {
+        let icon_x = ((w as i32 - icon.width as i32) / 2).max(0);
+        if frame.text_alpha > 0 {
+            draw_icon_rgba(
+                bytes,
+                w,
+                h,
+                icon_x,
+                base_y,
+                icon,
+                frame.foreground,
+                frame.text_alpha,
+            );
+        }
+    }
+
+    let mut line_y = text_start_y;
+    for (line, (_, _, layer)) in frame.lines.iter().zip(cache.iter()) {
+        let ascent = line_ascent_size(line.size);
+        let base_x = match line.anchor {
+            LineAnchor::Center => ((w as i32 - layer.advance) / 2).max(0),
+            LineAnchor::CenterOnColon => {
+                if let Some(idx) = line.text.find(':') {
+                    let (left, _) = line.text.split_at(idx);
+                    let left_width = text_width_size(left, line.size);
+                    let colon_width = text_width_size(":", line.size);
+                    ((w as i32 / 2) - left_width - (colon_width / 2)).max(0)
+                } else {
+                    ((w as i32 - layer.advance) / 2).max(0)
+                }
+            }
+        };
+        let alpha = ((frame.text_alpha as f32) * line.alpha).round() as u8;
+        let fg = line.color.unwrap_or(frame.foreground);
+        layer.draw(
+            bytes,
+            w,
+            h,
+            base_x,
+            line_y + ascent,
+            [fg[0], fg[1], fg[2], alpha],
+        );
+        line_y += line_height_size(line.size);
+    }
+
+    if let (Some(bar), Some((x, y, width, thickness))) = (frame.progress, bar_rect) {
+        let filled = (width as f32 * bar.left.clamp(0.0, 1.0)).round() as i32;
+        let fg = frame.foreground;
+        let track_alpha = (frame.text_alpha as f32 * BAR_TRACK_ALPHA).round() as u8;
+        for (start, len, alpha) in [
+            (x, filled, frame.text_alpha),
+            (x + filled, width - filled, track_alpha),
+        ] {
+            let (Some((_, dst_x, cols)), Some((_, dst_y, rows))) = (
+                clip_span(start, len.max(0) as u32, w),
+                clip_span(y, thickness as u32, h),
+            ) else {
+                continue;
+            };
+            for row in dst_y..dst_y + rows {
+                let dst = &mut bytes[row * stride + dst_x * 4..][..cols * 4];
+                blend_row(dst, std::iter::repeat_n(255, cols), fg, alpha);
+            }
+        }
+    }
+
+    // Changes every frame, so not worth caching.
+    let mut debug_y = DEBUG_PAD;
+    for text in &frame.debug {
+        let fg = frame.foreground;
+        TextLayer::new(text, DEBUG_SIZE).draw(
+            bytes,
+            w,
+            h,
+            DEBUG_PAD,
+            debug_y + line_ascent_size(DEBUG_SIZE),
+            [fg[0], fg[1], fg[2], 255],
+        );
+        debug_y += line_height_size(DEBUG_SIZE);
+    }
+
+    if let Some(icon) = frame.small_icon.as_ref().filter(|_| full_repaint) {
+        let x = w as i32 - icon.width as i32 - SMALL_ICON_PAD;
+        let y = h as i32 - icon.height as i32 - SMALL_ICON_PAD;
+        draw_icon_rgba(bytes, w, h, x, y, icon, frame.foreground, 255);
+    }
+
+    // Everything drawn above is opaque, so the fade is a no-op at full alpha.
+    if !opaque {
+        fade(bytes, frame.overlay_alpha);
+    }
+
+    let painted = opaque.then_some(PaintedFrame {
+        layout,
+        text_top: text_start_y,
+        text_bottom: text_end_y,
+    });
+    (painted, (dirty_top, dirty_bottom))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_icon_rgba(
+    buf: &mut [u8],
+    width: u32,
+    height: u32,
+    x: i32,
+    y: i32,
+    icon: &Icon,
+    tint: [u8; 3],
+    alpha_mul: u8,
+) {
+    let (Some((src_x, dst_x, cols)), Some((src_y, dst_y, rows))) = (
+        clip_span(x, icon.width, width),
+        clip_span(y, icon.height, height),
+    ) else {
+        return;
+    };
+    let stride = width as usize * 4;
+    let src_stride = icon.width as usize * 4;
+    for row in 0..rows {
+        let src = &icon.rgba[(src_y + row) * src_stride + src_x * 4..][..cols * 4];
+        let dst = &mut buf[(dst_y + row) * stride + dst_x * 4..][..cols * 4];
+        let coverage = src.as_chunks::<4>().0.iter().map(|px| px[3]);
+        blend_row(dst, coverage, tint, alpha_mul);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(text: &str, overlay_alpha: u8) -> FrameSpec {
+        FrameSpec {
+            width: 200,
+            height: 100,
+            background: [0, 0, 0],
+            foreground: [255, 255, 255],
+            overlay_alpha,
+            text_alpha: 255,
+            lines: vec![LineSpec {
+                text: text.to_string(),
+                size: 20.0,
+                alpha: 1.0,
+                anchor: LineAnchor::CenterOnColon,
+                color: None,
+            }],
+            icon: None,
+            small_icon: None,
+            progress: None,
+            debug: Vec::new(),
+        }
+    }
+
+    fn bar(left: f32, placement: BarPlacement) -> Option<ProgressBar> {
+        Some(ProgressBar {
+            left,
+            thickness: 4,
+            placement,
+        })
+    }
+
+    fn pixel(buf: &[u8], x: usize, y: usize) -> [u8; 4] {
+        buf[(y * 200 + x) * 4..][..4].try_into().unwrap()
+    }
+
+    #[test]
+    fn bar_empties_from_the_right_and_is_repainted_on_edges() {
+        let mut buf = vec![0u8; 200 * 100 * 4];
+        let mut cache = Vec::new();
+        let mut spec = frame("", 255);
+        spec.lines.clear();
+        spec.progress = bar(0.5, BarPlacement::Bottom);
+        let (painted, _) = compose(&mut buf, &spec, None, &mut cache);
+        assert_eq!(pixel(&buf, 10, 98), [255, 255, 255, 255]);
+        let track = pixel(&buf, 190, 98);
+        assert!(track[0] > 0 && track[0] < 255, "{track:?}");
+        assert_eq!(pixel(&buf, 10, 94), [0, 0, 0, 255]);
+
+        spec.progress = bar(0.25, BarPlacement::Bottom);
+        let (_, damage) = compose(&mut buf, &spec, painted, &mut cache);
+        assert_eq!(damage.1, 100);
+        assert_eq!(pixel(&buf, 75, 98), track);
+    }
+
+    #[test]
+    fn bar_below_text_sits_under_the_lines() {
+        let mut buf = vec![0u8; 200 * 100 * 4];
+        let mut spec = frame("04:59", 255);
+        spec.progress = bar(1.0, BarPlacement::BelowText);
+        compose(&mut buf, &spec, None, &mut Vec::new());
+        let rows: Vec<usize> = (0..100)
+            .filter(|&y| pixel(&buf, 100, y) == [255, 255, 255, 255])
+            .collect();
+        assert_eq!(rows.len(), 4);
+        assert!(rows[0] > 50, "{rows:?}");
+        // 40% of the width, centred.
+        assert_eq!(pixel(&buf, 59, rows[0]), [0, 0, 0, 255]);
+        assert_eq!(pixel(&buf, 60, rows[0]), [255, 255, 255, 255]);
+        assert_eq!(pixel(&buf, 139, rows[0]), [255, 255, 255, 255]);
+        assert_eq!(pixel(&buf, 140, rows[0]), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn opaque_repeat_frame_only_repaints_text_band() {
+        let mut buf = vec![0u8; 200 * 100 * 4];
+        let mut cache = Vec::new();
+
+        let (painted, damage) = compose(&mut buf, &frame("04:59", 255), None, &mut cache);
+        assert_eq!(damage, (0, 100));
+        let first = buf.clone();
+
+        let (_, damage) = compose(&mut buf, &frame("04:58", 255), painted, &mut cache);
+        assert!(damage.0 > 0 && damage.1 < 100, "{damage:?}");
+        assert_ne!(buf, first);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn frame_stats_sum_up_a_fade() {
+        let mut stats = FrameStats::default();
+        assert_eq!(stats.average(), Duration::ZERO);
+        stats.record(Duration::from_millis(2));
+        stats.record(Duration::from_millis(6));
+        stats.dropped += 1;
+        assert_eq!(stats.average(), Duration::from_millis(4));
+        assert_eq!(stats.max, Duration::from_millis(6));
+        assert_eq!(
+            stats.summary(),
+            "2 frames, 1 dropped, 4.0 ms average, 6.0 ms max"
+        );
+    }
+
+    #[test]
+    fn debug_lines_are_repainted_in_the_corner() {
+        let mut buf = vec![0u8; 200 * 100 * 4];
+        let mut cache = Vec::new();
+        let mut spec = frame("04:59", 255);
+        spec.debug = vec!["frame 1.0 ms".to_string()];
+        let (painted, _) = compose(&mut buf, &spec, None, &mut cache);
+        let corner = |buf: &[u8]| -> Vec<[u8; 4]> {
+            (DEBUG_PAD as usize..DEBUG_PAD as usize + 14)
+                .flat_map(|y| (0..100).map(move |x| (x, y)))
+                .map(|(x, y)| pixel(buf, x, y))
+                .collect()
+        };
+        let first = corner(&buf);
+        assert!(first.iter().any(|px| *px != [0, 0, 0, 255]));
+
+        spec.debug = vec!["frame 9.9 ms".to_string()];
+        let (_, damage) = compose(&mut buf, &spec, painted, &mut cache);
+        assert!(damage.0 <= DEBUG_PAD && damage.1 < 100, "{damage:?}");
+        assert_ne!(corner(&buf), first);
+    }
+
+    #[test]
+    fn fading_frame_is_never_reused() {
+        let mut buf = vec![0u8; 200 * 100 * 4];
+        let mut cache = Vec::new();
+        let (painted, _) = compose(&mut buf, &frame("Break", 128), None, &mut cache);
+        assert!(painted.is_none());
+        assert!(buf.chunks_exact(4).all(|px| px[3] == 128));
+    }
+}
@@ -1,3 +1,7 @@
+use chrono::{Local, NaiveDate};
+use crossbeam_channel::{Receiver, Sender, unbounded};
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -9,6 +13,145 @@ pub enum Phase {
     Snoozing,
 }
 
+/// Something that happened in the scheduler, for subscribers that react to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulerEvent {
+    /// `kind` is the break the new phase leads up to, is part of, or
+    /// follows.
+    PhaseChanged {
+        from: Phase,
+        to: Phase,
+        kind: BreakKind,
+    },
+    SnoozeTaken {
+        duration: Duration,
+        count: u32,
+    },
+    /// A break was skipped; `carried_over` is owed to the next one.
+    BreakSkipped {
+        carried_over: Duration,
+    },
+    /// The next break was pushed back and is now `next_in` away.
+    Deferred {
+        next_in: Duration,
+    },
+    /// The work interval is about to end; see [`Config::warn_before`].
+    BreakWarning {
+        break_in: Duration,
+    },
+    /// The work time left dropped to one of [`Config::milestones`].
+    Milestone {
+        left: Duration,
+    },
+    /// A micro-break of `duration` began; see [`Config::micro_every`].
+    MicroBreakStarted {
+        duration: Duration,
+    },
+    MicroBreakFinished,
+}
+
+/// Where the scheduler reads the time. Tests use a clock they move by hand,
+/// so sequences that depend on timing replay exactly.
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    /// Starts at midnight on `midnight`, and the date moves as `now` does.
+    #[cfg(test)]
+    Manual {
+        now: Arc<Mutex<Instant>>,
+        origin: Instant,
+        midnight: NaiveDate,
+    },
+}
+
+impl Clock {
+    pub fn now(&self) -> Instant {
+        match self {
+            Self::System => Instant::now(),
+            #[cfg(test)]
+            Self::Manual { now, .. } => *now.lock().unwrap(),
+        }
+    }
+
+    /// The local calendar date, read from the same clock as [`Clock::now`].
+    pub fn today(&self) -> NaiveDate {
+        match self {
+            Self::System => Local::now().date_naive(),
+            #[cfg(test)]
+            Self::Manual {
+                now,
+                origin,
+                midnight,
+            } => {
+                let days = now.lock().unwrap().duration_since(*origin).as_secs() / 86_400;
+                *midnight + chrono::Days::new(days)
+            }
+        }
+    }
+
+    /// A clock that only moves when `now` is moved, starting at `now`.
+    #[cfg(test)]
+    pub fn manual(now: &Arc<Mutex<Instant>>) -> Self {
+        Self::Manual {
+            now: Arc::clone(now),
+            origin: *now.lock().unwrap(),
+            midnight: NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+        }
+    }
+}
+
+/// The sort of break, for everything that looks or sounds different per sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BreakKind {
+    Regular,
+    /// Every [`Config::long_break_every`]th break.
+    Long,
+}
+
+/// What a break asks of the user, picked once per cycle as the break becomes
+/// due; unlike [`BreakKind`] it changes the words, not the length.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BreakVariant {
+    #[default]
+    Rest,
+    /// The first break of the day, spent planning it; see
+    /// [`Config::planning_break`].
+    Planning,
+    /// The last break of the day, [`Config::wrap_up_len`] long; see
+    /// [`Scheduler::wrap_up`].
+    WrapUp,
+}
+
+impl BreakVariant {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Rest => "rest",
+            Self::Planning => "planning",
+            Self::WrapUp => "wrap-up",
+        }
+    }
+
+    pub fn parse(label: &str) -> Option<Self> {
+        [Self::Rest, Self::Planning, Self::WrapUp]
+            .into_iter()
+            .find(|variant| variant.label() == label)
+    }
+}
+
+/// How each snooze in a cycle is shorter than the one before.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnoozeCurve {
+    /// Each snooze is `decay` times the previous one.
+    Exponential { decay: f64 },
+    /// Each snooze is `step` shorter than the previous one.
+    Linear { step: Duration },
+    /// Every snooze lasts [`Config::snooze_base`].
+    Constant,
+    /// Snooze `n` lasts entry `n`; the last entry repeats.
+    Schedule(Vec<Duration>),
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub interval: Duration,
@@ -16,9 +159,35 @@ pub struct Config {
     pub initial_interval: Duration,
     pub initial_break_len: Duration,
     pub snooze_base: Duration,
-    pub snooze_decay: f64,
+    pub snooze_curve: SnoozeCurve,
+    /// Floor for every snooze, whatever the curve.
     pub snooze_min: Duration,
     pub max_snoozes: Option<u32>,
+    /// Most snooze time allowed per work cycle; the last snooze is cut to fit.
+    pub max_snooze_total: Option<Duration>,
+    pub long_break_every: Option<u32>,
+    pub long_break_len: Duration,
+    /// Fraction of a skipped break added to the next one (0 = disabled).
+    pub carry_over: f64,
+    /// How long before a break is due to emit [`SchedulerEvent::BreakWarning`].
+    pub warn_before: Option<Duration>,
+    /// Work time left at which to emit [`SchedulerEvent::Milestone`].
+    pub milestones: Vec<Duration>,
+    /// Fraction of a break that must pass before it can be ended early.
+    pub early_finish_after: Option<f64>,
+    /// Fraction of an idle gap during work given back to the work interval.
+    pub idle_credit: f64,
+    /// How much sustained input shortens the work interval, and light use
+    /// lengthens it; see [`Scheduler::weigh_work`].
+    pub adaptive_strain: f64,
+    /// Make the first break of each day a [`BreakVariant::Planning`] one.
+    pub planning_break: bool,
+    /// Length of the wrap-up break at the end of the working day.
+    pub wrap_up_len: Duration,
+    /// Work time between micro-breaks: short eye rests that need no answer
+    /// and leave the break timer running. `None` turns them off.
+    pub micro_every: Option<Duration>,
+    pub micro_len: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -26,20 +195,77 @@ pub struct Scheduler {
     pub phase: Phase,
     pub deadline: Option<Instant>,
     pub snooze_count: u32,
+    /// Snooze time taken this cycle, for [`Config::max_snooze_total`].
+    pub snoozed_total: Duration,
     pub cfg: Config,
     pub initial_cycle_done: bool,
+    /// Completed breaks, used to place every Nth break as a long break.
+    pub break_cycle: u32,
+    /// Break time owed from skipped breaks, added to the next break.
+    pub carried_over: Duration,
+    /// Break time actually taken when the last break was ended early.
+    pub ended_early: Option<Duration>,
+    /// The variant of the current (or last) break.
+    pub variant: BreakVariant,
+    /// The day a break last became due, to find the first one of a day.
+    pub last_break_day: Option<NaiveDate>,
     paused_remaining: Option<Duration>,
+    /// Work time left at the previous tick, to notice thresholds being crossed.
+    last_left: Option<Duration>,
+    /// When the next micro-break is due, and when the running one ends.
+    micro_deadline: Option<Instant>,
+    micro_until: Option<Instant>,
+    subscribers: Vec<Sender<SchedulerEvent>>,
+    clock: Clock,
 }
 
 impl Scheduler {
     pub fn new(cfg: Config) -> Self {
+        Self::with_clock(cfg, Clock::System)
+    }
+
+    pub fn with_clock(cfg: Config, clock: Clock) -> Self {
         Self {
             phase: Phase::Working,
-            deadline: Some(Instant::now() + cfg.initial_interval),
+            deadline: Some(clock.now() + cfg.initial_interval),
             snooze_count: 0,
+            snoozed_total: Duration::ZERO,
             cfg,
             initial_cycle_done: false,
+            break_cycle: 0,
+            carried_over: Duration::ZERO,
+            ended_early: None,
+            variant: BreakVariant::Rest,
+            last_break_day: None,
             paused_remaining: None,
+            last_left: None,
+            micro_deadline: None,
+            micro_until: None,
+            subscribers: Vec::new(),
+            clock,
+        }
+    }
+
+    /// Returns a receiver for every event from now on.
+    pub fn subscribe(&mut self) -> Receiver<SchedulerEvent> {
+        let (tx, rx) = unbounded();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    fn emit(&mut self, event: SchedulerEvent) {
+        self.subscribers.retain(|tx| tx.send(event).is_ok());
+    }
+
+    fn set_phase(&mut self, to: Phase) {
+        let from = self.phase;
+        self.phase = to;
+        if from != to {
+            self.emit(SchedulerEvent::PhaseChanged {
+                from,
+                to,
+                kind: self.break_kind(),
+            });
         }
     }
 
@@ -47,26 +273,141 @@ impl Scheduler {
         if self.paused_remaining.is_some() {
             return;
         }
-        let now = Instant::now();
+        self.emit_crossings();
+        let now = self.clock.now();
         if let Some(dl) = self.deadline
-            && now >= dl {
-                match self.phase {
-                    Phase::Working => {
-                        self.phase = Phase::LockedAwaitingAction;
-                        self.deadline = None;
-                    }
-                    Phase::OnBreak => {
-                        self.phase = Phase::BreakFinished;
-                        self.deadline = None;
-                        self.initial_cycle_done = true;
-                    }
-                    Phase::Snoozing => {
-                        self.phase = Phase::LockedAwaitingAction;
-                        self.deadline = None;
-                    }
-                    _ => {}
+            && now >= dl
+        {
+            match self.phase {
+                Phase::Working => {
+                    self.pick_variant();
+                    self.set_phase(Phase::LockedAwaitingAction);
+                    self.deadline = None;
+                }
+                Phase::OnBreak => {
+                    self.set_phase(Phase::BreakFinished);
+                    self.deadline = None;
+                    self.initial_cycle_done = true;
                 }
+                Phase::Snoozing => {
+                    self.set_phase(Phase::LockedAwaitingAction);
+                    self.deadline = None;
+                }
+                _ => {}
             }
+        }
+        self.tick_micro(now);
+    }
+
+    /// Starts and ends micro-breaks. They run on their own timer while
+    /// working, which starts over after every break or pause, and one that
+    /// would come within half an interval of the real break is left out.
+    fn tick_micro(&mut self, now: Instant) {
+        let Some(every) = self.cfg.micro_every else {
+            return;
+        };
+        if !matches!(self.phase, Phase::Working | Phase::Snoozing) {
+            self.reset_micro();
+            return;
+        }
+        if let Some(until) = self.micro_until {
+            if now >= until {
+                self.micro_until = None;
+                self.micro_deadline = Some(now + every);
+                self.emit(SchedulerEvent::MicroBreakFinished);
+            }
+            return;
+        }
+        let due = *self.micro_deadline.get_or_insert(now + every);
+        if now < due {
+            return;
+        }
+        self.micro_deadline = Some(now + every);
+        if self.time_left().is_some_and(|left| left < every / 2) {
+            return;
+        }
+        self.micro_until = Some(now + self.cfg.micro_len);
+        self.emit(SchedulerEvent::MicroBreakStarted {
+            duration: self.cfg.micro_len,
+        });
+    }
+
+    /// Drops a running micro-break and starts the timer to the next over.
+    fn reset_micro(&mut self) {
+        self.micro_until = None;
+        self.micro_deadline = None;
+    }
+
+    /// Time left in the running micro-break.
+    pub fn micro_break_left(&self) -> Option<Duration> {
+        self.micro_until
+            .map(|until| until.saturating_duration_since(self.clock.now()))
+    }
+
+    /// Emits the warning and milestones once the work time left drops to
+    /// them. Jumping straight past the deadline (e.g. after suspend) emits
+    /// nothing, and of several milestones crossed at once only the last counts.
+    fn emit_crossings(&mut self) {
+        let left = self
+            .time_left()
+            .filter(|_| matches!(self.phase, Phase::Working | Phase::Snoozing));
+        let prev = self.last_left;
+        self.last_left = left;
+        let (Some(prev), Some(left)) = (prev, left) else {
+            return;
+        };
+        if left.is_zero() {
+            return;
+        }
+        let crossed = |at: Duration| prev > at && left <= at;
+        if let Some(milestone) = self
+            .cfg
+            .milestones
+            .iter()
+            .copied()
+            .filter(|at| crossed(*at))
+            .min()
+        {
+            self.emit(SchedulerEvent::Milestone { left: milestone });
+        }
+        if self.cfg.warn_before.is_some_and(crossed) {
+            self.emit(SchedulerEvent::BreakWarning { break_in: left });
+        }
+    }
+
+    /// When `tick` next has something to do: the deadline, or the warning or
+    /// a milestone coming up before it. `None` while paused or without a deadline.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        let deadline = self.deadline?;
+        if self.paused_remaining.is_some() {
+            return None;
+        }
+        let now = self.clock.now();
+        self.cfg
+            .warn_before
+            .iter()
+            .chain(&self.cfg.milestones)
+            .filter_map(|before| deadline.checked_sub(*before))
+            .filter(|at| *at > now)
+            .chain([deadline])
+            .chain(self.micro_deadline)
+            .chain(self.micro_until)
+            .min()
+    }
+
+    /// When the time left next drops to a whole number of minutes, after
+    /// which the status, shown in minutes, reads differently. `None` while
+    /// paused or without a deadline.
+    pub fn next_minute_mark(&self) -> Option<Instant> {
+        let deadline = self.deadline.filter(|_| self.paused_remaining.is_none())?;
+        let now = self.clock.now();
+        let minutes = deadline.saturating_duration_since(now).as_secs() / 60;
+        let mark = deadline.checked_sub(Duration::from_secs(minutes * 60))?;
+        Some(if mark > now {
+            mark
+        } else {
+            deadline.checked_sub(Duration::from_secs(minutes.saturating_sub(1) * 60))?
+        })
     }
 
     pub fn time_left(&self) -> Option<Duration> {
@@ -74,44 +415,281 @@ impl Scheduler {
             return Some(remaining);
         }
         self.deadline
-            .map(|d| d.saturating_duration_since(Instant::now()))
+            .map(|d| d.saturating_duration_since(self.clock.now()))
     }
 
     pub fn snooze_duration(&self) -> Duration {
-        let base = self.cfg.snooze_base.as_secs_f64();
-        let dec = self.cfg.snooze_decay.clamp(0.01, 0.999);
-        let dur = base * dec.powi(self.snooze_count as i32);
-        let dur = dur.round().max(self.cfg.snooze_min.as_secs_f64());
-        Duration::from_secs(dur as u64)
+        let base = self.cfg.snooze_base;
+        let dur = match &self.cfg.snooze_curve {
+            SnoozeCurve::Exponential { decay } => {
+                let dec = decay.clamp(0.01, 0.999);
+                let secs = base.as_secs_f64() * dec.powi(self.snooze_count as i32);
+                Duration::from_secs(secs.round() as u64)
+            }
+            SnoozeCurve::Linear { step } => base.saturating_sub(*step * self.snooze_count),
+            SnoozeCurve::Constant => base,
+            SnoozeCurve::Schedule(schedule) => schedule
+                .get(self.snooze_count as usize)
+                .or(schedule.last())
+                .copied()
+                .unwrap_or(base),
+        };
+        let dur = dur.max(self.cfg.snooze_min);
+        match self.snooze_budget() {
+            Some(budget) => dur.min(budget),
+            None => dur,
+        }
     }
 
-    pub fn can_snooze(&self) -> bool {
-        match self.cfg.max_snoozes {
-            None => true,
-            Some(n) => self.snooze_count < n,
+    /// Snooze time left this cycle; `None` without a cap.
+    fn snooze_budget(&self) -> Option<Duration> {
+        self.cfg
+            .max_snooze_total
+            .map(|max| max.saturating_sub(self.snoozed_total))
+    }
+
+    /// Snoozes still allowed this cycle; `None` when unlimited.
+    pub fn snoozes_left(&self) -> Option<u32> {
+        if self.snooze_budget().is_some_and(|budget| budget.is_zero()) {
+            return Some(0);
         }
+        self.cfg
+            .max_snoozes
+            .map(|max| max.saturating_sub(self.snooze_count))
+    }
+
+    pub fn can_snooze(&self) -> bool {
+        self.snoozes_left() != Some(0)
+    }
+
+    /// Forgets this cycle's snoozes.
+    fn reset_snoozes(&mut self) {
+        self.snooze_count = 0;
+        self.snoozed_total = Duration::ZERO;
     }
 
     pub fn start_break(&mut self) {
-        self.phase = Phase::OnBreak;
-        self.deadline = Some(Instant::now() + self.break_duration());
+        self.set_phase(Phase::OnBreak);
+        self.deadline = Some(self.clock.now() + self.break_duration());
         self.paused_remaining = None;
+        self.ended_early = None;
+    }
+
+    /// Whether enough of the running break has passed to end it early.
+    pub fn can_finish_early(&self) -> bool {
+        let (Some(fraction), Some(left)) = (self.cfg.early_finish_after, self.time_left()) else {
+            return false;
+        };
+        let total = self.break_duration();
+        self.phase == Phase::OnBreak
+            && (total - left.min(total)).as_secs_f64() >= total.as_secs_f64() * fraction
+    }
+
+    /// Ends the running break now if [`Self::can_finish_early`] allows it.
+    pub fn finish_early(&mut self) -> bool {
+        if !self.can_finish_early() {
+            return false;
+        }
+        let total = self.break_duration();
+        let left = self.time_left().unwrap_or_default();
+        self.ended_early = Some(total - left.min(total));
+        self.set_phase(Phase::BreakFinished);
+        self.deadline = None;
+        self.initial_cycle_done = true;
+        true
     }
 
     pub fn finish_and_restart(&mut self) {
-        self.phase = Phase::Working;
+        if self.phase == Phase::BreakFinished {
+            self.break_cycle = self.break_cycle.wrapping_add(1);
+        }
+        self.variant = BreakVariant::Rest;
+        self.set_phase(Phase::Working);
         self.initial_cycle_done = true;
-        self.deadline = Some(Instant::now() + self.cfg.interval);
-        self.snooze_count = 0;
+        self.deadline = Some(self.clock.now() + self.cfg.interval);
+        self.reset_snoozes();
+        self.carried_over = Duration::ZERO;
         self.paused_remaining = None;
+        self.reset_micro();
+    }
+
+    /// Skips the due (or running) break and starts the next work interval,
+    /// carrying a fraction of the skipped break over to the next one.
+    pub fn skip_break(&mut self) {
+        let owed = self
+            .break_duration()
+            .mul_f64(self.cfg.carry_over.clamp(0.0, 1.0));
+        self.finish_and_restart();
+        self.carried_over = Duration::from_secs(owed.as_secs());
+        self.emit(SchedulerEvent::BreakSkipped {
+            carried_over: self.carried_over,
+        });
+    }
+
+    /// Gives back part of an idle gap (a phone call, a chat) to the work
+    /// interval, so natural pauses count partially as rest. The time left
+    /// never grows past a full interval. Returns the time credited.
+    pub fn credit_idle(&mut self, idle: Duration) -> Option<Duration> {
+        if self.cfg.idle_credit <= 0.0 || !matches!(self.phase, Phase::Working | Phase::Snoozing) {
+            return None;
+        }
+        let left = self.time_left()?;
+        let credit = idle
+            .mul_f64(self.cfg.idle_credit.min(1.0))
+            .min(self.interval_duration().saturating_sub(left));
+        match self.paused_remaining.as_mut() {
+            Some(remaining) => *remaining += credit,
+            None => self.deadline = Some(self.clock.now() + left + credit),
+        }
+        Some(credit)
     }
 
+    /// Counts `elapsed` the timer did not see, e.g. a suspend, as work: the
+    /// break comes that much sooner, and at once if that is already past.
+    pub fn charge_work(&mut self, elapsed: Duration) {
+        if !matches!(self.phase, Phase::Working | Phase::Snoozing) {
+            return;
+        }
+        match self.paused_remaining.as_mut() {
+            Some(remaining) => *remaining = remaining.saturating_sub(elapsed),
+            None => {
+                if let Some(deadline) = self.deadline {
+                    let now = self.clock.now();
+                    self.deadline = Some(deadline.checked_sub(elapsed).unwrap_or(now).max(now));
+                }
+            }
+        }
+    }
+
+    /// Counts `elapsed` of work by the `active` share of it that had input:
+    /// constant input counts it as [`Config::adaptive_strain`] longer, none
+    /// as that much shorter, and half of it as it was.
+    pub fn weigh_work(&mut self, elapsed: Duration, active: f64) {
+        if self.cfg.adaptive_strain <= 0.0 || self.phase != Phase::Working || self.is_paused() {
+            return;
+        }
+        let Some(deadline) = self.deadline else {
+            return;
+        };
+        // Capped, so an idle stretch still counts for some work.
+        let strain = self.cfg.adaptive_strain.min(0.9) * (2.0 * active.clamp(0.0, 1.0) - 1.0);
+        let shift = elapsed.mul_f64(strain.abs());
+        self.deadline = Some(if strain > 0.0 {
+            let now = self.clock.now();
+            deadline.checked_sub(shift).unwrap_or(now).max(now)
+        } else {
+            deadline + shift
+        });
+    }
+
+    /// Changes the work interval. A work period under way gets the difference
+    /// too, so it ends as if it had started with the new interval (and at
+    /// once if that is already over).
+    pub fn set_interval(&mut self, interval: Duration) {
+        let old = self.interval_duration();
+        self.cfg.interval = interval;
+        if self.phase != Phase::Working || !self.initial_cycle_done {
+            return;
+        }
+        let adjust = |left: Duration| {
+            if interval >= old {
+                left + (interval - old)
+            } else {
+                left.saturating_sub(old - interval)
+            }
+        };
+        if let Some(remaining) = &mut self.paused_remaining {
+            *remaining = adjust(*remaining);
+        } else if let Some(deadline) = self.deadline {
+            let now = self.clock.now();
+            self.deadline = Some(now + adjust(deadline.saturating_duration_since(now)));
+        }
+    }
+
+    /// Moves this timer to match another instance that is in `phase` with
+    /// `left` of it to go (`None` while it holds breaks back). Breaks start
+    /// and end only when that phase `changed`, so a break skipped here is not
+    /// brought back until the next one.
+    pub fn mirror(&mut self, phase: Phase, left: Option<Duration>, changed: bool) {
+        let now = self.clock.now();
+        let working = matches!(self.phase, Phase::Working | Phase::Snoozing);
+        match phase {
+            Phase::Working | Phase::Snoozing => {
+                if changed && !working {
+                    self.finish_and_restart();
+                }
+                if matches!(self.phase, Phase::Working | Phase::Snoozing) && !self.is_paused() {
+                    self.deadline = Some(now + left.unwrap_or(self.cfg.interval));
+                }
+            }
+            Phase::LockedAwaitingAction if changed && working => self.break_now(),
+            Phase::OnBreak => {
+                if changed && working {
+                    self.break_now();
+                }
+                if changed && self.phase == Phase::LockedAwaitingAction {
+                    self.start_break();
+                }
+                if let (Phase::OnBreak, Some(left)) = (self.phase, left) {
+                    self.deadline = Some(now + left);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Makes the break due right away, e.g. when asked for one early.
+    pub fn break_now(&mut self) {
+        if self.phase == Phase::Working {
+            self.pick_variant();
+        }
+        self.set_phase(Phase::LockedAwaitingAction);
+        self.deadline = None;
+        self.paused_remaining = None;
+    }
+
+    /// Makes the wrap-up break due right away, at the end of the working day.
+    pub fn wrap_up(&mut self) {
+        self.variant = BreakVariant::WrapUp;
+        self.last_break_day = Some(self.clock.today());
+        self.set_phase(Phase::LockedAwaitingAction);
+        self.deadline = None;
+        self.paused_remaining = None;
+    }
+
+    /// Lengthens the running break by `by`.
+    pub fn extend_break(&mut self, by: Duration) {
+        if self.phase == Phase::OnBreak
+            && let Some(deadline) = self.deadline
+        {
+            self.deadline = Some(deadline + by);
+        }
+    }
+
+    /// Snoozes the due (or running) break. While still working, the snooze
+    /// is added to the time left instead, pushing the next break back without
+    /// leaving the phase, so the break still counts as a new one when due.
     pub fn snooze(&mut self) -> Duration {
         let d = self.snooze_duration();
         self.snooze_count = self.snooze_count.saturating_add(1);
-        self.phase = Phase::Snoozing;
-        self.deadline = Some(Instant::now() + d);
-        self.paused_remaining = None;
+        self.snoozed_total += d;
+        if matches!(self.phase, Phase::Working | Phase::Snoozing) {
+            // Without either, the session lock holds the timer, and the
+            // unlock starts a fresh interval anyway.
+            if let Some(remaining) = self.paused_remaining.as_mut() {
+                *remaining += d;
+            } else if let Some(deadline) = self.deadline.as_mut() {
+                *deadline += d;
+            }
+        } else {
+            self.set_phase(Phase::Snoozing);
+            self.deadline = Some(self.clock.now() + d);
+            self.paused_remaining = None;
+        }
+        self.emit(SchedulerEvent::SnoozeTaken {
+            duration: d,
+            count: self.snooze_count,
+        });
         d
     }
 
@@ -119,7 +697,7 @@ impl Scheduler {
         let base = self.current_break_len().as_secs_f64();
         let multiplier = 1.0 + (self.snooze_count as f64 * 0.1);
         let dur = (base * multiplier).round().max(base);
-        Duration::from_secs(dur as u64)
+        Duration::from_secs(dur as u64) + self.carried_over
     }
 
     pub fn interval_duration(&self) -> Duration {
@@ -130,26 +708,93 @@ impl Scheduler {
         }
     }
 
+    /// Whether the current (or upcoming) break is the long one of its cycle.
+    pub fn is_long_break(&self) -> bool {
+        self.initial_cycle_done
+            && self
+                .cfg
+                .long_break_every
+                .is_some_and(|every| self.break_cycle.wrapping_add(1).is_multiple_of(every))
+    }
+
+    /// Where the current (or upcoming) break falls in the long-break cycle:
+    /// `(n, every)`, where break `every` is the long one. `None` without
+    /// long breaks and before the first break.
+    pub fn break_in_cycle(&self) -> Option<(u32, u32)> {
+        let every = self.cfg.long_break_every.filter(|&every| every > 0)?;
+        self.initial_cycle_done
+            .then(|| (self.break_cycle % every + 1, every))
+    }
+
+    /// Chooses the variant of the break becoming due at the start of a cycle;
+    /// a snoozed break coming back keeps its variant.
+    fn pick_variant(&mut self) {
+        let today = self.clock.today();
+        let first_today = self.last_break_day != Some(today);
+        self.last_break_day = Some(today);
+        self.variant = if self.cfg.planning_break && first_today {
+            BreakVariant::Planning
+        } else {
+            BreakVariant::Rest
+        };
+    }
+
+    pub fn break_kind(&self) -> BreakKind {
+        if self.is_long_break() {
+            BreakKind::Long
+        } else {
+            BreakKind::Regular
+        }
+    }
+
     fn current_break_len(&self) -> Duration {
-        if self.initial_cycle_done {
+        if self.variant == BreakVariant::WrapUp {
+            self.cfg.wrap_up_len
+        } else if self.is_long_break() {
+            self.cfg.long_break_len
+        } else if self.initial_cycle_done {
             self.cfg.break_len
         } else {
             self.cfg.initial_break_len
         }
     }
 
+    /// Pushes the next break at least `grace` into the future, so a restored
+    /// state whose break was already due does not fire right after startup.
+    pub fn apply_startup_grace(&mut self, grace: Duration) {
+        if grace.is_zero() {
+            return;
+        }
+        let earliest = self.clock.now() + grace;
+        match self.phase {
+            Phase::Working | Phase::Snoozing => {
+                if self.deadline.is_none_or(|deadline| deadline >= earliest) {
+                    return;
+                }
+                self.deadline = Some(earliest);
+            }
+            Phase::LockedAwaitingAction | Phase::OnBreak | Phase::BreakFinished => {
+                self.set_phase(Phase::Working);
+                self.deadline = Some(earliest);
+            }
+        }
+        self.emit(SchedulerEvent::Deferred { next_in: grace });
+    }
+
     pub fn handle_session_locked(&mut self) {
-        self.phase = Phase::Working;
+        self.set_phase(Phase::Working);
         self.deadline = None;
-        self.snooze_count = 0;
+        self.reset_snoozes();
         self.paused_remaining = None;
+        self.reset_micro();
     }
 
     pub fn handle_session_unlocked(&mut self) {
-        self.phase = Phase::Working;
-        self.deadline = Some(Instant::now() + self.interval_duration());
-        self.snooze_count = 0;
+        self.set_phase(Phase::Working);
+        self.deadline = Some(self.clock.now() + self.interval_duration());
+        self.reset_snoozes();
         self.paused_remaining = None;
+        self.reset_micro();
     }
 
     pub fn pause_interval(&mut self) -> bool {
@@ -162,9 +807,10 @@ impl Scheduler {
         let Some(deadline) = self.deadline else {
             return false;
         };
-        let remaining = deadline.saturating_duration_since(Instant::now());
+        let remaining = deadline.saturating_duration_since(self.clock.now());
         self.deadline = None;
         self.paused_remaining = Some(remaining);
+        self.reset_micro();
         true
     }
 
@@ -173,7 +819,7 @@ impl Scheduler {
             return false;
         };
         if matches!(self.phase, Phase::Working | Phase::Snoozing) {
-            self.deadline = Some(Instant::now() + remaining);
+            self.deadline = Some(self.clock.now() + remaining);
         } else {
             self.deadline = None;
         }
@@ -188,6 +834,8 @@ impl Scheduler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+    use proptest::test_runner::TestCaseError;
 
     fn test_cfg() -> Config {
         let interval = Duration::from_secs(10);
@@ -198,9 +846,22 @@ mod tests {
             initial_interval: interval,
             initial_break_len: break_len,
             snooze_base: Duration::from_secs(100),
-            snooze_decay: 0.5,
+            snooze_curve: SnoozeCurve::Exponential { decay: 0.5 },
             snooze_min: Duration::from_secs(30),
             max_snoozes: Some(2),
+            max_snooze_total: None,
+            long_break_every: None,
+            long_break_len: Duration::from_secs(60),
+            carry_over: 0.5,
+            warn_before: Some(Duration::from_secs(3)),
+            milestones: Vec::new(),
+            early_finish_after: Some(0.5),
+            idle_credit: 0.5,
+            adaptive_strain: 0.0,
+            planning_break: false,
+            wrap_up_len: Duration::from_secs(600),
+            micro_every: None,
+            micro_len: Duration::from_secs(20),
         }
     }
 
@@ -223,6 +884,323 @@ mod tests {
         assert!(sched.deadline.is_none());
     }
 
+    #[test]
+    fn subscribers_see_phase_changes_and_snoozes() {
+        let mut sched = Scheduler::new(test_cfg());
+        let events = sched.subscribe();
+        sched.deadline = Some(Instant::now() - Duration::from_secs(1));
+        sched.tick();
+        let d = sched.snooze();
+        sched.skip_break();
+        let got: Vec<_> = events.try_iter().collect();
+        assert_eq!(
+            got,
+            vec![
+                SchedulerEvent::PhaseChanged {
+                    from: Phase::Working,
+                    to: Phase::LockedAwaitingAction,
+                    kind: BreakKind::Regular,
+                },
+                SchedulerEvent::PhaseChanged {
+                    from: Phase::LockedAwaitingAction,
+                    to: Phase::Snoozing,
+                    kind: BreakKind::Regular,
+                },
+                SchedulerEvent::SnoozeTaken {
+                    duration: d,
+                    count: 1
+                },
+                SchedulerEvent::PhaseChanged {
+                    from: Phase::Snoozing,
+                    to: Phase::Working,
+                    kind: BreakKind::Regular,
+                },
+                SchedulerEvent::BreakSkipped {
+                    carried_over: Duration::from_secs(3)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn dropped_subscribers_are_forgotten() {
+        let mut sched = Scheduler::new(test_cfg());
+        drop(sched.subscribe());
+        sched.break_now();
+        assert!(sched.subscribers.is_empty());
+    }
+
+    #[test]
+    fn warning_fires_once_when_threshold_is_crossed() {
+        let mut sched = Scheduler::new(test_cfg());
+        let events = sched.subscribe();
+        sched.tick();
+        sched.deadline = Some(Instant::now() + Duration::from_secs(2));
+        sched.tick();
+        sched.tick();
+        let warnings: Vec<_> = events
+            .try_iter()
+            .filter(|event| matches!(event, SchedulerEvent::BreakWarning { .. }))
+            .collect();
+        assert_eq!(warnings.len(), 1);
+
+        // A deadline that has already passed gets the break, not a warning.
+        sched.finish_and_restart();
+        sched.tick();
+        sched.deadline = Some(Instant::now() - Duration::from_secs(1));
+        sched.tick();
+        assert!(
+            !events
+                .try_iter()
+                .any(|event| matches!(event, SchedulerEvent::BreakWarning { .. }))
+        );
+        assert_eq!(sched.phase, Phase::LockedAwaitingAction);
+    }
+
+    #[test]
+    fn milestones_report_the_last_one_crossed() {
+        let mut cfg = test_cfg();
+        cfg.milestones = [60, 30, 10].map(Duration::from_secs).to_vec();
+        let mut sched = Scheduler::new(cfg);
+        let events = sched.subscribe();
+        sched.deadline = Some(Instant::now() + Duration::from_secs(45));
+        sched.tick();
+        sched.deadline = Some(Instant::now() + Duration::from_secs(20));
+        sched.tick();
+        sched.deadline = Some(Instant::now() + Duration::from_secs(5));
+        sched.tick();
+        let milestones: Vec<_> = events
+            .try_iter()
+            .filter_map(|event| match event {
+                SchedulerEvent::Milestone { left } => Some(left.as_secs()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(milestones, vec![30, 10]);
+    }
+
+    #[test]
+    fn next_minute_mark_is_where_the_minutes_left_change() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let mut sched = Scheduler::with_clock(test_cfg(), Clock::manual(&now));
+        let deadline = sched.clock.now() + Duration::from_millis(125_500);
+        sched.deadline = Some(deadline);
+        assert_eq!(
+            sched.next_minute_mark(),
+            Some(deadline - Duration::from_secs(120))
+        );
+        // Right on a mark, the next one is a minute on.
+        *now.lock().unwrap() = deadline - Duration::from_secs(120);
+        assert_eq!(
+            sched.next_minute_mark(),
+            Some(deadline - Duration::from_secs(60))
+        );
+        *now.lock().unwrap() = deadline - Duration::from_secs(20);
+        assert_eq!(sched.next_minute_mark(), Some(deadline));
+        assert!(sched.pause_interval());
+        assert_eq!(sched.next_minute_mark(), None);
+    }
+
+    #[test]
+    fn next_wakeup_stops_at_warning_and_milestones() {
+        let mut cfg = test_cfg();
+        cfg.milestones = vec![Duration::from_secs(60)];
+        let mut sched = Scheduler::new(cfg);
+        let deadline = Instant::now() + Duration::from_secs(120);
+        sched.deadline = Some(deadline);
+        assert_eq!(
+            sched.next_wakeup(),
+            Some(deadline - Duration::from_secs(60))
+        );
+        sched.deadline = Some(Instant::now() + Duration::from_secs(30));
+        assert_eq!(
+            sched.next_wakeup(),
+            Some(sched.deadline.unwrap() - Duration::from_secs(3))
+        );
+        sched.deadline = Some(Instant::now() + Duration::from_secs(2));
+        assert_eq!(sched.next_wakeup(), sched.deadline);
+        assert!(sched.pause_interval());
+        assert_eq!(sched.next_wakeup(), None);
+    }
+
+    #[test]
+    fn break_now_and_extend() {
+        let mut sched = Scheduler::new(test_cfg());
+        sched.break_now();
+        assert_eq!(sched.phase, Phase::LockedAwaitingAction);
+        assert!(sched.deadline.is_none());
+
+        sched.extend_break(Duration::from_secs(60));
+        assert!(sched.deadline.is_none());
+        sched.start_break();
+        let before = sched.deadline.unwrap();
+        sched.extend_break(Duration::from_secs(60));
+        assert_eq!(sched.deadline.unwrap() - before, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn break_ends_early_only_after_minimum() {
+        let mut sched = Scheduler::new(test_cfg());
+        sched.start_break();
+        assert!(!sched.finish_early());
+        assert_eq!(sched.phase, Phase::OnBreak);
+
+        sched.deadline = Some(Instant::now() + Duration::from_secs(2));
+        assert!(sched.finish_early());
+        assert_eq!(sched.phase, Phase::BreakFinished);
+        assert!(sched.ended_early.unwrap() >= Duration::from_secs(2));
+
+        sched.cfg.early_finish_after = None;
+        sched.start_break();
+        sched.deadline = Some(Instant::now());
+        assert!(!sched.can_finish_early());
+    }
+
+    #[test]
+    fn idle_credit_is_capped_at_an_interval() {
+        let mut sched = Scheduler::new(test_cfg());
+        sched.deadline = Some(Instant::now() + Duration::from_secs(4));
+        let credit = sched.credit_idle(Duration::from_secs(4)).unwrap();
+        assert_eq!(credit, Duration::from_secs(2));
+        assert!(sched.time_left().unwrap() > Duration::from_secs(5));
+
+        let credit = sched.credit_idle(Duration::from_secs(60)).unwrap();
+        assert!(credit < Duration::from_secs(5));
+        assert!(sched.time_left().unwrap() <= Duration::from_secs(10));
+
+        sched.start_break();
+        assert_eq!(sched.credit_idle(Duration::from_secs(4)), None);
+    }
+
+    #[test]
+    fn charged_work_brings_the_break_closer() {
+        let mut sched = Scheduler::new(test_cfg());
+        let now = Instant::now();
+        sched.deadline = Some(now + Duration::from_secs(8));
+        sched.charge_work(Duration::from_secs(3));
+        assert_eq!(sched.deadline, Some(now + Duration::from_secs(5)));
+        sched.charge_work(Duration::from_secs(60));
+        assert!(sched.time_left().unwrap().is_zero());
+    }
+
+    #[test]
+    fn heavy_use_brings_the_break_closer() {
+        let mut cfg = test_cfg();
+        cfg.adaptive_strain = 0.25;
+        let mut sched = Scheduler::new(cfg);
+        let minute = Duration::from_secs(60);
+        let deadline = Instant::now() + 10 * minute;
+        sched.deadline = Some(deadline);
+        sched.weigh_work(minute, 1.0);
+        assert_eq!(sched.deadline, Some(deadline - minute / 4));
+        sched.weigh_work(minute, 0.5);
+        assert_eq!(sched.deadline, Some(deadline - minute / 4));
+        sched.weigh_work(2 * minute, 0.0);
+        assert_eq!(sched.deadline, Some(deadline + minute / 4));
+
+        sched.start_break();
+        let on_break = sched.deadline;
+        sched.weigh_work(minute, 1.0);
+        assert_eq!(sched.deadline, on_break);
+    }
+
+    #[test]
+    fn a_new_interval_moves_the_running_work_period() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let mut sched = Scheduler::with_clock(test_cfg(), Clock::manual(&now));
+        let secs = Duration::from_secs;
+        sched.initial_cycle_done = true;
+        sched.deadline = Some(*now.lock().unwrap() + secs(6));
+        sched.set_interval(secs(30));
+        assert_eq!(sched.time_left(), Some(secs(26)));
+        sched.set_interval(secs(5));
+        assert_eq!(sched.time_left(), Some(secs(1)));
+        sched.set_interval(secs(2));
+        assert_eq!(sched.time_left(), Some(Duration::ZERO));
+
+        sched.start_break();
+        let on_break = sched.deadline;
+        sched.set_interval(secs(60));
+        assert_eq!(sched.deadline, on_break);
+        assert_eq!(sched.cfg.interval, secs(60));
+    }
+
+    #[test]
+    fn micro_breaks_run_between_breaks() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let secs = Duration::from_secs;
+        let cfg = Config {
+            interval: secs(100),
+            initial_interval: secs(100),
+            micro_every: Some(secs(20)),
+            micro_len: secs(5),
+            ..test_cfg()
+        };
+        let mut sched = Scheduler::with_clock(cfg, Clock::manual(&now));
+        let events = sched.subscribe();
+        let advance = |by: Duration| *now.lock().unwrap() += by;
+        sched.tick();
+        assert_eq!(sched.next_wakeup(), Some(*now.lock().unwrap() + secs(20)));
+        advance(secs(20));
+        sched.tick();
+        assert_eq!(sched.micro_break_left(), Some(secs(5)));
+        assert_eq!(
+            events.try_recv(),
+            Ok(SchedulerEvent::MicroBreakStarted { duration: secs(5) })
+        );
+        advance(secs(5));
+        sched.tick();
+        assert_eq!(sched.micro_break_left(), None);
+        assert_eq!(events.try_recv(), Ok(SchedulerEvent::MicroBreakFinished));
+        // The real break keeps its time; a micro-break right before it is
+        // left out.
+        assert_eq!(sched.time_left(), Some(secs(75)));
+        sched.deadline = Some(*now.lock().unwrap() + secs(24));
+        advance(secs(20));
+        sched.tick();
+        assert_eq!(sched.micro_break_left(), None);
+
+        // A pause or a break starts the count over.
+        sched.finish_and_restart();
+        sched.tick();
+        advance(secs(15));
+        sched.tick();
+        assert!(sched.pause_interval());
+        assert!(sched.resume_interval());
+        sched.tick();
+        advance(secs(15));
+        sched.tick();
+        assert_eq!(sched.micro_break_left(), None);
+        advance(secs(5));
+        sched.tick();
+        assert_eq!(sched.micro_break_left(), Some(secs(5)));
+    }
+
+    #[test]
+    fn follower_mirrors_the_leaders_breaks() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let mut sched = Scheduler::with_clock(test_cfg(), Clock::manual(&now));
+        let secs = Duration::from_secs;
+        sched.mirror(Phase::Working, Some(secs(30)), true);
+        assert_eq!(sched.time_left(), Some(secs(30)));
+
+        sched.mirror(Phase::LockedAwaitingAction, None, true);
+        assert_eq!(sched.phase, Phase::LockedAwaitingAction);
+        sched.mirror(Phase::OnBreak, Some(secs(8)), true);
+        assert_eq!(sched.phase, Phase::OnBreak);
+        assert_eq!(sched.time_left(), Some(secs(8)));
+
+        // Skipped here: the leader's break is not forced back.
+        sched.skip_break();
+        sched.mirror(Phase::OnBreak, Some(secs(6)), false);
+        assert_eq!(sched.phase, Phase::Working);
+
+        sched.break_now();
+        sched.mirror(Phase::Working, Some(secs(40)), true);
+        assert_eq!(sched.phase, Phase::Working);
+        assert_eq!(sched.time_left(), Some(secs(40)));
+    }
+
     #[test]
     fn snooze_duration_decays_with_floor() {
         let mut sched = Scheduler::new(test_cfg());
@@ -234,6 +1212,70 @@ mod tests {
         assert_eq!(sched.snooze_duration().as_secs(), 30);
     }
 
+    fn snoozes_with(curve: SnoozeCurve) -> Vec<u64> {
+        let mut sched = Scheduler::new(Config {
+            snooze_curve: curve,
+            ..test_cfg()
+        });
+        (0..4)
+            .map(|count| {
+                sched.snooze_count = count;
+                sched.snooze_duration().as_secs()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snooze_duration_shrinks_linearly() {
+        let curve = SnoozeCurve::Linear {
+            step: Duration::from_secs(30),
+        };
+        assert_eq!(snoozes_with(curve), vec![100, 70, 40, 30]);
+    }
+
+    #[test]
+    fn snooze_duration_can_stay_constant() {
+        assert_eq!(snoozes_with(SnoozeCurve::Constant), vec![100; 4]);
+    }
+
+    #[test]
+    fn snooze_duration_follows_schedule() {
+        let schedule = [300, 120, 60].map(Duration::from_secs).to_vec();
+        assert_eq!(
+            snoozes_with(SnoozeCurve::Schedule(schedule)),
+            vec![300, 120, 60, 60]
+        );
+        // The floor still applies, and an empty schedule falls back to the base.
+        let schedule = vec![Duration::from_secs(10)];
+        assert_eq!(snoozes_with(SnoozeCurve::Schedule(schedule)), vec![30; 4]);
+        assert_eq!(
+            snoozes_with(SnoozeCurve::Schedule(Vec::new())),
+            vec![100; 4]
+        );
+    }
+
+    #[test]
+    fn total_snooze_time_is_capped() {
+        let mut sched = Scheduler::new(Config {
+            max_snoozes: None,
+            max_snooze_total: Some(Duration::from_secs(170)),
+            ..test_cfg()
+        });
+        assert_eq!(sched.snooze().as_secs(), 100);
+        assert!(sched.can_snooze());
+        assert_eq!(sched.snooze().as_secs(), 50);
+        // The floor would make the third one 30, but only 20 are left.
+        assert_eq!(sched.snooze_duration().as_secs(), 20);
+        assert_eq!(sched.snooze().as_secs(), 20);
+        assert_eq!(sched.snoozed_total.as_secs(), 170);
+        assert!(!sched.can_snooze());
+        assert_eq!(sched.snoozes_left(), Some(0));
+        sched.finish_and_restart();
+        assert!(sched.can_snooze());
+        assert_eq!(sched.snoozes_left(), None);
+        assert_eq!(sched.snoozed_total, Duration::ZERO);
+    }
+
     #[test]
     fn snooze_resets_after_finish() {
         let mut sched = Scheduler::new(test_cfg());
@@ -244,6 +1286,57 @@ mod tests {
         assert_eq!(sched.phase, Phase::Working);
     }
 
+    #[test]
+    fn snooze_while_working_delays_next_break() {
+        let mut sched = Scheduler::new(test_cfg());
+        let d = sched.snooze();
+        assert_eq!(d.as_secs(), 100);
+        assert_eq!(sched.phase, Phase::Working);
+        assert!(sched.time_left().unwrap() > Duration::from_secs(109));
+        assert_eq!(sched.break_duration().as_secs(), 6);
+
+        assert!(sched.pause_interval());
+        let _ = sched.snooze();
+        assert!(sched.is_paused());
+        assert!(sched.time_left().unwrap() > Duration::from_secs(159));
+    }
+
+    #[test]
+    fn break_snoozed_while_working_is_still_a_new_break() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let cfg = Config {
+            planning_break: true,
+            ..test_cfg()
+        };
+        let mut sched = Scheduler::with_clock(cfg, Clock::manual(&now));
+        sched.last_break_day = Some(sched.clock.today().pred_opt().unwrap());
+        sched.variant = BreakVariant::WrapUp;
+        let rx = sched.subscribe();
+        let _ = sched.snooze();
+        *now.lock().unwrap() += Duration::from_secs(110);
+        sched.tick();
+        assert_eq!(sched.phase, Phase::LockedAwaitingAction);
+        // Stats and the headline count a break that became due from Working.
+        let from = rx.try_iter().find_map(|event| match event {
+            SchedulerEvent::PhaseChanged { from, .. } => Some(from),
+            _ => None,
+        });
+        assert_eq!(from, Some(Phase::Working));
+        // The variant is picked afresh and the planning day rolls over.
+        assert_eq!(sched.variant, BreakVariant::Planning);
+        assert_eq!(sched.last_break_day, Some(sched.clock.today()));
+    }
+
+    #[test]
+    fn snooze_under_a_session_lock_sets_no_deadline() {
+        let mut sched = Scheduler::new(test_cfg());
+        sched.handle_session_locked();
+        let _ = sched.snooze();
+        assert_eq!(sched.phase, Phase::Working);
+        assert_eq!(sched.deadline, None);
+        assert!(!sched.is_paused());
+    }
+
     #[test]
     fn break_duration_increases_with_snooze_count() {
         let mut cfg = test_cfg();
@@ -262,9 +1355,11 @@ mod tests {
     fn can_snooze_respects_max() {
         let mut sched = Scheduler::new(test_cfg());
         assert!(sched.can_snooze());
+        assert_eq!(sched.snoozes_left(), Some(2));
         let _ = sched.snooze();
         let _ = sched.snooze();
         assert!(!sched.can_snooze());
+        assert_eq!(sched.snoozes_left(), Some(0));
     }
 
     #[test]
@@ -322,4 +1417,294 @@ mod tests {
         assert!(!sched.is_paused());
         assert!(sched.deadline.is_some());
     }
+
+    #[test]
+    fn every_nth_break_is_long() {
+        let mut cfg = test_cfg();
+        cfg.long_break_every = Some(3);
+        let mut sched = Scheduler::new(cfg);
+        let events = sched.subscribe();
+        sched.initial_cycle_done = true;
+        let mut lens = Vec::new();
+        let mut kinds = Vec::new();
+        let mut positions = Vec::new();
+        for _ in 0..6 {
+            lens.push(sched.break_duration().as_secs());
+            positions.push(sched.break_in_cycle().map(|(n, _)| n));
+            sched.start_break();
+            sched.deadline = Some(Instant::now() - Duration::from_secs(1));
+            sched.tick();
+            assert_eq!(sched.phase, Phase::BreakFinished);
+            assert_eq!(sched.break_duration().as_secs(), *lens.last().unwrap());
+            sched.finish_and_restart();
+            kinds.extend(events.try_iter().filter_map(|event| match event {
+                SchedulerEvent::PhaseChanged {
+                    to: Phase::OnBreak,
+                    kind,
+                    ..
+                } => Some(kind),
+                _ => None,
+            }));
+        }
+        assert_eq!(lens, vec![5, 5, 60, 5, 5, 60]);
+        assert_eq!(positions, [1, 2, 3, 1, 2, 3].map(Some));
+        let (regular, long) = (BreakKind::Regular, BreakKind::Long);
+        assert_eq!(kinds, vec![regular, regular, long, regular, regular, long]);
+    }
+
+    #[test]
+    fn idle_restart_does_not_advance_long_break_cycle() {
+        let mut cfg = test_cfg();
+        cfg.long_break_every = Some(2);
+        let mut sched = Scheduler::new(cfg);
+        sched.initial_cycle_done = true;
+        sched.finish_and_restart();
+        assert_eq!(sched.break_cycle, 0);
+        assert!(!sched.is_long_break());
+    }
+
+    #[test]
+    fn first_break_of_the_day_is_for_planning() {
+        let mut cfg = test_cfg();
+        cfg.planning_break = true;
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let mut sched = Scheduler::with_clock(cfg, Clock::manual(&now));
+        let due = |sched: &mut Scheduler| {
+            sched.deadline = Some(sched.clock.now());
+            sched.tick();
+        };
+        due(&mut sched);
+        assert_eq!(sched.variant, BreakVariant::Planning);
+        // Coming back from a snooze is the same break.
+        sched.snooze();
+        due(&mut sched);
+        assert_eq!(sched.variant, BreakVariant::Planning);
+        sched.skip_break();
+        due(&mut sched);
+        assert_eq!(sched.variant, BreakVariant::Rest);
+        // The date comes from the scheduler's clock, so a day later on it
+        // plans again.
+        sched.skip_break();
+        *now.lock().unwrap() += Duration::from_secs(24 * 60 * 60);
+        due(&mut sched);
+        assert_eq!(sched.variant, BreakVariant::Planning);
+
+        sched.finish_and_restart();
+        sched.cfg.planning_break = false;
+        sched.last_break_day = None;
+        sched.break_now();
+        assert_eq!(sched.variant, BreakVariant::Rest);
+    }
+
+    #[test]
+    fn wrap_up_is_a_longer_break_for_one_cycle() {
+        let mut sched = Scheduler::new(test_cfg());
+        sched.wrap_up();
+        assert_eq!(sched.phase, Phase::LockedAwaitingAction);
+        assert_eq!(sched.variant, BreakVariant::WrapUp);
+        assert_eq!(sched.break_duration(), Duration::from_secs(600));
+        sched.start_break();
+        sched.deadline = Some(Instant::now() - Duration::from_secs(1));
+        sched.tick();
+        sched.finish_and_restart();
+        assert_eq!(sched.variant, BreakVariant::Rest);
+        assert_eq!(sched.break_duration(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn skipped_break_carries_over_until_taken() {
+        let mut cfg = test_cfg();
+        cfg.break_len = Duration::from_secs(100);
+        cfg.initial_break_len = cfg.break_len;
+        let mut sched = Scheduler::new(cfg);
+        sched.phase = Phase::LockedAwaitingAction;
+        sched.deadline = None;
+        sched.skip_break();
+        assert_eq!(sched.phase, Phase::Working);
+        assert!(sched.deadline.is_some());
+        assert_eq!(sched.carried_over.as_secs(), 50);
+        assert_eq!(sched.break_duration().as_secs(), 150);
+
+        sched.phase = Phase::LockedAwaitingAction;
+        sched.skip_break();
+        assert_eq!(sched.carried_over.as_secs(), 75);
+
+        sched.start_break();
+        sched.deadline = Some(Instant::now() - Duration::from_secs(1));
+        sched.tick();
+        sched.finish_and_restart();
+        assert_eq!(sched.carried_over, Duration::ZERO);
+        assert_eq!(sched.break_duration().as_secs(), 100);
+    }
+
+    #[test]
+    fn startup_grace_defers_due_break() {
+        let grace = Duration::from_secs(120);
+        let mut sched = Scheduler::new(test_cfg());
+        sched.phase = Phase::LockedAwaitingAction;
+        sched.deadline = None;
+        sched.snooze_count = 1;
+        let before = Instant::now();
+        sched.apply_startup_grace(grace);
+        assert_eq!(sched.phase, Phase::Working);
+        assert!(sched.deadline.expect("deadline set") >= before + grace);
+        assert_eq!(sched.snooze_count, 1);
+    }
+
+    #[test]
+    fn startup_grace_keeps_later_deadline() {
+        let mut sched = Scheduler::new(test_cfg());
+        let later = Instant::now() + Duration::from_secs(600);
+        sched.deadline = Some(later);
+        sched.apply_startup_grace(Duration::from_secs(120));
+        assert_eq!(sched.deadline, Some(later));
+
+        sched.deadline = Some(Instant::now() + Duration::from_secs(5));
+        sched.apply_startup_grace(Duration::from_secs(120));
+        assert!(sched.deadline.unwrap() > Instant::now() + Duration::from_secs(100));
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Op {
+        Advance(u64),
+        Tick,
+        StartBreak,
+        Snooze,
+        Skip,
+        FinishEarly,
+        FinishAndRestart,
+        BreakNow,
+        ExtendBreak,
+        Pause,
+        Resume,
+        CreditIdle(u64),
+        SessionLocked,
+        SessionUnlocked,
+        StartupGrace(u64),
+    }
+
+    impl Op {
+        /// Whether the operation starts a new cycle, forgetting its snoozes.
+        fn starts_cycle(self) -> bool {
+            matches!(
+                self,
+                Self::Skip | Self::FinishAndRestart | Self::SessionLocked | Self::SessionUnlocked
+            )
+        }
+
+        fn apply(self, sched: &mut Scheduler, now: &Mutex<Instant>) {
+            match self {
+                Self::Advance(secs) => *now.lock().unwrap() += Duration::from_secs(secs),
+                Self::Tick => sched.tick(),
+                Self::StartBreak => sched.start_break(),
+                Self::Snooze => {
+                    let _ = sched.snooze();
+                }
+                Self::Skip => sched.skip_break(),
+                Self::FinishEarly => {
+                    let _ = sched.finish_early();
+                }
+                Self::FinishAndRestart => sched.finish_and_restart(),
+                Self::BreakNow => sched.break_now(),
+                Self::ExtendBreak => sched.extend_break(Duration::from_secs(60)),
+                Self::Pause => {
+                    let _ = sched.pause_interval();
+                }
+                Self::Resume => {
+                    let _ = sched.resume_interval();
+                }
+                Self::CreditIdle(secs) => {
+                    let _ = sched.credit_idle(Duration::from_secs(secs));
+                }
+                Self::SessionLocked => sched.handle_session_locked(),
+                Self::SessionUnlocked => sched.handle_session_unlocked(),
+                Self::StartupGrace(secs) => sched.apply_startup_grace(Duration::from_secs(secs)),
+            }
+        }
+    }
+
+    fn op_strategy() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..20u64).prop_map(Op::Advance),
+            Just(Op::Tick),
+            Just(Op::StartBreak),
+            Just(Op::Snooze),
+            Just(Op::Skip),
+            Just(Op::FinishEarly),
+            Just(Op::FinishAndRestart),
+            Just(Op::BreakNow),
+            Just(Op::ExtendBreak),
+            Just(Op::Pause),
+            Just(Op::Resume),
+            (0..60u64).prop_map(Op::CreditIdle),
+            Just(Op::SessionLocked),
+            Just(Op::SessionUnlocked),
+            (0..30u64).prop_map(Op::StartupGrace),
+        ]
+    }
+
+    /// `held` is whether a session lock holds the timer, the one time a
+    /// working timer neither runs nor is paused.
+    fn check_invariants(sched: &Scheduler, held: bool) -> Result<(), String> {
+        let has_deadline = sched.deadline.is_some();
+        match sched.phase {
+            // A timed phase runs to a deadline or is paused, never both.
+            Phase::Working | Phase::Snoozing if has_deadline && sched.is_paused() => {
+                return Err("deadline and paused time both set".to_string());
+            }
+            Phase::Working if held => {}
+            Phase::Working | Phase::Snoozing if !has_deadline && !sched.is_paused() => {
+                return Err(format!("{:?} without a deadline", sched.phase));
+            }
+            Phase::Working | Phase::Snoozing => {}
+            Phase::OnBreak if !has_deadline => return Err("break without a deadline".to_string()),
+            Phase::LockedAwaitingAction | Phase::BreakFinished if has_deadline => {
+                return Err(format!("deadline set in {:?}", sched.phase));
+            }
+            _ if sched.is_paused() => return Err(format!("paused in {:?}", sched.phase)),
+            _ => {}
+        }
+        let snooze = sched.snooze_duration();
+        if snooze < sched.cfg.snooze_min && sched.snooze_budget() != Some(snooze) {
+            return Err(format!("snooze of {snooze:?} below the floor"));
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn invariants_hold_across_random_sequences(
+            max_snoozes in prop::option::of(Just(3)),
+            max_snooze_total in prop::option::of(Just(Duration::from_secs(150))),
+            ops in prop::collection::vec(op_strategy(), 1..80),
+        ) {
+            let now = Arc::new(Mutex::new(Instant::now()));
+            let cfg = Config {
+                max_snoozes,
+                max_snooze_total,
+                long_break_every: Some(3),
+                ..test_cfg()
+            };
+            let mut sched = Scheduler::with_clock(cfg, Clock::manual(&now));
+            let mut held = false;
+            for (i, &op) in ops.iter().enumerate() {
+                let count_before = sched.snooze_count;
+                op.apply(&mut sched, &now);
+                // The hold lasts until something gives the timer a deadline.
+                held = matches!(op, Op::SessionLocked)
+                    || held
+                        && sched.phase == Phase::Working
+                        && sched.deadline.is_none()
+                        && !sched.is_paused();
+                let mut result = check_invariants(&sched, held);
+                if result.is_ok() && !op.starts_cycle() && sched.snooze_count < count_before {
+                    result = Err("snooze count went down within a cycle".to_string());
+                }
+                if let Err(broken) = result {
+                    let ops = &ops[..=i];
+                    return Err(TestCaseError::fail(format!("{broken} after {ops:?}\n{sched:#?}")));
+                }
+            }
+        }
+    }
 }
@@ -6,6 +6,8 @@ use std::thread;
 use zbus::blocking::{Connection, Proxy};
 use zbus::zvariant::{OwnedObjectPath, Value};
 
+use crate::logging::error;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SessionLockEvent {
     Locked,
@@ -17,7 +19,7 @@ pub fn spawn_session_lock_watcher(tx: Sender<SessionLockEvent>) -> Result<()> {
         .name("session-lock-watcher".to_string())
         .spawn(move || {
             if let Err(err) = watch_session_lock(tx) {
-                eprintln!("session lock watcher failed: {err:?}");
+                error!("session lock watcher failed: {err:?}");
             }
         })
         .context("spawn session lock watcher thread")?;
@@ -156,11 +158,10 @@ fn find_active_session(
         if let Ok(true) = session.get_property::<bool>("Active") {
             return Some(path.clone());
         }
-        if let Ok(state) = session.get_property::<String>("State") {
-            if matches!(state.as_str(), "active" | "online") {
+        if let Ok(state) = session.get_property::<String>("State")
+            && matches!(state.as_str(), "active" | "online") {
                 return Some(path.clone());
             }
-        }
     }
     None
 }
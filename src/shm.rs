@@ -1,19 +1,263 @@
-use anyhow::Result;
+//! Shared-memory frame buffers for `wl_shm`.
+//!
+//! [`ShmBuffer`] is a memfd mapping split into equally sized planes, one per
+//! frame; [`ShmPool`] wraps it in a `wl_shm_pool` with a `wl_buffer` per plane.
+
+use anyhow::{Result, anyhow};
 use memmap2::MmapMut;
-use rustix::fs::{memfd_create, MemfdFlags};
+use rustix::fs::{MemfdFlags, ftruncate, memfd_create};
+use std::ops::Range;
 use std::os::fd::{AsFd, OwnedFd};
+use wayland_client::{
+    Dispatch, QueueHandle,
+    protocol::{wl_buffer::WlBuffer, wl_shm, wl_shm::WlShm, wl_shm_pool::WlShmPool},
+};
+
+/// Pixel format of every frame; byte order in memory is B, G, R, A.
+pub const FORMAT: wl_shm::Format = wl_shm::Format::Argb8888;
+pub const BYTES_PER_PIXEL: usize = 4;
 
+pub fn stride(width: u32) -> usize {
+    width as usize * BYTES_PER_PIXEL
+}
+
+pub fn frame_size(width: u32, height: u32) -> usize {
+    stride(width) * height as usize
+}
+
+/// A memfd mapping holding `planes` frames of `width` x `height` pixels.
 pub struct ShmBuffer {
-    pub fd: OwnedFd,
-    pub map: MmapMut,
-    pub len: usize,
+    fd: OwnedFd,
+    map: Option<MmapMut>,
+    len: usize,
+    width: u32,
+    height: u32,
+    planes: usize,
+}
+
+impl ShmBuffer {
+    pub fn new(width: u32, height: u32, planes: usize) -> Result<Self> {
+        let fd = memfd_create("interlude-frame", MemfdFlags::CLOEXEC)
+            .map_err(|e| anyhow!("memfd_create: {e}"))?;
+        let mut buffer = Self {
+            fd,
+            map: None,
+            len: 0,
+            width: 0,
+            height: 0,
+            planes,
+        };
+        buffer.resize(width, height)?;
+        Ok(buffer)
+    }
+
+    /// Changes the frame size. The file only ever grows, since a `wl_shm_pool`
+    /// cannot shrink; returns whether it did.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<bool> {
+        let needed = frame_size(width, height) * self.planes;
+        let grew = needed > self.len;
+        if grew {
+            if self.len > 0 && self.map.is_none() {
+                return Err(anyhow!("shm buffer grown while its map is lent out"));
+            }
+            // Drop the old mapping before creating the new one.
+            self.map = None;
+            ftruncate(&self.fd, needed as u64).map_err(|e| anyhow!("ftruncate: {e}"))?;
+            self.len = needed;
+            let map = unsafe { MmapMut::map_mut(&self.fd) }.map_err(|e| anyhow!("mmap: {e}"))?;
+            self.map = Some(map);
+        }
+        self.width = width;
+        self.height = height;
+        Ok(grew)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn stride(&self) -> usize {
+        stride(self.width)
+    }
+
+    /// Total size of the backing file in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn planes(&self) -> usize {
+        self.planes
+    }
+
+    /// Byte range of plane `index` within the mapping.
+    pub fn plane_range(&self, index: usize) -> Range<usize> {
+        let size = frame_size(self.width, self.height);
+        index * size..(index + 1) * size
+    }
+
+    /// Lends the whole mapping out, e.g. to the render thread.
+    pub fn take_map(&mut self) -> Option<MmapMut> {
+        self.map.take()
+    }
+
+    pub fn restore_map(&mut self, map: MmapMut) {
+        self.map = Some(map);
+    }
+
+    pub fn fd(&self) -> &OwnedFd {
+        &self.fd
+    }
+}
+
+/// One `wl_buffer` over a plane of the pool.
+pub struct ShmSlot {
+    pub buffer: WlBuffer,
+    /// Attached and not yet released by the compositor.
+    pub busy: bool,
+}
+
+/// A `wl_shm_pool` over a [`ShmBuffer`], with a `wl_buffer` for each plane.
+pub struct ShmPool {
+    pub memory: ShmBuffer,
+    pool: WlShmPool,
+    pub slots: Vec<ShmSlot>,
 }
 
-pub fn create_shm(len: usize) -> Result<ShmBuffer> {
-    let fd = memfd_create("interlude-shm", MemfdFlags::CLOEXEC)?;
-    rustix::fs::ftruncate(&fd, len as u64)?;
+impl ShmPool {
+    pub fn new<D>(
+        shm: &WlShm,
+        qh: &QueueHandle<D>,
+        width: u32,
+        height: u32,
+        planes: usize,
+    ) -> Result<Self>
+    where
+        D: Dispatch<WlShmPool, ()> + Dispatch<WlBuffer, ()> + 'static,
+    {
+        let memory = ShmBuffer::new(width, height, planes)?;
+        let pool = shm.create_pool(memory.fd().as_fd(), memory.len() as i32, qh, ());
+        let mut pool = Self {
+            memory,
+            pool,
+            slots: Vec::new(),
+        };
+        pool.create_buffers(qh);
+        Ok(pool)
+    }
+
+    /// Recreates the buffers for a new frame size, growing the pool if needed.
+    pub fn resize<D>(&mut self, qh: &QueueHandle<D>, width: u32, height: u32) -> Result<()>
+    where
+        D: Dispatch<WlShmPool, ()> + Dispatch<WlBuffer, ()> + 'static,
+    {
+        if self.memory.resize(width, height)? {
+            self.pool.resize(self.memory.len() as i32);
+        }
+        for slot in self.slots.drain(..) {
+            slot.buffer.destroy();
+        }
+        self.create_buffers(qh);
+        Ok(())
+    }
+
+    pub fn matches(&self, width: u32, height: u32) -> bool {
+        self.memory.width() == width && self.memory.height() == height
+    }
+
+    /// Marks the slot backed by `buffer` free again; returns whether it was ours.
+    pub fn release(&mut self, buffer: &WlBuffer) -> bool {
+        match self.slots.iter_mut().find(|slot| &slot.buffer == buffer) {
+            Some(slot) => {
+                slot.busy = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn create_buffers<D>(&mut self, qh: &QueueHandle<D>)
+    where
+        D: Dispatch<WlShmPool, ()> + Dispatch<WlBuffer, ()> + 'static,
+    {
+        let (width, height) = (self.memory.width(), self.memory.height());
+        self.slots = (0..self.memory.planes())
+            .map(|plane| {
+                let offset = self.memory.plane_range(plane).start;
+                let buffer = self.pool.create_buffer(
+                    offset as i32,
+                    width as i32,
+                    height as i32,
+                    self.memory.stride() as i32,
+                    FORMAT,
+                    qh,
+                    (),
+                );
+                ShmSlot {
+                    buffer,
+                    busy: false,
+                }
+            })
+            .collect();
+    }
+}
+
+impl Drop for ShmPool {
+    fn drop(&mut self) {
+        for slot in &self.slots {
+            slot.buffer.destroy();
+        }
+        self.pool.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stride_and_frame_size() {
+        assert_eq!(stride(1920), 7680);
+        assert_eq!(frame_size(1920, 1080), 7680 * 1080);
+    }
+
+    #[test]
+    fn planes_do_not_overlap() {
+        let mut buf = ShmBuffer::new(8, 4, 2).unwrap();
+        assert_eq!(buf.len(), 8 * 4 * 4 * 2);
+        assert_eq!(buf.plane_range(0), 0..128);
+        assert_eq!(buf.plane_range(1), 128..256);
+
+        let mut map = buf.take_map().unwrap();
+        map[buf.plane_range(0)].fill(1);
+        map[buf.plane_range(1)].fill(2);
+        assert!(map[buf.plane_range(0)].iter().all(|b| *b == 1));
+        assert_eq!(map.len(), buf.len());
+    }
+
+    #[test]
+    fn resize_only_grows_the_file() {
+        let mut buf = ShmBuffer::new(8, 4, 2).unwrap();
+        assert!(buf.resize(16, 8).unwrap());
+        assert_eq!(buf.len(), 16 * 8 * 4 * 2);
 
-    let map = unsafe { MmapMut::map_mut(fd.as_fd())? };
+        assert!(!buf.resize(4, 4).unwrap());
+        assert_eq!(buf.len(), 16 * 8 * 4 * 2);
+        assert_eq!(buf.plane_range(1), 64..128);
+        assert_eq!(buf.stride(), 16);
+    }
 
-    Ok(ShmBuffer { fd, map, len })
+    #[test]
+    fn lent_map_comes_back() {
+        let mut buf = ShmBuffer::new(2, 2, 1).unwrap();
+        let map = buf.take_map().unwrap();
+        assert!(buf.take_map().is_none());
+        assert!(buf.resize(4, 4).is_err());
+        assert!(!buf.resize(1, 2).unwrap());
+        buf.restore_map(map);
+        assert!(buf.take_map().is_some());
+    }
 }
@@ -0,0 +1,28 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use signal_hook::consts::{SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+use std::thread;
+
+use crate::actions::Action;
+
+/// Forwards `SIGUSR1` (toggle pause) and `SIGUSR2` (break now) as actions.
+pub fn spawn_signal_watcher(tx: Sender<Action>) -> Result<()> {
+    let mut signals = Signals::new([SIGUSR1, SIGUSR2]).context("register signal handlers")?;
+    thread::Builder::new()
+        .name("signal-watcher".to_string())
+        .spawn(move || {
+            for signal in signals.forever() {
+                let action = match signal {
+                    SIGUSR1 => Action::Pause,
+                    SIGUSR2 => Action::StartBreak,
+                    _ => continue,
+                };
+                if tx.send(action).is_err() {
+                    break;
+                }
+            }
+        })
+        .context("spawn signal watcher thread")?;
+    Ok(())
+}
@@ -1,23 +1,16 @@
-use std::env;
+use chrono::NaiveDate;
 use std::fs;
-use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::scheduler::{Config, Phase, Scheduler};
+use crate::paths;
+use crate::scheduler::{BreakVariant, Config, Phase, Scheduler};
 
 const SAVE_INTERVAL: Duration = Duration::from_secs(1);
-const STATE_FILE: &str = "state.txt";
-
-fn state_dir() -> Option<PathBuf> {
-    if let Some(dir) = env::var_os("XDG_STATE_HOME") {
-        return Some(PathBuf::from(dir).join("interlude"));
-    }
-    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/interlude"))
-}
-
-fn state_path() -> Option<PathBuf> {
-    state_dir().map(|dir| dir.join(STATE_FILE))
-}
+/// Longest duration a state file may hold; anything above is corrupt.
+const MAX_RESTORED_SECS: u64 = 7 * 24 * 60 * 60;
+/// Highest snooze count taken from a state file, which keeps the break
+/// length it scales finite.
+const MAX_RESTORED_SNOOZES: u32 = 1000;
 
 fn phase_to_str(phase: Phase) -> &'static str {
     match phase {
@@ -48,7 +41,7 @@ fn now_unix_secs() -> u64 {
 }
 
 pub fn save_scheduler(sched: &Scheduler) -> std::io::Result<()> {
-    let Some(path) = state_path() else {
+    let Some(path) = paths::state_file() else {
         return Ok(());
     };
     if let Some(dir) = path.parent() {
@@ -56,13 +49,21 @@ pub fn save_scheduler(sched: &Scheduler) -> std::io::Result<()> {
     }
     let remaining = sched.time_left().map(|d| d.as_secs());
     let content = format!(
-        "phase={}\nremaining={}\nsnooze_count={}\ninitial_done={}\nsaved_at={}\n",
+        "phase={}\nremaining={}\nsnooze_count={}\nsnoozed_total={}\ninitial_done={}\nbreak_cycle={}\ncarried_over={}\nvariant={}\nlast_break_day={}\nsaved_at={}\n",
         phase_to_str(sched.phase),
         remaining
             .map(|v| v.to_string())
             .unwrap_or_else(|| "none".to_string()),
         sched.snooze_count,
+        sched.snoozed_total.as_secs(),
         sched.initial_cycle_done,
+        sched.break_cycle,
+        sched.carried_over.as_secs(),
+        sched.variant.label(),
+        sched
+            .last_break_day
+            .map(|day| day.to_string())
+            .unwrap_or_else(|| "none".to_string()),
         now_unix_secs()
     );
     let tmp_path = path.with_extension("tmp");
@@ -72,29 +73,53 @@ pub fn save_scheduler(sched: &Scheduler) -> std::io::Result<()> {
 }
 
 pub fn load_scheduler(cfg: &Config) -> Option<Scheduler> {
-    let path = state_path()?;
+    let path = paths::state_file()?;
     let data = fs::read_to_string(path).ok()?;
+    scheduler_from_str(&data, cfg)
+}
 
+/// Rebuilds a scheduler from saved state. Lines that do not parse and
+/// values too large to be real are ignored one by one rather than failing
+/// the whole load, and nothing in `data` can make this panic.
+pub fn scheduler_from_str(data: &str, cfg: &Config) -> Option<Scheduler> {
     let mut phase: Option<Phase> = None;
     let mut remaining: Option<u64> = None;
     let mut snooze_count: Option<u32> = None;
+    let mut snoozed_total: Option<u64> = None;
     let mut initial_done: Option<bool> = None;
+    let mut break_cycle: Option<u32> = None;
+    let mut carried_over: Option<u64> = None;
+    let mut variant: Option<BreakVariant> = None;
+    let mut last_break_day: Option<NaiveDate> = None;
     let mut saved_at: Option<u64> = None;
 
+    let seconds = |value: &str| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs <= MAX_RESTORED_SECS)
+    };
     for line in data.lines() {
-        let (key, value) = line.split_once('=')?;
-        match key {
-            "phase" => phase = str_to_phase(value.trim()),
-            "remaining" => {
-                if value.trim() == "none" {
-                    remaining = None;
-                } else {
-                    remaining = value.trim().parse::<u64>().ok();
-                }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "phase" => phase = str_to_phase(value),
+            "remaining" => remaining = seconds(value),
+            "snooze_count" => {
+                snooze_count = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|count| *count <= MAX_RESTORED_SNOOZES)
             }
-            "snooze_count" => snooze_count = value.trim().parse::<u32>().ok(),
-            "initial_done" => initial_done = value.trim().parse::<bool>().ok(),
-            "saved_at" => saved_at = value.trim().parse::<u64>().ok(),
+            "snoozed_total" => snoozed_total = seconds(value),
+            "initial_done" => initial_done = value.parse::<bool>().ok(),
+            "break_cycle" => break_cycle = value.parse::<u32>().ok(),
+            "carried_over" => carried_over = seconds(value),
+            "variant" => variant = BreakVariant::parse(value),
+            "last_break_day" => last_break_day = value.parse::<NaiveDate>().ok(),
+            "saved_at" => saved_at = value.parse::<u64>().ok(),
             _ => {}
         }
     }
@@ -109,11 +134,22 @@ pub fn load_scheduler(cfg: &Config) -> Option<Scheduler> {
     let mut sched = Scheduler::new(cfg.clone());
     sched.phase = phase;
     sched.snooze_count = snooze_count;
+    sched.snoozed_total = Duration::from_secs(snoozed_total.unwrap_or(0));
     sched.initial_cycle_done = initial_done;
+    sched.break_cycle = break_cycle.unwrap_or(0);
+    sched.carried_over = Duration::from_secs(carried_over.unwrap_or(0));
+    sched.variant = variant.unwrap_or_default();
+    sched.last_break_day = last_break_day;
+    let deadline = |r: u64| std::time::Instant::now() + Duration::from_secs(r);
     sched.deadline = match sched.phase {
-        Phase::Working => remaining.map(|r| std::time::Instant::now() + Duration::from_secs(r)),
-        Phase::Snoozing => remaining.map(|r| std::time::Instant::now() + Duration::from_secs(r)),
-        Phase::OnBreak => remaining.map(|r| std::time::Instant::now() + Duration::from_secs(r)),
+        // Saved while the session was locked: no unlock will come to restart
+        // the timer, so start a fresh interval.
+        Phase::Working | Phase::Snoozing => {
+            Some(remaining.map_or_else(|| deadline(sched.interval_duration().as_secs()), deadline))
+        }
+        Phase::OnBreak => Some(deadline(
+            remaining.unwrap_or(sched.break_duration().as_secs()),
+        )),
         Phase::LockedAwaitingAction => None,
         Phase::BreakFinished => None,
     };
@@ -140,7 +176,7 @@ pub fn save_interval() -> Duration {
 }
 
 pub fn clear_saved_state() -> std::io::Result<()> {
-    let Some(path) = state_path() else {
+    let Some(path) = paths::state_file() else {
         return Ok(());
     };
     match fs::remove_file(path) {
@@ -149,3 +185,71 @@ pub fn clear_saved_state() -> std::io::Result<()> {
         Err(err) => Err(err),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::SnoozeCurve;
+
+    fn cfg() -> Config {
+        Config {
+            interval: Duration::from_secs(1800),
+            break_len: Duration::from_secs(180),
+            initial_interval: Duration::from_secs(3600),
+            initial_break_len: Duration::from_secs(180),
+            snooze_base: Duration::from_secs(300),
+            snooze_curve: SnoozeCurve::Constant,
+            snooze_min: Duration::from_secs(60),
+            max_snoozes: None,
+            max_snooze_total: None,
+            long_break_every: None,
+            long_break_len: Duration::from_secs(600),
+            carry_over: 0.0,
+            warn_before: None,
+            milestones: Vec::new(),
+            early_finish_after: None,
+            idle_credit: 0.0,
+            adaptive_strain: 0.0,
+            planning_break: true,
+            wrap_up_len: Duration::from_secs(900),
+            micro_every: None,
+            micro_len: Duration::from_secs(20),
+        }
+    }
+
+    #[test]
+    fn bad_lines_are_skipped_one_by_one() {
+        let sched = scheduler_from_str(
+            "garbage\nphase=OnBreak\nremaining=99999999999999999\nsnooze_count=4000000000\n\
+             carried_over=-3\nsnooze_count=2\n",
+            &cfg(),
+        )
+        .expect("phase is enough to restore");
+        assert_eq!(sched.phase, Phase::OnBreak);
+        assert_eq!(sched.snooze_count, 2);
+        assert_eq!(sched.carried_over, Duration::ZERO);
+        assert_eq!(sched.variant, BreakVariant::Rest);
+        let left = sched.time_left().unwrap();
+        assert!(left <= sched.break_duration() && left.as_secs() > 100);
+
+        assert!(scheduler_from_str("remaining=10\n", &cfg()).is_none());
+    }
+
+    #[test]
+    fn planning_break_survives_a_restart() {
+        let sched = scheduler_from_str(
+            "phase=LockedAwaitingAction\nremaining=none\nvariant=planning\nlast_break_day=2024-03-01\n",
+            &cfg(),
+        )
+        .unwrap();
+        assert_eq!(sched.variant, BreakVariant::Planning);
+        assert_eq!(sched.last_break_day, NaiveDate::from_ymd_opt(2024, 3, 1));
+    }
+
+    #[test]
+    fn locked_session_state_restarts_the_interval() {
+        let sched = scheduler_from_str("phase=Working\nremaining=none\n", &cfg()).unwrap();
+        assert_eq!(sched.phase, Phase::Working);
+        assert!(sched.time_left().unwrap().as_secs() > 1700);
+    }
+}
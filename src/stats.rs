@@ -0,0 +1,872 @@
+use chrono::{Datelike, Duration as ChronoDuration, Local, NaiveDate, TimeZone};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::logging::error;
+use crate::paths;
+
+const CHART_DAYS: i64 = 7;
+const CHART_WIDTH: usize = 28;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatsEvent {
+    BreakDue,
+    /// `away` is the share of the break, in percent, that passed without
+    /// input; `None` where idle tracking was unavailable.
+    BreakTaken {
+        seconds: u64,
+        away: Option<u8>,
+    },
+    /// A break ended early after its minimum portion.
+    BreakPartial {
+        seconds: u64,
+        away: Option<u8>,
+    },
+    Snoozed,
+    /// `reason` is set when `--skip-reason` asked for one.
+    Skipped {
+        reason: Option<SkipReason>,
+    },
+    Feedback {
+        rating: u8,
+    },
+    /// Work time under a tag set with `interlude ctl tag`, written when its
+    /// work cycle ends or the tag changes. Tags are one word.
+    Focused {
+        seconds: u64,
+        tag: String,
+    },
+}
+
+/// Why a break was skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    Meeting,
+    Urgent,
+    Other,
+}
+
+impl SkipReason {
+    /// In the order of the keys that pick them, from 1.
+    pub const ALL: [Self; 3] = [Self::Meeting, Self::Urgent, Self::Other];
+
+    /// The reason picked by digit key `key`.
+    pub fn from_key(key: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(key).checked_sub(1)?).copied()
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Meeting => "meeting",
+            Self::Urgent => "urgent",
+            Self::Other => "other",
+        }
+    }
+
+    fn parse(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.label() == label)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub at: u64,
+    pub event: StatsEvent,
+}
+
+fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn format_record(record: &Record) -> String {
+    let event = match &record.event {
+        StatsEvent::BreakDue => "break_due".to_string(),
+        StatsEvent::BreakTaken { seconds, away } => {
+            format!("break_taken seconds={seconds}{}", away_field(*away))
+        }
+        StatsEvent::BreakPartial { seconds, away } => {
+            format!("break_partial seconds={seconds}{}", away_field(*away))
+        }
+        StatsEvent::Snoozed => "snoozed".to_string(),
+        StatsEvent::Skipped { reason: None } => "skipped".to_string(),
+        StatsEvent::Skipped {
+            reason: Some(reason),
+        } => format!("skipped reason={}", reason.label()),
+        StatsEvent::Feedback { rating } => format!("feedback rating={rating}"),
+        StatsEvent::Focused { seconds, tag } => format!("focused seconds={seconds} tag={tag}"),
+    };
+    format!("{} {}\n", record.at, event)
+}
+
+fn away_field(away: Option<u8>) -> String {
+    away.map(|away| format!(" away={away}")).unwrap_or_default()
+}
+
+fn parse_record(line: &str) -> Option<Record> {
+    let mut parts = line.split_whitespace();
+    let at = parts.next()?.parse::<u64>().ok()?;
+    let kind = parts.next()?;
+    let mut field = |key: &str| -> Option<&str> {
+        let (k, v) = parts.next()?.split_once('=')?;
+        (k == key).then_some(v)
+    };
+    // Older logs have no away score.
+    let away = |field: Option<&str>| match field {
+        Some(away) => away.parse().ok().filter(|away| *away <= 100).map(Some),
+        None => Some(None),
+    };
+    let event = match kind {
+        "break_due" => StatsEvent::BreakDue,
+        "break_taken" => StatsEvent::BreakTaken {
+            seconds: field("seconds")?.parse().ok()?,
+            away: away(field("away"))?,
+        },
+        "break_partial" => StatsEvent::BreakPartial {
+            seconds: field("seconds")?.parse().ok()?,
+            away: away(field("away"))?,
+        },
+        "snoozed" => StatsEvent::Snoozed,
+        // Older logs have no reason at all.
+        "skipped" if line.contains('=') => StatsEvent::Skipped {
+            reason: Some(SkipReason::parse(field("reason")?)?),
+        },
+        "skipped" => StatsEvent::Skipped { reason: None },
+        "feedback" => StatsEvent::Feedback {
+            rating: field("rating")?.parse().ok()?,
+        },
+        "focused" => StatsEvent::Focused {
+            seconds: field("seconds")?.parse().ok()?,
+            tag: field("tag").filter(|tag| !tag.is_empty())?.to_string(),
+        },
+        _ => return None,
+    };
+    Some(Record { at, event })
+}
+
+/// Appends an event to the stats log. The log is line based so a partially
+/// written line only loses that record.
+pub fn record(event: &StatsEvent) -> std::io::Result<()> {
+    let Some(path) = paths::stats_file() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(
+        format_record(&Record {
+            at: now_unix_secs(),
+            event: event.clone(),
+        })
+        .as_bytes(),
+    )
+}
+
+/// Loads every readable record from the stats log, skipping malformed lines.
+pub fn load() -> std::io::Result<Vec<Record>> {
+    let Some(path) = paths::stats_file() else {
+        return Ok(Vec::new());
+    };
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(parse_log(&data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Every well-formed record in `data`, in order.
+pub fn parse_log(data: &str) -> Vec<Record> {
+    data.lines().filter_map(parse_record).collect()
+}
+
+/// Local calendar date of a unix timestamp.
+pub fn local_date(at: u64) -> Option<NaiveDate> {
+    let at = i64::try_from(at).ok()?;
+    Local
+        .timestamp_opt(at, 0)
+        .earliest()
+        .map(|dt| dt.date_naive())
+}
+
+/// Per-day (scheduled, taken) break counts for the `days` days ending `today`.
+fn daily_counts(
+    records: &[Record],
+    today: NaiveDate,
+    days: i64,
+    date_of: impl Fn(u64) -> Option<NaiveDate>,
+) -> Vec<(NaiveDate, u32, u32)> {
+    let mut counts: Vec<(NaiveDate, u32, u32)> = (0..days)
+        .rev()
+        .map(|back| (today - ChronoDuration::days(back), 0, 0))
+        .collect();
+    for record in records {
+        let Some(date) = date_of(record.at) else {
+            continue;
+        };
+        let Some(day) = counts.iter_mut().find(|(d, _, _)| *d == date) else {
+            continue;
+        };
+        match &record.event {
+            StatsEvent::BreakDue => day.1 += 1,
+            StatsEvent::BreakTaken { .. } | StatsEvent::BreakPartial { .. } => day.2 += 1,
+            _ => {}
+        }
+    }
+    counts
+}
+
+/// Share of the day's measured break time, in percent, that passed without
+/// input; `None` when no break that day was measured.
+fn away_percent(
+    records: &[Record],
+    day: NaiveDate,
+    date_of: impl Fn(u64) -> Option<NaiveDate>,
+) -> Option<u64> {
+    let (away, total) = records
+        .iter()
+        .filter(|record| date_of(record.at) == Some(day))
+        .filter_map(|record| match &record.event {
+            StatsEvent::BreakTaken {
+                seconds,
+                away: Some(away),
+            }
+            | StatsEvent::BreakPartial {
+                seconds,
+                away: Some(away),
+            } => Some((seconds.saturating_mul(u64::from(*away)), *seconds)),
+            _ => None,
+        })
+        .fold((0u64, 0u64), |(a, t), (away, secs)| {
+            (a.saturating_add(away), t.saturating_add(secs))
+        });
+    (total > 0).then(|| away.saturating_add(total / 2) / total)
+}
+
+/// Break counts for one local day.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DayTotals {
+    pub due: u32,
+    /// Completed and partial breaks.
+    pub taken: u32,
+    pub skipped: u32,
+    pub snoozed: u32,
+}
+
+impl DayTotals {
+    fn for_day(
+        records: &[Record],
+        day: NaiveDate,
+        date_of: impl Fn(u64) -> Option<NaiveDate>,
+    ) -> Self {
+        let mut totals = Self::default();
+        for record in records {
+            if date_of(record.at) == Some(day) {
+                totals.add(&record.event);
+            }
+        }
+        totals
+    }
+
+    fn add(&mut self, event: &StatsEvent) {
+        match event {
+            StatsEvent::BreakDue => self.due += 1,
+            StatsEvent::BreakTaken { .. } | StatsEvent::BreakPartial { .. } => self.taken += 1,
+            StatsEvent::Skipped { .. } => self.skipped += 1,
+            StatsEvent::Snoozed => self.snoozed += 1,
+            StatsEvent::Feedback { .. } | StatsEvent::Focused { .. } => {}
+        }
+    }
+}
+
+/// Today's totals, read from the log once and then kept in step with each
+/// recorded event, so the status does not re-read the log every tick.
+pub struct Today {
+    date: NaiveDate,
+    totals: DayTotals,
+}
+
+impl Today {
+    pub fn load() -> Self {
+        let date = Local::now().date_naive();
+        let records = load().unwrap_or_else(|err| {
+            error!("stats read failed: {err}");
+            Vec::new()
+        });
+        Self {
+            date,
+            totals: DayTotals::for_day(&records, date, local_date),
+        }
+    }
+
+    pub fn add(&mut self, event: &StatsEvent) {
+        self.roll_over();
+        self.totals.add(event);
+    }
+
+    pub fn totals(&mut self) -> DayTotals {
+        self.roll_over();
+        self.totals
+    }
+
+    fn roll_over(&mut self) {
+        let date = Local::now().date_naive();
+        if date != self.date {
+            self.date = date;
+            self.totals = DayTotals::default();
+        }
+    }
+}
+
+/// Renders a bar per day: filled blocks are breaks taken, shaded blocks are
+/// scheduled breaks that were not taken. Days with measured breaks end with
+/// how much of them was spent away from the keyboard.
+pub fn render_chart(
+    records: &[Record],
+    today: NaiveDate,
+    date_of: impl Fn(u64) -> Option<NaiveDate>,
+) -> String {
+    let counts = daily_counts(records, today, CHART_DAYS, &date_of);
+    let max = counts
+        .iter()
+        .map(|(_, scheduled, taken)| (*scheduled).max(*taken))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let scale = |n: u32| (n as usize * CHART_WIDTH).div_ceil(max as usize);
+    let mut out = format!("Breaks taken / scheduled, last {CHART_DAYS} days\n");
+    for (date, scheduled, taken) in counts {
+        let filled = scale(taken);
+        let shaded = scale(scheduled).saturating_sub(filled);
+        let bar = format!("{}{}", "█".repeat(filled), "░".repeat(shaded));
+        out.push_str(&format!(
+            "{} {:02}-{:02}  {:<width$}  {}/{}\n",
+            date.weekday(),
+            date.month(),
+            date.day(),
+            bar,
+            taken,
+            scheduled,
+            width = CHART_WIDTH
+        ));
+        if let Some(away) = away_percent(records, date, &date_of) {
+            out.insert_str(out.len() - 1, &format!("  {away:>3}% away"));
+        }
+    }
+    out
+}
+
+/// Renders the work time under each tag today, over the last week and in
+/// all, the most worked tag first.
+pub fn render_tags(
+    records: &[Record],
+    today: NaiveDate,
+    date_of: impl Fn(u64) -> Option<NaiveDate>,
+) -> String {
+    let week_start = today - ChronoDuration::days(CHART_DAYS - 1);
+    // Today, this week and all time, per tag.
+    let mut tags: BTreeMap<&str, [u64; 3]> = BTreeMap::new();
+    for record in records {
+        let StatsEvent::Focused { seconds, tag } = &record.event else {
+            continue;
+        };
+        let date = date_of(record.at);
+        let totals = tags.entry(tag.as_str()).or_default();
+        let spans = [
+            date == Some(today),
+            date.is_some_and(|date| week_start <= date && date <= today),
+            true,
+        ];
+        for (total, _) in totals.iter_mut().zip(spans).filter(|(_, within)| *within) {
+            *total = total.saturating_add(*seconds);
+        }
+    }
+    if tags.is_empty() {
+        return "No tagged work yet; tag it with `interlude ctl tag NAME`\n".to_string();
+    }
+    let mut tags: Vec<_> = tags.into_iter().collect();
+    tags.sort_by_key(|(_, [_, _, all])| std::cmp::Reverse(*all));
+    let width = tags
+        .iter()
+        .map(|(tag, _)| tag.chars().count())
+        .fold("Tag".len(), usize::max);
+    let hours = |seconds: u64| format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60);
+    let mut out = format!(
+        "{:<width$}  {:>9}  {:>9}  {:>9}\n",
+        "Tag", "Today", "7 days", "All time"
+    );
+    for (tag, [today, week, all]) in tags {
+        out.push_str(&format!(
+            "{tag:<width$}  {:>9}  {:>9}  {:>9}\n",
+            hours(today),
+            hours(week),
+            hours(all)
+        ));
+    }
+    out
+}
+
+/// Work time under the tag set with `interlude ctl tag`. The clock runs
+/// while the work timer does; what it has counted is taken as a
+/// [`StatsEvent::Focused`] when the work cycle ends or the tag changes.
+#[derive(Debug, Default)]
+pub struct FocusClock {
+    tag: Option<String>,
+    running_since: Option<Instant>,
+    counted: Duration,
+}
+
+impl FocusClock {
+    /// Starts or stops the clock, as the work timer runs or not.
+    pub fn set_running(&mut self, running: bool, now: Instant) {
+        match (running, self.running_since) {
+            (true, None) => self.running_since = Some(now),
+            (false, Some(since)) => {
+                self.counted += now.saturating_duration_since(since);
+                self.running_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Switches to `tag`, returning the time counted under the old one.
+    pub fn set_tag(&mut self, tag: Option<String>, now: Instant) -> Option<StatsEvent> {
+        let taken = self.take(now);
+        self.tag = tag;
+        taken
+    }
+
+    /// The time counted since the last take, when there is a tag and at
+    /// least a second of it; the clock keeps running.
+    pub fn take(&mut self, now: Instant) -> Option<StatsEvent> {
+        let mut counted = std::mem::take(&mut self.counted);
+        if let Some(since) = self.running_since.as_mut() {
+            counted += now.saturating_duration_since(*since);
+            *since = now;
+        }
+        let tag = self.tag.clone()?;
+        (counted.as_secs() > 0).then_some(StatsEvent::Focused {
+            seconds: counted.as_secs(),
+            tag,
+        })
+    }
+}
+
+fn seconds_away(seconds: u64, away: Option<u8>) -> u64 {
+    seconds.saturating_mul(u64::from(away.unwrap_or(0))) / 100
+}
+
+/// Renders lifetime counters from the stats log in OpenMetrics text format.
+pub fn render_metrics(records: &[Record]) -> String {
+    let mut scheduled = 0u64;
+    let mut taken = 0u64;
+    let mut partial = 0u64;
+    let mut break_seconds = 0u64;
+    let mut away_seconds = 0u64;
+    let mut snoozes = 0u64;
+    let mut skips = 0u64;
+    let mut skip_reasons = [0u64; SkipReason::ALL.len()];
+    let mut ratings = [0u64; 3];
+    let mut focused = BTreeMap::new();
+    for record in records {
+        match record.event {
+            StatsEvent::BreakDue => scheduled += 1,
+            StatsEvent::BreakTaken { seconds, away } => {
+                taken += 1;
+                break_seconds = break_seconds.saturating_add(seconds);
+                away_seconds = away_seconds.saturating_add(seconds_away(seconds, away));
+            }
+            StatsEvent::BreakPartial { seconds, away } => {
+                partial += 1;
+                break_seconds = break_seconds.saturating_add(seconds);
+                away_seconds = away_seconds.saturating_add(seconds_away(seconds, away));
+            }
+            StatsEvent::Snoozed => snoozes += 1,
+            StatsEvent::Skipped { reason } => {
+                skips += 1;
+                if let Some(reason) = reason {
+                    skip_reasons[reason as usize] += 1;
+                }
+            }
+            StatsEvent::Feedback { rating } => {
+                if let Some(count) = ratings.get_mut(usize::from(rating).wrapping_sub(1)) {
+                    *count += 1;
+                }
+            }
+            StatsEvent::Focused { seconds, ref tag } => {
+                let total = focused.entry(tag.as_str()).or_insert(0u64);
+                *total = total.saturating_add(seconds);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let mut counter = |name: &str, help: &str, samples: &[(String, u64)]| {
+        out.push_str(&format!("# TYPE {name} counter\n# HELP {name} {help}\n"));
+        for (labels, value) in samples {
+            out.push_str(&format!("{name}_total{labels} {value}\n"));
+        }
+    };
+    counter(
+        "interlude_breaks_scheduled",
+        "Breaks that became due.",
+        &[(String::new(), scheduled)],
+    );
+    counter(
+        "interlude_breaks_taken",
+        "Breaks that ran to completion.",
+        &[(String::new(), taken)],
+    );
+    counter(
+        "interlude_breaks_partial",
+        "Breaks ended early after their minimum portion.",
+        &[(String::new(), partial)],
+    );
+    counter(
+        "interlude_break_seconds",
+        "Seconds spent in breaks, including partial ones.",
+        &[(String::new(), break_seconds)],
+    );
+    counter(
+        "interlude_break_away_seconds",
+        "Seconds of breaks that passed without input, where idle tracking was available.",
+        &[(String::new(), away_seconds)],
+    );
+    counter(
+        "interlude_snoozes",
+        "Snoozes taken.",
+        &[(String::new(), snoozes)],
+    );
+    counter(
+        "interlude_breaks_skipped",
+        "Breaks skipped.",
+        &[(String::new(), skips)],
+    );
+    let reason_samples: Vec<(String, u64)> = SkipReason::ALL
+        .iter()
+        .zip(skip_reasons)
+        .map(|(reason, count)| (format!("{{reason=\"{}\"}}", reason.label()), count))
+        .collect();
+    counter(
+        "interlude_skip_reasons",
+        "Skipped breaks by the reason given for them.",
+        &reason_samples,
+    );
+    let rating_samples: Vec<(String, u64)> = ratings
+        .iter()
+        .enumerate()
+        .map(|(i, count)| (format!("{{rating=\"{}\"}}", i + 1), *count))
+        .collect();
+    counter(
+        "interlude_feedback_ratings",
+        "Post-break feedback answers by rating.",
+        &rating_samples,
+    );
+    let focused_samples: Vec<(String, u64)> = focused
+        .into_iter()
+        .map(|(tag, seconds)| {
+            let tag = tag.replace('\\', "\\\\").replace('"', "\\\"");
+            (format!("{{tag=\"{tag}\"}}"), seconds)
+        })
+        .collect();
+    counter(
+        "interlude_focused_seconds",
+        "Work seconds under each tag set with `interlude ctl tag`.",
+        &focused_samples,
+    );
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip() {
+        let events = [
+            StatsEvent::BreakDue,
+            StatsEvent::BreakTaken {
+                seconds: 180,
+                away: None,
+            },
+            StatsEvent::BreakTaken {
+                seconds: 180,
+                away: Some(100),
+            },
+            StatsEvent::BreakPartial {
+                seconds: 90,
+                away: Some(40),
+            },
+            StatsEvent::Snoozed,
+            StatsEvent::Skipped { reason: None },
+            StatsEvent::Skipped {
+                reason: Some(SkipReason::Urgent),
+            },
+            StatsEvent::Feedback { rating: 3 },
+            StatsEvent::Focused {
+                seconds: 1500,
+                tag: "project-x".to_string(),
+            },
+        ];
+        for event in events {
+            let record = Record {
+                at: 1_700_000_000,
+                event,
+            };
+            let line = format_record(&record);
+            assert_eq!(parse_record(line.trim_end()), Some(record));
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lines() {
+        assert_eq!(parse_record(""), None);
+        assert_eq!(parse_record("abc break_due"), None);
+        assert_eq!(parse_record("1 break_taken"), None);
+        assert_eq!(parse_record("1 break_taken minutes=3"), None);
+        assert_eq!(parse_record("1 feedback rating=900"), None);
+        assert_eq!(parse_record("1 unknown"), None);
+        assert_eq!(parse_record("1 skipped reason=bored"), None);
+        assert_eq!(parse_record("1 skipped why=meeting"), None);
+        assert_eq!(parse_record("1 break_taken seconds=60 away=101"), None);
+        assert_eq!(parse_record("1 break_taken seconds=60 away=most"), None);
+        assert_eq!(parse_record("1 focused seconds=60"), None);
+        assert_eq!(parse_record("1 focused seconds=60 tag="), None);
+    }
+
+    fn utc_date(at: u64) -> Option<NaiveDate> {
+        chrono::DateTime::from_timestamp(at as i64, 0).map(|dt| dt.date_naive())
+    }
+
+    #[test]
+    fn chart_counts_scheduled_and_taken_per_day() {
+        let day = 86_400;
+        // 2023-11-14 (Tue) 22:13:20 UTC
+        let base = 1_700_000_000;
+        let records = [
+            Record {
+                at: base,
+                event: StatsEvent::BreakDue,
+            },
+            Record {
+                at: base + 60,
+                event: StatsEvent::BreakTaken {
+                    seconds: 180,
+                    away: None,
+                },
+            },
+            Record {
+                at: base + 120,
+                event: StatsEvent::BreakDue,
+            },
+            Record {
+                at: base - day,
+                event: StatsEvent::BreakDue,
+            },
+            Record {
+                at: base - 30 * day,
+                event: StatsEvent::BreakDue,
+            },
+        ];
+        let today = utc_date(base).unwrap();
+        let counts = daily_counts(&records, today, CHART_DAYS, utc_date);
+        assert_eq!(counts.len(), 7);
+        assert_eq!(counts[6], (today, 2, 1));
+        assert_eq!(counts[5], (today - ChronoDuration::days(1), 1, 0));
+        assert!(counts[..5].iter().all(|(_, s, t)| *s == 0 && *t == 0));
+
+        let chart = render_chart(&records, today, utc_date);
+        let last = chart.lines().last().unwrap();
+        assert!(last.starts_with("Tue 11-14"));
+        assert!(last.ends_with("1/2"));
+        assert_eq!(last.matches('█').count(), CHART_WIDTH / 2);
+        assert_eq!(last.matches('░').count(), CHART_WIDTH / 2);
+        assert!(!last.contains("away"));
+
+        let mut totals = DayTotals::for_day(&records, today, utc_date);
+        assert_eq!(
+            totals,
+            DayTotals {
+                due: 2,
+                taken: 1,
+                ..DayTotals::default()
+            }
+        );
+        totals.add(&StatsEvent::Skipped { reason: None });
+        totals.add(&StatsEvent::Feedback { rating: 3 });
+        assert_eq!(totals.skipped, 1);
+    }
+
+    #[test]
+    fn chart_weights_time_away_by_break_length() {
+        let base = 1_700_000_000;
+        let taken = |at, seconds, away| Record {
+            at,
+            event: StatsEvent::BreakTaken { seconds, away },
+        };
+        let records = [
+            taken(base, 300, Some(100)),
+            taken(base + 600, 100, Some(20)),
+            taken(base + 900, 600, None),
+        ];
+        let today = utc_date(base).unwrap();
+        assert_eq!(away_percent(&records, today, utc_date), Some(80));
+        let chart = render_chart(&records, today, utc_date);
+        assert!(chart.lines().last().unwrap().ends_with("3/0   80% away"));
+        assert_eq!(chart.matches("away").count(), 1);
+    }
+
+    #[test]
+    fn metrics_render_counters() {
+        let records = [
+            Record {
+                at: 1,
+                event: StatsEvent::BreakDue,
+            },
+            Record {
+                at: 2,
+                event: StatsEvent::BreakTaken {
+                    seconds: 180,
+                    away: Some(50),
+                },
+            },
+            Record {
+                at: 3,
+                event: StatsEvent::BreakTaken {
+                    seconds: 300,
+                    away: None,
+                },
+            },
+            Record {
+                at: 4,
+                event: StatsEvent::Snoozed,
+            },
+            Record {
+                at: 4,
+                event: StatsEvent::BreakPartial {
+                    seconds: 20,
+                    away: Some(100),
+                },
+            },
+            Record {
+                at: 5,
+                event: StatsEvent::Feedback { rating: 2 },
+            },
+            Record {
+                at: 6,
+                event: StatsEvent::Skipped {
+                    reason: Some(SkipReason::Meeting),
+                },
+            },
+            Record {
+                at: 7,
+                event: StatsEvent::Focused {
+                    seconds: 1500,
+                    tag: "say-\"hi\"".to_string(),
+                },
+            },
+        ];
+        let metrics = render_metrics(&records);
+        assert!(metrics.contains("# TYPE interlude_breaks_taken counter\n"));
+        assert!(metrics.contains("interlude_breaks_scheduled_total 1\n"));
+        assert!(metrics.contains("interlude_breaks_taken_total 2\n"));
+        assert!(metrics.contains("interlude_breaks_partial_total 1\n"));
+        assert!(metrics.contains("interlude_break_seconds_total 500\n"));
+        assert!(metrics.contains("interlude_break_away_seconds_total 110\n"));
+        assert!(metrics.contains("interlude_snoozes_total 1\n"));
+        assert!(metrics.contains("interlude_breaks_skipped_total 1\n"));
+        assert!(metrics.contains("interlude_skip_reasons_total{reason=\"meeting\"} 1\n"));
+        assert!(metrics.contains("interlude_skip_reasons_total{reason=\"other\"} 0\n"));
+        assert!(metrics.contains("interlude_feedback_ratings_total{rating=\"1\"} 0\n"));
+        assert!(metrics.contains("interlude_feedback_ratings_total{rating=\"2\"} 1\n"));
+        assert!(metrics.contains("interlude_focused_seconds_total{tag=\"say-\\\"hi\\\"\"} 1500\n"));
+        assert!(metrics.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn focused_time_adds_up_per_tag() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut clock = FocusClock::default();
+        clock.set_running(true, start);
+        // Untagged time is not kept.
+        assert_eq!(clock.set_tag(Some("a".to_string()), at(100)), None);
+        clock.set_running(false, at(400));
+        clock.set_running(true, at(1000));
+        let focused = |seconds, tag: &str| {
+            Some(StatsEvent::Focused {
+                seconds,
+                tag: tag.to_string(),
+            })
+        };
+        assert_eq!(clock.take(at(1100)), focused(400, "a"));
+        assert_eq!(
+            clock.set_tag(Some("b".to_string()), at(1160)),
+            focused(60, "a")
+        );
+        assert_eq!(clock.take(at(1160)), None);
+
+        let day = 86_400;
+        let base = 1_700_000_000;
+        let record = |at, seconds, tag: &str| Record {
+            at,
+            event: StatsEvent::Focused {
+                seconds,
+                tag: tag.to_string(),
+            },
+        };
+        let records = [
+            record(base, 1500, "b"),
+            record(base - 2 * day, 3600, "a"),
+            record(base - 30 * day, 7200, "a"),
+            record(base + 60, 300, "b"),
+        ];
+        let tags = render_tags(&records, utc_date(base).unwrap(), utc_date);
+        assert_eq!(
+            tags,
+            "Tag      Today     7 days   All time\n\
+             a       0h 00m     1h 00m     3h 00m\n\
+             b       0h 30m     0h 30m     0h 30m\n"
+        );
+        assert!(render_tags(&[], utc_date(base).unwrap(), utc_date).starts_with("No tagged"));
+    }
+
+    #[test]
+    fn records_format_as_lines() {
+        let record = |event| Record {
+            at: 1_700_000_000,
+            event,
+        };
+        assert_eq!(
+            format_record(&record(StatsEvent::BreakDue)),
+            "1700000000 break_due\n"
+        );
+        assert_eq!(
+            format_record(&record(StatsEvent::BreakTaken {
+                seconds: 180,
+                away: None
+            })),
+            "1700000000 break_taken seconds=180\n"
+        );
+        assert_eq!(
+            format_record(&record(StatsEvent::BreakPartial {
+                seconds: 90,
+                away: Some(75)
+            })),
+            "1700000000 break_partial seconds=90 away=75\n"
+        );
+        assert_eq!(
+            format_record(&record(StatsEvent::Snoozed)),
+            "1700000000 snoozed\n"
+        );
+        assert_eq!(
+            format_record(&record(StatsEvent::Feedback { rating: 3 })),
+            "1700000000 feedback rating=3\n"
+        );
+    }
+}
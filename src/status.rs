@@ -0,0 +1,324 @@
+//! What the daemon is doing, described once for every frontend.
+//!
+//! The main loop assembles a [`Status`] each pass; the status file that bars
+//! read, `interlude ctl status` and the D-Bus properties all render from it,
+//! so a pause or a deferred break reads the same everywhere and a new field
+//! only has to be added here.
+
+use anyhow::{Context, Result};
+use chrono::NaiveTime;
+use interlude_client::Settings;
+use std::fs;
+
+use crate::inhibitors::InhibitorSource;
+use crate::paths;
+use crate::render::FrameStats;
+use crate::scheduler::Phase;
+use crate::stats::DayTotals;
+
+/// Why breaks are held back while working.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hold {
+    /// Paused with SIGUSR1 or `interlude ctl pause`.
+    Requested,
+    Inhibitor(InhibitorSource),
+    /// The timer waits for the session to unlock.
+    SessionLocked,
+    /// The next break was pushed back, e.g. by `--startup-grace`.
+    Deferred,
+    /// Past `--workday-end`, until the working day starts again.
+    OffHours,
+}
+
+impl Hold {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Requested => "requested",
+            Self::Inhibitor(_) => "inhibitor",
+            Self::SessionLocked => "session-locked",
+            Self::Deferred => "deferred",
+            Self::OffHours => "off-hours",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub phase: Phase,
+    /// Time left in the current phase, when it runs on a timer.
+    pub seconds_left: Option<u64>,
+    pub hold: Option<Hold>,
+    /// Local time the hold ends by itself, if it does.
+    pub paused_until: Option<NaiveTime>,
+    /// Snoozes still allowed before the break; `None` when unlimited.
+    pub snoozes_left: Option<u32>,
+    /// Times the current break has been snoozed.
+    pub snooze_count: u32,
+    pub today: DayTotals,
+    /// The options `interlude ctl set` can change, as they are now.
+    pub settings: Settings,
+    /// A newer release, found with `--check-updates`.
+    pub update_available: Option<String>,
+    /// How the overlay's last fade was drawn, for reports of a choppy one.
+    pub last_fade: Option<FrameStats>,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self {
+            phase: Phase::Working,
+            seconds_left: None,
+            hold: None,
+            paused_until: None,
+            snoozes_left: None,
+            snooze_count: 0,
+            today: DayTotals::default(),
+            settings: Settings::default(),
+            update_available: None,
+            last_fade: None,
+        }
+    }
+}
+
+impl Status {
+    /// Short text for a bar.
+    pub fn text(&self) -> String {
+        let minutes = || format!("{}m", self.seconds_left.unwrap_or(0).div_ceil(60));
+        match (self.hold, self.phase) {
+            (Some(Hold::Deferred), _) => minutes(),
+            (Some(Hold::OffHours), _) => "off".to_string(),
+            (Some(_), _) => "paused".to_string(),
+            (None, Phase::Working | Phase::Snoozing) => minutes(),
+            (None, Phase::LockedAwaitingAction) => "break".to_string(),
+            (None, Phase::OnBreak) => mm_ss(self.seconds_left.unwrap_or(0)),
+            (None, Phase::BreakFinished) => "done".to_string(),
+        }
+    }
+
+    /// One sentence, e.g. "Paused until 15:30", and a second one when an
+    /// update is available.
+    pub fn tooltip(&self) -> String {
+        match &self.update_available {
+            Some(version) => format!("{}. Interlude {version} is available", self.sentence()),
+            None => self.sentence(),
+        }
+    }
+
+    fn sentence(&self) -> String {
+        let left = self.seconds_left.unwrap_or(0);
+        let until = self
+            .paused_until
+            .map(|at| format!(" until {}", at.format("%H:%M")))
+            .unwrap_or_default();
+        match (self.hold, self.phase) {
+            (Some(Hold::Requested), _) => format!("Paused{until}"),
+            (Some(Hold::Inhibitor(source)), _) => format!("Paused ({})", source.label()),
+            (Some(Hold::SessionLocked), _) => "Paused (session locked)".to_string(),
+            (Some(Hold::Deferred), _) => format!("Break deferred{until}"),
+            (Some(Hold::OffHours), _) => format!("Off hours{until}"),
+            (None, Phase::Working) => format!("Next break in {}", minutes(left)),
+            (None, Phase::Snoozing) => format!("Snoozed, break in {}", minutes(left)),
+            (None, Phase::LockedAwaitingAction) => "Break due".to_string(),
+            (None, Phase::OnBreak) => format!("On break, {} left", mm_ss(left)),
+            (None, Phase::BreakFinished) => "Break complete".to_string(),
+        }
+    }
+
+    /// CSS class for bars to style on.
+    pub fn class(&self) -> &'static str {
+        match (self.hold, self.phase) {
+            (Some(Hold::Deferred), _) => "deferred",
+            (Some(Hold::OffHours), _) => "off-hours",
+            (Some(_), _) => "paused",
+            (None, Phase::Working) => "working",
+            (None, Phase::Snoozing) => "snoozed",
+            (None, Phase::LockedAwaitingAction) => "due",
+            (None, Phase::OnBreak) => "break",
+            (None, Phase::BreakFinished) => "finished",
+        }
+    }
+
+    pub fn phase_label(&self) -> &'static str {
+        match self.phase {
+            Phase::Working => "working",
+            Phase::Snoozing => "snoozed",
+            Phase::LockedAwaitingAction => "due",
+            Phase::OnBreak => "break",
+            Phase::BreakFinished => "finished",
+        }
+    }
+
+    /// The object a waybar `custom` module with `"return-type": "json"` reads,
+    /// with every other field alongside for scripts.
+    pub fn to_json(&self) -> String {
+        let t = &self.today;
+        let s = &self.settings;
+        format!(
+            "{{\"text\":{},\"tooltip\":{},\"class\":{},\"alt\":{},\"phase\":{},\
+             \"seconds_left\":{},\"hold\":{},\"paused_until\":{},\"snoozes_left\":{},\
+             \"snooze_count\":{},\"today\":{{\"due\":{},\"taken\":{},\"skipped\":{},\"snoozed\":{}}},\
+             \"settings\":{{\"interval_minutes\":{},\"break_seconds\":{},\
+             \"snooze_base_seconds\":{},\"max_snoozes\":{}}},\"update_available\":{},\"last_fade\":{}}}",
+            json_string(&self.text()),
+            json_string(&self.tooltip()),
+            json_string(self.class()),
+            json_string(self.class()),
+            json_string(self.phase_label()),
+            json_or_null(self.seconds_left),
+            json_or_null(self.hold.map(|hold| json_string(hold.label()))),
+            json_or_null(
+                self.paused_until
+                    .map(|at| json_string(&at.format("%H:%M").to_string()))
+            ),
+            json_or_null(self.snoozes_left),
+            self.snooze_count,
+            t.due,
+            t.taken,
+            t.skipped,
+            t.snoozed,
+            s.interval_minutes,
+            s.break_seconds,
+            s.snooze_base_seconds,
+            s.max_snoozes,
+            json_or_null(self.update_available.as_deref().map(json_string)),
+            json_or_null(self.last_fade.map(|frames| format!(
+                "{{\"frames\":{},\"dropped\":{},\"average_us\":{},\"max_us\":{}}}",
+                frames.frames,
+                frames.dropped,
+                frames.average().as_micros(),
+                frames.max.as_micros()
+            ))),
+        )
+    }
+}
+
+fn minutes(secs: u64) -> String {
+    format!("{} min", secs.div_ceil(60))
+}
+
+fn mm_ss(secs: u64) -> String {
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+fn json_or_null(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "null".to_string(), |v| v.to_string())
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Replaces the status file in one step, so a bar never reads half of it.
+pub fn write_status_file(status: &Status) -> Result<()> {
+    let path = paths::status_path();
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, format!("{}\n", status.to_json()))
+        .with_context(|| format!("write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_override_the_phase() {
+        let status = Status {
+            seconds_left: Some(25 * 60 - 10),
+            ..Status::default()
+        };
+        assert_eq!(status.text(), "25m");
+        assert_eq!(status.tooltip(), "Next break in 25 min");
+        assert_eq!(status.class(), "working");
+
+        let paused = Status {
+            hold: Some(Hold::Requested),
+            paused_until: NaiveTime::from_hms_opt(15, 30, 0),
+            ..status.clone()
+        };
+        assert_eq!(paused.text(), "paused");
+        assert_eq!(paused.tooltip(), "Paused until 15:30");
+        assert_eq!(paused.class(), "paused");
+
+        let inhibited = Status {
+            hold: Some(Hold::Inhibitor(InhibitorSource::Game)),
+            ..status.clone()
+        };
+        assert_eq!(inhibited.tooltip(), "Paused (game running)");
+
+        let deferred = Status {
+            hold: Some(Hold::Deferred),
+            paused_until: NaiveTime::from_hms_opt(9, 5, 0),
+            ..status
+        };
+        assert_eq!(deferred.text(), "25m");
+        assert_eq!(deferred.tooltip(), "Break deferred until 09:05");
+
+        let off = Status {
+            hold: Some(Hold::OffHours),
+            paused_until: NaiveTime::from_hms_opt(8, 30, 0),
+            ..deferred
+        };
+        assert_eq!(off.text(), "off");
+        assert_eq!(off.tooltip(), "Off hours until 08:30");
+        assert_eq!(off.class(), "off-hours");
+    }
+
+    #[test]
+    fn json_carries_every_field() {
+        let status = Status {
+            phase: Phase::OnBreak,
+            seconds_left: Some(133),
+            snoozes_left: Some(1),
+            snooze_count: 2,
+            today: DayTotals {
+                due: 4,
+                taken: 2,
+                skipped: 1,
+                snoozed: 3,
+            },
+            ..Status::default()
+        };
+        assert_eq!(
+            status.to_json(),
+            concat!(
+                r#"{"text":"02:13","tooltip":"On break, 02:13 left","class":"break","alt":"break","#,
+                r#""phase":"break","seconds_left":133,"hold":null,"paused_until":null,"snoozes_left":1,"#,
+                r#""snooze_count":2,"today":{"due":4,"taken":2,"skipped":1,"snoozed":3},"#,
+                r#""settings":{"interval_minutes":0,"break_seconds":0,"snooze_base_seconds":0,"max_snoozes":0},"#,
+                r#""update_available":null,"last_fade":null}"#
+            )
+        );
+        let update = Status {
+            update_available: Some("v0.2.0".to_string()),
+            last_fade: Some(FrameStats {
+                frames: 2,
+                dropped: 1,
+                total: std::time::Duration::from_micros(5000),
+                max: std::time::Duration::from_micros(3500),
+            }),
+            ..status
+        };
+        assert_eq!(
+            update.tooltip(),
+            "On break, 02:13 left. Interlude v0.2.0 is available"
+        );
+        assert!(update.to_json().ends_with(concat!(
+            r#","update_available":"v0.2.0","#,
+            r#""last_fade":{"frames":2,"dropped":1,"average_us":2500,"max_us":3500}}"#
+        )));
+        assert_eq!(json_string("a \"b\"\\\n"), r#""a \"b\"\\\n""#);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::pixels::{blend_row, clip_span};
 use fontdue::Font;
 use std::sync::OnceLock;
 
@@ -36,65 +37,99 @@ pub fn text_width_size(text: &str, size: f32) -> i32 {
         if ch == '\n' {
             break;
         }
-        let (metrics, _) = font.rasterize(ch, size);
+        let metrics = font.metrics(ch, size);
         width += metrics.advance_width.round() as i32;
     }
     width
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn draw_text_rgba_size(
-    buf: &mut [u8],
-    width: u32,
-    height: u32,
-    x: i32,
-    y: i32,
-    text: &str,
-    rgba: [u8; 4],
-    size: f32,
-) {
-    let font = font();
-    let mut pen_x = x;
-    let mut pen_y = y;
+/// A line of text rasterised once into a coverage mask, so it can be
+/// composited at any alpha without going back through fontdue.
+pub struct TextLayer {
+    /// Horizontal pen advance of the whole line, as `text_width_size` reports it.
+    pub advance: i32,
+    left: i32,
+    top: i32,
+    width: usize,
+    height: usize,
+    coverage: Vec<u8>,
+}
 
-    for ch in text.chars() {
-        if ch == '\n' {
-            pen_x = x;
-            pen_y += line_height_size(size);
-            continue;
+impl TextLayer {
+    /// Rasterises the first line of `text`.
+    pub fn new(text: &str, size: f32) -> Self {
+        let font = font();
+        let mut glyphs = Vec::new();
+        let mut pen_x = 0i32;
+        for ch in text.chars().take_while(|ch| *ch != '\n') {
+            let (metrics, bitmap) = font.rasterize(ch, size);
+            let glyph_x = pen_x + metrics.xmin;
+            let glyph_y = -(metrics.height as i32) - metrics.ymin;
+            if metrics.width > 0 && metrics.height > 0 {
+                glyphs.push((glyph_x, glyph_y, metrics.width, bitmap));
+            }
+            pen_x += metrics.advance_width.round() as i32;
         }
 
-        let (metrics, bitmap) = font.rasterize(ch, size);
-        let glyph_x = pen_x + metrics.xmin;
-        let glyph_y = pen_y - metrics.height as i32 - metrics.ymin;
-
-        for row in 0..metrics.height {
-            for col in 0..metrics.width {
-                let alpha = bitmap[row * metrics.width + col];
-                if alpha == 0 {
-                    continue;
-                }
+        let left = glyphs.iter().map(|g| g.0).min().unwrap_or(0);
+        let top = glyphs.iter().map(|g| g.1).min().unwrap_or(0);
+        let right = glyphs.iter().map(|g| g.0 + g.2 as i32).max().unwrap_or(0);
+        let bottom = glyphs
+            .iter()
+            .map(|g| g.1 + (g.3.len() / g.2) as i32)
+            .max()
+            .unwrap_or(0);
+        let width = (right - left) as usize;
+        let height = (bottom - top) as usize;
 
-                let px = glyph_x + col as i32;
-                let py = glyph_y + row as i32;
-                if px < 0 || py < 0 || (px as u32) >= width || (py as u32) >= height {
-                    continue;
+        let mut coverage = vec![0u8; width * height];
+        for (glyph_x, glyph_y, glyph_w, bitmap) in &glyphs {
+            let x0 = (glyph_x - left) as usize;
+            let y0 = (glyph_y - top) as usize;
+            for (row, src) in bitmap.chunks_exact(*glyph_w).enumerate() {
+                let dst = &mut coverage[(y0 + row) * width + x0..][..*glyph_w];
+                for (d, s) in dst.iter_mut().zip(src) {
+                    *d = (*d).max(*s);
                 }
-
-                let idx = ((py as u32 * width + px as u32) * 4) as usize;
-                blend_pixel(&mut buf[idx..idx + 4], rgba, alpha);
             }
         }
 
-        pen_x += metrics.advance_width.round() as i32;
+        Self {
+            advance: pen_x,
+            left,
+            top,
+            width,
+            height,
+            coverage,
+        }
     }
-}
 
-fn blend_pixel(dst: &mut [u8], rgba: [u8; 4], alpha: u8) {
-    let a = (alpha as u16 * rgba[3] as u16) / 255;
-    let inv = 255u16.saturating_sub(a);
-    dst[0] = ((rgba[0] as u16 * a + dst[0] as u16 * inv) / 255) as u8;
-    dst[1] = ((rgba[1] as u16 * a + dst[1] as u16 * inv) / 255) as u8;
-    dst[2] = ((rgba[2] as u16 * a + dst[2] as u16 * inv) / 255) as u8;
-    dst[3] = 255;
+    /// Composites the line with its pen origin at `x` and baseline at `baseline`.
+    pub fn draw(
+        &self,
+        buf: &mut [u8],
+        width: u32,
+        height: u32,
+        x: i32,
+        baseline: i32,
+        rgba: [u8; 4],
+    ) {
+        let (Some((src_x, dst_x, cols)), Some((src_y, dst_y, rows))) = (
+            clip_span(x + self.left, self.width as u32, width),
+            clip_span(baseline + self.top, self.height as u32, height),
+        ) else {
+            return;
+        };
+        let stride = width as usize * 4;
+        for row in 0..rows {
+            let src = &self.coverage[(src_y + row) * self.width + src_x..][..cols];
+            let dst = &mut buf[(dst_y + row) * stride + dst_x * 4..][..cols * 4];
+            blend_row(
+                dst,
+                src.iter().copied(),
+                [rgba[0], rgba[1], rgba[2]],
+                rgba[3],
+            );
+        }
+    }
 }
@@ -0,0 +1,341 @@
+//! `interlude tui`: the daemon's status, its live settings and today's breaks
+//! on one terminal screen, e.g. over SSH.
+//!
+//! Everything goes through the control socket, like `interlude ctl`: the
+//! status is polled every second and settings are changed with `set`, so
+//! they last until the daemon restarts. Today's history is read from the
+//! stats log.
+
+use anyhow::Result;
+use interlude_client::{Client, Hold, Request, Setting, Status};
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use std::time::{Duration, Instant};
+
+use crate::paths;
+use crate::stats::{self, Record, StatsEvent};
+
+const STATUS_REFRESH: Duration = Duration::from_secs(1);
+const HISTORY_REFRESH: Duration = Duration::from_secs(10);
+
+struct App {
+    client: Client,
+    status: Result<Status, String>,
+    /// Whether the daemon understands `set`.
+    can_set: bool,
+    selected: usize,
+    history: Vec<Record>,
+    /// The answer to the last key that asked the daemon for something.
+    message: String,
+}
+
+impl App {
+    fn refresh_status(&mut self) {
+        self.status = self.client.status().map_err(|err| err.to_string());
+    }
+
+    fn refresh_history(&mut self) {
+        let today = chrono::Local::now().date_naive();
+        self.history = stats::load()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|record| stats::local_date(record.at) == Some(today))
+            .collect();
+    }
+
+    fn send(&mut self, request: Request) {
+        self.message = match self.client.request(&request) {
+            Ok(_) => format!("{}: ok", request.to_line()),
+            Err(err) => format!("{}: {err}", request.to_line()),
+        };
+        self.refresh_status();
+    }
+
+    /// Steps the selected setting up or down.
+    fn adjust(&mut self, up: bool) {
+        if !self.can_set {
+            self.message = "this daemon is too old to change settings".to_string();
+            return;
+        }
+        let Some(settings) = self.status.as_ref().ok().and_then(|s| s.settings) else {
+            return;
+        };
+        let setting = Setting::ALL[self.selected];
+        let value = adjusted(setting, settings.get(setting), up);
+        if value != settings.get(setting) {
+            self.send(Request::Set(setting, value));
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        let paused = self
+            .status
+            .as_ref()
+            .is_ok_and(|status| status.hold == Some(Hold::Requested));
+        self.send(if paused {
+            Request::Resume
+        } else {
+            Request::Pause(None)
+        });
+    }
+}
+
+pub fn run() -> Result<()> {
+    let client = Client::at(paths::socket_path());
+    let can_set = client
+        .capabilities()
+        .is_ok_and(|caps| caps.supports(&Request::Set(Setting::MaxSnoozes, 0)));
+    let mut app = App {
+        status: Err(String::new()),
+        client,
+        can_set,
+        selected: 0,
+        history: Vec::new(),
+        message: String::new(),
+    };
+    app.refresh_status();
+    app.refresh_history();
+
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<()> {
+        let mut last_status = Instant::now();
+        let mut last_history = Instant::now();
+        loop {
+            terminal.draw(|frame| draw(frame, &app))?;
+            if event::poll(STATUS_REFRESH.saturating_sub(last_status.elapsed()))?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        app.selected = app.selected.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        app.selected = (app.selected + 1).min(Setting::ALL.len() - 1);
+                    }
+                    KeyCode::Left | KeyCode::Char('-' | 'h') => app.adjust(false),
+                    KeyCode::Right | KeyCode::Char('+' | '=' | 'l') => app.adjust(true),
+                    KeyCode::Char('p') => app.toggle_pause(),
+                    KeyCode::Char('s') => app.send(Request::Snooze),
+                    _ => {}
+                }
+            }
+            if last_status.elapsed() >= STATUS_REFRESH {
+                app.refresh_status();
+                last_status = Instant::now();
+            }
+            if last_history.elapsed() >= HISTORY_REFRESH {
+                app.refresh_history();
+                last_history = Instant::now();
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [status_area, settings_area, history_area, help_area] = Layout::vertical([
+        Constraint::Length(5),
+        Constraint::Length(Setting::ALL.len() as u16 + 2),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let status_lines = match &app.status {
+        Ok(status) => {
+            let today = status.today;
+            let mut lines = vec![
+                Line::from(status.tooltip.clone())
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+                Line::from(format!(
+                    "Today: {} due, {} taken, {} skipped, {} snoozed",
+                    today.due, today.taken, today.skipped, today.snoozed
+                )),
+            ];
+            if let Some(left) = status.snoozes_left {
+                lines.push(Line::from(format!("Snoozes left: {left}")));
+            }
+            lines
+        }
+        Err(err) => vec![Line::from(format!("Not connected: {err}"))],
+    };
+    frame.render_widget(
+        Paragraph::new(status_lines).block(Block::bordered().title(" interlude ")),
+        status_area,
+    );
+
+    let settings = app.status.as_ref().ok().and_then(|status| status.settings);
+    let items: Vec<ListItem> = Setting::ALL
+        .iter()
+        .map(|setting| {
+            let value =
+                settings.map_or_else(|| "?".to_string(), |s| show(*setting, s.get(*setting)));
+            ListItem::new(format!("{:<22}{value}", setting.key()))
+        })
+        .collect();
+    let title = if app.can_set {
+        " Settings (until restart) "
+    } else {
+        " Settings (read-only) "
+    };
+    let mut state = ListState::default().with_selected(Some(app.selected));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+        settings_area,
+        &mut state,
+    );
+
+    let history: Vec<ListItem> = app
+        .history
+        .iter()
+        .rev()
+        .map(|record| {
+            let at = chrono::DateTime::from_timestamp(record.at as i64, 0)
+                .map(|at| at.with_timezone(&chrono::Local).format("%H:%M").to_string())
+                .unwrap_or_default();
+            ListItem::new(format!("{at}  {}", describe(&record.event)))
+        })
+        .collect();
+    frame.render_widget(
+        List::new(history).block(Block::bordered().title(" Today ")),
+        history_area,
+    );
+
+    let help = if app.message.is_empty() {
+        "↑↓ select  ←→ change  p pause/resume  s snooze  q quit".to_string()
+    } else {
+        app.message.clone()
+    };
+    frame.render_widget(Paragraph::new(help), help_area);
+}
+
+/// The value after one step of the left or right key, within the range
+/// the daemon accepts.
+fn adjusted(setting: Setting, value: u64, up: bool) -> u64 {
+    let step = match setting {
+        Setting::IntervalMinutes => 5,
+        Setting::BreakSeconds => 30,
+        Setting::SnoozeBaseSeconds => 60,
+        Setting::MaxSnoozes => 1,
+    };
+    let value = if up {
+        value.saturating_add(step)
+    } else {
+        value.saturating_sub(step)
+    };
+    value.clamp(*setting.range().start(), *setting.range().end())
+}
+
+fn show(setting: Setting, value: u64) -> String {
+    match setting {
+        Setting::IntervalMinutes => format!("{value} min"),
+        Setting::BreakSeconds | Setting::SnoozeBaseSeconds => {
+            format!("{}:{:02}", value / 60, value % 60)
+        }
+        Setting::MaxSnoozes if value == 0 => "unlimited".to_string(),
+        Setting::MaxSnoozes => value.to_string(),
+    }
+}
+
+fn describe(event: &StatsEvent) -> String {
+    let minutes = |seconds: u64| format!("{}:{:02}", seconds / 60, seconds % 60);
+    match event {
+        StatsEvent::BreakDue => "break due".to_string(),
+        StatsEvent::BreakTaken { seconds, .. } => format!("break taken ({})", minutes(*seconds)),
+        StatsEvent::BreakPartial { seconds, .. } => {
+            format!("break ended early ({})", minutes(*seconds))
+        }
+        StatsEvent::Snoozed => "snoozed".to_string(),
+        StatsEvent::Skipped {
+            reason: Some(reason),
+        } => format!("skipped ({})", reason.label()),
+        StatsEvent::Skipped { reason: None } => "skipped".to_string(),
+        StatsEvent::Feedback { rating } => format!("rated {rating}/3"),
+        StatsEvent::Focused { seconds, tag } => format!("{} on {tag}", minutes(*seconds)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interlude_client::Settings;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn steps_stay_in_range() {
+        assert_eq!(adjusted(Setting::IntervalMinutes, 30, true), 35);
+        assert_eq!(adjusted(Setting::IntervalMinutes, 5, false), 1);
+        assert_eq!(adjusted(Setting::BreakSeconds, 20, false), 1);
+        assert_eq!(adjusted(Setting::MaxSnoozes, 0, false), 0);
+        assert_eq!(adjusted(Setting::MaxSnoozes, 1000, true), 1000);
+        assert_eq!(show(Setting::BreakSeconds, 150), "2:30");
+        assert_eq!(show(Setting::MaxSnoozes, 0), "unlimited");
+    }
+
+    #[test]
+    fn describes_history_events() {
+        assert_eq!(describe(&StatsEvent::Feedback { rating: 3 }), "rated 3/3");
+        assert_eq!(describe(&StatsEvent::Skipped { reason: None }), "skipped");
+        let focused = StatsEvent::Focused {
+            seconds: 1500,
+            tag: "review".to_string(),
+        };
+        assert_eq!(describe(&focused), "25:00 on review");
+    }
+
+    #[test]
+    fn draws_status_settings_and_history() {
+        let status = crate::status::Status {
+            seconds_left: Some(600),
+            settings: Settings {
+                interval_minutes: 30,
+                break_seconds: 180,
+                snooze_base_seconds: 300,
+                max_snoozes: 0,
+            },
+            ..crate::status::Status::default()
+        };
+        let app = App {
+            client: Client::at("/nonexistent"),
+            status: Ok(Status::from_json(&status.to_json()).unwrap()),
+            can_set: true,
+            selected: 1,
+            history: vec![Record {
+                at: 0,
+                event: StatsEvent::BreakTaken {
+                    seconds: 180,
+                    away: None,
+                },
+            }],
+            message: String::new(),
+        };
+        let mut terminal = Terminal::new(TestBackend::new(60, 18)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        for text in [
+            "Next break in 10 min",
+            "interval-minutes      30 min",
+            "break-seconds         3:00",
+            "unlimited",
+            "break taken (3:00)",
+        ] {
+            assert!(screen.contains(text), "{text:?} missing");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! `--check-updates`: look for a newer release once a day.
+//!
+//! Off by default; without the flag interlude makes no network requests at
+//! all. The latest release tag comes from the GitHub releases API through
+//! `curl`, and the time of the last check is kept in the state directory so
+//! restarts do not ask again. A newer version shows in the status tooltip
+//! and the status JSON; nothing is downloaded or installed.
+
+use anyhow::{Context, Result, bail};
+use crossbeam_channel::Sender;
+use std::fs;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::logging::rate_limited;
+use crate::paths;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/mikebirdgeneau/interlude/releases/latest";
+const CHECK_EVERY: Duration = Duration::from_secs(24 * 60 * 60);
+/// Before trying again after a failed check.
+const RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Checks for a release newer than this build in the background, and sends
+/// its version to `tx` when there is one.
+pub fn spawn_update_checker(tx: Sender<String>) -> Result<()> {
+    thread::Builder::new()
+        .name("update-check".to_string())
+        .spawn(move || {
+            loop {
+                let now = unix_now();
+                let (latest, next) = match last_check() {
+                    Some((at, latest)) if now.saturating_sub(at) < CHECK_EVERY.as_secs() => {
+                        (latest, at + CHECK_EVERY.as_secs())
+                    }
+                    _ => match fetch_latest() {
+                        Ok(latest) => {
+                            if let Err(err) = save_check(now, &latest) {
+                                rate_limited("update check not saved", format!("{err:#}"));
+                            }
+                            (Some(latest), now + CHECK_EVERY.as_secs())
+                        }
+                        Err(err) => {
+                            rate_limited("update check failed", format!("{err:#}"));
+                            (None, now + RETRY_AFTER.as_secs())
+                        }
+                    },
+                };
+                if let Some(latest) = latest.filter(|tag| is_newer(tag, env!("CARGO_PKG_VERSION")))
+                    && tx.send(latest).is_err()
+                {
+                    return;
+                }
+                thread::sleep(Duration::from_secs(next.saturating_sub(now).max(1)));
+            }
+        })
+        .context("spawn update check thread")?;
+    Ok(())
+}
+
+/// The tag of the latest release.
+fn fetch_latest() -> Result<String> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", "20"])
+        .args(["--header", "Accept: application/vnd.github+json"])
+        .arg(RELEASES_URL)
+        .output()
+        .context("run curl")?;
+    if !output.status.success() {
+        bail!(
+            "curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    tag_name(&String::from_utf8_lossy(&output.stdout)).context("no tag_name in the release")
+}
+
+/// The `"tag_name"` string of a release object.
+fn tag_name(body: &str) -> Option<String> {
+    let rest = &body[body.find("\"tag_name\"")? + "\"tag_name\"".len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_string())
+}
+
+/// Whether release `tag` (e.g. `v0.2.0`) is a later version than `current`.
+/// Pre-release suffixes are ignored.
+fn is_newer(tag: &str, current: &str) -> bool {
+    let parts = |version: &str| -> Option<Vec<u64>> {
+        let version = version.trim().trim_start_matches('v');
+        let version = version.split(['-', '+']).next()?;
+        version.split('.').map(|n| n.parse().ok()).collect()
+    };
+    match (parts(tag), parts(current)) {
+        (Some(tag), Some(current)) => tag > current,
+        _ => false,
+    }
+}
+
+/// When the last check ran, in Unix seconds, and the tag it found.
+fn last_check() -> Option<(u64, Option<String>)> {
+    let text = fs::read_to_string(paths::update_check_file()?).ok()?;
+    let mut words = text.split_whitespace();
+    let at = words.next()?.parse().ok()?;
+    Some((at, words.next().map(str::to_string)))
+}
+
+fn save_check(at: u64, latest: &str) -> Result<()> {
+    let Some(path) = paths::update_check_file() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    fs::write(&path, format!("{at} {latest}\n"))
+        .with_context(|| format!("write {}", path.display()))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_release_tags_with_this_version() {
+        assert!(is_newer("v0.2.0", "0.1.8"));
+        assert!(is_newer("0.1.10", "0.1.8"));
+        assert!(is_newer("v1.0.0-rc1", "0.9"));
+        assert!(!is_newer("v0.1.8", "0.1.8"));
+        assert!(!is_newer("v0.1.7", "0.1.8"));
+        assert!(!is_newer("nightly", "0.1.8"));
+        assert_eq!(
+            tag_name(r#"{"url": "x", "tag_name" : "v0.2.0", "name": "Interlude 0.2"}"#).as_deref(),
+            Some("v0.2.0")
+        );
+        assert_eq!(tag_name(r#"{"message": "Not Found"}"#), None);
+    }
+}
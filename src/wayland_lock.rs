@@ -1,17 +1,27 @@
 use anyhow::{Result, anyhow};
 use crossbeam_channel::Sender;
-use rustix::fd::IntoRawFd;
-use std::os::fd::{AsFd, FromRawFd};
+use std::sync::Arc;
 
 use wayland_client::{
     Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
     backend::WaylandError,
     protocol::{
-        wl_buffer, wl_buffer::WlBuffer, wl_compositor::WlCompositor, wl_keyboard, wl_output,
-        wl_output::WlOutput, wl_pointer, wl_region::WlRegion, wl_registry, wl_seat::WlSeat,
-        wl_shm::WlShm, wl_shm_pool::WlShmPool, wl_surface::WlSurface,
+        wl_buffer, wl_buffer::WlBuffer, wl_callback, wl_callback::WlCallback,
+        wl_compositor::WlCompositor, wl_keyboard, wl_output, wl_output::WlOutput, wl_pointer,
+        wl_region::WlRegion, wl_registry, wl_seat::WlSeat, wl_shm::WlShm, wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
     },
 };
+use wayland_protocols::ext::session_lock::v1::client::{
+    ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+    ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
+    ext_session_lock_v1::{self, ExtSessionLockV1},
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::{self, XdgSurface},
+    xdg_toplevel::{self, XdgToplevel},
+    xdg_wm_base::{self, XdgWmBase},
+};
 use wayland_protocols_wlr::layer_shell::v1::client::{
     zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
     zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
@@ -19,19 +29,30 @@ use wayland_protocols_wlr::layer_shell::v1::client::{
 
 use xkbcommon::xkb;
 
-use crate::tiny_font::{draw_text_rgba_size, line_ascent_size, line_height_size, text_width_size};
+use crate::cli::{Backend, BarPlacement, Countdown, OverlayLayer, WidgetAnchor};
+use crate::color::{Gradient, Rgba};
+use crate::keys::{KeyEvent, KeyLayout, Modifiers};
+use crate::logging::{info, rate_limited};
+use crate::render::{
+    FrameSpec, FrameStats, Icon, LineAnchor, LineSpec, PaintedFrame, ProgressBar, RenderDone,
+    RenderJob, Renderer,
+};
+use crate::scheduler::BreakVariant;
+use crate::shm::ShmPool;
+use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use memmap2::MmapMut;
 use resvg::tiny_skia::{Pixmap, Transform};
 use resvg::usvg::{Options, TreeParsing};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum UiEvent {
-    PressZ,
-    PressEnter,
-    PointerClick,
-    AnyKey,
+    Key(KeyEvent),
+    /// A key has been held down long enough to count as a deliberate hold.
+    KeyHold(KeyEvent),
+    /// The keymap or active layout changed.
+    KeyLayout(KeyLayout),
+    PointerGesture,
 }
 
 #[derive(Debug, Clone)]
@@ -39,18 +60,67 @@ pub enum UiMode {
     BreakDue {
         break_secs: u64,
         snooze_count: u32,
+        long_break: bool,
+        /// Where the break falls in the long-break cycle, as
+        /// [`Scheduler::break_in_cycle`](crate::scheduler::Scheduler::break_in_cycle).
+        cycle: Option<(u32, u32)>,
+        carried_secs: u64,
+        /// Keys and what they do, e.g. "z: snooze", when keys work before
+        /// the break starts.
+        hints: Vec<String>,
+        variant: BreakVariant,
+        /// Shown instead of "BREAK STARTING" for rest breaks.
+        headline: Option<String>,
+        /// Shown instead of the rotating suggestion.
+        message: Option<String>,
+        /// Lines added with `interlude ctl note`.
+        notes: Vec<String>,
     },
     OnBreak {
         secs_left: u64,
+        /// When the break ends, for the countdown color between whole
+        /// seconds.
+        ends_at: Instant,
+        /// Length of the whole break, for the countdown bar.
+        break_secs: u64,
         snooze_count: u32,
+        long_break: bool,
+        cycle: Option<(u32, u32)>,
+        /// Shown instead of the rotating suggestion.
+        message: Option<String>,
+        notes: Vec<String>,
+        /// Keys that act now and what they do; no snooze hint once snoozes
+        /// run out.
+        hints: Vec<String>,
+    },
+    BreakFinished {
+        feedback_prompt: bool,
+        /// Keys that act now besides dismissing it, as for `OnBreak`.
+        hints: Vec<String>,
+    },
+    /// Asking why the break is being skipped; `choices` lists the keys.
+    SkipReason {
+        choices: String,
+    },
+    /// The key that dismissed the break-complete screen stayed with the
+    /// overlay; said for a moment before it fades out.
+    KeyCaptured,
+    /// The last minute of work, on the countdown card.
+    WorkEnding {
+        secs_left: u64,
+    },
+    MicroBreak {
+        secs_left: u64,
+        message: String,
     },
-    BreakFinished,
 }
 
 pub struct Locker {
     conn: Connection,
     event_queue: EventQueue<State>,
     state: State,
+    renderer: Renderer,
+    next_surface_id: u64,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -59,37 +129,192 @@ pub struct UiColors {
     pub foreground: [u8; 4],
 }
 
-#[derive(Clone)]
-struct Icon {
-    width: u32,
-    height: u32,
-    rgba: Vec<u8>,
+/// Overlay behaviour that is fixed for the lifetime of the locker.
+#[derive(Debug, Clone)]
+pub struct LockerOptions {
+    /// Outputs (by name, or part of their description) that never get an overlay.
+    pub exclude_outputs: Vec<String>,
+    /// Only draw text on the output holding keyboard/pointer focus.
+    pub follow_focus: bool,
+    /// Outputs that get a small break card instead of the full-screen overlay.
+    pub card_outputs: Vec<String>,
+    pub layer: OverlayLayer,
+    /// Exclusive zone of full-screen overlays; -1 also covers panels.
+    pub exclusive_zone: i32,
+    /// Margins of full-screen overlays as top, right, bottom, left.
+    pub margin: [i32; 4],
+    /// Finish the fade-in early when the pointer moves over the overlay.
+    pub wake_on_pointer: bool,
+    /// Take the keyboard once the text starts fading in, so any key finishes the fade.
+    pub fast_forward_keys: bool,
+    /// Key presses this soon after the keyboard is taken are dropped.
+    pub key_grace: Duration,
+    /// Backends the compositor supports, most preferred first; not empty.
+    pub backends: Vec<Backend>,
+    /// How the time left in a break is shown.
+    pub countdown: Countdown,
+    /// Colors the countdown moves through after the foreground, if any.
+    pub countdown_gradient: Option<Gradient>,
+    /// Seconds before the end of a break the gradient starts; 0 for all of it.
+    pub countdown_gradient_secs: u64,
+    pub bar_thickness: u32,
+    pub bar_placement: BarPlacement,
+    /// Show internal state in the top-left corner of full-screen overlays.
+    pub debug_overlay: bool,
+    pub card: Placement,
+    /// The `--osd-countdown` card.
+    pub osd: Placement,
+}
+
+/// Size and position of a card. Only layer-shell can place one; other
+/// backends use the size.
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub size: [u32; 2],
+    pub anchor: WidgetAnchor,
+    pub margin: [i32; 4],
+}
+
+impl Placement {
+    fn apply(&self, layer_surface: &ZwlrLayerSurfaceV1) {
+        use zwlr_layer_surface_v1::Anchor;
+        let anchor = match self.anchor {
+            // No anchors: the compositor centres the surface on the output.
+            WidgetAnchor::Center => Anchor::empty(),
+            WidgetAnchor::Top => Anchor::Top,
+            WidgetAnchor::Bottom => Anchor::Bottom,
+            WidgetAnchor::Left => Anchor::Left,
+            WidgetAnchor::Right => Anchor::Right,
+            WidgetAnchor::TopLeft => Anchor::Top | Anchor::Left,
+            WidgetAnchor::TopRight => Anchor::Top | Anchor::Right,
+            WidgetAnchor::BottomLeft => Anchor::Bottom | Anchor::Left,
+            WidgetAnchor::BottomRight => Anchor::Bottom | Anchor::Right,
+        };
+        let [top, right, bottom, left] = self.margin;
+        let [width, height] = self.size;
+        layer_surface.set_anchor(anchor);
+        layer_surface.set_margin(top, right, bottom, left);
+        layer_surface.set_size(width, height);
+    }
+}
+
+/// A bound `wl_output` with the identification it advertised (v4+).
+struct OutputInfo {
+    global_name: u32,
+    output: WlOutput,
+    name: Option<String>,
+    description: Option<String>,
+}
+
+impl OutputInfo {
+    fn label(&self) -> String {
+        match (&self.name, &self.description) {
+            (Some(name), Some(desc)) => format!("{name} ({desc})"),
+            (Some(name), None) => name.clone(),
+            (None, Some(desc)) => desc.clone(),
+            (None, None) => format!("output #{}", self.global_name),
+        }
+    }
+
+    fn matches(&self, pattern: &str) -> bool {
+        self.name.as_deref() == Some(pattern)
+            || self
+                .description
+                .as_deref()
+                .is_some_and(|desc| desc.contains(pattern))
+    }
+}
+
+/// How a surface covers its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SurfaceKind {
+    /// Full-screen dim that takes input once the break starts.
+    Overlay,
+    /// A small centred card with the countdown that never takes input.
+    Card,
+}
+
+/// How a surface is placed on its output.
+enum Role {
+    Layer(ZwlrLayerSurfaceV1),
+    /// Fallback without layer-shell: a fullscreen window, which the
+    /// compositor decides how to stack and focus.
+    Window {
+        xdg_surface: XdgSurface,
+        toplevel: XdgToplevel,
+    },
+    /// A session lock surface, which always takes input.
+    Lock(ExtSessionLockSurfaceV1),
+}
+
+impl Role {
+    fn set_keyboard_capture(&self, capture: bool) {
+        // A window takes the keyboard whenever the compositor focuses it.
+        if let Role::Layer(layer_surface) = self {
+            layer_surface.set_keyboard_interactivity(if capture {
+                zwlr_layer_surface_v1::KeyboardInteractivity::Exclusive
+            } else {
+                zwlr_layer_surface_v1::KeyboardInteractivity::None
+            });
+        }
+    }
+
+    fn destroy(&self) {
+        match self {
+            Role::Layer(layer_surface) => layer_surface.destroy(),
+            Role::Window {
+                xdg_surface,
+                toplevel,
+            } => {
+                toplevel.destroy();
+                xdg_surface.destroy();
+            }
+            Role::Lock(lock_surface) => lock_surface.destroy(),
+        }
+    }
 }
 
 struct SurfaceCtx {
-    _output: WlOutput,
+    id: u64,
+    output: WlOutput,
+    kind: SurfaceKind,
     wl_surface: WlSurface,
-    layer_surface: ZwlrLayerSurfaceV1,
+    role: Role,
     width: u32,
     height: u32,
     input_region: Option<WlRegion>,
-    icon: Option<Icon>,
-    small_icon: Option<Icon>,
+    icon: Option<Arc<Icon>>,
+    small_icon: Option<Arc<Icon>>,
     small_icon_size: u32,
+    /// The shm map is with the render thread.
+    in_flight: bool,
+    /// When the frame in flight went to the render thread.
+    submitted: Option<Instant>,
+    redraw_pending: bool,
+    /// A frame callback is out for the last commit: it is not on screen yet.
+    presenting: bool,
 
     // SHM objects (recreated on resize/configure)
-    shm_pool: Option<WlShmPool>,
-    shm_file: Option<std::fs::File>,
-    shm_map: Option<MmapMut>,
-    shm_size: usize,
-    buffer_slots: Vec<BufferSlot>,
-    stride: i32,
+    shm_pool: Option<ShmPool>,
+    /// What was last painted into each pool slot.
+    painted: Vec<Option<PaintedFrame>>,
 }
 
-struct BufferSlot {
-    buffer: WlBuffer,
-    offset: usize,
-    busy: bool,
+/// A held pointer button, tracked to recognise deliberate dismiss gestures.
+#[derive(Debug, Clone, Copy)]
+struct PointerPress {
+    start: Instant,
+    start_x: f64,
+    fired: bool,
+}
+
+/// A held key, tracked to recognise a deliberate hold.
+#[derive(Debug, Clone)]
+struct KeyPress {
+    key: u32,
+    start: Instant,
+    event: KeyEvent,
+    fired: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -105,14 +330,68 @@ struct State {
     shm: Option<WlShm>,
     seat: Option<WlSeat>,
     layer_shell: Option<ZwlrLayerShellV1>,
+    wm_base: Option<XdgWmBase>,
+    session_lock_manager: Option<ExtSessionLockManagerV1>,
+    /// The lock held while the session-lock backend shows a break.
+    session_lock: Option<ExtSessionLockV1>,
+    /// The compositor confirmed the lock; it must be unlocked, not just destroyed.
+    session_locked: bool,
+    /// The compositor refused or ended the lock before we released it.
+    session_lock_finished: bool,
+    /// Supported backends, most preferred first; the first one is in use.
+    backends: Vec<Backend>,
     icon_tree: Option<resvg::Tree>,
 
-    outputs: Vec<WlOutput>,
+    outputs: Vec<OutputInfo>,
+    exclude_outputs: Vec<String>,
+    card_outputs: Vec<String>,
+    /// Every output gets a card, e.g. while a game runs.
+    cards_only: bool,
+    /// The surfaces are the countdown card before a break, not the break.
+    osd: bool,
+    /// The surfaces are a micro-break, which never takes input.
+    micro: bool,
+    layer: Layer,
+    exclusive_zone: i32,
+    margin: [i32; 4],
+    card: Placement,
+    osd_placement: Placement,
+    wake_on_pointer: bool,
+    /// Where the pointer entered during the fade-in, to measure how far it moved.
+    hover_origin: Option<(f64, f64)>,
+    wake_requested: bool,
+    fast_forward_keys: bool,
+    /// The keyboard was taken before the fade-in finished.
+    keyboard_early: bool,
+    key_grace: Duration,
+    /// When the overlay last took the keyboard, for the grace window.
+    keyboard_since: Option<Instant>,
+    follow_focus: bool,
+    countdown: Countdown,
+    countdown_gradient: Option<Gradient>,
+    countdown_gradient_secs: u64,
+    bar_thickness: u32,
+    bar_placement: BarPlacement,
+    debug_overlay: bool,
+    /// The daemon's part of the debug lines, e.g. the scheduler phase.
+    debug_info: Vec<String>,
+    /// Render time of the last frame.
+    frame_time: Duration,
+    /// Frames of the fade running now, and of the last one to finish.
+    fade_frames: FrameStats,
+    last_fade: Option<FrameStats>,
+    focused_surface: Option<WlSurface>,
+    focus_changed: bool,
+    /// Commits ask for a frame callback, so `unlock` can wait for the last
+    /// frame to be shown.
+    want_presentation: bool,
     surfaces: Vec<SurfaceCtx>,
 
     overlay_active: bool,
     overlay_alpha: u8,
     fade: FadeState,
+    /// Held at [`DIM_FRACTION`] of the opacity, without text or input.
+    dim: bool,
     input_captured: bool,
     desired_capture: bool,
     fade_in_complete: bool,
@@ -122,9 +401,14 @@ struct State {
 
     keyboard: Option<wl_keyboard::WlKeyboard>,
     pointer: Option<wl_pointer::WlPointer>,
+    pointer_x: f64,
+    pointer_width: u32,
+    pointer_press: Option<PointerPress>,
+    key_press: Option<KeyPress>,
     xkb_context: xkb::Context,
     xkb_keymap: Option<xkb::Keymap>,
     xkb_state: Option<xkb::State>,
+    xkb_layout: xkb::LayoutIndex,
 
     ui_mode: UiMode,
     tx_ui: Sender<UiEvent>,
@@ -133,10 +417,29 @@ struct State {
 const FADE_IN_DURATION: Duration = Duration::from_secs(15);
 const FADE_OUT_DURATION: Duration = Duration::from_millis(500);
 const TEXT_FADE_IN_WINDOW: Duration = Duration::from_secs(3);
+const DIM_FRACTION: f32 = 0.4;
+/// A micro-break is over in seconds, so it fades in quickly and not all the way.
+const MICRO_FADE_IN_DURATION: Duration = Duration::from_secs(2);
+const MICRO_FRACTION: f32 = 0.7;
 const ICON_SVG: &[u8] = include_bytes!("../assets/plant-2.svg");
 const ICON_BASE_SIZE: u32 = 120;
-const ICON_GAP: i32 = 20;
+const BUFFER_COUNT: usize = 2;
+/// Share of the overlay opacity the countdown card gets.
+const OSD_ALPHA: f32 = 0.6;
+const RENDER_WAIT_TIMEOUT: Duration = Duration::from_millis(200);
+/// How long the last frame before unlocking may take to reach the screen;
+/// compositors skip frame callbacks for outputs that are off.
+const PRESENT_WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+const PRESENT_POLL: Duration = Duration::from_millis(2);
 const MESSAGE_ROTATE_SECONDS: u64 = 60;
+const POINTER_HOLD_DURATION: Duration = Duration::from_millis(1500);
+const KEY_HOLD_DURATION: Duration = Duration::from_millis(1500);
+const POINTER_SWIPE_FRACTION: f64 = 0.25;
+/// Pointer travel over the fading overlay that counts as noticing it.
+const POINTER_WAKE_DISTANCE: f64 = 80.0;
+/// Size of the centred hotspot that sees the pointer during the fade-in.
+const WAKE_HOTSPOT_FRACTION: f32 = 0.4;
+const POINTER_SWIPE_MIN: f64 = 200.0;
 
 const WELLNESS_MESSAGES: &[&str] = &[
     "Soften your gaze and breathe slowly.",
@@ -206,6 +509,436 @@ fn wellness_message(snooze_count: u32) -> &'static str {
     messages[idx]
 }
 
+/// Most key hints on one footer line.
+const HINTS_PER_LINE: usize = 3;
+
+/// The footer under the break text, a few key hints to a line.
+fn note_lines(notes: &[String], size: f32) -> impl Iterator<Item = LineSpec> + '_ {
+    notes.iter().map(move |note| LineSpec {
+        text: note.clone(),
+        size,
+        alpha: 0.8,
+        anchor: LineAnchor::Center,
+        color: None,
+    })
+}
+
+fn hint_lines(hints: &[String], size: f32) -> Vec<LineSpec> {
+    hints
+        .chunks(HINTS_PER_LINE)
+        .map(|chunk| LineSpec {
+            text: chunk.join("   ·   "),
+            size,
+            alpha: 0.45,
+            anchor: LineAnchor::Center,
+            color: None,
+        })
+        .collect()
+}
+
+/// Which kind of break this is, e.g. "Short break 2 of 3", when there are
+/// long breaks to tell it from.
+fn break_type(long_break: bool, cycle: Option<(u32, u32)>) -> Option<String> {
+    match cycle {
+        _ if long_break => Some("Long break".to_string()),
+        Some((n, every)) => Some(format!("Short break {n} of {}", every - 1)),
+        None => None,
+    }
+}
+
+/// Text for a full-screen overlay.
+/// `digits` is false when only the bar counts the break down.
+/// `countdown` colors the digits of a running break, in place of the foreground.
+fn overlay_lines(
+    mode: &UiMode,
+    w: u32,
+    h: u32,
+    digits: bool,
+    countdown: Option<[u8; 3]>,
+) -> Vec<LineSpec> {
+    let base_size = (w.min(h) as f32 / 16.0).clamp(42.0, 110.0);
+    let large_size = (base_size * 1.35).clamp(56.0, 150.0);
+    let small_size = (base_size * 0.7).clamp(28.0, 80.0);
+
+    match mode {
+        UiMode::BreakDue {
+            break_secs,
+            snooze_count,
+            long_break,
+            cycle,
+            carried_secs,
+            hints,
+            variant,
+            headline,
+            message,
+            notes,
+        } => {
+            let l1 = match (variant, headline) {
+                (BreakVariant::Rest, Some(headline)) => headline,
+                (BreakVariant::Rest, None) => "BREAK STARTING",
+                (BreakVariant::Planning, _) => "PLAN YOUR DAY",
+                (BreakVariant::WrapUp, _) => "TIME TO WRAP UP",
+            }
+            .to_string();
+            let m = break_secs / 60;
+            let s = break_secs % 60;
+            let l2 = match break_type(*long_break, *cycle) {
+                Some(kind) => format!("{kind} — {:02}:{:02}", m, s),
+                None => format!("Break: {:02}:{:02}", m, s),
+            };
+            let l3 = message
+                .clone()
+                .unwrap_or_else(|| wellness_message(*snooze_count).to_string());
+            let mut lines = vec![
+                LineSpec {
+                    text: l1,
+                    size: base_size,
+                    alpha: 1.0,
+                    anchor: LineAnchor::Center,
+                    color: None,
+                },
+                LineSpec {
+                    text: l2,
+                    size: small_size,
+                    alpha: 0.65,
+                    anchor: LineAnchor::Center,
+                    color: None,
+                },
+            ];
+            if *carried_secs > 0 {
+                lines.push(LineSpec {
+                    text: format!(
+                        "includes {}:{:02} carried over",
+                        carried_secs / 60,
+                        carried_secs % 60
+                    ),
+                    size: small_size * 0.8,
+                    alpha: 0.45,
+                    anchor: LineAnchor::Center,
+                    color: None,
+                });
+            }
+            lines.push(LineSpec {
+                text: l3,
+                size: small_size,
+                alpha: 0.65,
+                anchor: LineAnchor::Center,
+                color: None,
+            });
+            lines.extend(note_lines(notes, small_size));
+            lines.extend(hint_lines(hints, small_size * 0.8));
+            lines
+        }
+        UiMode::OnBreak {
+            secs_left,
+            snooze_count,
+            long_break,
+            cycle,
+            message,
+            notes,
+            hints,
+            ..
+        } => {
+            let m = secs_left / 60;
+            let s = secs_left % 60;
+            let mut lines = Vec::new();
+            if let Some(kind) = break_type(*long_break, *cycle) {
+                lines.push(LineSpec {
+                    text: kind,
+                    size: small_size,
+                    alpha: 0.65,
+                    anchor: LineAnchor::Center,
+                    color: None,
+                });
+            }
+            if digits {
+                lines.push(LineSpec {
+                    text: format!("{:02}:{:02}", m, s),
+                    size: large_size,
+                    alpha: 1.0,
+                    anchor: LineAnchor::CenterOnColon,
+                    color: countdown,
+                });
+            }
+            lines.extend([LineSpec {
+                text: message
+                    .clone()
+                    .unwrap_or_else(|| wellness_message(*snooze_count).to_string()),
+                size: small_size,
+                alpha: 0.65,
+                anchor: LineAnchor::Center,
+                color: None,
+            }]);
+            lines.extend(note_lines(notes, small_size));
+            lines.extend(hint_lines(hints, small_size * 0.8));
+            lines
+        }
+        UiMode::BreakFinished {
+            feedback_prompt,
+            hints,
+        } => {
+            let mut lines = vec![LineSpec {
+                text: "Break Complete.".to_string(),
+                size: base_size,
+                alpha: 1.0,
+                anchor: LineAnchor::Center,
+                color: None,
+            }];
+            if *feedback_prompt {
+                lines.push(LineSpec {
+                    text: "How do you feel? 1 tired · 2 okay · 3 rested".to_string(),
+                    size: small_size,
+                    alpha: 0.65,
+                    anchor: LineAnchor::Center,
+                    color: None,
+                });
+            }
+            lines.push(LineSpec {
+                text: "Press any key or click and hold to continue".to_string(),
+                size: small_size,
+                alpha: 0.65,
+                anchor: LineAnchor::Center,
+                color: None,
+            });
+            lines.extend(hint_lines(hints, small_size * 0.8));
+            lines
+        }
+        UiMode::WorkEnding { secs_left } => vec![LineSpec {
+            text: format!("Break in {:02}:{:02}", secs_left / 60, secs_left % 60),
+            size: base_size,
+            alpha: 1.0,
+            anchor: LineAnchor::CenterOnColon,
+            color: None,
+        }],
+        UiMode::MicroBreak { secs_left, message } => vec![
+            LineSpec {
+                text: message.clone(),
+                size: base_size,
+                alpha: 1.0,
+                anchor: LineAnchor::Center,
+                color: None,
+            },
+            LineSpec {
+                text: format!("{:02}:{:02}", secs_left / 60, secs_left % 60),
+                size: small_size,
+                alpha: 0.65,
+                anchor: LineAnchor::CenterOnColon,
+                color: None,
+            },
+        ],
+        UiMode::KeyCaptured => vec![
+            LineSpec {
+                text: "Key captured — not sent to your app".to_string(),
+                size: base_size,
+                alpha: 1.0,
+                anchor: LineAnchor::Center,
+                color: None,
+            },
+            LineSpec {
+                text: "Keys go to your apps again".to_string(),
+                size: small_size,
+                alpha: 0.65,
+                anchor: LineAnchor::Center,
+                color: None,
+            },
+        ],
+        UiMode::SkipReason { choices } => vec![
+            LineSpec {
+                text: "Why skip this break?".to_string(),
+                size: base_size,
+                alpha: 1.0,
+                anchor: LineAnchor::Center,
+                color: None,
+            },
+            LineSpec {
+                text: choices.clone(),
+                size: small_size,
+                alpha: 0.65,
+                anchor: LineAnchor::Center,
+                color: None,
+            },
+            LineSpec {
+                text: "Any other key goes back to the break".to_string(),
+                size: small_size * 0.8,
+                alpha: 0.45,
+                anchor: LineAnchor::Center,
+                color: None,
+            },
+        ],
+    }
+}
+
+/// Whether a button pressed at `start` has been held long enough by `now`
+/// to dismiss the break-complete screen; a plain click does not.
+fn pointer_held(start: Instant, now: Instant) -> bool {
+    now.saturating_duration_since(start) >= POINTER_HOLD_DURATION
+}
+
+/// Whether a press that moved `distance` sideways across a `width`-wide
+/// surface is a swipe: a quarter of the width, and never less than
+/// [`POINTER_SWIPE_MIN`].
+fn pointer_swiped(distance: f64, width: u32) -> bool {
+    distance.abs() >= (width as f64 * POINTER_SWIPE_FRACTION).max(POINTER_SWIPE_MIN)
+}
+
+/// When the key grace window runs from after the overlay takes (`capture`)
+/// or lets go of the keyboard: from `now` when it is taken, from `since` when
+/// it was already taken early in the fade-in, and not at all once let go.
+fn grace_start(
+    capture: bool,
+    early: bool,
+    since: Option<Instant>,
+    now: Instant,
+) -> Option<Instant> {
+    match (capture, early) {
+        (false, _) => None,
+        (true, true) => since,
+        (true, false) => Some(now),
+    }
+}
+
+/// Whether a key pressed at `now` falls in the grace window that began at
+/// `since`, and was most likely meant for the window underneath.
+fn in_key_grace(since: Option<Instant>, grace: Duration, now: Instant) -> bool {
+    since.is_some_and(|since| now.saturating_duration_since(since) < grace)
+}
+
+/// The countdown color with `left` of a `break_secs` break to go: the
+/// foreground until `span_secs` before the end (the whole break for 0), then
+/// on along `gradient`, smoothly rather than a step a second.
+fn countdown_color(
+    gradient: &Gradient,
+    foreground: Rgba,
+    span_secs: u64,
+    break_secs: u64,
+    left: Duration,
+) -> Option<[u8; 3]> {
+    let span = match span_secs {
+        0 => break_secs,
+        secs => secs.min(break_secs),
+    };
+    let left = left.as_secs_f32();
+    if span == 0 || left >= span as f32 {
+        return None;
+    }
+    let mut stops = vec![foreground];
+    stops.extend_from_slice(&gradient.0);
+    Some(Gradient(stops).at(1.0 - left / span as f32))
+}
+
+/// Text for a break card: just what the break is doing and the time, sized
+/// to the card.
+fn card_lines(mode: &UiMode, h: u32, countdown: Option<[u8; 3]>) -> Vec<LineSpec> {
+    let large_size = h as f32 / 3.0;
+    let small_size = h as f32 / 7.0;
+    let line = |text: String, size, alpha, anchor| LineSpec {
+        text,
+        size,
+        alpha,
+        anchor,
+        color: None,
+    };
+    let clock = |secs: u64| format!("{:02}:{:02}", secs / 60, secs % 60);
+    match mode {
+        UiMode::BreakDue {
+            break_secs,
+            long_break,
+            cycle,
+            variant,
+            headline,
+            ..
+        } => vec![
+            line(
+                match (variant, headline) {
+                    (BreakVariant::Planning, _) => "Planning break starting".to_string(),
+                    (BreakVariant::WrapUp, _) => "Wrap-up break starting".to_string(),
+                    (BreakVariant::Rest, Some(headline)) => headline.clone(),
+                    (BreakVariant::Rest, None) => match break_type(*long_break, *cycle) {
+                        Some(kind) => format!("{kind} starting"),
+                        None => "Break starting".to_string(),
+                    },
+                },
+                small_size,
+                1.0,
+                LineAnchor::Center,
+            ),
+            line(
+                clock(*break_secs),
+                large_size,
+                0.65,
+                LineAnchor::CenterOnColon,
+            ),
+        ],
+        UiMode::OnBreak {
+            secs_left,
+            long_break,
+            cycle,
+            ..
+        } => vec![
+            line(
+                break_type(*long_break, *cycle).unwrap_or_else(|| "On a break".to_string()),
+                small_size,
+                0.65,
+                LineAnchor::Center,
+            ),
+            LineSpec {
+                color: countdown,
+                ..line(
+                    clock(*secs_left),
+                    large_size,
+                    1.0,
+                    LineAnchor::CenterOnColon,
+                )
+            },
+        ],
+        UiMode::BreakFinished { .. } | UiMode::KeyCaptured => vec![line(
+            "Break complete".to_string(),
+            small_size * 1.5,
+            1.0,
+            LineAnchor::Center,
+        )],
+        UiMode::WorkEnding { secs_left } => vec![line(
+            format!("Break in {}", clock(*secs_left)),
+            h as f32 / 2.5,
+            1.0,
+            LineAnchor::Center,
+        )],
+        UiMode::MicroBreak { secs_left, message } => vec![
+            line(message.clone(), small_size, 1.0, LineAnchor::Center),
+            line(
+                clock(*secs_left),
+                large_size,
+                0.65,
+                LineAnchor::CenterOnColon,
+            ),
+        ],
+        UiMode::SkipReason { .. } => vec![line(
+            "Skipping break".to_string(),
+            small_size * 1.5,
+            0.65,
+            LineAnchor::Center,
+        )],
+    }
+}
+
+/// Input region for an overlay that lets input through: empty, or with a
+/// hotspot in the middle so the pointer moving over it is noticed.
+fn passive_region(
+    compositor: &WlCompositor,
+    qh: &QueueHandle<State>,
+    width: u32,
+    height: u32,
+    hotspot: bool,
+) -> WlRegion {
+    let region = compositor.create_region(qh, ());
+    if hotspot {
+        let w = (width as f32 * WAKE_HOTSPOT_FRACTION) as i32;
+        let h = (height as f32 * WAKE_HOTSPOT_FRACTION) as i32;
+        region.add((width as i32 - w) / 2, (height as i32 - h) / 2, w, h);
+    }
+    region
+}
+
 fn render_icon(tree: &resvg::Tree, size: u32) -> Option<Icon> {
     let mut pixmap = Pixmap::new(size, size)?;
     let sx = size as f32 / tree.size.width();
@@ -219,42 +952,8 @@ fn render_icon(tree: &resvg::Tree, size: u32) -> Option<Icon> {
     })
 }
 
-#[allow(clippy::too_many_arguments)]
-fn draw_icon_rgba(
-    buf: &mut [u8],
-    width: u32,
-    height: u32,
-    x: i32,
-    y: i32,
-    icon: &Icon,
-    tint: [u8; 3],
-    alpha_mul: u8,
-) {
-    for iy in 0..icon.height {
-        for ix in 0..icon.width {
-            let px = x + ix as i32;
-            let py = y + iy as i32;
-            if px < 0 || py < 0 || (px as u32) >= width || (py as u32) >= height {
-                continue;
-            }
-            let src_idx = ((iy * icon.width + ix) * 4) as usize;
-            let alpha = (icon.rgba[src_idx + 3] as u16 * alpha_mul as u16 / 255) as u8;
-            if alpha == 0 {
-                continue;
-            }
-            let dst_idx = ((py as u32 * width + px as u32) * 4) as usize;
-            let inv = 255u16.saturating_sub(alpha as u16);
-            let a = alpha as u16;
-            buf[dst_idx] = ((tint[0] as u16 * a + buf[dst_idx] as u16 * inv) / 255) as u8;
-            buf[dst_idx + 1] = ((tint[1] as u16 * a + buf[dst_idx + 1] as u16 * inv) / 255) as u8;
-            buf[dst_idx + 2] = ((tint[2] as u16 * a + buf[dst_idx + 2] as u16 * inv) / 255) as u8;
-            buf[dst_idx + 3] = 255;
-        }
-    }
-}
-
 impl Locker {
-    pub fn new(tx_ui: Sender<UiEvent>, colors: UiColors) -> Result<Self> {
+    pub fn new(tx_ui: Sender<UiEvent>, colors: UiColors, options: LockerOptions) -> Result<Self> {
         let conn = Connection::connect_to_env()?;
         let mut event_queue = conn.new_event_queue();
         let qh = event_queue.handle();
@@ -272,12 +971,55 @@ impl Locker {
             shm: None,
             seat: None,
             layer_shell: None,
+            wm_base: None,
+            session_lock_manager: None,
+            session_lock: None,
+            session_locked: false,
+            session_lock_finished: false,
+            backends: options.backends,
             icon_tree,
             outputs: vec![],
+            exclude_outputs: options.exclude_outputs,
+            card_outputs: options.card_outputs,
+            cards_only: false,
+            osd: false,
+            micro: false,
+            layer: match options.layer {
+                OverlayLayer::Background => Layer::Background,
+                OverlayLayer::Bottom => Layer::Bottom,
+                OverlayLayer::Top => Layer::Top,
+                OverlayLayer::Overlay => Layer::Overlay,
+            },
+            exclusive_zone: options.exclusive_zone,
+            margin: options.margin,
+            card: options.card,
+            osd_placement: options.osd,
+            wake_on_pointer: options.wake_on_pointer,
+            hover_origin: None,
+            wake_requested: false,
+            fast_forward_keys: options.fast_forward_keys,
+            key_grace: options.key_grace,
+            keyboard_since: None,
+            keyboard_early: false,
+            follow_focus: options.follow_focus,
+            countdown: options.countdown,
+            countdown_gradient: options.countdown_gradient,
+            countdown_gradient_secs: options.countdown_gradient_secs,
+            bar_thickness: options.bar_thickness,
+            bar_placement: options.bar_placement,
+            debug_overlay: options.debug_overlay,
+            debug_info: Vec::new(),
+            frame_time: Duration::ZERO,
+            fade_frames: FrameStats::default(),
+            last_fade: None,
+            focused_surface: None,
+            focus_changed: false,
+            want_presentation: false,
             surfaces: vec![],
             overlay_active: false,
             overlay_alpha: colors.background[3],
             fade: FadeState::None,
+            dim: false,
             input_captured: false,
             desired_capture: false,
             fade_in_complete: false,
@@ -286,24 +1028,43 @@ impl Locker {
             colors,
             keyboard: None,
             pointer: None,
+            pointer_x: 0.0,
+            pointer_width: 0,
+            pointer_press: None,
+            key_press: None,
             xkb_context: xkb::Context::new(xkb::CONTEXT_NO_FLAGS),
             xkb_keymap: None,
             xkb_state: None,
+            xkb_layout: 0,
             ui_mode: UiMode::BreakDue {
                 break_secs: 0,
                 snooze_count: 0,
+                long_break: false,
+                cycle: None,
+                carried_secs: 0,
+                hints: Vec::new(),
+                variant: BreakVariant::Rest,
+                headline: None,
+                message: None,
+                notes: Vec::new(),
             },
             tx_ui,
         };
         event_queue.roundtrip(&mut state)?;
 
-        if state.compositor.is_none()
-            || state.shm.is_none()
-            || state.seat.is_none()
-            || state.layer_shell.is_none()
-        {
+        let backend = state.backend();
+        let has_role = match backend {
+            Backend::SessionLock => state.session_lock_manager.is_some(),
+            Backend::LayerShell => state.layer_shell.is_some(),
+            Backend::XdgFullscreen => state.wm_base.is_some(),
+            Backend::NotifyOnly => true,
+        };
+        let has_surfaces =
+            state.compositor.is_some() && state.shm.is_some() && state.seat.is_some();
+        if !has_role || (backend != Backend::NotifyOnly && !has_surfaces) {
             return Err(anyhow!(
-                "Missing required Wayland globals (compositor/shm/seat/layer_shell)"
+                "Missing required Wayland globals for the {} backend",
+                backend.label()
             ));
         }
 
@@ -311,6 +1072,8 @@ impl Locker {
             conn,
             event_queue,
             state,
+            renderer: Renderer::spawn()?,
+            next_surface_id: 0,
         };
 
         // Let initial globals events settle
@@ -324,6 +1087,30 @@ impl Locker {
     }
 
     pub fn pump(&mut self) -> Result<()> {
+        self.read_events()?;
+        self.take_refused_lock();
+        self.state.check_pointer_hold();
+        self.state.check_key_hold();
+        while let Some(done) = self.renderer.try_recv() {
+            self.finish_render(done);
+        }
+        if std::mem::take(&mut self.state.focus_changed) {
+            self.redraw_all();
+        }
+        for idx in 0..self.state.surfaces.len() {
+            let s = &mut self.state.surfaces[idx];
+            if !s.in_flight
+                && std::mem::take(&mut s.redraw_pending)
+                && let Err(err) = self.redraw_surface(idx)
+            {
+                rate_limited("redraw error", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispatches whatever the compositor has sent, without waiting for more.
+    fn read_events(&mut self) -> Result<()> {
         self.event_queue.dispatch_pending(&mut self.state)?;
         self.conn.flush()?;
         if let Some(guard) = self.event_queue.prepare_read() {
@@ -342,6 +1129,26 @@ impl Locker {
         self.redraw_all();
     }
 
+    /// Lines of the daemon's state for `--debug-overlay`, shown from the
+    /// next redraw.
+    pub fn set_debug_info(&mut self, lines: Vec<String>) {
+        self.state.debug_info = lines;
+    }
+
+    /// Switches the overlay colors, e.g. for a long break. Takes effect from
+    /// the next fade-in, or right away while the overlay is fully shown.
+    pub fn set_colors(&mut self, colors: UiColors) {
+        let shown = self.state.overlay_active
+            && matches!(self.state.fade, FadeState::None)
+            && !self.state.dim;
+        self.state.colors = colors;
+        self.state.max_alpha = colors.background[3];
+        if shown {
+            self.state.overlay_alpha = colors.background[3];
+        }
+        self.redraw_all();
+    }
+
     pub fn start_fade_in(&mut self) {
         if matches!(self.state.fade, FadeState::In { .. }) {
             return;
@@ -349,10 +1156,16 @@ impl Locker {
         self.state.fade = FadeState::In {
             start: Instant::now(),
         };
+        self.state.fade_frames = FrameStats::default();
         self.state.overlay_alpha = 0;
         self.state.text_alpha = 0;
         self.state.fade_in_complete = false;
+        self.state.hover_origin = None;
+        self.state.wake_requested = false;
         self.set_input_capture(false);
+        if self.state.wake_on_pointer {
+            self.refresh_passive_regions();
+        }
         self.redraw_all();
     }
 
@@ -363,16 +1176,50 @@ impl Locker {
         self.state.fade = FadeState::Out {
             start: Instant::now(),
         };
-        self.state.overlay_alpha = self.state.max_alpha;
-        self.state.text_alpha = 255;
+        self.state.fade_frames = FrameStats::default();
+        self.state.overlay_alpha = (self.state.max_alpha as f32 * self.state.ceiling()) as u8;
+        self.state.text_alpha = if self.state.dim { 0 } else { 255 };
         self.set_input_capture(false);
         self.redraw_all();
     }
 
+    /// Holds the overlay at part of its opacity, without text or input, or
+    /// lets it carry on fading in from there.
+    pub fn set_dim(&mut self, dim: bool) {
+        if self.state.dim == dim {
+            return;
+        }
+        self.state.dim = dim;
+        if !self.state.overlay_active || matches!(self.state.fade, FadeState::Out { .. }) {
+            return;
+        }
+        let shown = self.state.overlay_alpha as f32 / self.state.max_alpha.max(1) as f32;
+        let start = if dim {
+            Instant::now()
+        } else {
+            Instant::now() - FADE_IN_DURATION.mul_f32(shown.clamp(0.0, 1.0))
+        };
+        self.state.fade = FadeState::In { start };
+    }
+
+    pub fn is_dimmed(&self) -> bool {
+        self.state.dim
+    }
+
     pub fn is_fading(&self) -> bool {
         !matches!(self.state.fade, FadeState::None)
     }
 
+    /// Frame counts and times of the last fade to finish.
+    pub fn last_fade_frames(&self) -> Option<FrameStats> {
+        self.state.last_fade
+    }
+
+    /// Skips the rest of the fade-in, if one is running.
+    pub fn finish_fade_in(&mut self) {
+        self.state.wake_requested = true;
+    }
+
     pub fn take_fade_in_complete(&mut self) -> bool {
         if self.state.fade_in_complete {
             self.state.fade_in_complete = false;
@@ -386,16 +1233,29 @@ impl Locker {
         self.set_input_capture(true);
     }
 
+    /// Lets keys and the pointer through to the windows underneath while
+    /// the overlay stays up.
+    pub fn release_input(&mut self) {
+        self.set_input_capture(false);
+    }
+
     pub fn update_fade(&mut self) -> bool {
+        if std::mem::take(&mut self.state.wake_requested)
+            && matches!(self.state.fade, FadeState::In { .. })
+        {
+            // Pretend the fade started long enough ago to be complete.
+            self.state.fade = FadeState::In {
+                start: Instant::now() - self.state.fade_in_duration(),
+            };
+        }
         let (alpha, done, finished_fade_out) = match self.state.fade.clone() {
             FadeState::None => return false,
             FadeState::In { start } => {
-                let progress =
-                    (Instant::now() - start).as_secs_f32() / FADE_IN_DURATION.as_secs_f32();
+                let duration = self.state.fade_in_duration();
+                let progress = (Instant::now() - start).as_secs_f32() / duration.as_secs_f32();
                 let p = progress.clamp(0.0, 1.0);
-                let alpha = (self.state.max_alpha as f32 * p).round() as u8;
-                let text_start =
-                    1.0 - (TEXT_FADE_IN_WINDOW.as_secs_f32() / FADE_IN_DURATION.as_secs_f32());
+                let alpha = (self.state.max_alpha as f32 * self.state.ceiling() * p).round() as u8;
+                let text_start = 1.0 - (TEXT_FADE_IN_WINDOW.as_secs_f32() / duration.as_secs_f32());
                 let text_progress = if p <= text_start {
                     0.0
                 } else {
@@ -404,15 +1264,25 @@ impl Locker {
                 self.state.text_alpha = (self.state.colors.foreground[3] as f32
                     * text_progress.clamp(0.0, 1.0))
                 .round() as u8;
+                if self.state.dim {
+                    self.state.text_alpha = 0;
+                } else if text_progress > 0.0 && self.state.fast_forward_keys && !self.state.micro {
+                    self.capture_keyboard_early();
+                }
                 (alpha, p >= 1.0, false)
             }
             FadeState::Out { start } => {
                 let progress =
                     (Instant::now() - start).as_secs_f32() / FADE_OUT_DURATION.as_secs_f32();
                 let p = progress.clamp(0.0, 1.0);
-                let alpha = (self.state.max_alpha as f32 * (1.0 - p)).round() as u8;
-                self.state.text_alpha = ((self.state.colors.foreground[3] as u16 * alpha as u16)
-                    / self.state.max_alpha as u16) as u8;
+                let full = self.state.max_alpha as f32 * (1.0 - p);
+                let alpha = (full * self.state.ceiling()).round() as u8;
+                self.state.text_alpha = if self.state.dim {
+                    0
+                } else {
+                    ((self.state.colors.foreground[3] as u16 * alpha as u16)
+                        / self.state.max_alpha as u16) as u8
+                };
                 (alpha, p >= 1.0, true)
             }
         };
@@ -424,10 +1294,19 @@ impl Locker {
 
         if done {
             self.state.fade = FadeState::None;
+            let frames = std::mem::take(&mut self.state.fade_frames);
+            info!(
+                "Fade {} Finished ({})",
+                if finished_fade_out { "Out" } else { "In" },
+                frames.summary()
+            );
+            self.state.last_fade = Some(frames);
             if !finished_fade_out {
                 self.state.fade_in_complete = true;
-                self.state.text_alpha = 255;
-                self.set_input_capture(true);
+                if !self.state.dim && !self.state.micro {
+                    self.state.text_alpha = 255;
+                    self.set_input_capture(true);
+                }
             }
         }
 
@@ -435,37 +1314,41 @@ impl Locker {
     }
 
     fn set_input_capture(&mut self, enable: bool) {
-        if self.state.input_captured == enable {
+        if self.state.input_captured == enable && !self.state.keyboard_early {
             return;
         }
+        self.state.keyboard_since = grace_start(
+            enable,
+            self.state.keyboard_early,
+            self.state.keyboard_since,
+            Instant::now(),
+        );
+        self.state.keyboard_early = false;
         self.state.input_captured = enable;
         self.state.desired_capture = enable;
         if !self.state.overlay_active {
             return;
         }
 
-        let interactivity = if enable {
-            zwlr_layer_surface_v1::KeyboardInteractivity::Exclusive
-        } else {
-            zwlr_layer_surface_v1::KeyboardInteractivity::None
-        };
-
         let compositor = match self.state.compositor.clone() {
             Some(compositor) => compositor,
             None => return,
         };
 
         let qh = self.event_queue.handle();
+        let hotspot = self.state.wants_hotspot();
 
         for surface in self.state.surfaces.iter_mut() {
-            surface
-                .layer_surface
-                .set_keyboard_interactivity(interactivity);
+            if surface.kind == SurfaceKind::Card {
+                continue;
+            }
+            surface.role.set_keyboard_capture(enable);
             if enable {
                 surface.wl_surface.set_input_region(None);
                 surface.input_region = None;
             } else {
-                let region = compositor.create_region(&qh, ());
+                let region =
+                    passive_region(&compositor, &qh, surface.width, surface.height, hotspot);
                 surface.wl_surface.set_input_region(Some(&region));
                 surface.input_region = Some(region);
             }
@@ -473,109 +1356,372 @@ impl Locker {
         }
     }
 
+    /// Takes the keyboard while the pointer still passes through, so a key
+    /// press can finish the fade-in.
+    fn capture_keyboard_early(&mut self) {
+        if self.state.keyboard_early || self.state.input_captured {
+            return;
+        }
+        self.state.keyboard_early = true;
+        self.state.keyboard_since = Some(Instant::now());
+        for s in &self.state.surfaces {
+            if s.kind == SurfaceKind::Overlay {
+                s.role.set_keyboard_capture(true);
+                s.wl_surface.commit();
+            }
+        }
+    }
+
+    /// Re-applies the input region of overlays that are letting input through.
+    fn refresh_passive_regions(&mut self) {
+        if self.state.input_captured {
+            return;
+        }
+        let Some(compositor) = self.state.compositor.clone() else {
+            return;
+        };
+        let qh = self.event_queue.handle();
+        let hotspot = self.state.wants_hotspot();
+        for s in self.state.surfaces.iter_mut() {
+            if s.kind == SurfaceKind::Card {
+                continue;
+            }
+            let region = passive_region(&compositor, &qh, s.width, s.height, hotspot);
+            s.wl_surface.set_input_region(Some(&region));
+            s.input_region = Some(region);
+            s.wl_surface.commit();
+        }
+    }
+
+    /// Use break cards on every output from the next `lock` on; they never
+    /// capture the keyboard.
+    /// Shows the countdown card at the top of every output: see-through,
+    /// without input, and without a fade. Only layer-shell can place it.
+    pub fn show_osd(&mut self) -> Result<()> {
+        if self.is_locked() || self.backend() != Backend::LayerShell {
+            return Ok(());
+        }
+        self.state.osd = true;
+        self.lock()?;
+        self.state.fade = FadeState::None;
+        self.state.overlay_alpha = (self.state.max_alpha as f32 * OSD_ALPHA) as u8;
+        self.state.text_alpha = 255;
+        self.redraw_all();
+        Ok(())
+    }
+
+    /// Whether the surfaces up are the countdown card.
+    pub fn is_osd(&self) -> bool {
+        self.state.osd && self.is_locked()
+    }
+
+    /// Fades in a micro-break over the work: part of the way, and without
+    /// taking the keyboard or the pointer, so typing carries on underneath.
+    /// Only layer-shell can show a surface that does not take the focus.
+    pub fn show_micro_break(&mut self) -> Result<()> {
+        if self.is_locked() || self.backend() != Backend::LayerShell {
+            return Ok(());
+        }
+        self.state.micro = true;
+        self.lock()?;
+        self.start_fade_in();
+        Ok(())
+    }
+
+    pub fn is_micro_break(&self) -> bool {
+        self.state.micro && self.is_locked()
+    }
+
+    pub fn set_cards_only(&mut self, cards_only: bool) {
+        self.state.cards_only = cards_only;
+    }
+
+    /// Whether no surface can take input, so nothing can dismiss the overlay.
+    pub fn is_cards_only(&self) -> bool {
+        self.state
+            .surfaces
+            .iter()
+            .all(|s| s.kind == SurfaceKind::Card)
+    }
+
     pub fn is_locked(&self) -> bool {
         self.state.overlay_active
     }
 
+    /// The backend breaks are shown with.
+    pub fn backend(&self) -> Backend {
+        self.state.backend()
+    }
+
     pub fn lock(&mut self) -> Result<()> {
         if self.is_locked() {
             return Ok(());
         }
-        let qh = self.event_queue.handle();
+        if self.backend() == Backend::SessionLock {
+            let manager = self.state.session_lock_manager.clone().unwrap();
+            let qh = self.event_queue.handle();
+            self.state.session_lock = Some(manager.lock(&qh, ()));
+            self.state.session_locked = false;
+            self.state.session_lock_finished = false;
+        }
 
         // create a surface per output
         self.state.surfaces.clear();
         self.state.input_captured = false;
         self.state.desired_capture = false;
-        let compositor = self.state.compositor.clone().unwrap();
-        let layer_shell = self.state.layer_shell.clone().unwrap();
-
-        for out in self.state.outputs.iter().cloned() {
-            let wl_surface = compositor.create_surface(&qh, ());
-            let layer_surface = layer_shell.get_layer_surface(
-                &wl_surface,
-                Some(&out),
-                Layer::Overlay,
-                "interlude".to_string(),
-                &qh,
-                (),
-            );
-            layer_surface.set_anchor(
-                zwlr_layer_surface_v1::Anchor::Top
-                    | zwlr_layer_surface_v1::Anchor::Bottom
-                    | zwlr_layer_surface_v1::Anchor::Left
-                    | zwlr_layer_surface_v1::Anchor::Right,
-            );
-            let interactivity = if self.state.desired_capture {
-                zwlr_layer_surface_v1::KeyboardInteractivity::Exclusive
-            } else {
-                zwlr_layer_surface_v1::KeyboardInteractivity::None
-            };
-            layer_surface.set_keyboard_interactivity(interactivity);
-            layer_surface.set_exclusive_zone(-1);
-            layer_surface.set_size(0, 0);
-            let input_region = if !self.state.desired_capture {
-                let region = compositor.create_region(&qh, ());
-                wl_surface.set_input_region(Some(&region));
-                Some(region)
-            } else {
-                wl_surface.set_input_region(None);
-                None
-            };
-            wl_surface.commit();
-
-            // placeholder until configure
-            let (w, h) = (0u32, 0u32);
-
-            self.state.surfaces.push(SurfaceCtx {
-                _output: out,
-                wl_surface,
-                layer_surface,
-                width: w,
-                height: h,
-                input_region,
-                icon: None,
-                small_icon: None,
-                small_icon_size: 0,
-                shm_pool: None,
-                shm_file: None,
-                shm_map: None,
-                shm_size: 0,
-                buffer_slots: Vec::new(),
-                stride: (w as i32) * 4,
-            });
+        for info in &self.state.outputs {
+            if self.state.is_excluded(info) {
+                info!("Overlay skipped on excluded output {}", info.label());
+            }
+        }
+        for (output, kind) in self.uncovered_outputs() {
+            self.add_surface(output, kind);
         }
 
         // roundtrip so we receive configure sizes
         self.roundtrip()?;
 
         self.state.overlay_active = true;
+        if self.take_refused_lock() {
+            return self.lock();
+        }
         self.redraw_all();
         Ok(())
     }
 
-    pub fn unlock(&mut self) {
-        if self.state.overlay_active {
-            self.state.overlay_alpha = 0;
-            self.state.text_alpha = 0;
-            self.redraw_all();
-            let _ = self.conn.flush();
+    /// When the compositor refused or ended the session lock, drops the
+    /// overlay and falls back to the next backend. Returns whether it did.
+    fn take_refused_lock(&mut self) -> bool {
+        if !std::mem::take(&mut self.state.session_lock_finished) {
+            return false;
         }
-        for surface in self.state.surfaces.drain(..) {
-            surface.layer_surface.destroy();
-            surface.wl_surface.destroy();
+        self.unlock();
+        if self.state.backends.len() > 1 {
+            self.state.backends.remove(0);
         }
-        self.state.overlay_active = false;
-        self.state.input_captured = false;
-        self.state.desired_capture = false;
-        let _ = self.conn.flush();
+        info!(
+            "Session lock refused; using the {} backend",
+            self.backend().label()
+        );
+        true
     }
 
-    fn redraw_all(&mut self) {
-        for i in 0..self.state.surfaces.len() {
-            if let Err(err) = self.redraw_surface(i) {
-                eprintln!("redraw error: {err}");
-            }
+    /// Outputs that should have an overlay surface but do not have one yet.
+    /// A session lock must cover every output, so it ignores exclusions and
+    /// cards; notify-only covers none.
+    fn uncovered_outputs(&self) -> Vec<(WlOutput, SurfaceKind)> {
+        let backend = self.backend();
+        if backend == Backend::NotifyOnly {
+            return Vec::new();
+        }
+        let locking = backend == Backend::SessionLock;
+        self.state
+            .outputs
+            .iter()
+            .filter(|info| locking || !self.state.is_excluded(info))
+            .filter(|info| !self.state.surfaces.iter().any(|s| s.output == info.output))
+            .map(|info| {
+                let kind = if !locking && self.state.is_card(info) {
+                    info!("Break card on output {}", info.label());
+                    SurfaceKind::Card
+                } else {
+                    info!("Overlay on output {}", info.label());
+                    SurfaceKind::Overlay
+                };
+                (info.output.clone(), kind)
+            })
+            .collect()
+    }
+
+    fn add_surface(&mut self, out: WlOutput, kind: SurfaceKind) {
+        let qh = self.event_queue.handle();
+        let compositor = self.state.compositor.clone().unwrap();
+        let wl_surface = compositor.create_surface(&qh, ());
+        let role = match self.backend() {
+            Backend::SessionLock => {
+                let lock = self.state.session_lock.as_ref().unwrap();
+                Role::Lock(lock.get_lock_surface(&wl_surface, &out, &qh, ()))
+            }
+            Backend::LayerShell => {
+                let layer_shell = self.state.layer_shell.as_ref().unwrap();
+                let layer_surface = layer_shell.get_layer_surface(
+                    &wl_surface,
+                    Some(&out),
+                    self.state.layer,
+                    "interlude".to_string(),
+                    &qh,
+                    (),
+                );
+                match kind {
+                    SurfaceKind::Overlay => {
+                        layer_surface.set_anchor(
+                            zwlr_layer_surface_v1::Anchor::Top
+                                | zwlr_layer_surface_v1::Anchor::Bottom
+                                | zwlr_layer_surface_v1::Anchor::Left
+                                | zwlr_layer_surface_v1::Anchor::Right,
+                        );
+                        let [top, right, bottom, left] = self.state.margin;
+                        layer_surface.set_margin(top, right, bottom, left);
+                        layer_surface.set_exclusive_zone(self.state.exclusive_zone);
+                        layer_surface.set_size(0, 0);
+                    }
+                    SurfaceKind::Card if self.state.osd => {
+                        self.state.osd_placement.apply(&layer_surface);
+                    }
+                    SurfaceKind::Card => self.state.card.apply(&layer_surface),
+                }
+                Role::Layer(layer_surface)
+            }
+            Backend::XdgFullscreen | Backend::NotifyOnly => {
+                let wm_base = self.state.wm_base.clone().unwrap();
+                let xdg_surface = wm_base.get_xdg_surface(&wl_surface, &qh, ());
+                let toplevel = xdg_surface.get_toplevel(&qh, ());
+                toplevel.set_title("interlude".to_string());
+                toplevel.set_app_id("interlude".to_string());
+                match kind {
+                    SurfaceKind::Overlay => toplevel.set_fullscreen(Some(&out)),
+                    SurfaceKind::Card => {
+                        let [width, height] = self.state.card.size.map(|side| side as i32);
+                        toplevel.set_min_size(width, height);
+                        toplevel.set_max_size(width, height);
+                    }
+                }
+                Role::Window {
+                    xdg_surface,
+                    toplevel,
+                }
+            }
+        };
+        let captures = self.state.desired_capture && kind == SurfaceKind::Overlay;
+        role.set_keyboard_capture(captures);
+        let input_region = if !captures {
+            let region = compositor.create_region(&qh, ());
+            wl_surface.set_input_region(Some(&region));
+            Some(region)
+        } else {
+            wl_surface.set_input_region(None);
+            None
+        };
+        wl_surface.commit();
+
+        // placeholder until configure
+        let (w, h) = (0u32, 0u32);
+
+        self.next_surface_id += 1;
+        self.state.surfaces.push(SurfaceCtx {
+            id: self.next_surface_id,
+            output: out,
+            kind,
+            wl_surface,
+            role,
+            width: w,
+            height: h,
+            input_region,
+            icon: None,
+            small_icon: None,
+            small_icon_size: 0,
+            in_flight: false,
+            submitted: None,
+            redraw_pending: false,
+            presenting: false,
+            shm_pool: None,
+            painted: Vec::new(),
+        });
+    }
+
+    /// Stops covering outputs matching `pattern`, removing their overlay if one is up.
+    pub fn exclude_output(&mut self, pattern: &str) {
+        if !self.state.exclude_outputs.iter().any(|p| p == pattern) {
+            self.state.exclude_outputs.push(pattern.to_string());
+        }
+        let excluded: Vec<WlOutput> = self
+            .state
+            .outputs
+            .iter()
+            .filter(|info| info.matches(pattern))
+            .map(|info| info.output.clone())
+            .collect();
+        let mut idx = 0;
+        while idx < self.state.surfaces.len() {
+            if !excluded.contains(&self.state.surfaces[idx].output) {
+                idx += 1;
+                continue;
+            }
+            let surface = self.state.surfaces.remove(idx);
+            if self.state.focused_surface.as_ref() == Some(&surface.wl_surface) {
+                self.state.focused_surface = None;
+                self.state.focus_changed = true;
+            }
+            self.renderer.forget(surface.id);
+            surface.role.destroy();
+            surface.wl_surface.destroy();
+        }
+        let _ = self.conn.flush();
+    }
+
+    /// Covers outputs matching `pattern` again, whichever exclusion hid them.
+    /// While the overlay is up they get a surface straight away.
+    pub fn include_output(&mut self, pattern: &str) -> Result<()> {
+        let outputs = &self.state.outputs;
+        self.state.exclude_outputs.retain(|excluded| {
+            excluded != pattern
+                && !outputs
+                    .iter()
+                    .any(|info| info.matches(pattern) && info.matches(excluded))
+        });
+        if !self.state.overlay_active {
+            return Ok(());
+        }
+        let uncovered = self.uncovered_outputs();
+        if uncovered.is_empty() {
+            return Ok(());
+        }
+        for (output, kind) in uncovered {
+            self.add_surface(output, kind);
+        }
+        self.roundtrip()?;
+        self.redraw_all();
+        Ok(())
+    }
+
+    pub fn unlock(&mut self) {
+        if self.state.overlay_active {
+            self.state.overlay_alpha = 0;
+            self.state.text_alpha = 0;
+            self.state.want_presentation = true;
+            self.redraw_all();
+            self.finish_pending_renders();
+            self.wait_for_presentation();
+            self.state.want_presentation = false;
+        }
+        for surface in self.state.surfaces.drain(..) {
+            self.renderer.forget(surface.id);
+            surface.role.destroy();
+            surface.wl_surface.destroy();
+        }
+        if let Some(lock) = self.state.session_lock.take() {
+            if std::mem::take(&mut self.state.session_locked) {
+                lock.unlock_and_destroy();
+            } else {
+                lock.destroy();
+            }
+        }
+        self.state.overlay_active = false;
+        self.state.osd = false;
+        self.state.micro = false;
+        self.state.input_captured = false;
+        self.state.desired_capture = false;
+        self.state.keyboard_early = false;
+        self.state.keyboard_since = None;
+        self.state.focused_surface = None;
+        let _ = self.conn.flush();
+    }
+
+    fn redraw_all(&mut self) {
+        for i in 0..self.state.surfaces.len() {
+            if let Err(err) = self.redraw_surface(i) {
+                rate_limited("redraw error", err);
+            }
         }
     }
 
@@ -594,98 +1740,19 @@ impl Locker {
             return Ok(());
         }
 
-        let stride = (w as i32) * 4;
-        let size = (stride as usize) * (h as usize);
-
-        let white = [
-            self.state.colors.foreground[0],
-            self.state.colors.foreground[1],
-            self.state.colors.foreground[2],
-            self.state.text_alpha,
-        ];
-        #[derive(Clone, Copy)]
-        enum LineAnchor {
-            Center,
-            CenterOnColon,
-        }
-
-        struct LineSpec {
-            text: String,
-            size: f32,
-            alpha: f32,
-            anchor: LineAnchor,
+        if self.state.surfaces[idx].in_flight {
+            // Picked up again once the frame being painted comes back.
+            self.state.defer_redraw(idx);
+            return Ok(());
         }
 
-        let base_size = (w.min(h) as f32 / 16.0).clamp(42.0, 110.0);
-        let large_size = (base_size * 1.35).clamp(56.0, 150.0);
-        let small_size = (base_size * 0.7).clamp(28.0, 80.0);
-
-        let lines = match &self.state.ui_mode {
-            UiMode::BreakDue {
-                break_secs,
-                snooze_count,
-            } => {
-                let l1 = "BREAK STARTING".to_string();
-                let m = break_secs / 60;
-                let s = break_secs % 60;
-                let l2 = format!("Break: {:02}:{:02}", m, s);
-                let l3 = wellness_message(*snooze_count).to_string();
-                vec![
-                    LineSpec {
-                        text: l1,
-                        size: base_size,
-                        alpha: 1.0,
-                        anchor: LineAnchor::Center,
-                    },
-                    LineSpec {
-                        text: l2,
-                        size: small_size,
-                        alpha: 0.65,
-                        anchor: LineAnchor::Center,
-                    },
-                    LineSpec {
-                        text: l3,
-                        size: small_size,
-                        alpha: 0.65,
-                        anchor: LineAnchor::Center,
-                    },
-                ]
-            }
-            UiMode::OnBreak {
-                secs_left,
-                snooze_count,
-            } => {
-                let m = secs_left / 60;
-                let s = secs_left % 60;
-                vec![
-                    LineSpec {
-                        text: format!("{:02}:{:02}", m, s),
-                        size: large_size,
-                        alpha: 1.0,
-                        anchor: LineAnchor::CenterOnColon,
-                    },
-                    LineSpec {
-                        text: wellness_message(*snooze_count).to_string(),
-                        size: small_size,
-                        alpha: 0.65,
-                        anchor: LineAnchor::Center,
-                    },
-                ]
-            }
-            UiMode::BreakFinished => vec![
-                LineSpec {
-                    text: "Break Complete.".to_string(),
-                    size: base_size,
-                    alpha: 1.0,
-                    anchor: LineAnchor::Center,
-                },
-                LineSpec {
-                    text: "Press any key to continue".to_string(),
-                    size: small_size,
-                    alpha: 0.65,
-                    anchor: LineAnchor::Center,
-                },
-            ],
+        let card = self.state.surfaces[idx].kind == SurfaceKind::Card;
+        let countdown = self.state.countdown_color();
+        let lines = if card {
+            card_lines(&self.state.ui_mode, h, countdown)
+        } else {
+            let digits = self.state.countdown != Countdown::Bar;
+            overlay_lines(&self.state.ui_mode, w, h, digits, countdown)
         };
 
         let icon_size = {
@@ -701,19 +1768,17 @@ impl Locker {
                 .as_ref()
                 .map(|icon| icon.width != icon_size)
                 .unwrap_or(true);
-            if needs_icon
-                && let Some(tree) = &self.state.icon_tree {
-                    s.icon = render_icon(tree, icon_size);
-                }
+            if needs_icon && let Some(tree) = &self.state.icon_tree {
+                s.icon = render_icon(tree, icon_size).map(Arc::new);
+            }
 
             let small_icon = if matches!(self.state.fade, FadeState::In { .. }) {
                 let small_size = (icon_size / 3).max(24);
                 let needs_small = s.small_icon_size != small_size || s.small_icon.is_none();
-                if needs_small
-                    && let Some(tree) = &self.state.icon_tree {
-                        s.small_icon = render_icon(tree, small_size);
-                        s.small_icon_size = small_size;
-                    }
+                if needs_small && let Some(tree) = &self.state.icon_tree {
+                    s.small_icon = render_icon(tree, small_size).map(Arc::new);
+                    s.small_icon_size = small_size;
+                }
                 s.small_icon.clone()
             } else {
                 None
@@ -722,177 +1787,408 @@ impl Locker {
             (s.icon.clone(), small_icon)
         };
 
-        let icon_height = icon.as_ref().map(|icon| icon.height as i32).unwrap_or(0);
+        // With follow-focus only the focused output shows content; the rest just dim.
+        let show_content = self.state.shows_content(idx);
+        let lines = if show_content { lines } else { Vec::new() };
+        let icon = icon.filter(|_| show_content && !card);
+        let small_icon = small_icon.filter(|_| show_content && !card);
+        let progress = match self.state.ui_mode {
+            UiMode::OnBreak {
+                secs_left,
+                break_secs,
+                ..
+            } if show_content
+                && !card
+                && self.state.countdown != Countdown::Digits
+                && break_secs > 0 =>
+            {
+                Some(ProgressBar {
+                    left: secs_left as f32 / break_secs as f32,
+                    thickness: self.state.bar_thickness,
+                    placement: self.state.bar_placement,
+                })
+            }
+            _ => None,
+        };
+
+        let debug = if self.state.debug_overlay && !card {
+            self.state.debug_lines(idx)
+        } else {
+            Vec::new()
+        };
+
+        let fg = self.state.colors.foreground;
+        let bg = self.state.colors.background;
+        let frame = FrameSpec {
+            width: w,
+            height: h,
+            background: [bg[0], bg[1], bg[2]],
+            foreground: [fg[0], fg[1], fg[2]],
+            overlay_alpha: self.state.overlay_alpha,
+            text_alpha: self.state.text_alpha,
+            lines,
+            icon,
+            small_icon,
+            progress,
+            debug,
+        };
 
         let qh = self.event_queue.handle();
-        let (buffer, offset) = {
+        let (map, plane, slot_idx, prev_frame) = {
             let s = &mut self.state.surfaces[idx];
-            let shm_pool_needs_init =
-                s.shm_pool.is_none() || s.shm_size != size || s.stride != stride;
-            if shm_pool_needs_init {
-                let fd =
-                    rustix::fs::memfd_create("interlude-frame", rustix::fs::MemfdFlags::CLOEXEC)
-                        .map_err(|e| anyhow!("memfd_create: {e}"))?;
-                let total_size = size * 2;
-                rustix::fs::ftruncate(&fd, total_size as u64)
-                    .map_err(|e| anyhow!("ftruncate: {e}"))?;
-                let raw_fd = fd.into_raw_fd();
-                let file = unsafe { std::fs::File::from_raw_fd(raw_fd) };
-
-                let map = unsafe { memmap2::MmapMut::map_mut(&file) }
-                    .map_err(|e| anyhow!("mmap: {e}"))?;
-
-                let pool = shm.create_pool(file.as_fd(), total_size as i32, &qh, ());
-                let buffer_a = pool.create_buffer(
-                    0,
-                    w as i32,
-                    h as i32,
-                    stride,
-                    wayland_client::protocol::wl_shm::Format::Argb8888,
-                    &qh,
-                    (),
-                );
-                let buffer_b = pool.create_buffer(
-                    size as i32,
-                    w as i32,
-                    h as i32,
-                    stride,
-                    wayland_client::protocol::wl_shm::Format::Argb8888,
-                    &qh,
-                    (),
-                );
-
-                s.shm_pool = Some(pool);
-                s.shm_file = Some(file);
-                s.shm_map = Some(map);
-                s.shm_size = size;
-                s.buffer_slots = vec![
-                    BufferSlot {
-                        buffer: buffer_a,
-                        offset: 0,
-                        busy: false,
-                    },
-                    BufferSlot {
-                        buffer: buffer_b,
-                        offset: size,
-                        busy: false,
-                    },
-                ];
-                s.stride = stride;
+            match s.shm_pool.as_mut() {
+                Some(pool) if pool.matches(w, h) => {}
+                Some(pool) => {
+                    pool.resize(&qh, w, h)?;
+                    s.painted = vec![None; BUFFER_COUNT];
+                }
+                None => {
+                    s.shm_pool = Some(ShmPool::new(&shm, &qh, w, h, BUFFER_COUNT)?);
+                    s.painted = vec![None; BUFFER_COUNT];
+                }
             }
+            let pool = s
+                .shm_pool
+                .as_mut()
+                .ok_or_else(|| anyhow!("missing shm pool"))?;
 
-            let slot_idx = match s.buffer_slots.iter().position(|slot| !slot.busy) {
+            let slot_idx = match pool.slots.iter().position(|slot| !slot.busy) {
                 Some(idx) => idx,
-                None => return Ok(()),
+                None => {
+                    // Both buffers are with the compositor; retry after a release.
+                    self.state.defer_redraw(idx);
+                    return Ok(());
+                }
             };
-            s.buffer_slots[slot_idx].busy = true;
-            let buffer = s.buffer_slots[slot_idx].buffer.clone();
-            let offset = s.buffer_slots[slot_idx].offset;
-            (buffer, offset)
+            pool.slots[slot_idx].busy = true;
+            let plane = pool.memory.plane_range(slot_idx);
+            let map = pool
+                .memory
+                .take_map()
+                .ok_or_else(|| anyhow!("missing shm map"))?;
+            let prev_frame = s.painted[slot_idx].take();
+            (map, plane, slot_idx, prev_frame)
         };
 
-        let bytes = {
-            let s = &mut self.state.surfaces[idx];
-            let map = s
-                .shm_map
-                .as_mut()
-                .ok_or_else(|| anyhow!("missing shm map"))?;
-            &mut map[offset..offset + size]
+        let s = &mut self.state.surfaces[idx];
+        s.in_flight = true;
+        s.submitted = Some(Instant::now());
+        self.renderer.submit(RenderJob {
+            surface: s.id,
+            slot: slot_idx,
+            map,
+            plane,
+            prev: prev_frame,
+            frame,
+        });
+        Ok(())
+    }
+
+    /// Attaches a frame the render thread has finished painting.
+    fn finish_render(&mut self, done: RenderDone) {
+        let Some(idx) = self
+            .state
+            .surfaces
+            .iter()
+            .position(|s| s.id == done.surface)
+        else {
+            self.renderer.forget(done.surface);
+            return;
         };
+        let s = &mut self.state.surfaces[idx];
+        s.in_flight = false;
+        if let Some(submitted) = s.submitted.take() {
+            self.state.frame_time = submitted.elapsed();
+            if !matches!(self.state.fade, FadeState::None) {
+                self.state.fade_frames.record(self.state.frame_time);
+            }
+        }
+        let s = &mut self.state.surfaces[idx];
+        let (top, bottom) = done.damage;
+        if let Some(pool) = s.shm_pool.as_mut() {
+            pool.memory.restore_map(done.map);
+        }
+        if let Some(slot) = s.shm_pool.as_ref().and_then(|p| p.slots.get(done.slot)) {
+            s.painted[done.slot] = done.painted;
+            s.wl_surface.attach(Some(&slot.buffer), 0, 0);
+            s.wl_surface
+                .damage_buffer(0, top, s.width as i32, bottom - top);
+            if self.state.want_presentation {
+                s.wl_surface.frame(&self.event_queue.handle(), s.id);
+                s.presenting = true;
+            }
+            s.wl_surface.commit();
+        }
+        if std::mem::take(&mut s.redraw_pending)
+            && let Err(err) = self.redraw_surface(idx)
+        {
+            rate_limited("redraw error", err);
+        }
+    }
 
-        // Dim background: mostly opaque black
-        let bg_alpha = 255;
-        for px in bytes.chunks_exact_mut(4) {
-            px.copy_from_slice(&[
-                self.state.colors.background[0],
-                self.state.colors.background[1],
-                self.state.colors.background[2],
-                bg_alpha,
-            ]);
-        }
-
-        let text_height: i32 = lines.iter().map(|line| line_height_size(line.size)).sum();
-        let total_height = icon_height + if icon_height > 0 { ICON_GAP } else { 0 } + text_height;
-        let base_y = ((h as i32 - total_height) / 2).max(0);
-
-        let tint = [
-            self.state.colors.foreground[0],
-            self.state.colors.foreground[1],
-            self.state.colors.foreground[2],
-        ];
-
-        if let Some(icon) = icon.as_ref() {
-            let icon_x = ((w as i32 - icon.width as i32) / 2).max(0);
-            if self.state.text_alpha > 0 {
-                draw_icon_rgba(
-                    bytes,
-                    w,
-                    h,
-                    icon_x,
-                    base_y,
-                    icon,
-                    tint,
-                    self.state.text_alpha,
-                );
+    /// Waits briefly for the compositor to show the frames committed last, so
+    /// destroying the surfaces does not flash whatever was on them before.
+    fn wait_for_presentation(&mut self) {
+        let start = Instant::now();
+        while self.state.surfaces.iter().any(|s| s.presenting)
+            && start.elapsed() < PRESENT_WAIT_TIMEOUT
+        {
+            if let Err(err) = self.read_events() {
+                rate_limited("wayland read", err);
+                return;
             }
+            thread::sleep(PRESENT_POLL);
         }
+    }
 
-        let text_start_y = base_y + icon_height + if icon_height > 0 { ICON_GAP } else { 0 };
-        let mut line_y = text_start_y;
-        for line in &lines {
-            let ascent = line_ascent_size(line.size);
-            let base_x = match line.anchor {
-                LineAnchor::Center => {
-                    let line_width = text_width_size(&line.text, line.size);
-                    ((w as i32 - line_width) / 2).max(0)
-                }
-                LineAnchor::CenterOnColon => {
-                    if let Some(idx) = line.text.find(':') {
-                        let (left, _) = line.text.split_at(idx);
-                        let left_width = text_width_size(left, line.size);
-                        let colon_width = text_width_size(":", line.size);
-                        ((w as i32 / 2) - left_width - (colon_width / 2)).max(0)
-                    } else {
-                        let line_width = text_width_size(&line.text, line.size);
-                        ((w as i32 - line_width) / 2).max(0)
-                    }
-                }
-            };
-            let alpha = ((self.state.text_alpha as f32) * line.alpha).round() as u8;
-            let rgba = [white[0], white[1], white[2], alpha];
-            draw_text_rgba_size(
-                bytes,
-                w,
-                h,
-                base_x,
-                line_y + ascent,
-                &line.text,
-                rgba,
-                line.size,
-            );
-            line_y += line_height_size(line.size);
+    /// Waits briefly for frames still being painted, so they reach the screen.
+    fn finish_pending_renders(&mut self) {
+        while self.state.surfaces.iter().any(|s| s.in_flight) {
+            match self.renderer.recv_timeout(RENDER_WAIT_TIMEOUT) {
+                Some(done) => self.finish_render(done),
+                None => break,
+            }
+        }
+    }
+}
+
+impl State {
+    /// Whether overlays letting input through should still see the pointer.
+    fn wants_hotspot(&self) -> bool {
+        self.wake_on_pointer && !self.micro && matches!(self.fade, FadeState::In { .. })
+    }
+
+    fn check_pointer_wake(&mut self, x: f64, y: f64) {
+        if !self.wake_on_pointer
+            || self.input_captured
+            || !matches!(self.fade, FadeState::In { .. })
+        {
+            return;
         }
+        match self.hover_origin {
+            Some((x0, y0)) if (x - x0).hypot(y - y0) >= POINTER_WAKE_DISTANCE => {
+                self.wake_requested = true;
+            }
+            Some(_) => {}
+            None => self.hover_origin = Some((x, y)),
+        }
+    }
 
-        if let Some(icon) = small_icon.as_ref() {
-            let pad = 20;
-            let x = w as i32 - icon.width as i32 - pad;
-            let y = h as i32 - icon.height as i32 - pad;
-            draw_icon_rgba(bytes, w, h, x, y, icon, tint, 255);
+    /// Marks surface `idx` to be drawn again once it is free. A redraw
+    /// already waiting is folded into this one, which during a fade is a
+    /// frame the screen never got.
+    fn defer_redraw(&mut self, idx: usize) {
+        if std::mem::replace(&mut self.surfaces[idx].redraw_pending, true)
+            && !matches!(self.fade, FadeState::None)
+        {
+            self.fade_frames.dropped += 1;
         }
+    }
 
-        let fade = self.state.overlay_alpha as u16;
-        for px in bytes.chunks_exact_mut(4) {
-            px[0] = ((px[0] as u16 * fade) / 255) as u8;
-            px[1] = ((px[1] as u16 * fade) / 255) as u8;
-            px[2] = ((px[2] as u16 * fade) / 255) as u8;
-            px[3] = fade as u8;
+    /// What `--debug-overlay` shows on surface `idx`: the daemon's lines,
+    /// then the overlay's own.
+    fn debug_lines(&self, idx: usize) -> Vec<String> {
+        let s = &self.surfaces[idx];
+        let fade = match &self.fade {
+            FadeState::None => "none".to_string(),
+            FadeState::In { start } => format!("in, {:.1}s", start.elapsed().as_secs_f32()),
+            FadeState::Out { start } => format!("out, {:.1}s", start.elapsed().as_secs_f32()),
+        };
+        let output = self
+            .outputs
+            .iter()
+            .find(|info| info.output == s.output)
+            .map_or_else(|| "?".to_string(), OutputInfo::label);
+        let mut lines = self.debug_info.clone();
+        lines.extend([
+            format!(
+                "fade {fade}, alpha {}/{}, text {}{}",
+                self.overlay_alpha,
+                self.max_alpha,
+                self.text_alpha,
+                if self.dim { ", dimmed" } else { "" }
+            ),
+            format!(
+                "input {}{}, keyboard {}, pointer {}",
+                if self.input_captured {
+                    "captured"
+                } else {
+                    "passed through"
+                },
+                if self.desired_capture != self.input_captured {
+                    " (changing)"
+                } else {
+                    ""
+                },
+                if self.keyboard.is_some() { "yes" } else { "no" },
+                if self.pointer.is_some() { "yes" } else { "no" },
+            ),
+            format!(
+                "frame {:.1} ms, last fade {}",
+                self.frame_time.as_secs_f64() * 1000.0,
+                self.last_fade
+                    .map_or_else(|| "-".to_string(), |frames| frames.summary())
+            ),
+            format!(
+                "{} on {output}, {}x{}",
+                self.backend().label(),
+                s.width,
+                s.height
+            ),
+        ]);
+        lines
+    }
+
+    /// The most of `max_alpha` the overlay reaches.
+    fn ceiling(&self) -> f32 {
+        if self.dim {
+            DIM_FRACTION
+        } else if self.micro {
+            MICRO_FRACTION
+        } else {
+            1.0
         }
+    }
 
-        let s = &self.state.surfaces[idx];
-        s.wl_surface.attach(Some(&buffer), 0, 0);
-        s.wl_surface.damage_buffer(0, 0, w as i32, h as i32);
-        s.wl_surface.commit();
-        Ok(())
+    fn fade_in_duration(&self) -> Duration {
+        if self.micro {
+            MICRO_FADE_IN_DURATION
+        } else {
+            FADE_IN_DURATION
+        }
+    }
+
+    fn is_card(&self, info: &OutputInfo) -> bool {
+        self.cards_only
+            || self.osd
+            || self
+                .card_outputs
+                .iter()
+                .any(|pattern| info.matches(pattern))
+    }
+
+    fn backend(&self) -> Backend {
+        self.backends[0]
+    }
+
+    fn is_excluded(&self, info: &OutputInfo) -> bool {
+        self.exclude_outputs
+            .iter()
+            .any(|pattern| info.matches(pattern))
+    }
+
+    /// Where the countdown digits are on `--countdown-gradient`, worked out
+    /// afresh for every frame from the break's deadline.
+    fn countdown_color(&self) -> Option<[u8; 3]> {
+        let gradient = self.countdown_gradient.as_ref()?;
+        let UiMode::OnBreak {
+            ends_at,
+            break_secs,
+            ..
+        } = self.ui_mode
+        else {
+            return None;
+        };
+        countdown_color(
+            gradient,
+            self.colors.foreground,
+            self.countdown_gradient_secs,
+            break_secs,
+            ends_at.saturating_duration_since(Instant::now()),
+        )
+    }
+
+    fn shows_content(&self, idx: usize) -> bool {
+        // Cards are there to be read from across the room.
+        if !self.follow_focus
+            || self
+                .surfaces
+                .get(idx)
+                .is_some_and(|s| s.kind == SurfaceKind::Card)
+        {
+            return true;
+        }
+        match &self.focused_surface {
+            Some(focused) => self
+                .surfaces
+                .get(idx)
+                .is_some_and(|s| &s.wl_surface == focused),
+            // Nothing focused yet: show content everywhere so it is not lost.
+            None => true,
+        }
+    }
+
+    fn set_focus(&mut self, surface: WlSurface) {
+        if !self.surfaces.iter().any(|s| s.wl_surface == surface) {
+            return;
+        }
+        if self.focused_surface.as_ref() != Some(&surface) {
+            self.focused_surface = Some(surface);
+            self.focus_changed = true;
+        }
+    }
+
+    fn check_pointer_hold(&mut self) {
+        if let Some(press) = &mut self.pointer_press
+            && !press.fired
+            && pointer_held(press.start, Instant::now())
+        {
+            press.fired = true;
+            let _ = self.tx_ui.send(UiEvent::PointerGesture);
+        }
+    }
+
+    /// Applies a configured size to surface `idx` (0 keeps the current one)
+    /// and sets its input region now that the size is known.
+    fn configure_surface(&mut self, idx: usize, width: u32, height: u32, qh: &QueueHandle<Self>) {
+        let hotspot = self.wants_hotspot();
+        let s = &mut self.surfaces[idx];
+        if width > 0 {
+            s.width = width;
+        }
+        if height > 0 {
+            s.height = height;
+        }
+        if s.width > 0 && s.height > 0 && self.overlay_active {
+            if self.desired_capture && s.kind == SurfaceKind::Overlay {
+                s.wl_surface.set_input_region(None);
+                s.input_region = None;
+            } else if let Some(compositor) = &self.compositor {
+                let hotspot = hotspot && s.kind == SurfaceKind::Overlay;
+                let region = passive_region(compositor, qh, s.width, s.height, hotspot);
+                s.wl_surface.set_input_region(Some(&region));
+                s.input_region = Some(region);
+            }
+            s.wl_surface.commit();
+        }
+    }
+
+    fn check_key_hold(&mut self) {
+        if let Some(press) = &mut self.key_press
+            && !press.fired
+            && press.start.elapsed() >= KEY_HOLD_DURATION
+        {
+            press.fired = true;
+            let _ = self.tx_ui.send(UiEvent::KeyHold(press.event.clone()));
+        }
+    }
+
+    fn check_pointer_swipe(&mut self) {
+        if let Some(press) = &mut self.pointer_press
+            && !press.fired
+            && pointer_swiped(self.pointer_x - press.start_x, self.pointer_width)
+        {
+            press.fired = true;
+            let _ = self.tx_ui.send(UiEvent::PointerGesture);
+        }
+    }
+
+    fn send_key_layout(&self) {
+        let layout = match &self.xkb_keymap {
+            Some(keymap) => KeyLayout::from_keymap(keymap, self.xkb_layout),
+            None => KeyLayout::default(),
+        };
+        let _ = self.tx_ui.send(UiEvent::KeyLayout(layout));
     }
 }
 
@@ -924,48 +2220,65 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
                             s.to_string(),
                             xkb::KEYMAP_FORMAT_TEXT_V1,
                             xkb::COMPILE_NO_FLAGS,
-                        ) {
-                            state.xkb_state = Some(xkb::State::new(&keymap));
-                            state.xkb_keymap = Some(keymap);
-                        }
+                        )
+                    {
+                        state.xkb_state = Some(xkb::State::new(&keymap));
+                        state.xkb_keymap = Some(keymap);
+                        state.xkb_layout = 0;
+                        state.send_key_layout();
+                    }
                 }
             }
-            wl_keyboard::Event::Enter { .. } => {}
-            wl_keyboard::Event::Leave { .. } => {}
+            wl_keyboard::Event::Enter { surface, .. } => state.set_focus(surface),
+            wl_keyboard::Event::Leave { .. } => state.key_press = None,
             wl_keyboard::Event::Key {
                 key, state: kstate, ..
             } => {
                 if kstate != WEnum::Value(wl_keyboard::KeyState::Pressed) {
+                    if state
+                        .key_press
+                        .as_ref()
+                        .is_some_and(|press| press.key == key)
+                    {
+                        state.key_press = None;
+                    }
                     return;
                 }
-                if let Some(xkbs) = &mut state.xkb_state {
-                    // Wayland keycodes are offset by 8 from evdev
-                    let sym = xkbs.key_get_one_sym((key + 8).into());
-
-                    // Decode minimal keys: Enter and 'z' (snooze)
-                    // xkbcommon keysyms: Return = 0xff0d, z = 0x007a
-                    match sym.raw() {
-                        0xff0d => {
-                            let _ = state.tx_ui.send(UiEvent::PressEnter);
-                        }
-                        0x007a | 0x005a => {
-                            let _ = state.tx_ui.send(UiEvent::PressZ);
-                        }
-                        _ => {}
-                    }
-                } else {
-                    // Fallback to common evdev keycodes if no keymap yet.
-                    match key {
-                        28 => {
-                            let _ = state.tx_ui.send(UiEvent::PressEnter);
-                        }
-                        44 => {
-                            let _ = state.tx_ui.send(UiEvent::PressZ);
-                        }
-                        _ => {}
-                    }
+                // Most likely typed into the window underneath just as the
+                // overlay took the keyboard; it should not snooze or dismiss.
+                if in_key_grace(state.keyboard_since, state.key_grace, Instant::now()) {
+                    return;
                 }
-                let _ = state.tx_ui.send(UiEvent::AnyKey);
+                // Wayland keycodes are offset by 8 from evdev.
+                let keycode = xkb::Keycode::new(key + 8);
+                let event = match &state.xkb_state {
+                    Some(xkbs) => KeyEvent {
+                        keysym: Some(xkbs.key_get_one_sym(keycode).raw()),
+                        keycode: key,
+                        text: xkbs.key_get_utf8(keycode),
+                        modifiers: Modifiers {
+                            shift: xkbs
+                                .mod_name_is_active(xkb::MOD_NAME_SHIFT, xkb::STATE_MODS_EFFECTIVE),
+                            ctrl: xkbs
+                                .mod_name_is_active(xkb::MOD_NAME_CTRL, xkb::STATE_MODS_EFFECTIVE),
+                            alt: xkbs
+                                .mod_name_is_active(xkb::MOD_NAME_ALT, xkb::STATE_MODS_EFFECTIVE),
+                            logo: xkbs
+                                .mod_name_is_active(xkb::MOD_NAME_LOGO, xkb::STATE_MODS_EFFECTIVE),
+                        },
+                    },
+                    None => KeyEvent {
+                        keycode: key,
+                        ..KeyEvent::default()
+                    },
+                };
+                state.key_press = Some(KeyPress {
+                    key,
+                    start: Instant::now(),
+                    event: event.clone(),
+                    fired: false,
+                });
+                let _ = state.tx_ui.send(UiEvent::Key(event));
             }
             wl_keyboard::Event::Modifiers {
                 mods_depressed,
@@ -976,6 +2289,11 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
             } => {
                 if let Some(xkbs) = &mut state.xkb_state {
                     xkbs.update_mask(mods_depressed, mods_latched, mods_locked, 0, 0, group);
+                    let layout = xkbs.serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE);
+                    if layout != state.xkb_layout {
+                        state.xkb_layout = layout;
+                        state.send_key_layout();
+                    }
                 }
             }
             _ => {}
@@ -990,7 +2308,7 @@ impl Dispatch<ZwlrLayerSurfaceV1, ()> for State {
         event: <ZwlrLayerSurfaceV1 as Proxy>::Event,
         _data: &(),
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
     ) {
         match event {
             zwlr_layer_surface_v1::Event::Configure {
@@ -999,31 +2317,12 @@ impl Dispatch<ZwlrLayerSurfaceV1, ()> for State {
                 height,
             } => {
                 proxy.ack_configure(serial);
-
-                for s in state.surfaces.iter_mut() {
-                    if &s.layer_surface == proxy {
-                        if width > 0 {
-                            s.width = width;
-                        }
-                        if height > 0 {
-                            s.height = height;
-                        }
-                        if s.width > 0 && s.height > 0 {
-                            s.stride = (s.width as i32) * 4;
-                            if state.overlay_active {
-                                if state.desired_capture {
-                                    s.wl_surface.set_input_region(None);
-                                    s.input_region = None;
-                                } else if let Some(compositor) = state.compositor.clone() {
-                                    let region = compositor.create_region(_qh, ());
-                                    s.wl_surface.set_input_region(Some(&region));
-                                    s.input_region = Some(region);
-                                }
-                                s.wl_surface.commit();
-                            }
-                        }
-                        break;
-                    }
+                if let Some(idx) = state
+                    .surfaces
+                    .iter()
+                    .position(|s| matches!(&s.role, Role::Layer(l) if l == proxy))
+                {
+                    state.configure_surface(idx, width, height, qh);
                 }
             }
             zwlr_layer_surface_v1::Event::Closed => {
@@ -1064,31 +2363,59 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                 }
                 "wl_output" => {
                     let ver = version.min(WlOutput::interface().version);
-                    let out = proxy.bind(name, ver, qh, ());
-                    state.outputs.push(out);
+                    let output = proxy.bind(name, ver, qh, ());
+                    state.outputs.push(OutputInfo {
+                        global_name: name,
+                        output,
+                        name: None,
+                        description: None,
+                    });
                 }
                 "zwlr_layer_shell_v1" if state.layer_shell.is_none() => {
                     let ver = version.min(ZwlrLayerShellV1::interface().version);
                     state.layer_shell = Some(proxy.bind(name, ver, qh, ()));
                 }
+                "xdg_wm_base" if state.wm_base.is_none() => {
+                    let ver = version.min(XdgWmBase::interface().version);
+                    state.wm_base = Some(proxy.bind(name, ver, qh, ()));
+                }
+                "ext_session_lock_manager_v1" if state.session_lock_manager.is_none() => {
+                    let ver = version.min(ExtSessionLockManagerV1::interface().version);
+                    state.session_lock_manager = Some(proxy.bind(name, ver, qh, ()));
+                }
                 _ => {}
             },
-            wl_registry::Event::GlobalRemove { .. } => {}
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(pos) = state.outputs.iter().position(|o| o.global_name == name) {
+                    let info = state.outputs.remove(pos);
+                    info!("Output removed: {}", info.label());
+                    if info.output.version() >= 3 {
+                        info.output.release();
+                    }
+                }
+            }
             _ => {}
         }
     }
 }
 
-// Boilerplate: unused but required for compilation in some setups
 impl Dispatch<wl_output::WlOutput, ()> for State {
     fn event(
-        _state: &mut Self,
-        _proxy: &wl_output::WlOutput,
-        _event: wl_output::Event,
+        state: &mut Self,
+        proxy: &wl_output::WlOutput,
+        event: wl_output::Event,
         _data: &(),
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
     ) {
+        let Some(info) = state.outputs.iter_mut().find(|o| &o.output == proxy) else {
+            return;
+        };
+        match event {
+            wl_output::Event::Name { name } => info.name = Some(name),
+            wl_output::Event::Description { description } => info.description = Some(description),
+            _ => {}
+        }
     }
 }
 
@@ -1102,11 +2429,10 @@ impl Dispatch<WlBuffer, ()> for State {
         _qh: &QueueHandle<Self>,
     ) {
         for surface in _state.surfaces.iter_mut() {
-            for slot in surface.buffer_slots.iter_mut() {
-                if &slot.buffer == proxy {
-                    slot.busy = false;
-                    return;
-                }
+            if let Some(pool) = surface.shm_pool.as_mut()
+                && pool.release(proxy)
+            {
+                return;
             }
         }
     }
@@ -1136,6 +2462,132 @@ impl Dispatch<ZwlrLayerShellV1, ()> for State {
     }
 }
 
+impl Dispatch<ExtSessionLockManagerV1, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ExtSessionLockManagerV1,
+        _event: <ExtSessionLockManagerV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtSessionLockV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtSessionLockV1,
+        event: ext_session_lock_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_session_lock_v1::Event::Locked => state.session_locked = true,
+            ext_session_lock_v1::Event::Finished => {
+                state.session_locked = false;
+                state.session_lock_finished = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockSurfaceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &ExtSessionLockSurfaceV1,
+        event: ext_session_lock_surface_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let ext_session_lock_surface_v1::Event::Configure {
+            serial,
+            width,
+            height,
+        } = event
+        {
+            proxy.ack_configure(serial);
+            if let Some(idx) = state
+                .surfaces
+                .iter()
+                .position(|s| matches!(&s.role, Role::Lock(l) if l == proxy))
+            {
+                state.configure_surface(idx, width, height, qh);
+            }
+        }
+    }
+}
+
+impl Dispatch<XdgWmBase, ()> for State {
+    fn event(
+        _state: &mut Self,
+        proxy: &XdgWmBase,
+        event: xdg_wm_base::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            proxy.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgSurface, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &XdgSurface,
+        event: xdg_surface::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            proxy.ack_configure(serial);
+            if let Some(idx) = state.surfaces.iter().position(
+                |s| matches!(&s.role, Role::Window { xdg_surface, .. } if xdg_surface == proxy),
+            ) {
+                // The size came with the toplevel configure just before.
+                state.configure_surface(idx, 0, 0, qh);
+            }
+        }
+    }
+}
+
+impl Dispatch<XdgToplevel, ()> for State {
+    fn event(
+        state: &mut Self,
+        proxy: &XdgToplevel,
+        event: xdg_toplevel::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Closing the window (e.g. Alt+F4) is ignored like any other attempt
+        // to get rid of the overlay; only the size matters here.
+        if let xdg_toplevel::Event::Configure { width, height, .. } = event
+            && let Some(s) = state
+                .surfaces
+                .iter_mut()
+                .find(|s| matches!(&s.role, Role::Window { toplevel, .. } if toplevel == proxy))
+        {
+            if width > 0 {
+                s.width = width as u32;
+            }
+            if height > 0 {
+                s.height = height as u32;
+            }
+            if s.kind == SurfaceKind::Card && (s.width == 0 || s.height == 0) {
+                // The compositor leaves a floating window's size to us.
+                [s.width, s.height] = state.card.size;
+            }
+        }
+    }
+}
+
 impl Dispatch<WlCompositor, ()> for State {
     fn event(
         _state: &mut Self,
@@ -1191,14 +2643,14 @@ impl Dispatch<WlSeat, ()> for State {
                 }
                 state.xkb_state = None;
                 state.xkb_keymap = None;
+                state.send_key_layout();
             }
 
             if has_pointer && state.pointer.is_none() {
                 state.pointer = Some(proxy.get_pointer(qh, ()));
-            } else if !has_pointer
-                && let Some(ptr) = state.pointer.take() {
-                    ptr.release();
-                }
+            } else if !has_pointer && let Some(ptr) = state.pointer.take() {
+                ptr.release();
+            }
         }
     }
 }
@@ -1215,6 +2667,23 @@ impl Dispatch<WlSurface, ()> for State {
     }
 }
 
+impl Dispatch<WlCallback, u64> for State {
+    fn event(
+        state: &mut Self,
+        _proxy: &WlCallback,
+        event: wl_callback::Event,
+        surface: &u64,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event
+            && let Some(s) = state.surfaces.iter_mut().find(|s| s.id == *surface)
+        {
+            s.presenting = false;
+        }
+    }
+}
+
 impl Dispatch<WlRegion, ()> for State {
     fn event(
         _state: &mut Self,
@@ -1236,11 +2705,122 @@ impl Dispatch<wl_pointer::WlPointer, ()> for State {
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
     ) {
-        if let wl_pointer::Event::Button {
+        // Dismissing needs a deliberate gesture (click-and-hold or a
+        // horizontal swipe) so a stray click does not end the screen.
+        match event {
+            wl_pointer::Event::Enter {
+                surface,
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.pointer_x = surface_x;
+                state.hover_origin = Some((surface_x, surface_y));
+                state.pointer_width = state
+                    .surfaces
+                    .iter()
+                    .find(|s| s.wl_surface == surface)
+                    .map(|s| s.width)
+                    .unwrap_or(0);
+                state.set_focus(surface);
+            }
+            wl_pointer::Event::Leave { .. } => {
+                state.pointer_press = None;
+                state.hover_origin = None;
+            }
+            wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.pointer_x = surface_x;
+                state.check_pointer_swipe();
+                state.check_pointer_wake(surface_x, surface_y);
+            }
+            wl_pointer::Event::Button {
                 state: btn_state, ..
-            } = event
-            && btn_state == WEnum::Value(wl_pointer::ButtonState::Pressed) {
-                let _ = state.tx_ui.send(UiEvent::PointerClick);
+            } => {
+                if btn_state == WEnum::Value(wl_pointer::ButtonState::Pressed) {
+                    state.pointer_press = Some(PointerPress {
+                        start: Instant::now(),
+                        start_x: state.pointer_x,
+                        fired: false,
+                    });
+                } else {
+                    state.pointer_press = None;
+                }
             }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_gestures_need_a_long_hold_or_a_wide_swipe() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(!pointer_held(start, at(0)));
+        assert!(!pointer_held(start, at(1499)));
+        assert!(pointer_held(start, at(1500)));
+        assert!(!pointer_held(start, start - Duration::from_millis(10)));
+
+        // A quarter of a 1920-pixel screen, either way.
+        assert!(!pointer_swiped(479.0, 1920));
+        assert!(pointer_swiped(480.0, 1920));
+        assert!(pointer_swiped(-480.0, 1920));
+        // Never less than the minimum on a narrow surface, or before the
+        // width is known.
+        assert!(!pointer_swiped(199.0, 400));
+        assert!(pointer_swiped(200.0, 400));
+        assert!(!pointer_swiped(199.0, 0));
+    }
+
+    #[test]
+    fn key_grace_runs_from_when_the_keyboard_was_taken() {
+        let grace = Duration::from_millis(200);
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+
+        let since = grace_start(true, false, None, at(0));
+        assert!(in_key_grace(since, grace, at(150)));
+        assert!(!in_key_grace(since, grace, at(200)));
+
+        // Taken early in the fade-in at 0, then for good at 100: the window
+        // still ends at 200.
+        let early = Some(at(0));
+        let since = grace_start(true, true, early, at(100));
+        assert_eq!(since, early);
+        assert!(!in_key_grace(since, grace, at(250)));
+        // Not taken early, the window starts over.
+        assert!(in_key_grace(
+            grace_start(true, false, early, at(100)),
+            grace,
+            at(250)
+        ));
+
+        assert_eq!(grace_start(false, true, early, at(100)), None);
+        assert!(!in_key_grace(None, grace, at(0)));
+    }
+
+    #[test]
+    fn countdown_color_moves_between_whole_seconds() {
+        let gradient = Gradient(vec![[200, 0, 0, 255]]);
+        let white = [255, 255, 255, 255];
+        let color = |span, left_ms| {
+            countdown_color(&gradient, white, span, 60, Duration::from_millis(left_ms))
+        };
+        assert_eq!(color(10, 30_000), None);
+        assert_eq!(color(10, 10_000), None);
+        let early = color(10, 5_900).unwrap();
+        let late = color(10, 5_100).unwrap();
+        assert!(late[1] < early[1], "{early:?} then {late:?}");
+        assert_eq!(color(10, 0), Some([200, 0, 0]));
+        // 0 spreads the gradient over the whole break.
+        let halfway = Gradient(vec![white, [200, 0, 0, 255]]).at(0.5);
+        assert_eq!(color(0, 30_000), Some(halfway));
     }
 }
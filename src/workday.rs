@@ -0,0 +1,94 @@
+//! The working day set by `--workday-end` (and optionally `--workday-start`).
+//!
+//! When the end passes, interlude calls one last, longer wrap-up break and
+//! then holds every break back until the day starts again: the next
+//! `--workday-start`, or midnight without one.
+//!
+//! Both times are local wall-clock times. When the UTC offset changes, by
+//! a DST transition or a new time zone, off-hours under way are measured
+//! again from the new local time, so they end at the start of the day
+//! where the user is now.
+
+use chrono::{NaiveDateTime, NaiveTime, TimeDelta};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workday {
+    pub start: Option<NaiveTime>,
+    pub end: NaiveTime,
+}
+
+impl Workday {
+    /// Whether the local time `at` lies outside the working day. A start
+    /// later than the end is a day that runs past midnight.
+    pub fn is_off_hours(&self, at: NaiveTime) -> bool {
+        match self.start {
+            None => at >= self.end,
+            Some(start) if start <= self.end => at < start || at >= self.end,
+            Some(start) => at >= self.end && at < start,
+        }
+    }
+
+    /// When off-hours under way end if the local time became `now`, e.g.
+    /// after crossing time zones; `None` when `now` is working time.
+    pub fn off_hours_left(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.is_off_hours(now.time())
+            .then(|| self.off_hours_end(now))
+    }
+
+    /// When the off-hours under way at `now` end.
+    pub fn off_hours_end(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let start = now.date().and_time(self.start.unwrap_or(NaiveTime::MIN));
+        if start > now {
+            start
+        } else {
+            start + TimeDelta::days(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn at(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 3, day)
+            .unwrap()
+            .and_time(time(h, m))
+    }
+
+    #[test]
+    fn off_hours_run_from_the_end_to_the_next_start() {
+        let day = Workday {
+            start: Some(time(8, 30)),
+            end: time(17, 0),
+        };
+        assert!(day.is_off_hours(time(7, 0)));
+        assert!(!day.is_off_hours(time(8, 30)));
+        assert!(day.is_off_hours(time(17, 0)));
+        assert_eq!(day.off_hours_end(at(1, 17, 0)), at(2, 8, 30));
+        assert_eq!(day.off_hours_end(at(2, 6, 0)), at(2, 8, 30));
+        // Flown west at night, or east into the morning.
+        assert_eq!(day.off_hours_left(at(1, 20, 0)), Some(at(2, 8, 30)));
+        assert_eq!(day.off_hours_left(at(2, 9, 0)), None);
+
+        let open_start = Workday {
+            start: None,
+            end: time(17, 0),
+        };
+        assert!(!open_start.is_off_hours(time(0, 0)));
+        assert_eq!(open_start.off_hours_end(at(1, 18, 0)), at(2, 0, 0));
+
+        let night_shift = Workday {
+            start: Some(time(22, 0)),
+            end: time(6, 0),
+        };
+        assert!(!night_shift.is_off_hours(time(23, 0)));
+        assert!(!night_shift.is_off_hours(time(5, 59)));
+        assert!(night_shift.is_off_hours(time(6, 0)));
+        assert_eq!(night_shift.off_hours_end(at(1, 6, 0)), at(1, 22, 0));
+    }
+}
@@ -0,0 +1,206 @@
+//! End-to-end runs against a headless sway.
+//!
+//! Each test starts its own `sway` on the wlroots headless backend with a
+//! private runtime directory, runs the interlude binary against it and
+//! follows the status file through a break. Without `sway` on `PATH` the
+//! tests pass without doing anything, so a plain `cargo test` stays usable;
+//! CI installs sway (and `wtype` for the keyboard test) to run them for real.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Generous, since a cold CI runner can take a while to start a compositor.
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+struct Session {
+    dir: PathBuf,
+    sway: Child,
+    wayland_display: String,
+    interlude: Option<Child>,
+}
+
+impl Session {
+    /// A headless sway in its own runtime directory, or `None` when sway is
+    /// not installed.
+    fn start(name: &str) -> Option<Self> {
+        if !on_path("sway") {
+            eprintln!("sway not found, skipping {name}");
+            return None;
+        }
+        let dir = env::temp_dir().join(format!("interlude-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("runtime")).unwrap();
+        fs::create_dir_all(dir.join("state")).unwrap();
+        set_private(&dir.join("runtime"));
+        let config = dir.join("sway.conf");
+        fs::write(&config, "output HEADLESS-1 resolution 1280x720\n").unwrap();
+        let sway = Command::new("sway")
+            .arg("--config")
+            .arg(&config)
+            .env("XDG_RUNTIME_DIR", dir.join("runtime"))
+            .env("WLR_BACKENDS", "headless")
+            .env("WLR_RENDERER", "pixman")
+            .env("WLR_LIBINPUT_NO_DEVICES", "1")
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("SWAYSOCK")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("spawn sway");
+        let mut session = Self {
+            dir,
+            sway,
+            wayland_display: String::new(),
+            interlude: None,
+        };
+        session.wayland_display = wait_for("wayland socket", || {
+            fs::read_dir(session.dir.join("runtime"))
+                .ok()?
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .find(|name| name.starts_with("wayland-") && !name.ends_with(".lock"))
+        });
+        Some(session)
+    }
+
+    fn command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        command
+            .env("XDG_RUNTIME_DIR", self.dir.join("runtime"))
+            .env("XDG_STATE_HOME", self.dir.join("state"))
+            .env("WAYLAND_DISPLAY", &self.wayland_display)
+            .env_remove("SWAYSOCK");
+        command
+    }
+
+    fn run_interlude(&mut self, args: &[&str]) {
+        let child = self
+            .command(env!("CARGO_BIN_EXE_interlude"))
+            .args(["--reset-state", "--fade-fps", "120"])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("spawn interlude");
+        self.interlude = Some(child);
+    }
+
+    fn ctl(&self, args: &[&str]) -> String {
+        let output = self
+            .command(env!("CARGO_BIN_EXE_interlude"))
+            .arg("ctl")
+            .args(args)
+            .output()
+            .expect("run interlude ctl");
+        assert!(output.status.success(), "ctl {args:?} failed: {output:?}");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// The `class` the status file currently reports.
+    fn class(&self) -> Option<String> {
+        let json = fs::read_to_string(self.dir.join("runtime/interlude-status.json")).ok()?;
+        let (_, rest) = json.split_once("\"class\":\"")?;
+        Some(rest.split('"').next()?.to_string())
+    }
+
+    fn wait_for_class(&self, class: &str) {
+        wait_for(&format!("status class {class:?}"), || {
+            (self.class().as_deref() == Some(class)).then_some(())
+        });
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(interlude) = &mut self.interlude {
+            let _ = interlude.kill();
+            let _ = interlude.wait();
+        }
+        let _ = self.sway.kill();
+        let _ = self.sway.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Wayland refuses runtime directories others can read.
+fn set_private(dir: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).unwrap();
+}
+
+fn wait_for<T>(what: &str, mut check: impl FnMut() -> Option<T>) -> T {
+    let start = Instant::now();
+    loop {
+        if let Some(value) = check() {
+            return value;
+        }
+        assert!(start.elapsed() < TIMEOUT, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn break_locks_runs_and_unlocks() {
+    let Some(mut session) = Session::start("lock-cycle") else {
+        return;
+    };
+    session.run_interlude(&["--immediate", "--break-seconds", "2"]);
+    session.wait_for_class("break");
+    session.wait_for_class("working");
+}
+
+#[test]
+fn ctl_snooze_lifts_a_due_break() {
+    let Some(mut session) = Session::start("ctl-snooze") else {
+        return;
+    };
+    session.run_interlude(&["--immediate", "--break-due-policy", "wait"]);
+    session.wait_for_class("due");
+    assert_eq!(session.ctl(&["snooze"]), "ok");
+    session.wait_for_class("snoozed");
+    assert!(session.ctl(&["status"]).starts_with("Snoozed, break in"));
+}
+
+#[test]
+fn keyboard_reaches_the_overlay() {
+    let Some(mut session) = Session::start("keyboard") else {
+        return;
+    };
+    if !on_path("wtype") {
+        eprintln!("wtype not found, skipping keyboard_reaches_the_overlay");
+        return;
+    }
+    session.run_interlude(&["--immediate", "--break-seconds", "600"]);
+    session.wait_for_class("break");
+    // The overlay only has the keyboard once it grabbed it, so keep pressing
+    // skip until the break ends.
+    wait_for("skip key to end the break", || {
+        let _ = session.command("wtype").arg("s").status();
+        (session.class().as_deref() == Some("working")).then_some(())
+    });
+}
+
+#[test]
+fn test_overlay_shows_and_exits() {
+    let Some(session) = Session::start("test-overlay") else {
+        return;
+    };
+    let mut overlay = session
+        .command(env!("CARGO_BIN_EXE_interlude"))
+        .args(["--fade-fps", "120", "test-overlay", "--seconds", "1"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn interlude test-overlay");
+    let status = wait_for("test-overlay to exit", || overlay.try_wait().unwrap());
+    assert!(status.success(), "test-overlay failed: {status}");
+}